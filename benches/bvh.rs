@@ -0,0 +1,90 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use simple_engine::types::{
+    bvh::{Aabb, Bvh},
+    frustum::Frustum,
+    matrices::Matrix4f,
+    ray::Ray,
+    vectors::Vec3f,
+};
+
+const BOX_COUNTS: [usize; 2] = [1_000, 10_000];
+
+// Boxes up to 2 units wide scattered over a 200 unit cube, the same for every run.
+fn boxes(count: usize, seed: u32) -> Vec<Aabb> {
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (state >> 8) as f32 / (1 << 24) as f32
+    };
+    (0..count)
+        .map(|_| {
+            let min = Vec3f::new([next() * 200.0 - 100.0, next() * 200.0 - 100.0, next() * 200.0 - 100.0]);
+            Aabb::new(min, min + Vec3f::new([next() * 2.0, next() * 2.0, next() * 2.0]))
+        })
+        .collect()
+}
+
+fn bvh(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bvh");
+    for count in BOX_COUNTS {
+        let bounds = boxes(count, 1);
+        let moved = boxes(count, 2);
+        let tree = Bvh::build(&bounds);
+
+        group.bench_with_input(BenchmarkId::new("build", count), &count, |bench, _| {
+            bench.iter(|| Bvh::build(black_box(&bounds)))
+        });
+        group.bench_with_input(BenchmarkId::new("refit", count), &count, |bench, _| {
+            let mut tree = tree.clone();
+            bench.iter(|| tree.refit(black_box(&moved)))
+        });
+
+        let rays: Vec<Ray> = boxes(256, 3)
+            .into_iter()
+            .map(|x| Ray::new(Vec3f::new([0.0, 0.0, -150.0]), x.min))
+            .collect();
+        group.bench_with_input(BenchmarkId::new("ray_256", count), &count, |bench, _| {
+            bench.iter(|| {
+                for ray in rays.iter() {
+                    black_box(tree.traverse_ray(ray, f32::INFINITY, |x, _| ray.intersect_aabb(bounds[x].min, bounds[x].max)));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("ray_256_brute_force", count), &count, |bench, _| {
+            bench.iter(|| {
+                for ray in rays.iter() {
+                    black_box(
+                        bounds
+                            .iter()
+                            .filter_map(|x| ray.intersect_aabb(x.min, x.max))
+                            .fold(f32::INFINITY, f32::min),
+                    );
+                }
+            })
+        });
+
+        let projection = Matrix4f::perspective(1.2, 16.0 / 9.0, 0.1, 1000.0);
+        let view = Matrix4f::look_at_rh(Vec3f::new([0.0, 0.0, -150.0]), Vec3f::new([20.0, 0.0, 0.0]), Vec3f::new([0.0, 1.0, 0.0]));
+        let frustum = Frustum::from_matrix(projection * view);
+        group.bench_with_input(BenchmarkId::new("frustum", count), &count, |bench, _| {
+            bench.iter(|| {
+                let mut visible = 0;
+                tree.traverse_frustum(black_box(&frustum), |_| visible += 1);
+                visible
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("overlap_pairs", count), &count, |bench, _| {
+            bench.iter(|| {
+                let mut pairs = 0;
+                for (a, aabb) in bounds.iter().enumerate() {
+                    tree.traverse_aabb(aabb, |b| pairs += (b > a) as usize);
+                }
+                pairs
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bvh);
+criterion_main!(benches);
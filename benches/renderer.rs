@@ -0,0 +1,130 @@
+use std::time::{Duration, Instant};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use simple_engine::{
+    ecs::World,
+    rendering::{self, Renderer},
+    stress::StressScene,
+    types::{buffers::UpdatableBuffer, matrices::Matrix4f, mesh::DynamicMesh, vectors::Vec3f},
+};
+use vulkano::buffer::BufferUsage;
+
+const MESH_COUNTS: [usize; 2] = [1_000, 10_000];
+
+fn matrices(c: &mut Criterion) {
+    let a = Matrix4f::rotation_yxz(Vec3f::new([0.3, 1.2, -0.7])) * Matrix4f::translation(Vec3f::new([1.0, 2.0, 3.0]));
+    let b = Matrix4f::perspective(1.2, 16.0 / 9.0, 0.1, 1000.0);
+    let points: Vec<Vec3f> = (0..1024).map(|i| Vec3f::new([i as f32, (i % 7) as f32, -(i as f32)])).collect();
+
+    let mut group = c.benchmark_group("matrix4f");
+    group.bench_function("mul", |bench| bench.iter(|| black_box(a) * black_box(b)));
+    group.bench_function("inverse", |bench| bench.iter(|| black_box(a).inverse()));
+    group.bench_function("transform_point_1024", |bench| {
+        bench.iter(|| {
+            for point in points.iter() {
+                black_box(a.transform_point(*point));
+            }
+        })
+    });
+    group.bench_function("transform_points_1024", |bench| {
+        let mut batch = points.clone();
+        bench.iter(|| a.transform_points(black_box(&mut batch)))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("vec3f");
+    let mut u = Vec3f::new([0.3, -1.2, 2.5]);
+    let v = Vec3f::new([1.0, 0.5, -0.25]);
+    group.bench_function("dot", |bench| bench.iter(|| black_box(u).dot(black_box(v))));
+    group.bench_function("cross", |bench| bench.iter(|| black_box(u).cross(black_box(v))));
+    group.bench_function("normalize", |bench| bench.iter(|| black_box(&mut u).normalize()));
+    group.finish();
+}
+
+// Buffers need a device. Without a Vulkan driver these benchmarks are skipped.
+fn device() -> Option<Renderer> {
+    let mut renderer = Renderer::new();
+    match rendering::init_device_only(&mut renderer) {
+        Ok(()) => Some(renderer),
+        Err(e) => {
+            eprintln!("skipping GPU benchmarks, no device: {e}");
+            None
+        }
+    }
+}
+
+fn for_each_mesh(world: &World, f: impl FnMut(&mut DynamicMesh)) {
+    world.borrow_component_vec_mut::<DynamicMesh>().unwrap().iter_mut().flatten().for_each(f);
+}
+
+fn dynamic_meshes(c: &mut Criterion) {
+    let Some(mut renderer) = device() else {
+        return;
+    };
+
+    let mut group = c.benchmark_group("dynamic_meshes");
+    group.sample_size(20);
+    for count in MESH_COUNTS {
+        let mut world = World::new();
+        StressScene::new(count, "default").spawn(&mut world);
+
+        // Every mesh gets fresh buffers, like the first frame after spawning.
+        group.bench_with_input(BenchmarkId::new("prepare_load", count), &count, |bench, _| {
+            bench.iter_custom(|iterations| {
+                let mut total = Duration::ZERO;
+                for _ in 0..iterations {
+                    for_each_mesh(&world, |x| x.release(&mut renderer));
+                    let start = Instant::now();
+                    rendering::prepare_dynamic_meshes(&world, &mut renderer);
+                    total += start.elapsed();
+                }
+                total
+            })
+        });
+
+        // Nothing to allocate, only the per-frame checks.
+        rendering::prepare_dynamic_meshes(&world, &mut renderer);
+        group.bench_with_input(BenchmarkId::new("prepare_steady", count), &count, |bench, _| {
+            bench.iter(|| rendering::prepare_dynamic_meshes(&world, &mut renderer))
+        });
+
+        group.bench_with_input(BenchmarkId::new("upload", count), &count, |bench, _| {
+            bench.iter_custom(|iterations| {
+                let mut total = Duration::ZERO;
+                for _ in 0..iterations {
+                    for_each_mesh(&world, |x| {
+                        let indices = std::mem::take(&mut x.indices);
+                        x.change_indices(indices);
+                    });
+                    let start = Instant::now();
+                    rendering::upload_dynamic_meshes(&world, &mut renderer, 0);
+                    total += start.elapsed();
+                }
+                total
+            })
+        });
+
+        for_each_mesh(&world, |x| x.release(&mut renderer));
+    }
+    group.finish();
+}
+
+fn uniform_buffers(c: &mut Criterion) {
+    let Some(renderer) = device() else {
+        return;
+    };
+
+    let buffers: Vec<UpdatableBuffer<Matrix4f>> =
+        (0..1_000).map(|_| UpdatableBuffer::new(&renderer, BufferUsage::UNIFORM_BUFFER)).collect();
+    let matrix = Matrix4f::translation(Vec3f::new([1.0, 2.0, 3.0]));
+    c.bench_function("updatable_buffer/write_1000", |bench| {
+        bench.iter(|| {
+            for buffer in buffers.iter() {
+                buffer.write_frame(0, black_box(matrix));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, matrices, dynamic_meshes, uniform_buffers);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+// Compiled into shaders/builtin/spv, which is committed and embedded by `builtin_shaders`.
+const BUILTIN_SHADERS: [&str; 13] = [
+    "mesh.vert",
+    "unlit_color.frag",
+    "unlit_textured.frag",
+    "lit.frag",
+    "skybox.vert",
+    "skybox.frag",
+    "ui.vert",
+    "ui.frag",
+    "color_grading.comp",
+    "lens.comp",
+    "lightmapped.frag",
+    "lit_ray_query.frag",
+    "occlusion_proxy.vert",
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=shaders/builtin");
+    println!("cargo:rerun-if-env-changed=SIMPLE_ENGINE_COMPILE_SHADERS");
+    println!("cargo:rerun-if-env-changed=GLSLC");
+
+    // Building needs no shader compiler. After editing shaders/builtin, build once with
+    // SIMPLE_ENGINE_COMPILE_SHADERS=1 to regenerate the SPIR-V and commit it with the sources.
+    if env::var_os("SIMPLE_ENGINE_COMPILE_SHADERS").is_none_or(|x| x.is_empty() || x == "0") {
+        return;
+    }
+
+    let glslc = env::var("GLSLC").unwrap_or_else(|_| "glslc".to_string());
+    let out_dir = Path::new("shaders/builtin/spv");
+    for shader in BUILTIN_SHADERS {
+        let source = format!("shaders/builtin/{shader}");
+        let output = out_dir.join(format!("{shader}.spv"));
+        // Ray queries need SPIR-V 1.4, the shader is only used on devices that have them.
+        let target_env = if shader.contains("ray_query") { "vulkan1.2" } else { "vulkan1.0" };
+        match Command::new(&glslc).arg(format!("--target-env={target_env}")).arg(&source).arg("-o").arg(&output).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => panic!("failed to compile {source}: {status}"),
+            Err(e) => panic!("failed to run {glslc}: {e}, set GLSLC to a glslc binary"),
+        }
+    }
+}
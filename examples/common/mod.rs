@@ -0,0 +1,141 @@
+// Helpers shared by the examples, not every example uses all of them.
+#![allow(dead_code)]
+
+use std::cell::Cell;
+
+use simple_engine::prelude::*;
+
+// Runs the example, or only its first `EXAMPLE_FRAMES` frames when that is set, so scripts can
+// smoke test every example with `EXAMPLE_FRAMES=120 cargo run --example <name>`.
+pub fn run(mut world: World, assets: AssetLibrary) {
+    if let Some(frames) = std::env::var("EXAMPLE_FRAMES").ok().and_then(|x| x.parse().ok()) {
+        world.add_system(FrameLimit {
+            remaining: Cell::new(frames),
+        });
+    }
+    simple_engine::run(world, assets);
+}
+
+struct FrameLimit {
+    remaining: Cell<u32>,
+}
+
+impl System for FrameLimit {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let remaining = self.remaining.get().saturating_sub(1);
+        self.remaining.set(remaining);
+        state.exit_requested |= remaining == 0;
+    }
+}
+
+// Asset library with the built-in shaders.
+pub fn assets() -> AssetLibrary {
+    let mut assets = AssetLibrary::default();
+    builtin_shaders::load(&mut assets).expect("examples need the built-in shaders");
+    assets
+}
+
+pub fn mesh(name: &str, material: &str, vertices: Vec<VertexData>, indices: Vec<u32>) -> Mesh {
+    Mesh {
+        name: name.to_string(),
+        vertices,
+        indices,
+        material: material.to_string(),
+        vertex_buffer: None,
+        index_buffer: None,
+        morph_targets: Vec::new(),
+        morph_buffer: None,
+        meshlets: Vec::new(),
+        bvh: None,
+    }
+}
+
+// Unit cube centered on the origin, every face gets its own normals, UVs and `colors` entry.
+pub fn cube(name: &str, material: &str, colors: [Color; 6]) -> Mesh {
+    let faces = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+        ([0.0, 0.0, -1.0], [1.0, 0.0, 0.0]),
+    ];
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for ((normal, up), color) in faces.into_iter().zip(colors) {
+        let mut normal = Vec3f::new(normal);
+        let up = Vec3f::new(up);
+        let right = normal.cross(up);
+        let base = vertices.len() as u32;
+        for (u, v) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            let position = normal * 0.5 + right * (u - 0.5) + up * (v - 0.5);
+            vertices.push(VertexData::new(position, Vec2f::new([u, v]), normal).with_color(color));
+        }
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+    mesh(name, material, vertices, indices)
+}
+
+// Square in the XZ plane facing up.
+pub fn plane(name: &str, material: &str, size: f32, color: Color) -> Mesh {
+    let normal = Vec3f::new([0.0, 1.0, 0.0]);
+    let vertices = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+        .into_iter()
+        .map(|(u, v)| {
+            let position = Vec3f::new([(u - 0.5) * size, 0.0, (0.5 - v) * size]);
+            VertexData::new(position, Vec2f::new([u, v]), normal).with_color(color)
+        })
+        .collect();
+    mesh(name, material, vertices, vec![0, 1, 2, 0, 2, 3])
+}
+
+// Cameras look down +X, `Camera` rotations are applied in XZY order. This gives the rotation
+// that turns by `yaw` around Y after pitching up by `pitch`, without any roll.
+pub fn camera_rotation(yaw: f32, pitch: f32) -> Vec3f {
+    let (sy, cy) = yaw.sin_cos();
+    let (sp, cp) = pitch.sin_cos();
+    Vec3f::new([(sy * sp).atan2(cp), (sy).atan2(cy * cp), (cy * sp).clamp(-1.0, 1.0).asin()])
+}
+
+// Yaw and pitch for a camera at `from` looking at `to`.
+pub fn look_at(from: Vec3d, to: Vec3d) -> (f32, f32) {
+    let d = to - from;
+    let yaw = (-d.z).atan2(d.x) as f32;
+    let pitch = (d.y / (d.x * d.x + d.y * d.y + d.z * d.z).sqrt()).asin() as f32;
+    (yaw, pitch)
+}
+
+pub fn spawn_camera(world: &mut World, position: Vec3d, target: Vec3d) -> usize {
+    let (yaw, pitch) = look_at(position, target);
+    let mut camera = CameraBundle::at(position, 1.2);
+    camera.transform.rotation = camera_rotation(yaw, pitch);
+    world.spawn_bundle(camera)
+}
+
+// Rotates the entity's transform by `speed` radians per second around each axis.
+#[derive(Clone, Copy)]
+pub struct Spin {
+    pub speed: Vec3f,
+}
+
+pub struct Spinner {}
+
+impl System for Spinner {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(spins) = world.borrow_component_vec_mut::<Spin>() else {
+            return;
+        };
+        let mut transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+        for (entity, spin) in spins.iter().enumerate() {
+            let (Some(spin), Some(transform)) = (spin, transforms[entity].as_mut()) else {
+                continue;
+            };
+            transform.rotation += spin.speed * state.delta_time as f32;
+            world.mark_changed::<Transform>(entity);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+// Free flying camera: WASD to move, space and shift for up and down, hold the right mouse button
+// to look around, escape to quit.
+//
+//     cargo run --example input_camera
+
+mod common;
+
+use std::cell::Cell;
+
+use simple_engine::prelude::*;
+use winit::event::MouseButton;
+use winit::keyboard::{Key, NamedKey};
+
+const SPEED: f64 = 6.0;
+const SENSITIVITY: f32 = 0.003;
+
+struct FlyCamera {
+    yaw: Cell<f32>,
+    pitch: Cell<f32>,
+}
+
+impl System for FlyCamera {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let input = &state.input;
+        if input.pressed.contains(&Key::Named(NamedKey::Escape)) {
+            state.exit_requested = true;
+        }
+
+        let looking = input.mouse_down.contains(&MouseButton::Right);
+        if input.mouse_pressed.contains(&MouseButton::Right) || input.mouse_released.contains(&MouseButton::Right) {
+            state.window.set_cursor_visible(!looking);
+            if let Err(e) = state.window.set_cursor_grab(looking) {
+                log::warn!("cursor grab failed: {e}");
+            }
+        }
+        if looking {
+            let delta = state.input.get_mouse_delta();
+            self.yaw.set(self.yaw.get() - delta.x * SENSITIVITY);
+            self.pitch.set((self.pitch.get() - delta.y * SENSITIVITY).clamp(-1.5, 1.5));
+        }
+
+        let key = |name: &str| state.input.down.contains(&Key::Character(name.into()));
+        let axis = |positive: bool, negative: bool| positive as i32 as f64 - negative as i32 as f64;
+        let forward = axis(key("w"), key("s"));
+        let right = axis(key("d"), key("a"));
+        let up = axis(
+            state.input.down.contains(&Key::Named(NamedKey::Space)),
+            state.input.down.contains(&Key::Named(NamedKey::Shift)),
+        );
+
+        let cameras = world.borrow_component_vec_mut::<Camera>().unwrap();
+        let mut transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+        let Some(entity) = (0..cameras.len()).find(|x| cameras[*x].is_some() && transforms[*x].is_some()) else {
+            return;
+        };
+        let transform = transforms[entity].as_mut().unwrap();
+        let (yaw, pitch) = (self.yaw.get() as f64, self.pitch.get() as f64);
+        let heading = Vec3d::new([yaw.cos(), 0.0, -yaw.sin()]);
+        let side = Vec3d::new([yaw.sin(), 0.0, yaw.cos()]);
+        let step = SPEED * state.delta_time;
+        transform.position += heading * (forward * pitch.cos() * step) + side * (right * step) + Vec3d::new([0.0, up * step, 0.0]);
+        transform.position.y += forward * pitch.sin() * step;
+        transform.rotation = common::camera_rotation(self.yaw.get(), self.pitch.get());
+        world.mark_changed::<Transform>(entity);
+    }
+}
+
+fn main() {
+    let mut assets = common::assets();
+    assets.materials.push(Material::unlit_color("unlit"));
+    assets.meshes.push(common::plane("ground", "unlit", 60.0, Color::GRAY));
+    let colors = [Color::RED, Color::CYAN, Color::GREEN, Color::MAGENTA, Color::BLUE, Color::YELLOW];
+    assets.meshes.push(common::cube("cube", "unlit", colors));
+
+    let mut world = World::new();
+    let start = Vec3d::new([-8.0, 2.0, 0.0]);
+    common::spawn_camera(&mut world, start, Vec3d::new([0.0, 2.0, 0.0]));
+    world.spawn_bundle(MeshBundle::at("ground", Vec3d::new([0.0, 0.0, 0.0])));
+    for i in 0..40 {
+        let angle = i as f64 * 0.7;
+        let distance = 4.0 + i as f64 * 0.6;
+        world.spawn_bundle(MeshBundle::at("cube", Vec3d::new([angle.cos() * distance, 0.5, angle.sin() * distance])));
+    }
+    world.add_system(FlyCamera {
+        yaw: Cell::new(0.0),
+        pitch: Cell::new(0.0),
+    });
+
+    common::run(world, assets);
+}
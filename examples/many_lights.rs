@@ -0,0 +1,75 @@
+// A field of cubes lit by a few hundred moving point lights, which goes through the clustered
+// light culling.
+//
+//     cargo run --example many_lights
+
+mod common;
+
+use simple_engine::prelude::*;
+
+const LIGHTS: usize = 256;
+const GRID: i32 = 12;
+
+// Each light circles its own center.
+#[derive(Clone, Copy)]
+struct Orbit {
+    center: Vec3d,
+    radius: f64,
+    speed: f64,
+    phase: f64,
+}
+
+struct Orbiter {}
+
+impl System for Orbiter {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let orbits = world.borrow_component_vec_mut::<Orbit>().unwrap();
+        let mut transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+        for (entity, orbit) in orbits.iter().enumerate() {
+            let (Some(orbit), Some(transform)) = (orbit, transforms[entity].as_mut()) else {
+                continue;
+            };
+            let angle = orbit.phase + state.time * orbit.speed;
+            transform.position = orbit.center + Vec3d::new([angle.cos() * orbit.radius, 0.0, angle.sin() * orbit.radius]);
+            world.mark_changed::<Transform>(entity);
+        }
+    }
+}
+
+fn main() {
+    let mut assets = common::assets();
+    assets.materials.push(Material::lit("lit"));
+    assets.meshes.push(common::cube("cube", "lit", [Color::WHITE; 6]));
+    assets.meshes.push(common::plane("ground", "lit", GRID as f32 * 3.0, Color::GRAY));
+
+    let mut world = World::new();
+    common::spawn_camera(&mut world, Vec3d::new([-20.0, 12.0, 0.0]), Vec3d::new([0.0, 0.0, 0.0]));
+    world.spawn_bundle(MeshBundle::at("ground", Vec3d::new([0.0, -0.5, 0.0])));
+    for x in -GRID / 2..GRID / 2 {
+        for z in -GRID / 2..GRID / 2 {
+            world.spawn_bundle(MeshBundle::at("cube", Vec3d::new([x as f64 * 3.0, 0.0, z as f64 * 3.0])));
+        }
+    }
+
+    let palette = [Color::RED, Color::GREEN, Color::BLUE, Color::YELLOW, Color::CYAN, Color::MAGENTA];
+    let extent = GRID as f64 * 1.5;
+    for i in 0..LIGHTS {
+        // Golden angle spiral, spreads the orbits evenly over the field.
+        let t = i as f64 / LIGHTS as f64;
+        let angle = i as f64 * 2.399963;
+        let center = Vec3d::new([angle.cos() * t.sqrt() * extent, 1.0, angle.sin() * t.sqrt() * extent]);
+        let light = PointLight::new(palette[i % palette.len()], 4.0, 4.0);
+        let entity = world.spawn_bundle(LightBundle::new(light, center));
+        world.add_component(entity, Orbit {
+            center,
+            radius: 1.0 + (i % 3) as f64,
+            speed: 0.5 + (i % 5) as f64 * 0.2,
+            phase: angle,
+        });
+    }
+    world.add_system(Orbiter {});
+
+    common::run(world, assets);
+}
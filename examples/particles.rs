@@ -0,0 +1,118 @@
+// A fountain of additive particles, simulated on the CPU and drawn as one dynamic mesh of
+// camera facing quads.
+//
+//     cargo run --example particles
+
+mod common;
+
+use std::cell::RefCell;
+
+use simple_engine::prelude::*;
+
+const PARTICLES: usize = 2000;
+const LIFETIME: f32 = 3.0;
+const SIZE: f32 = 0.08;
+
+#[derive(Clone, Copy)]
+struct Particle {
+    position: Vec3f,
+    velocity: Vec3f,
+    age: f32,
+}
+
+struct Fountain {
+    particles: RefCell<Vec<Particle>>,
+    seed: RefCell<u32>,
+}
+
+impl Fountain {
+    fn random(&self) -> f32 {
+        let mut seed = self.seed.borrow_mut();
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 17;
+        *seed ^= *seed << 5;
+        *seed as f32 / u32::MAX as f32
+    }
+
+    fn emit(&self) -> Particle {
+        let angle = self.random() * std::f32::consts::TAU;
+        let spread = self.random() * 1.5;
+        Particle {
+            position: Vec3f::new([0.0, 0.0, 0.0]),
+            velocity: Vec3f::new([angle.cos() * spread, 6.0 + self.random() * 2.0, angle.sin() * spread]),
+            // Staggered so the fountain does not pulse.
+            age: self.random() * LIFETIME,
+        }
+    }
+}
+
+impl System for Fountain {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {
+        let particles = (0..PARTICLES).map(|_| self.emit()).collect();
+        *self.particles.borrow_mut() = particles;
+    }
+
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let dt = state.delta_time as f32;
+        let mut particles = self.particles.borrow_mut();
+        for particle in particles.iter_mut() {
+            particle.age += dt;
+            if particle.age > LIFETIME {
+                *particle = Particle { age: 0.0, ..self.emit() };
+            }
+            particle.velocity.y -= 9.81 * dt;
+            particle.position += particle.velocity * dt;
+        }
+
+        // The camera looks down +X, so quads in the YZ plane face it.
+        let mut vertices = Vec::with_capacity(particles.len() * 4);
+        let mut indices = Vec::with_capacity(particles.len() * 6);
+        let normal = Vec3f::new([-1.0, 0.0, 0.0]);
+        for particle in particles.iter() {
+            let t = particle.age / LIFETIME;
+            let color = Color::YELLOW.lerp(Color::RED, t).with_alpha(1.0 - t);
+            let base = vertices.len() as u32;
+            for (u, v) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                let offset = Vec3f::new([0.0, (v - 0.5) * SIZE, (u - 0.5) * SIZE]);
+                vertices.push(VertexData::new(particle.position + offset, Vec2f::new([u, v]), normal).with_color(color));
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
+        let mut meshes = world.borrow_component_vec_mut::<DynamicMesh>().unwrap();
+        let mesh = meshes.iter_mut().flatten().next().unwrap();
+        mesh.change_vertices(vertices);
+        mesh.change_indices(indices);
+    }
+}
+
+fn main() {
+    let mut assets = common::assets();
+    let render_state = RenderState {
+        depth_write: false,
+        ..Default::default()
+    }
+    .with_blend_mode(BlendMode::Additive);
+    assets.materials.push(
+        Material::unlit_color("particles")
+            .with_render_state(render_state)
+            .with_queue(RenderQueue::Transparent),
+    );
+
+    let mut world = World::new();
+    common::spawn_camera(&mut world, Vec3d::new([-8.0, 3.0, 0.0]), Vec3d::new([0.0, 3.0, 0.0]));
+    let mesh = DynamicMesh {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+        material: "particles".to_string(),
+        buffers: None,
+    };
+    let transform = Transform::new(Vec3d::new([0.0, 0.0, 0.0]), Vec3f::new([1.0, 1.0, 1.0]), Vec3f::new([0.0, 0.0, 0.0]));
+    world.spawn_bundle(DynamicMeshBundle::new(mesh, transform));
+    world.add_system(Fountain {
+        particles: RefCell::new(Vec::new()),
+        seed: RefCell::new(0x9e37_79b9),
+    });
+
+    common::run(world, assets);
+}
@@ -0,0 +1,23 @@
+// A vertex colored cube turning in front of the camera.
+//
+//     cargo run --example spinning_cube
+
+mod common;
+
+use common::{Spin, Spinner};
+use simple_engine::prelude::*;
+
+fn main() {
+    let mut assets = common::assets();
+    assets.materials.push(Material::unlit_color("cube"));
+    let colors = [Color::RED, Color::CYAN, Color::GREEN, Color::MAGENTA, Color::BLUE, Color::YELLOW];
+    assets.meshes.push(common::cube("cube", "cube", colors));
+
+    let mut world = World::new();
+    common::spawn_camera(&mut world, Vec3d::new([-3.0, 1.5, 0.0]), Vec3d::new([0.0, 0.0, 0.0]));
+    let cube = world.spawn_bundle(MeshBundle::at("cube", Vec3d::new([0.0, 0.0, 0.0])));
+    world.add_component(cube, Spin { speed: Vec3f::new([0.4, 0.9, 0.0]) });
+    world.add_system(Spinner {});
+
+    common::run(world, assets);
+}
@@ -0,0 +1,33 @@
+// Loads a glTF model and draws it with an unlit texture.
+//
+//     cargo run --example textured_model -- path/to/model.gltf path/to/texture.png
+
+mod common;
+
+use common::{Spin, Spinner};
+use simple_engine::prelude::*;
+use simple_engine::types::gltf::load_gltf;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (Some(model), Some(texture)) = (args.next(), args.next()) else {
+        eprintln!("usage: textured_model <model.gltf> <texture.png>");
+        std::process::exit(1);
+    };
+
+    let mut assets = common::assets();
+    assets.textures.push(Texture::new(texture.clone()));
+    assets.materials.push(Material::unlit_textured("model", &texture));
+    let meshes = load_gltf(&model, "model").unwrap_or_else(|e| panic!("failed to load {model}: {e}"));
+
+    let mut world = World::new();
+    common::spawn_camera(&mut world, Vec3d::new([-4.0, 1.5, 0.0]), Vec3d::new([0.0, 0.0, 0.0]));
+    for mesh in meshes.iter() {
+        let entity = world.spawn_bundle(MeshBundle::at(&mesh.name, Vec3d::new([0.0, 0.0, 0.0])));
+        world.add_component(entity, Spin { speed: Vec3f::new([0.0, 0.5, 0.0]) });
+    }
+    assets.meshes.extend(meshes);
+    world.add_system(Spinner {});
+
+    common::run(world, assets);
+}
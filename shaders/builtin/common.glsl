@@ -0,0 +1,42 @@
+// Interface shared by every built-in shader. The engine binds set 0 (frame) and set 1 (model) for
+// every draw, the shadow passes only write binding 0 of both so vertex shaders must not read more.
+
+layout(set = 0, binding = 0) uniform VPData {
+    mat4 view;
+    mat4 projection;
+    vec4 clip_planes[4];
+    mat4 ui_projection;
+} vp;
+
+// `Fog::to_data`, mode 0 is no fog, 1 linear, 2 exponential and 3 exponential squared.
+layout(set = 0, binding = 1) uniform FogData {
+    vec4 color;
+    float density;
+    float start;
+    float end;
+    uint mode;
+} fog;
+
+// tint and emissive come from the entity's `MaterialOverride`, emissive.a is 1 when it replaces
+// the material's emissive.
+layout(set = 1, binding = 0) uniform ModelData {
+    mat4 model;
+    mat4 rotation;
+    vec4 tint;
+    vec4 emissive;
+} model_data;
+
+// Blends towards the fog color by the view depth, like `view_depth` from mesh.vert.
+vec3 apply_fog(vec3 color, float depth) {
+    float visibility;
+    if (fog.mode == 1u) {
+        visibility = clamp((fog.end - depth) / max(fog.end - fog.start, 1e-4), 0.0, 1.0);
+    } else if (fog.mode == 2u) {
+        visibility = exp(-fog.density * depth);
+    } else if (fog.mode == 3u) {
+        visibility = exp(-fog.density * fog.density * depth * depth);
+    } else {
+        return color;
+    }
+    return mix(fog.color.rgb, color, visibility);
+}
@@ -0,0 +1,20 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "common.glsl"
+#include "material.glsl"
+
+// Baked by `lightmapper::bake`, irradiance over pi in the second UV set.
+layout(set = 1, binding = 3) uniform sampler2D lightmap;
+
+layout(location = 3) in vec4 color;
+layout(location = 4) in float view_depth;
+layout(location = 5) in vec2 uv2;
+layout(location = 6) flat in vec4 instance_emissive;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    vec3 emissive = mix(material.emissive.rgb * material.emissive_intensity, instance_emissive.rgb, instance_emissive.a);
+    out_color = vec4(apply_fog(color.rgb * texture(lightmap, uv2).rgb + emissive, view_depth), color.a);
+}
@@ -0,0 +1,177 @@
+// Body of lit.frag and lit_ray_query.frag, the latter defines RAY_QUERY_SHADOWS.
+
+#include "common.glsl"
+#include "material.glsl"
+
+// Metallic-roughness GGX over the clustered point lights, albedo is the vertex color.
+const float METALLIC = 0.0;
+const float ROUGHNESS = 0.5;
+const float AMBIENT = 0.03;
+const float PI = 3.14159265;
+
+struct PointLight {
+    vec3 position;
+    float range;
+    vec4 color;
+    float intensity;
+    int shadow_index;
+    float shadow_bias;
+    float shadow_near;
+};
+
+// Depth cubes of the shadow casting lights, see `shadows::record_shadow_passes`. Unused slots hold
+// a dummy cube.
+layout(set = 0, binding = 2) uniform samplerCubeShadow shadow_maps[4];
+
+layout(set = 0, binding = 4) uniform ClusterData {
+    uvec3 grid;
+    uint light_count;
+    float near;
+    float far;
+    float slice_scale;
+    float slice_bias;
+    vec2 resolution;
+} cluster_data;
+
+layout(set = 0, binding = 5) readonly buffer Lights {
+    PointLight lights[];
+};
+
+// Offset and count into `light_indices` for every cluster.
+layout(set = 0, binding = 6) readonly buffer Clusters {
+    uint clusters[];
+};
+
+layout(set = 0, binding = 7) readonly buffer LightIndices {
+    uint light_indices[];
+};
+
+layout(location = 0) in vec3 position;
+layout(location = 2) in vec3 normal;
+layout(location = 3) in vec4 color;
+layout(location = 4) in float view_depth;
+layout(location = 6) flat in vec4 instance_emissive;
+
+layout(location = 0) out vec4 out_color;
+
+// Each face was drawn with `Matrix4f::perspective` from the light, so the stored depth is that of
+// the distance along the major axis of the light to fragment vector, with -1..1 mapped to itself.
+float shadow_map_visibility(PointLight light, vec3 position) {
+    if (light.shadow_index < 0) {
+        return 1.0;
+    }
+    vec3 from_light = position - light.position;
+    vec3 axes = abs(from_light);
+    float depth = max(max(axes.x, axes.y), axes.z) - light.shadow_bias;
+    float near = light.shadow_near;
+    float far = light.range;
+    float reference = ((far + near) - 2.0 * far * near / max(depth, near)) / (far - near);
+    vec4 coord = vec4(from_light, reference);
+    // Constant indices, so the array needs no dynamic indexing feature.
+    switch (light.shadow_index) {
+        case 0: return texture(shadow_maps[0], coord);
+        case 1: return texture(shadow_maps[1], coord);
+        case 2: return texture(shadow_maps[2], coord);
+        case 3: return texture(shadow_maps[3], coord);
+    }
+    return 1.0;
+}
+
+#ifdef RAY_QUERY_SHADOWS
+layout(set = 0, binding = 8) uniform accelerationStructureEXT scene;
+
+const uint SHADOW_RAYS = 4;
+const float LIGHT_RADIUS = 0.1;
+
+// Fraction of a few rays towards jittered points on a small sphere around the light that reach it,
+// which softens the shadow edges.
+float light_visibility(PointLight light, vec3 origin, vec3 n, float distance) {
+    vec3 start = origin + n * 1e-3 * max(distance, 1.0);
+    float visible = 0.0;
+    for (uint i = 0; i < SHADOW_RAYS; i++) {
+        float angle = (float(i) + fract(sin(dot(gl_FragCoord.xy, vec2(12.9898, 78.233))) * 43758.5453)) * 2.39996;
+        vec3 jitter = vec3(cos(angle), sin(angle), float(i) / float(SHADOW_RAYS) * 2.0 - 1.0) * LIGHT_RADIUS;
+        vec3 to_light = light.position + jitter - start;
+        float length_to_light = length(to_light);
+
+        rayQueryEXT query;
+        rayQueryInitializeEXT(query, scene, gl_RayFlagsTerminateOnFirstHitEXT | gl_RayFlagsOpaqueEXT, 0xFF, start, 0.0,
+            to_light / length_to_light, length_to_light);
+        while (rayQueryProceedEXT(query)) {}
+        if (rayQueryGetIntersectionTypeEXT(query, true) == gl_RayQueryCommittedIntersectionNoneEXT) {
+            visible += 1.0;
+        }
+    }
+    return visible / float(SHADOW_RAYS);
+}
+#else
+// Without ray queries, e.g. when the device lacks them, the shadow cubes stand in.
+float light_visibility(PointLight light, vec3 origin, vec3 n, float distance) {
+    return shadow_map_visibility(light, origin);
+}
+#endif
+
+float distribution_ggx(float n_dot_h, float roughness) {
+    float a2 = roughness * roughness * roughness * roughness;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+uint cluster_index() {
+    uvec2 tile = min(uvec2(gl_FragCoord.xy / cluster_data.resolution * vec2(cluster_data.grid.xy)), cluster_data.grid.xy - 1);
+    uint slice = uint(max(log(view_depth) * cluster_data.slice_scale - cluster_data.slice_bias, 0.0));
+    slice = min(slice, cluster_data.grid.z - 1);
+    return slice * cluster_data.grid.x * cluster_data.grid.y + tile.y * cluster_data.grid.x + tile.x;
+}
+
+void main() {
+    vec3 albedo = color.rgb;
+    vec3 n = normalize(normal);
+    // The camera sits at the origin of view space, so its render space position is the
+    // translation of the inverse view matrix.
+    vec3 camera_position = inverse(vp.view)[3].xyz;
+    vec3 v = normalize(camera_position - position);
+    float n_dot_v = max(dot(n, v), 1e-4);
+    vec3 f0 = mix(vec3(0.04), albedo, METALLIC);
+
+    vec3 radiance = vec3(0.0);
+    uint cluster = cluster_index();
+    uint offset = clusters[cluster * 2];
+    uint count = clusters[cluster * 2 + 1];
+    for (uint i = 0; i < count; i++) {
+        PointLight light = lights[light_indices[offset + i]];
+        vec3 to_light = light.position - position;
+        float distance = length(to_light);
+        if (distance >= light.range) {
+            continue;
+        }
+        vec3 l = to_light / distance;
+        vec3 h = normalize(v + l);
+        float n_dot_l = max(dot(n, l), 0.0);
+        if (n_dot_l <= 0.0) {
+            continue;
+        }
+        // Inverse square, windowed to reach zero at the range.
+        float window = clamp(1.0 - pow(distance / light.range, 4.0), 0.0, 1.0);
+        float attenuation = window * window / (distance * distance + 1.0);
+
+        vec3 f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+        vec3 specular = distribution_ggx(max(dot(n, h), 0.0), ROUGHNESS) * geometry_smith(n_dot_v, n_dot_l, ROUGHNESS) * f
+            / (4.0 * n_dot_v * n_dot_l + 1e-4);
+        vec3 diffuse = (1.0 - f) * (1.0 - METALLIC) * albedo / PI;
+        radiance += (diffuse + specular) * light.color.rgb * light.intensity * attenuation * n_dot_l
+            * light_visibility(light, position, n, distance);
+    }
+
+    vec3 emissive = mix(material.emissive.rgb * material.emissive_intensity, instance_emissive.rgb, instance_emissive.a);
+    out_color = vec4(apply_fog(albedo * AMBIENT + radiance + emissive, view_depth), color.a);
+}
@@ -0,0 +1,7 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+#extension GL_EXT_ray_query : require
+
+// lit.frag with shadows from ray queries against the scene, see `RendererSettings::ray_traced_shadows`.
+#define RAY_QUERY_SHADOWS
+#include "lit.glsl"
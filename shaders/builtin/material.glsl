@@ -0,0 +1,6 @@
+layout(set = 1, binding = 1) uniform MaterialData {
+    vec4 emissive;
+    float emissive_intensity;
+    float time;
+    vec2 uv_scroll;
+} material;
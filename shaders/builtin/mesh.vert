@@ -0,0 +1,32 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "common.glsl"
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec3 normal;
+layout(location = 3) in vec4 color;
+layout(location = 4) in vec2 uv2;
+
+layout(location = 0) out vec3 out_position;
+layout(location = 1) out vec2 out_uv;
+layout(location = 2) out vec3 out_normal;
+layout(location = 3) out vec4 out_color;
+layout(location = 4) out float out_view_depth;
+layout(location = 5) out vec2 out_uv2;
+layout(location = 6) flat out vec4 out_emissive;
+
+void main() {
+    vec4 world_position = model_data.model * vec4(position, 1.0);
+    vec4 view_position = vp.view * world_position;
+    gl_Position = vp.projection * view_position;
+
+    out_position = world_position.xyz;
+    out_uv = uv;
+    out_normal = mat3(model_data.rotation) * normal;
+    out_color = color * model_data.tint;
+    out_view_depth = -view_position.z;
+    out_uv2 = uv2;
+    out_emissive = model_data.emissive;
+}
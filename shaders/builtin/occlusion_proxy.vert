@@ -0,0 +1,17 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "common.glsl"
+
+// Corner of the shared unit cube, scaled to the entity's local bounds.
+layout(location = 0) in vec3 position;
+
+layout(push_constant) uniform Bounds {
+    vec4 min;
+    vec4 extent;
+} bounds;
+
+void main() {
+    vec3 local = bounds.min.xyz + position * bounds.extent.xyz;
+    gl_Position = vp.projection * vp.view * model_data.model * vec4(local, 1.0);
+}
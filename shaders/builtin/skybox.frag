@@ -0,0 +1,16 @@
+#version 450
+
+// Equirectangular panorama, +Y up.
+layout(set = 2, binding = 0) uniform sampler2D sky;
+
+layout(location = 0) in vec3 direction;
+
+layout(location = 0) out vec4 out_color;
+
+const float PI = 3.14159265;
+
+void main() {
+    vec3 d = normalize(direction);
+    vec2 uv = vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+    out_color = vec4(texture(sky, uv).rgb, 1.0);
+}
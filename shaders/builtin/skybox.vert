@@ -0,0 +1,17 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "common.glsl"
+
+layout(location = 0) in vec3 position;
+
+layout(location = 0) out vec3 out_direction;
+
+// Draws the mesh around the camera at the far plane, use it with a cube and depth writes off.
+void main() {
+    vec4 clip = vp.projection * vec4(mat3(vp.view) * mat3(model_data.rotation) * position, 1.0);
+    // `projection[2][2]` is positive for reversed-Z, where the far plane is at 0.
+    bool reversed = vp.projection[2][2] >= 0.0;
+    gl_Position = vec4(clip.xy, reversed ? clip.w * 1e-6 : clip.w * 0.999999, clip.w);
+    out_direction = position;
+}
@@ -0,0 +1,18 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "common.glsl"
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 uv;
+layout(location = 3) in vec4 color;
+
+layout(location = 1) out vec2 out_uv;
+layout(location = 3) out vec4 out_color;
+
+// UI batches are in logical pixels from the top-left corner.
+void main() {
+    gl_Position = vp.ui_projection * model_data.model * vec4(position.xy, 0.0, 1.0);
+    out_uv = uv;
+    out_color = color;
+}
@@ -0,0 +1,14 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "common.glsl"
+
+layout(location = 3) in vec4 color;
+layout(location = 4) in float view_depth;
+layout(location = 6) flat in vec4 instance_emissive;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    out_color = vec4(apply_fog(color.rgb + instance_emissive.rgb * instance_emissive.a, view_depth), color.a);
+}
@@ -0,0 +1,19 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "common.glsl"
+#include "material.glsl"
+
+layout(set = 2, binding = 0) uniform sampler2D albedo;
+
+layout(location = 1) in vec2 uv;
+layout(location = 3) in vec4 color;
+layout(location = 4) in float view_depth;
+layout(location = 6) flat in vec4 instance_emissive;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    out_color = texture(albedo, uv + material.uv_scroll * material.time) * color;
+    out_color.rgb = apply_fog(out_color.rgb + instance_emissive.rgb * instance_emissive.a, view_depth);
+}
@@ -0,0 +1,119 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitStr};
+
+fn named_fields(input: &DeriveInput) -> syn::Result<Vec<&syn::Field>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields.named.iter().collect()),
+            Fields::Unit => Ok(Vec::new()),
+            Fields::Unnamed(_) => Err(syn::Error::new_spanned(&input.ident, "tuple structs cannot be reflected")),
+        },
+        _ => Err(syn::Error::new_spanned(&input.ident, "only structs can be reflected")),
+    }
+}
+
+// `#[reflect(skip)]`
+fn skipped(field: &syn::Field) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in field.attrs.iter().filter(|x| x.path().is_ident("reflect")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
+
+fn reflect(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let mut fields = Vec::new();
+    for field in named_fields(&input)? {
+        if !skipped(field)? {
+            fields.push(field.ident.clone().unwrap());
+        }
+    }
+    let names: Vec<LitStr> = fields.iter().map(|x| LitStr::new(&x.to_string(), x.span())).collect();
+
+    Ok(quote! {
+        impl #impl_generics ::simple_engine::reflect::Reflect for #name #type_generics #where_clause {
+            fn fields(&self) -> ::simple_engine::reflect::ReflectedFields {
+                vec![#((#names, ::simple_engine::reflect::ReflectValue::to_field(&self.#fields)),)*]
+            }
+
+            fn set_field(&mut self, name: &str, value: &::simple_engine::reflect::FieldValue) -> bool {
+                match name {
+                    #(#names => match ::simple_engine::reflect::ReflectValue::from_field(value) {
+                        Some(value) => {
+                            self.#fields = value;
+                            true
+                        }
+                        None => false,
+                    },)*
+                    _ => false,
+                }
+            }
+        }
+    })
+}
+
+// `#[component(persistent, reflect, name = "...")]`
+fn component(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let mut persistent = false;
+    let mut reflected = false;
+    let mut registered_name = LitStr::new(&name.to_string(), name.span());
+    for attr in input.attrs.iter().filter(|x| x.path().is_ident("component")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("persistent") {
+                persistent = true;
+            } else if meta.path.is_ident("reflect") {
+                reflected = true;
+            } else if meta.path.is_ident("name") {
+                registered_name = meta.value()?.parse()?;
+            } else {
+                return Err(meta.error("expected `persistent`, `reflect` or `name`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let world = Ident::new("world", name.span());
+    let persistent = persistent.then(|| quote! { #world.register_persistent::<Self>(#registered_name); });
+    let reflected = reflected.then(|| quote! { #world.register_reflect::<Self>(#registered_name); });
+    Ok(quote! {
+        impl #impl_generics ::simple_engine::ecs::Component for #name #type_generics #where_clause {
+            const NAME: &'static str = #registered_name;
+
+            fn register(#world: &mut ::simple_engine::ecs::World) {
+                #persistent
+                #reflected
+            }
+        }
+    })
+}
+
+/// Implements `Reflect` for a struct with named fields. Every field type has to implement
+/// `ReflectValue`, others can be left out with `#[reflect(skip)]`.
+#[proc_macro_derive(Reflect, attributes(reflect))]
+pub fn derive_reflect(input: TokenStream) -> TokenStream {
+    reflect(parse_macro_input!(input as DeriveInput))
+        .unwrap_or_else(|x| x.to_compile_error())
+        .into()
+}
+
+/// Implements `Component`, registered with `World::register`. `#[component(persistent)]` adds it to
+/// snapshots (needs serde), `#[component(reflect)]` to the inspector (needs `Reflect`).
+#[proc_macro_derive(Component, attributes(component))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    component(parse_macro_input!(input as DeriveInput))
+        .unwrap_or_else(|x| x.to_compile_error())
+        .into()
+}
@@ -1,8 +1,56 @@
-use crate::types::{material::Material, mesh::Mesh, shader::Shader, texture::Texture};
+use crate::state::State;
+use crate::types::animation::{AnimationClip, BlendTree, Skeleton};
+use crate::types::{atlas::TextureAtlas, material::Material, mesh::Mesh, shader::Shader, texture::Texture};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    Mesh,
+    Shader,
+    Texture,
+    Material,
+    Atlas,
+    Animation,
+    BlendTree,
+    Skeleton,
+}
+
+#[derive(Default)]
 pub struct AssetLibrary {
     pub meshes: Vec<Mesh>,
     pub shaders: Vec<Shader>,
     pub textures: Vec<Texture>,
-    pub materials: Vec<Material>
+    pub materials: Vec<Material>,
+    pub atlases: Vec<TextureAtlas>,
+    pub animations: Vec<AnimationClip>,
+    pub blend_trees: Vec<BlendTree>,
+    pub skeletons: Vec<Skeleton>,
+}
+
+impl AssetLibrary {
+    // GPU resources stay alive until the command buffers still referencing them are dropped,
+    // so unloading only has to make sure they are re-recorded without the asset.
+    pub fn unload(&mut self, state: &mut State, kind: AssetKind, name: &str) -> bool {
+        let removed = match kind {
+            AssetKind::Mesh => remove_named(&mut self.meshes, name, |x| &x.name),
+            AssetKind::Shader => remove_named(&mut self.shaders, name, |x| &x.name),
+            AssetKind::Texture => remove_named(&mut self.textures, name, |x| &x.name),
+            AssetKind::Material => remove_named(&mut self.materials, name, |x| &x.name),
+            AssetKind::Atlas => remove_named(&mut self.atlases, name, |x| &x.name),
+            AssetKind::Animation => remove_named(&mut self.animations, name, |x| &x.name),
+            AssetKind::BlendTree => remove_named(&mut self.blend_trees, name, |x| &x.name),
+            AssetKind::Skeleton => remove_named(&mut self.skeletons, name, |x| &x.name),
+        };
+        if removed {
+            state.renderer.command_buffer_outdated = true;
+        } else {
+            log::warn!("cannot unload {:?} {}, it is not loaded", kind, name);
+        }
+        removed
+    }
+}
+
+fn remove_named<T>(assets: &mut Vec<T>, name: &str, asset_name: fn(&T) -> &String) -> bool {
+    let len = assets.len();
+    assets.retain(|x| asset_name(x) != name);
+    assets.len() != len
 }
@@ -0,0 +1,79 @@
+use vulkano::shader::spirv::bytes_to_words;
+
+use crate::asset_library::AssetLibrary;
+use crate::types::shader::{Shader, ShaderType};
+
+// Shaders from shaders/builtin, embedded as the SPIR-V committed in shaders/builtin/spv, see build.rs
+// to regenerate it. Every vertex shader works with `VertexData` and the frame and model sets, see
+// shaders/builtin/common.glsl.
+// The scene fragment shaders apply `RendererSettings::fog` by the view depth from `MESH_VS`.
+pub const MESH_VS: &str = "builtin_mesh_vs";
+pub const UNLIT_COLOR_FS: &str = "builtin_unlit_color_fs";
+/// Set 2 binding 0 is the texture, multiplied by the vertex color.
+pub const UNLIT_TEXTURED_FS: &str = "builtin_unlit_textured_fs";
+/// Clustered point lights with GGX specular, albedo is the vertex color. Lights with
+/// `PointLight::with_shadows` are shadowed by their cube maps.
+pub const LIT_FS: &str = "builtin_lit_fs";
+pub const SKYBOX_VS: &str = "builtin_skybox_vs";
+/// Set 2 binding 0 is an equirectangular panorama.
+pub const SKYBOX_FS: &str = "builtin_skybox_fs";
+/// Expects vertices in logical pixels from the top-left corner, like `UiBatch` meshes, see
+/// `VPData::ui_projection`.
+pub const UI_VS: &str = "builtin_ui_vs";
+/// Set 2 binding 0 is the atlas, multiplied by the vertex color.
+pub const UI_FS: &str = "builtin_ui_fs";
+/// Post effect for `PostEffect::color_grading`.
+pub const COLOR_GRADING_CS: &str = "builtin_color_grading_cs";
+/// Post effect for `PostEffect::lens`.
+pub const LENS_CS: &str = "builtin_lens_cs";
+/// Set 1 binding 3 is a lightmap from `lightmapper::bake`, multiplied by the vertex color.
+pub const LIGHTMAPPED_FS: &str = "builtin_lightmapped_fs";
+/// Draws a unit cube scaled to bounds given as push constants, for occlusion queries. Reads only
+/// binding 0 of the frame and model sets.
+pub const OCCLUSION_PROXY_VS: &str = "builtin_occlusion_proxy_vs";
+
+macro_rules! builtin {
+    ($name:expr, $shader_type:expr, $file:literal) => {
+        ($name, $shader_type, include_bytes!(concat!("../shaders/builtin/spv/", $file, ".spv")).as_slice())
+    };
+}
+
+fn builtins() -> [(&'static str, ShaderType, &'static [u8]); 12] {
+    [
+        builtin!(MESH_VS, ShaderType::Vertex, "mesh.vert"),
+        builtin!(UNLIT_COLOR_FS, ShaderType::Fragment, "unlit_color.frag"),
+        builtin!(UNLIT_TEXTURED_FS, ShaderType::Fragment, "unlit_textured.frag"),
+        builtin!(LIT_FS, ShaderType::Fragment, "lit.frag"),
+        builtin!(SKYBOX_VS, ShaderType::Vertex, "skybox.vert"),
+        builtin!(SKYBOX_FS, ShaderType::Fragment, "skybox.frag"),
+        builtin!(UI_VS, ShaderType::Vertex, "ui.vert"),
+        builtin!(UI_FS, ShaderType::Fragment, "ui.frag"),
+        builtin!(COLOR_GRADING_CS, ShaderType::Compute, "color_grading.comp"),
+        builtin!(LENS_CS, ShaderType::Compute, "lens.comp"),
+        builtin!(LIGHTMAPPED_FS, ShaderType::Fragment, "lightmapped.frag"),
+        builtin!(OCCLUSION_PROXY_VS, ShaderType::Vertex, "occlusion_proxy.vert"),
+    ]
+}
+
+// Adds every built-in shader to `assets`, before the engine starts. Shaders with the same name
+// that are already loaded are kept.
+pub fn load(assets: &mut AssetLibrary) -> Result<(), String> {
+    for (name, shader_type, spirv) in builtins() {
+        if assets.shaders.iter().any(|x| x.name == name) {
+            continue;
+        }
+        // `include_bytes!` gives no alignment guarantee.
+        let words = bytes_to_words(spirv).map_err(|e| format!("built-in shader {name}: {e}"))?;
+        assets.shaders.push(Shader::from_words(name, shader_type, words.into_owned()));
+    }
+    Ok(())
+}
+
+// `LIT_FS` with ray queried shadows. Kept out of `load` so devices without ray queries never see
+// the module.
+pub(crate) fn lit_ray_query() -> Result<Shader, String> {
+    let spirv = include_bytes!("../shaders/builtin/spv/lit_ray_query.frag.spv").as_slice();
+    let words = bytes_to_words(spirv).map_err(|e| format!("built-in shader {LIT_FS} with ray queries: {e}"))?;
+    Ok(Shader::from_words(LIT_FS, ShaderType::Fragment, words.into_owned()))
+}
+
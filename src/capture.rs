@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo, PrimaryAutoCommandBuffer};
+use vulkano::format::Format;
+use vulkano::image::{Image, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+
+use crate::rendering::Renderer;
+
+#[derive(Clone, Debug)]
+pub struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed RGBA8, as presented (sRGB encoded for sRGB swapchains).
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Clone, Default)]
+pub struct FrameCapture {
+    requested: bool,
+    pending: Option<(Subbuffer<[u8]>, [u32; 2], Format)>,
+    result: Option<CapturedFrame>,
+}
+
+impl FrameCapture {
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    pub fn take(&mut self) -> Option<CapturedFrame> {
+        self.result.take()
+    }
+
+    // A copy still in flight on a lost device is requested again from the new one.
+    pub(crate) fn without_device(self) -> FrameCapture {
+        FrameCapture {
+            requested: self.requested || self.pending.is_some(),
+            pending: None,
+            result: self.result,
+        }
+    }
+}
+
+// Copies the swapchain image after the frame's commands and before presenting it.
+pub(crate) fn record_copy(renderer: &mut Renderer, image: Arc<Image>) -> Option<Arc<PrimaryAutoCommandBuffer>> {
+    if !renderer.capture.requested {
+        return None;
+    }
+    renderer.capture.requested = false;
+    if !image.usage().intersects(ImageUsage::TRANSFER_SRC) {
+        log::warn!("the swapchain images cannot be copied from, frame not captured");
+        return None;
+    }
+    if !matches!(image.format(), Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM | Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM) {
+        log::warn!("cannot capture swapchain format {:?}", image.format());
+        return None;
+    }
+
+    let [width, height, _] = image.extent();
+    let buffer = Buffer::new_slice::<u8>(
+        renderer.memeory_allocator.as_ref().unwrap().clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        width as u64 * height as u64 * 4,
+    )
+    .unwrap();
+
+    let queue = renderer.queue.as_ref().unwrap();
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(renderer.device.as_ref().unwrap().clone(), Default::default());
+    let mut builder =
+        AutoCommandBufferBuilder::primary(&command_buffer_allocator, queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit)
+            .unwrap();
+    builder
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image.clone(), buffer.clone()))
+        .unwrap();
+    renderer.capture.pending = Some((buffer, [width, height], image.format()));
+    Some(builder.build().unwrap())
+}
+
+pub(crate) fn is_pending(renderer: &Renderer) -> bool {
+    renderer.capture.pending.is_some()
+}
+
+// Called once the frame's fence signaled.
+pub(crate) fn read_back(renderer: &mut Renderer) {
+    let Some((buffer, [width, height], format)) = renderer.capture.pending.take() else {
+        return;
+    };
+    let mut rgba = buffer.read().unwrap().to_vec();
+    if matches!(format, Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM) {
+        for pixel in rgba.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    renderer.capture.result = Some(CapturedFrame { width, height, rgba });
+}
+
+pub fn save_png(frame: &CapturedFrame, path: &str) -> Result<(), String> {
+    let file = std::fs::File::create(path).map_err(|e| format!("{path}: {e}"))?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), frame.width, frame.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| format!("{path}: {e}"))?;
+    writer.write_image_data(&frame.rgba).map_err(|e| format!("{path}: {e}"))
+}
+
+pub fn load_png(path: &str) -> Result<CapturedFrame, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("{path}: {e}"))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8() | png::Transformations::ALPHA);
+    let mut reader = decoder.read_info().map_err(|e| format!("{path}: {e}"))?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(|e| format!("{path}: {e}"))?;
+    data.truncate(info.buffer_size());
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => data,
+        png::ColorType::GrayscaleAlpha => data.chunks_exact(2).flat_map(|x| [x[0], x[0], x[0], x[1]]).collect(),
+        other => return Err(format!("{path}: unexpected color type {other:?}")),
+    };
+    Ok(CapturedFrame {
+        width: info.width,
+        height: info.height,
+        rgba,
+    })
+}
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::descriptor_set::layout::DescriptorSetLayout;
+use vulkano::descriptor_set::WriteDescriptorSet;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+
+use crate::ecs::World;
+use crate::state::State;
+use crate::types::buffers::UpdatableBuffer;
+use crate::types::camera::Camera;
+use crate::types::light::PointLightData;
+use crate::types::vectors::*;
+
+pub const MAX_CLUSTERED_LIGHTS: usize = 1024;
+pub const MAX_CLUSTER_LIGHT_INDICES: usize = 32768;
+
+#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[repr(C)]
+pub struct ClusterData {
+    pub grid: [u32; 3],
+    pub light_count: u32,
+    pub near: f32,
+    pub far: f32,
+    pub slice_scale: f32,
+    pub slice_bias: f32,
+    pub resolution: Vec2f,
+    pub _padding: [f32; 2],
+}
+
+#[derive(Clone, Default)]
+pub struct LightClusters {
+    pub grid: [u32; 3],
+    uniform: Option<UpdatableBuffer<ClusterData>>,
+    lights: Vec<Subbuffer<[PointLightData]>>,
+    clusters: Vec<Subbuffer<[u32]>>,
+    indices: Vec<Subbuffer<[u32]>>,
+}
+
+impl LightClusters {
+    pub(crate) fn uniform_buffers(&self) -> Vec<Arc<Buffer>> {
+        self.uniform.iter().flat_map(|x| x.buffers.iter().map(|x| x.buffer().clone())).collect()
+    }
+
+    pub(crate) fn storage_buffers(&self) -> Vec<Arc<Buffer>> {
+        self.lights
+            .iter()
+            .map(|x| x.buffer().clone())
+            .chain(self.clusters.iter().map(|x| x.buffer().clone()))
+            .chain(self.indices.iter().map(|x| x.buffer().clone()))
+            .collect()
+    }
+}
+
+fn create_storage_buffers<T: BufferContents + Pod>(state: &State, len: usize) -> Vec<Subbuffer<[T]>> {
+    (0..state.renderer.frames_in_flight.max(1))
+        .map(|_| {
+            Buffer::new_slice::<T>(
+                state.renderer.memeory_allocator.as_ref().unwrap().clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::STORAGE_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                    ..Default::default()
+                },
+                len.max(1) as u64,
+            )
+            .unwrap()
+        })
+        .collect()
+}
+
+pub fn init(state: &mut State) {
+    let grid = state.renderer.settings.light_clusters;
+    let cluster_count = (grid[0] * grid[1] * grid[2]) as usize;
+    state.renderer.clusters = LightClusters {
+        grid,
+        uniform: Some(UpdatableBuffer::new(&state.renderer, BufferUsage::UNIFORM_BUFFER)),
+        lights: create_storage_buffers(state, MAX_CLUSTERED_LIGHTS),
+        clusters: create_storage_buffers(state, cluster_count * 2),
+        indices: create_storage_buffers(state, MAX_CLUSTER_LIGHT_INDICES),
+    };
+}
+
+fn slice_range(near: f32, far: f32, slices: u32, min_z: f32, max_z: f32) -> (u32, u32) {
+    let slice = |z: f32| {
+        let t = (z.clamp(near, far) / near).ln() / (far / near).ln();
+        ((t * slices as f32) as u32).min(slices - 1)
+    };
+    (slice(min_z), slice(max_z))
+}
+
+fn tile_range(state: &State, center: Vec3f, radius: f32, near: f32) -> ([u32; 2], [u32; 2]) {
+    let grid = state.renderer.clusters.grid;
+    let projection = state.renderer.vp_data.projection;
+    let mut min = Vec2f::new([1.0, 1.0]);
+    let mut max = Vec2f::new([-1.0, -1.0]);
+    for i in 0..8 {
+        let corner = Vec3f::new([
+            center.x + if i & 1 == 0 { -radius } else { radius },
+            center.y + if i & 2 == 0 { -radius } else { radius },
+            (center.z + if i & 4 == 0 { -radius } else { radius }).min(-near),
+        ]);
+        let ndc = projection.transform_point(corner);
+        min = Vec2f::new([min.x.min(ndc.x), min.y.min(ndc.y)]);
+        max = Vec2f::new([max.x.max(ndc.x), max.y.max(ndc.y)]);
+    }
+
+    let tile = |ndc: f32, count: u32| ((ndc.clamp(-1.0, 1.0) * 0.5 + 0.5) * count as f32).min(count as f32 - 1.0) as u32;
+    (
+        [tile(min.x, grid[0]), tile(max.x, grid[0])],
+        [tile(min.y, grid[1]), tile(max.y, grid[1])],
+    )
+}
+
+pub fn write_cluster_data(world: &World, state: &mut State, lights: &[PointLightData]) {
+    let Some(camera) = world
+        .borrow_component_vec_mut::<Camera>()
+        .and_then(|x| x.iter().flatten().next().copied())
+    else {
+        return;
+    };
+    let clusters = &state.renderer.clusters;
+    let [grid_x, grid_y, grid_z] = clusters.grid;
+    let lights = &lights[..lights.len().min(MAX_CLUSTERED_LIGHTS)];
+
+    let mut cluster_lights = vec![Vec::new(); (grid_x * grid_y * grid_z) as usize];
+    let view = state.renderer.vp_data.view;
+    let view_position = state.renderer.render_space(state.renderer.vp_pos);
+    for (light_i, light) in lights.iter().enumerate() {
+        let center = view.transform_point(light.position);
+        let depth = -center.z;
+        if depth + light.range < camera.near || depth - light.range > camera.far {
+            continue;
+        }
+        if (light.position - view_position).length_sqr() < light.range * light.range {
+            // The camera is inside the light volume, so every tile can be affected.
+            let (min_z, max_z) = slice_range(camera.near, camera.far, grid_z, depth - light.range, depth + light.range);
+            for z in min_z..=max_z {
+                for i in 0..grid_x * grid_y {
+                    cluster_lights[(z * grid_x * grid_y + i) as usize].push(light_i as u32);
+                }
+            }
+            continue;
+        }
+
+        let (tiles_x, tiles_y) = tile_range(state, center, light.range, camera.near);
+        let (min_z, max_z) = slice_range(camera.near, camera.far, grid_z, depth - light.range, depth + light.range);
+        for z in min_z..=max_z {
+            for y in tiles_y[0]..=tiles_y[1] {
+                for x in tiles_x[0]..=tiles_x[1] {
+                    cluster_lights[(z * grid_x * grid_y + y * grid_x + x) as usize].push(light_i as u32);
+                }
+            }
+        }
+    }
+
+    let frame = state.renderer.current_frame;
+    let mut offsets = clusters.clusters[frame].write().unwrap();
+    let mut indices = clusters.indices[frame].write().unwrap();
+    let mut next = 0;
+    for (cluster_i, cluster) in cluster_lights.iter().enumerate() {
+        let count = cluster.len().min(MAX_CLUSTER_LIGHT_INDICES - next);
+        indices[next..next + count].copy_from_slice(&cluster[..count]);
+        offsets[cluster_i * 2] = next as u32;
+        offsets[cluster_i * 2 + 1] = count as u32;
+        next += count;
+    }
+    if next == MAX_CLUSTER_LIGHT_INDICES {
+        log::warn!("light cluster index list is full, some lights are dropped");
+    }
+    drop(offsets);
+    drop(indices);
+
+    clusters.lights[frame].write().unwrap()[..lights.len()].copy_from_slice(lights);
+
+    let (near, far) = (camera.near, camera.far);
+    let log_ratio = (far / near).ln();
+    let extent = state.renderer.viewport.as_ref().unwrap().extent;
+    clusters.uniform.as_ref().unwrap().write(
+        state,
+        ClusterData {
+            grid: clusters.grid,
+            light_count: lights.len() as u32,
+            near,
+            far,
+            slice_scale: grid_z as f32 / log_ratio,
+            slice_bias: grid_z as f32 * near.ln() / log_ratio,
+            resolution: Vec2f::new(extent),
+            _padding: [0.0; 2],
+        },
+    );
+}
+
+pub fn descriptor_writes(state: &State, layout: &DescriptorSetLayout, frame_i: usize) -> Vec<WriteDescriptorSet> {
+    let clusters = &state.renderer.clusters;
+    let mut writes = Vec::new();
+    if layout.bindings().contains_key(&4) {
+        writes.push(WriteDescriptorSet::buffer(4, clusters.uniform.as_ref().unwrap().buffer(frame_i)));
+    }
+    if layout.bindings().contains_key(&5) {
+        writes.push(WriteDescriptorSet::buffer(5, clusters.lights[frame_i].clone()));
+    }
+    if layout.bindings().contains_key(&6) {
+        writes.push(WriteDescriptorSet::buffer(6, clusters.clusters[frame_i].clone()));
+    }
+    if layout.bindings().contains_key(&7) {
+        writes.push(WriteDescriptorSet::buffer(7, clusters.indices[frame_i].clone()));
+    }
+    writes
+}
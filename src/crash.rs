@@ -0,0 +1,105 @@
+use std::{
+    backtrace::Backtrace,
+    fmt::Write as _,
+    fs,
+    panic::{self, PanicHookInfo},
+    path::Path,
+    sync::{Arc, Mutex, TryLockError},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use vulkano::device::Device;
+
+// What the renderer was doing, reported when a panic happens mid-frame. Command buffers are only
+// recorded when outdated, so pass and material are the last ones recorded.
+struct CrashContext {
+    device: Option<Arc<Device>>,
+    frame: u64,
+    pass: Option<String>,
+    material: Option<String>,
+}
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    device: None,
+    frame: 0,
+    pass: None,
+    material: None,
+});
+
+fn with_context(f: impl FnOnce(&mut CrashContext)) {
+    let mut context = CONTEXT.lock().unwrap_or_else(|x| x.into_inner());
+    f(&mut context);
+}
+
+pub(crate) fn set_device(device: Option<Arc<Device>>) {
+    with_context(|x| x.device = device);
+}
+
+pub(crate) fn begin_frame() {
+    with_context(|x| x.frame += 1);
+}
+
+pub(crate) fn set_pass(name: &str) {
+    with_context(|x| x.pass = Some(name.to_string()));
+}
+
+pub(crate) fn set_material(name: &str) {
+    with_context(|x| x.material = Some(name.to_string()));
+}
+
+fn write_report(dir: &str, info: &PanicHookInfo, summary: &str) -> std::io::Result<String> {
+    fs::create_dir_all(dir)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let path = Path::new(dir).join(format!("crash-{}.txt", timestamp.as_secs()));
+
+    let mut report = String::new();
+    let _ = writeln!(report, "{info}");
+    let _ = writeln!(report, "thread: {}", thread::current().name().unwrap_or("<unnamed>"));
+    let _ = writeln!(report, "{summary}");
+    let _ = writeln!(report, "\n{}", Backtrace::force_capture());
+    fs::write(&path, report)?;
+    Ok(path.display().to_string())
+}
+
+// Waits for the GPU before unwinding drops any Vulkan object, so a panic mid-frame does not leave
+// work in flight on freed resources. With `report_dir` set a report with the frame state and a
+// backtrace is written there. Chains to the previously installed hook.
+pub fn install(report_dir: Option<String>) {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        previous(info);
+
+        // The panic may have happened while the context was locked on this thread.
+        let context = match CONTEXT.try_lock() {
+            Ok(x) => Some(x),
+            Err(TryLockError::Poisoned(x)) => Some(x.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        };
+        let Some(context) = context else {
+            log::error!("panic while updating the crash context, frame state unknown");
+            return;
+        };
+
+        let summary = format!(
+            "frame {} | pass {} | material {}",
+            context.frame,
+            context.pass.as_deref().unwrap_or("none"),
+            context.material.as_deref().unwrap_or("none"),
+        );
+        log::error!("panic during {summary}");
+
+        if let Some(device) = context.device.as_ref() {
+            if let Err(e) = unsafe { device.wait_idle() } {
+                log::error!("failed to wait for the device after panic: {e}");
+            }
+        }
+
+        if let Some(dir) = report_dir.as_ref() {
+            match write_report(dir, info, &summary) {
+                Ok(path) => log::error!("crash report written to {path}"),
+                Err(e) => log::error!("failed to write crash report: {e}"),
+            }
+        }
+    }));
+}
@@ -0,0 +1,72 @@
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::device::DeviceOwned;
+use vulkano::instance::debug::DebugUtilsLabel;
+use vulkano::VulkanObject;
+
+use crate::crash;
+use crate::rendering::Renderer;
+
+pub const PASS_COLOR: [f32; 4] = [0.3, 0.6, 1.0, 1.0];
+pub const BATCH_COLOR: [f32; 4] = [0.4, 0.9, 0.4, 1.0];
+pub const UPLOAD_COLOR: [f32; 4] = [1.0, 0.7, 0.2, 1.0];
+
+// Every helper is a no-op unless `ext_debug_utils` was enabled on the instance.
+pub fn begin<L>(builder: &mut AutoCommandBufferBuilder<L>, renderer: &Renderer, name: &str, color: [f32; 4]) {
+    if !renderer.debug_utils {
+        return;
+    }
+    builder
+        .begin_debug_utils_label(DebugUtilsLabel {
+            label_name: name.to_string(),
+            color,
+            ..Default::default()
+        })
+        .unwrap();
+}
+
+// Also remembered as the active pass for crash reports.
+pub fn begin_pass<L>(builder: &mut AutoCommandBufferBuilder<L>, renderer: &Renderer, name: &str) {
+    crash::set_pass(name);
+    begin(builder, renderer, name, PASS_COLOR);
+}
+
+// Callers always pair this with `begin` in the same command buffer.
+pub fn end<L>(builder: &mut AutoCommandBufferBuilder<L>, renderer: &Renderer) {
+    if !renderer.debug_utils {
+        return;
+    }
+    unsafe { builder.end_debug_utils_label() }.unwrap();
+}
+
+pub fn name_object<T: VulkanObject + DeviceOwned>(renderer: &Renderer, object: &T, name: &str) {
+    if !renderer.debug_utils {
+        return;
+    }
+    if let Err(e) = object.device().set_debug_utils_object_name(object, Some(name)) {
+        log::warn!("failed to name {name}: {e}");
+    }
+}
+
+// Wraps consecutive draws using the same material in one label.
+#[derive(Default)]
+pub struct BatchLabels {
+    current: Option<String>,
+}
+
+impl BatchLabels {
+    pub fn switch(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, renderer: &Renderer, material: &str) {
+        if self.current.as_deref() == Some(material) {
+            return;
+        }
+        crash::set_material(material);
+        self.finish(builder, renderer);
+        begin(builder, renderer, &format!("material {material}"), BATCH_COLOR);
+        self.current = Some(material.to_string());
+    }
+
+    pub fn finish(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, renderer: &Renderer) {
+        if self.current.take().is_some() {
+            end(builder, renderer);
+        }
+    }
+}
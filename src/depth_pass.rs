@@ -0,0 +1,84 @@
+use vulkano::buffer::Subbuffer;
+
+use crate::asset_library::AssetLibrary;
+use crate::ecs::World;
+use crate::rendering::VertexData;
+use crate::types::mesh::DynamicMesh;
+use crate::types::skin::SkinnedMesh;
+use crate::types::static_mesh::StaticMesh;
+use crate::types::transform::{ModelData, Transform};
+use crate::types::visibility::Visibility;
+
+// A mesh as depth-only passes see it, position only, so the material reduces to its vertex shader.
+#[derive(Clone)]
+pub struct DepthDraw {
+    pub entity: usize,
+    pub vertex_shader: String,
+    pub vertex_buffer: Subbuffer<[VertexData]>,
+    pub index_buffer: Subbuffer<[u32]>,
+    pub index_count: u32,
+    pub model: Subbuffer<ModelData>,
+}
+
+// Visible meshes for one frame in flight, gathered once when the command buffers are recorded and
+// shared by every depth-only pass, like each face of each shadow map. Sorted by vertex shader so
+// passes bind as few pipelines as possible.
+pub fn gather(world: &World, assets: &AssetLibrary, frame_i: usize) -> Vec<DepthDraw> {
+    let transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+    let static_meshes = world.borrow_component_vec_mut::<StaticMesh>();
+    let dynamic_meshes = world.borrow_component_vec_mut::<DynamicMesh>();
+    let skinned_meshes = world.borrow_component_vec_mut::<SkinnedMesh>();
+    let visibilities = world.borrow_component_vec_mut::<Visibility>();
+    let vertex_shader = |material: &str| {
+        assets.materials.iter().find(|x| x.name == material).unwrap().vertex_shader.clone()
+    };
+
+    let mut draws = Vec::new();
+    for (entity, transform) in transforms.iter().enumerate() {
+        let Some(transform) = transform.as_ref() else {
+            continue;
+        };
+        if visibilities.as_ref().and_then(|x| x[entity]).is_some_and(|x| !x.visible) {
+            continue;
+        }
+        let model = transform.buffer.as_ref().unwrap().buffer(frame_i);
+
+        if let Some(static_mesh) = static_meshes.as_ref().and_then(|x| x.get(entity)?.as_ref()) {
+            let mesh = assets.meshes.iter().find(|x| x.name == static_mesh.mesh_name).unwrap();
+            let index_buffer = mesh.index_buffer.as_ref().unwrap().clone();
+            draws.push(DepthDraw {
+                entity,
+                vertex_shader: vertex_shader(&mesh.material),
+                vertex_buffer: mesh.vertex_buffer.as_ref().unwrap().clone(),
+                index_count: index_buffer.len() as u32,
+                index_buffer,
+                model: model.clone(),
+            });
+        }
+        if let Some(dynamic_mesh) = dynamic_meshes.as_ref().and_then(|x| x.get(entity)?.as_ref()) {
+            let buffers = dynamic_mesh.buffers.as_ref().unwrap();
+            draws.push(DepthDraw {
+                entity,
+                vertex_shader: vertex_shader(&dynamic_mesh.material),
+                vertex_buffer: buffers.vertex[frame_i].clone(),
+                index_buffer: buffers.index[frame_i].clone(),
+                index_count: dynamic_mesh.indices.len() as u32,
+                model: model.clone(),
+            });
+        }
+        if let Some(skinned_mesh) = skinned_meshes.as_ref().and_then(|x| x.get(entity)?.as_ref()) {
+            let mesh = assets.meshes.iter().find(|x| x.name == skinned_mesh.mesh_name).unwrap();
+            let index_buffer = mesh.index_buffer.as_ref().unwrap().clone();
+            draws.push(DepthDraw {
+                entity,
+                vertex_shader: vertex_shader(&mesh.material),
+                vertex_buffer: skinned_mesh.buffers.as_ref().unwrap().output[frame_i].clone(),
+                index_count: index_buffer.len() as u32,
+                index_buffer,
+                model,
+            });
+        }
+    }
+    draws.sort_by(|a, b| a.vertex_shader.cmp(&b.vertex_shader));
+    draws
+}
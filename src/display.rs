@@ -0,0 +1,141 @@
+use winit::monitor::{MonitorHandle, VideoModeHandle};
+use winit::window::Fullscreen;
+
+use crate::rendering::{Renderer, Window};
+use crate::types::vectors::Vec2f;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayMode {
+    pub size: [u32; 2],
+    pub refresh_rate_millihertz: u32,
+    pub bit_depth: u16,
+}
+
+impl DisplayMode {
+    pub fn refresh_rate(&self) -> f32 {
+        self.refresh_rate_millihertz as f32 / 1000.0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MonitorInfo {
+    pub name: String,
+    pub position: [i32; 2],
+    pub size: [u32; 2],
+    pub refresh_rate_millihertz: Option<u32>,
+    pub scale_factor: f64,
+    /// Sorted from the largest and fastest mode down.
+    pub modes: Vec<DisplayMode>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FullscreenMode {
+    Windowed,
+    Borderless { monitor: usize },
+    /// `mode` indexes `MonitorInfo::modes` of that monitor.
+    Exclusive { monitor: usize, mode: usize },
+}
+
+fn sorted_modes(monitor: &MonitorHandle) -> Vec<VideoModeHandle> {
+    let mut modes: Vec<VideoModeHandle> = monitor.video_modes().collect();
+    modes.sort_by_key(|x| {
+        let size = x.size();
+        std::cmp::Reverse((size.width * size.height, x.refresh_rate_millihertz(), x.bit_depth()))
+    });
+    modes
+}
+
+fn monitor_info(monitor: &MonitorHandle) -> MonitorInfo {
+    let position = monitor.position();
+    let size = monitor.size();
+    MonitorInfo {
+        name: monitor.name().unwrap_or_default(),
+        position: [position.x, position.y],
+        size: [size.width, size.height],
+        refresh_rate_millihertz: monitor.refresh_rate_millihertz(),
+        scale_factor: monitor.scale_factor(),
+        modes: sorted_modes(monitor)
+            .iter()
+            .map(|x| DisplayMode {
+                size: [x.size().width, x.size().height],
+                refresh_rate_millihertz: x.refresh_rate_millihertz(),
+                bit_depth: x.bit_depth(),
+            })
+            .collect(),
+    }
+}
+
+impl Window {
+    // Physical pixels per logical pixel, e.g. 2.0 on most high-DPI laptops.
+    pub fn scale_factor(&self) -> f64 {
+        self.window_handle.scale_factor()
+    }
+
+    pub fn physical_size(&self) -> [u32; 2] {
+        let size = self.window_handle.inner_size();
+        [size.width, size.height]
+    }
+
+    pub fn logical_size(&self) -> [f32; 2] {
+        let size = self.window_handle.inner_size().to_logical::<f32>(self.scale_factor());
+        [size.width, size.height]
+    }
+
+    // Physical pixels, like `InputManager::cursor_pos`, to the logical pixels of
+    // `VPData::ui_projection`.
+    pub fn to_ui(&self, physical: Vec2f) -> Vec2f {
+        physical * (1.0 / self.scale_factor() as f32)
+    }
+
+    pub fn monitors(&self) -> Vec<MonitorInfo> {
+        self.window_handle.available_monitors().map(|x| monitor_info(&x)).collect()
+    }
+
+    /// Index into `monitors` of the monitor the window is mostly on.
+    pub fn current_monitor(&self) -> Option<usize> {
+        let current = self.window_handle.current_monitor()?;
+        self.window_handle.available_monitors().position(|x| x == current)
+    }
+
+    pub fn fullscreen_mode(&self) -> FullscreenMode {
+        let monitor_index = |monitor: &MonitorHandle| {
+            self.window_handle.available_monitors().position(|x| x == *monitor).unwrap_or(0)
+        };
+        match self.window_handle.fullscreen() {
+            None => FullscreenMode::Windowed,
+            Some(Fullscreen::Borderless(monitor)) => FullscreenMode::Borderless {
+                monitor: monitor.map_or(self.current_monitor().unwrap_or(0), |x| monitor_index(&x)),
+            },
+            Some(Fullscreen::Exclusive(mode)) => {
+                let monitor = mode.monitor();
+                FullscreenMode::Exclusive {
+                    monitor: monitor_index(&monitor),
+                    mode: sorted_modes(&monitor).iter().position(|x| *x == mode).unwrap_or(0),
+                }
+            }
+        }
+    }
+
+    // The swapchain is recreated on the next frame, as a mode switch does not always send a resize.
+    // Fails for a monitor or display mode that is not in `monitors`, leaving the window as it was.
+    pub fn set_fullscreen(&self, renderer: &mut Renderer, mode: FullscreenMode) -> Result<(), String> {
+        let monitor = |index: usize| {
+            self.window_handle
+                .available_monitors()
+                .nth(index)
+                .ok_or_else(|| format!("no monitor {index}"))
+        };
+        let fullscreen = match mode {
+            FullscreenMode::Windowed => None,
+            FullscreenMode::Borderless { monitor: index } => Some(Fullscreen::Borderless(Some(monitor(index)?))),
+            FullscreenMode::Exclusive { monitor: index, mode } => {
+                let modes = sorted_modes(&monitor(index)?);
+                let mode = modes.get(mode).ok_or_else(|| format!("monitor {index} has no display mode {mode}"))?;
+                Some(Fullscreen::Exclusive(mode.clone()))
+            }
+        };
+        self.window_handle.set_fullscreen(fullscreen);
+        renderer.window_resized = true;
+        Ok(())
+    }
+}
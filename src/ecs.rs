@@ -1,27 +1,218 @@
 use std::{
-    any::Any,
-    cell::{RefCell, RefMut},
+    any::{Any, TypeId},
+    cell::{Cell, RefCell, RefMut},
+    marker::PhantomData,
 };
 
-use crate::{asset_library::AssetLibrary, state::State};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+use crate::{asset_library::AssetLibrary, reflect::{FieldValue, Reflect, ReflectedFields}, state::State};
+
+pub type WorldCommand = Box<dyn FnOnce(&mut World, &mut AssetLibrary, &mut State)>;
 
 pub trait System {
+    fn name(&self) -> &str {
+        let name = std::any::type_name::<Self>();
+        let path = name.split('<').next().unwrap_or(name);
+        path.rsplit("::").next().unwrap_or(path)
+    }
     fn on_start(&self, world: &World, assets: &mut AssetLibrary, state: &mut State);
     fn on_update(&self, world: &World, assets: &mut AssetLibrary, state: &mut State);
+    fn on_pause(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_resume(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_exit(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_device_restored(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_despawn(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State, _entity_id: usize) {}
+}
+
+// Usually derived, see `simple_engine::Component`.
+pub trait Component: 'static + Sized {
+    const NAME: &'static str;
+    fn register(_world: &mut World) {}
+}
+
+// Ticks of the frame stage a component was added and last marked changed in. Every system run
+// gets its own tick, so a change is seen by each system exactly once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: u64,
+    pub changed: u64,
+}
+
+pub trait QueryFilter {
+    fn matches(world: &World, entity_id: usize, since: u64) -> bool;
+}
+
+pub struct Added<ComponentType>(PhantomData<ComponentType>);
+
+/// Also matches components added since, as adding counts as a change.
+pub struct Changed<ComponentType>(PhantomData<ComponentType>);
+
+impl<ComponentType: 'static> QueryFilter for Added<ComponentType> {
+    fn matches(world: &World, entity_id: usize, since: u64) -> bool {
+        world.component_ticks::<ComponentType>(entity_id).is_some_and(|x| x.added > since)
+    }
+}
+
+impl<ComponentType: 'static> QueryFilter for Changed<ComponentType> {
+    fn matches(world: &World, entity_id: usize, since: u64) -> bool {
+        world.component_ticks::<ComponentType>(entity_id).is_some_and(|x| x.changed > since)
+    }
 }
 
-pub trait Component {}
+impl<A: QueryFilter, B: QueryFilter> QueryFilter for (A, B) {
+    fn matches(world: &World, entity_id: usize, since: u64) -> bool {
+        A::matches(world, entity_id, since) && B::matches(world, entity_id, since)
+    }
+}
+
+// A set of components added together, see `World::spawn_bundle`.
+pub trait Bundle {
+    fn insert(self, world: &mut World, entity_id: usize);
+}
+
+macro_rules! tuple_bundle {
+    ($($name:ident),+) => {
+        impl<$($name: 'static),+> Bundle for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn insert(self, world: &mut World, entity_id: usize) {
+                let ($($name,)+) = self;
+                $(world.add_component(entity_id, $name);)+
+            }
+        }
+    };
+}
+
+tuple_bundle!(A);
+tuple_bundle!(A, B);
+tuple_bundle!(A, B, C);
+tuple_bundle!(A, B, C, D);
+tuple_bundle!(A, B, C, D, E);
+tuple_bundle!(A, B, C, D, E, F);
 
 pub trait ComponentVec {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
     fn push_none(&mut self);
+    fn remove(&mut self, entity_id: usize);
+}
+
+type Encoded = Result<Vec<u8>, rmp_serde::encode::Error>;
+type Decoded = Result<Box<dyn Any>, rmp_serde::decode::Error>;
+
+struct PersistentComponent {
+    name: String,
+    // Of the component's entry in `World::components`.
+    vec_type: TypeId,
+    save: fn(&World) -> Option<Encoded>,
+    decode: fn(&[u8]) -> Decoded,
+    apply: fn(&mut World, Box<dyn Any>, usize),
+}
+
+/// State outside the world that snapshots carry once registered with
+/// `World::register_persistent_resource`, such as `Rng` for deterministic rollback.
+pub trait PersistentResource: 'static + Serialize + DeserializeOwned {
+    fn get(state: &State) -> &Self;
+    fn get_mut(state: &mut State) -> &mut Self;
+}
+
+struct RegisteredResource {
+    name: String,
+    save: fn(&State) -> Encoded,
+    decode: fn(&[u8]) -> Decoded,
+    apply: fn(&mut State, Box<dyn Any>),
+}
+
+struct ReflectedComponent {
+    name: String,
+    fields: fn(&World, usize) -> Option<ReflectedFields>,
+    set_field: fn(&World, usize, &str, &FieldValue) -> bool,
+}
+
+fn reflect_fields<ComponentType: 'static + Clone + Reflect>(world: &World, entity_id: usize) -> Option<ReflectedFields> {
+    let component_vec = world.borrow_component_vec_mut::<ComponentType>()?;
+    Some(component_vec.get(entity_id)?.as_ref()?.fields())
+}
+
+fn reflect_set_field<ComponentType: 'static + Clone + Reflect>(
+    world: &World,
+    entity_id: usize,
+    field: &str,
+    value: &FieldValue,
+) -> bool {
+    let Some(mut component_vec) = world.borrow_component_vec_mut::<ComponentType>() else {
+        return false;
+    };
+    let Some(Some(component)) = component_vec.get_mut(entity_id) else {
+        return false;
+    };
+    let set = component.set_field(field, value);
+    if set {
+        world.mark_changed::<ComponentType>(entity_id);
+    }
+    set
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    entity_count: usize,
+    // Each a MessagePack encoded `Vec<Option<ComponentType>>`.
+    components: Vec<(String, ByteBuf)>,
+    // Missing from snapshots taken before resources were saved.
+    #[serde(default)]
+    resources: Vec<(String, ByteBuf)>,
+}
+
+fn save_component<ComponentType: 'static + Clone + Serialize>(world: &World) -> Option<Encoded> {
+    let component_vec = world.borrow_component_vec_mut::<ComponentType>()?;
+    Some(rmp_serde::to_vec(&*component_vec))
+}
+
+fn decode_component<ComponentType: 'static + Clone + DeserializeOwned>(bytes: &[u8]) -> Decoded {
+    let components: Vec<Option<ComponentType>> = rmp_serde::from_slice(bytes)?;
+    Ok(Box::new(components))
+}
+
+fn apply_component<ComponentType: 'static + Clone>(world: &mut World, components: Box<dyn Any>, entity_count: usize) {
+    let mut components = *components.downcast::<Vec<Option<ComponentType>>>().unwrap();
+    components.resize(entity_count, None);
+    for (entity_id, component) in components.into_iter().enumerate() {
+        match component {
+            Some(component) => world.add_component(entity_id, component),
+            None => world.remove_component::<ComponentType>(entity_id),
+        }
+    }
+}
+
+fn save_resource<ResourceType: PersistentResource>(state: &State) -> Encoded {
+    rmp_serde::to_vec(ResourceType::get(state))
+}
+
+fn decode_resource<ResourceType: PersistentResource>(bytes: &[u8]) -> Decoded {
+    let resource: ResourceType = rmp_serde::from_slice(bytes)?;
+    Ok(Box::new(resource))
+}
+
+fn apply_resource<ResourceType: PersistentResource>(state: &mut State, resource: Box<dyn Any>) {
+    *ResourceType::get_mut(state) = *resource.downcast::<ResourceType>().unwrap();
 }
 
 pub struct World {
     pub entity_count: usize,
     pub components: Vec<Box<dyn ComponentVec>>,
     pub systems: Vec<Box<dyn System>>,
+    // Parallel to `components` and `systems`.
+    component_ticks: Vec<RefCell<Vec<ComponentTicks>>>,
+    system_ticks: Vec<u64>,
+    change_tick: Cell<u64>,
+    last_run: Cell<u64>,
+    persistent: Vec<PersistentComponent>,
+    persistent_resources: Vec<RegisteredResource>,
+    reflected: Vec<ReflectedComponent>,
+    pending_restore: RefCell<Option<Vec<u8>>>,
+    pending_despawn: RefCell<Vec<usize>>,
+    pending_commands: RefCell<Vec<WorldCommand>>,
 }
 
 impl World {
@@ -30,6 +221,16 @@ impl World {
             entity_count: 0,
             components: Vec::new(),
             systems: Vec::new(),
+            component_ticks: Vec::new(),
+            system_ticks: Vec::new(),
+            change_tick: Cell::new(1),
+            last_run: Cell::new(0),
+            persistent: Vec::new(),
+            persistent_resources: Vec::new(),
+            reflected: Vec::new(),
+            pending_restore: RefCell::new(None),
+            pending_despawn: RefCell::new(Vec::new()),
+            pending_commands: RefCell::new(Vec::new()),
         }
     }
 
@@ -38,17 +239,23 @@ impl World {
         for component_vec in self.components.iter_mut() {
             component_vec.push_none();
         }
+        for ticks in self.component_ticks.iter_mut() {
+            ticks.get_mut().push(ComponentTicks::default());
+        }
         self.entity_count += 1;
         entity_id
     }
 
     pub fn add_component<Component: 'static>(&mut self, entity_id: usize, component: Component) {
-        for component_vec in self.components.iter_mut() {
+        let tick = self.change_tick.get();
+        let added = ComponentTicks { added: tick, changed: tick };
+        for (i, component_vec) in self.components.iter_mut().enumerate() {
             if let Some(component_vec) = component_vec
                 .as_any_mut()
                 .downcast_mut::<RefCell<Vec<Option<Component>>>>()
             {
                 component_vec.get_mut()[entity_id] = Some(component);
+                self.component_ticks[i].get_mut()[entity_id] = added;
                 return;
             }
         }
@@ -62,11 +269,100 @@ impl World {
         new_component_vec[entity_id] = Some(component);
         self.components
             .push(Box::new(RefCell::new(new_component_vec)));
+        let mut ticks = vec![ComponentTicks::default(); self.entity_count];
+        ticks[entity_id] = added;
+        self.component_ticks.push(RefCell::new(ticks));
+    }
+
+    pub fn spawn_bundle<BundleType: Bundle>(&mut self, bundle: BundleType) -> usize {
+        let entity_id = self.new_entity();
+        bundle.insert(self, entity_id);
+        entity_id
+    }
+
+    pub fn insert_bundle<BundleType: Bundle>(&mut self, entity_id: usize, bundle: BundleType) {
+        bundle.insert(self, entity_id);
+    }
+
+    pub fn remove_component<ComponentType: 'static + Clone>(&mut self, entity_id: usize) {
+        if let Some(mut component_vec) = self.borrow_component_vec_mut::<ComponentType>() {
+            component_vec[entity_id] = None;
+        }
+        if let Some(i) = self.component_index::<ComponentType>() {
+            self.component_ticks[i].get_mut()[entity_id] = ComponentTicks::default();
+        }
+    }
+
+    fn component_index<ComponentType: 'static>(&self) -> Option<usize> {
+        self.components
+            .iter()
+            .position(|x| x.as_any().is::<RefCell<Vec<Option<ComponentType>>>>())
+    }
+
+    // Writes through `borrow_component_vec_mut` are not tracked, so systems mutating a component
+    // others filter on with `Changed` have to call this.
+    pub fn mark_changed<ComponentType: 'static>(&self, entity_id: usize) {
+        if let Some(i) = self.component_index::<ComponentType>() {
+            if let Some(ticks) = self.component_ticks[i].borrow_mut().get_mut(entity_id) {
+                ticks.changed = self.change_tick.get();
+            }
+        }
+    }
+
+    pub fn component_ticks<ComponentType: 'static>(&self, entity_id: usize) -> Option<ComponentTicks> {
+        let i = self.component_index::<ComponentType>()?;
+        let ticks = self.component_ticks[i].borrow().get(entity_id).copied()?;
+        (ticks.added != 0).then_some(ticks)
+    }
+
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick.get()
+    }
+
+    /// The tick the running system last ran at. Outside of systems everything since the last
+    /// frame counts.
+    pub fn last_run(&self) -> u64 {
+        self.last_run.get()
+    }
+
+    // Entities matching `Filter` since the running system last ran.
+    pub fn query<Filter: QueryFilter>(&self) -> Vec<usize> {
+        self.query_since::<Filter>(self.last_run.get())
+    }
+
+    pub fn query_since<Filter: QueryFilter>(&self, since: u64) -> Vec<usize> {
+        (0..self.entity_count).filter(|x| Filter::matches(self, *x, since)).collect()
+    }
+
+    // Components are removed at the end of the frame, after every system got `on_despawn`.
+    pub fn despawn(&self, entity_id: usize) {
+        self.pending_despawn.borrow_mut().push(entity_id);
+    }
+
+    // Lets systems spawn entities, which needs `&mut World`. Commands run at the end of the frame,
+    // after despawning.
+    pub fn defer(&self, command: impl FnOnce(&mut World, &mut AssetLibrary, &mut State) + 'static) {
+        self.pending_commands.borrow_mut().push(Box::new(command));
+    }
+
+    fn flush_despawned(&mut self, assets: &mut AssetLibrary, state: &mut State) {
+        let despawned: Vec<usize> = self.pending_despawn.get_mut().drain(..).collect();
+        for entity_id in despawned.into_iter().filter(|x| *x < self.entity_count) {
+            for system in self.systems.iter() {
+                system.on_despawn(self, assets, state, entity_id);
+            }
+            for component_vec in self.components.iter_mut() {
+                component_vec.remove(entity_id);
+            }
+            for ticks in self.component_ticks.iter_mut() {
+                ticks.get_mut()[entity_id] = ComponentTicks::default();
+            }
+        }
     }
 
     pub fn borrow_component_vec_mut<ComponentType: 'static + Clone>(
         &self,
-    ) -> Option<RefMut<Vec<Option<ComponentType>>>> {
+    ) -> Option<RefMut<'_, Vec<Option<ComponentType>>>> {
         for component_vec in self.components.iter() {
             if let Some(component) = component_vec
                 .as_any()
@@ -78,8 +374,139 @@ impl World {
         None
     }
 
+    pub fn register_persistent<ComponentType: 'static + Clone + Serialize + DeserializeOwned>(&mut self, name: &str) {
+        if self.persistent.iter().any(|x| x.name == name) {
+            return;
+        }
+        self.persistent.push(PersistentComponent {
+            name: name.to_string(),
+            vec_type: TypeId::of::<RefCell<Vec<Option<ComponentType>>>>(),
+            save: save_component::<ComponentType>,
+            decode: decode_component::<ComponentType>,
+            apply: apply_component::<ComponentType>,
+        });
+    }
+
+    pub fn register_persistent_resource<ResourceType: PersistentResource>(&mut self, name: &str) {
+        if self.persistent_resources.iter().any(|x| x.name == name) {
+            return;
+        }
+        self.persistent_resources.push(RegisteredResource {
+            name: name.to_string(),
+            save: save_resource::<ResourceType>,
+            decode: decode_resource::<ResourceType>,
+            apply: apply_resource::<ResourceType>,
+        });
+    }
+
+    pub fn register<ComponentType: Component>(&mut self) {
+        ComponentType::register(self);
+    }
+
+    pub fn register_reflect<ComponentType: 'static + Clone + Reflect>(&mut self, name: &str) {
+        if self.reflected.iter().any(|x| x.name == name) {
+            return;
+        }
+        self.reflected.push(ReflectedComponent {
+            name: name.to_string(),
+            fields: reflect_fields::<ComponentType>,
+            set_field: reflect_set_field::<ComponentType>,
+        });
+    }
+
+    /// Registered components of the entity with their current field values.
+    pub fn reflect(&self, entity_id: usize) -> Vec<(String, ReflectedFields)> {
+        self.reflected
+            .iter()
+            .filter_map(|x| Some((x.name.clone(), (x.fields)(self, entity_id)?)))
+            .collect()
+    }
+
+    pub fn set_reflected_field(&self, entity_id: usize, component: &str, field: &str, value: &FieldValue) -> bool {
+        self.reflected
+            .iter()
+            .find(|x| x.name == component)
+            .is_some_and(|x| (x.set_field)(self, entity_id, field, value))
+    }
+
+    // True if the entity has any component, despawned entities keep their id but have none.
+    pub fn has_components(&self, entity_id: usize) -> bool {
+        self.component_ticks
+            .iter()
+            .any(|x| x.borrow().get(entity_id).is_some_and(|x| x.added != 0))
+    }
+
+    /// Every entity with its persistent components and the registered resources, MessagePack
+    /// encoded. Components not registered with `register_persistent` are left out, restoring
+    /// removes them.
+    pub fn snapshot(&self, state: &State) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        let mut components = Vec::new();
+        for component in self.persistent.iter() {
+            if let Some(bytes) = (component.save)(self) {
+                components.push((component.name.clone(), ByteBuf::from(bytes?)));
+            }
+        }
+        let mut resources = Vec::new();
+        for resource in self.persistent_resources.iter() {
+            resources.push((resource.name.clone(), ByteBuf::from((resource.save)(state)?)));
+        }
+        rmp_serde::to_vec(&Snapshot {
+            entity_count: self.entity_count,
+            components,
+            resources,
+        })
+    }
+
+    pub fn request_restore(&self, snapshot: Vec<u8>) {
+        *self.pending_restore.borrow_mut() = Some(snapshot);
+    }
+
+    // Everything is decoded before the world is touched, so a snapshot that fails to decode leaves
+    // it as it was.
+    pub fn restore(&mut self, state: &mut State, snapshot: &[u8]) -> Result<(), rmp_serde::decode::Error> {
+        let snapshot: Snapshot = rmp_serde::from_slice(snapshot)?;
+        let mut decoded = Vec::new();
+        for (name, bytes) in snapshot.components.iter() {
+            match self.persistent.iter().position(|x| x.name == *name) {
+                Some(i) => decoded.push((i, (self.persistent[i].decode)(bytes)?)),
+                None => log::warn!("snapshot contains unregistered component {name}"),
+            }
+        }
+        let mut decoded_resources = Vec::new();
+        for (name, bytes) in snapshot.resources.iter() {
+            match self.persistent_resources.iter().position(|x| x.name == *name) {
+                Some(i) => decoded_resources.push((i, (self.persistent_resources[i].decode)(bytes)?)),
+                None => log::warn!("snapshot contains unregistered resource {name}"),
+            }
+        }
+
+        while self.entity_count < snapshot.entity_count {
+            self.new_entity();
+        }
+        // Components the snapshot has no data for were not part of the state it was taken from.
+        let restored: Vec<TypeId> = decoded.iter().map(|(i, _)| self.persistent[*i].vec_type).collect();
+        for (component_vec, ticks) in self.components.iter_mut().zip(self.component_ticks.iter_mut()) {
+            if restored.contains(&Any::type_id(component_vec.as_any())) {
+                continue;
+            }
+            for entity_id in 0..self.entity_count {
+                component_vec.remove(entity_id);
+            }
+            ticks.get_mut().fill(ComponentTicks::default());
+        }
+        for (i, components) in decoded {
+            (self.persistent[i].apply)(self, components, self.entity_count);
+        }
+        for (i, resource) in decoded_resources {
+            (self.persistent_resources[i].apply)(state, resource);
+        }
+        state.renderer.command_buffer_outdated = true;
+        Ok(())
+    }
+
     pub fn add_system<SystemType: 'static + System>(&mut self, system: SystemType) {
         self.systems.push(Box::new(system));
+        self.system_ticks.push(0);
     }
 
     pub fn start(&mut self, assets: &mut AssetLibrary, state: &mut State) {
@@ -89,8 +516,55 @@ impl World {
     }
 
     pub fn update(&mut self, assets: &mut AssetLibrary, state: &mut State) {
-        for system in self.systems.iter() {
+        state.profiler.begin_frame();
+        for (i, system) in self.systems.iter().enumerate() {
+            self.change_tick.set(self.change_tick.get() + 1);
+            self.last_run.set(self.system_ticks[i]);
+            state.profiler.begin_span(system.name());
             system.on_update(self, assets, state);
+            state.profiler.end_span();
+            self.system_ticks[i] = self.change_tick.get();
+        }
+        state.profiler.end_frame();
+        state.profiler.record_frame(state.delta_time, &state.renderer.stats, state.renderer.memory.last_report.as_ref());
+
+        // Changes from commands and between frames are newer than every system's last run.
+        self.last_run.set(self.change_tick.get());
+        self.change_tick.set(self.change_tick.get() + 1);
+        self.flush_despawned(assets, state);
+        let commands: Vec<WorldCommand> = self.pending_commands.get_mut().drain(..).collect();
+        for command in commands {
+            command(self, assets, state);
+        }
+
+        if let Some(snapshot) = self.pending_restore.get_mut().take() {
+            if let Err(e) = self.restore(state, &snapshot) {
+                log::error!("failed to restore snapshot: {e}");
+            }
+        }
+    }
+
+    pub fn pause(&mut self, assets: &mut AssetLibrary, state: &mut State) {
+        for system in self.systems.iter() {
+            system.on_pause(self, assets, state);
+        }
+    }
+
+    pub fn resume(&mut self, assets: &mut AssetLibrary, state: &mut State) {
+        for system in self.systems.iter() {
+            system.on_resume(self, assets, state);
+        }
+    }
+
+    pub fn exit(&mut self, assets: &mut AssetLibrary, state: &mut State) {
+        for system in self.systems.iter() {
+            system.on_exit(self, assets, state);
+        }
+    }
+
+    pub fn device_restored(&mut self, assets: &mut AssetLibrary, state: &mut State) {
+        for system in self.systems.iter() {
+            system.on_device_restored(self, assets, state);
         }
     }
 }
@@ -113,4 +587,10 @@ impl<T: 'static> ComponentVec for RefCell<Vec<Option<T>>> {
     fn push_none(&mut self) {
         self.get_mut().push(None);
     }
+
+    fn remove(&mut self, entity_id: usize) {
+        if let Some(component) = self.get_mut().get_mut(entity_id) {
+            *component = None;
+        }
+    }
 }
@@ -1,6 +1,10 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 
-use winit::keyboard::Key;
+use winit::{
+    event::{MouseButton, Touch, TouchPhase},
+    keyboard::Key,
+};
 
 use crate::{
     asset_library::AssetLibrary,
@@ -9,14 +13,59 @@ use crate::{
     types::vectors::Vec2f,
 };
 
+// Touches lifted sooner and closer to where they started than this count as taps.
+pub const TAP_MAX_SECONDS: f32 = 0.3;
+pub const TAP_MAX_DISTANCE: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TouchPoint {
+    pub id: u64,
+    pub position: Vec2f,
+    pub start_position: Vec2f,
+    started: Instant,
+    // Set once the touch moved too far or was part of a pinch, so it can no longer be a tap.
+    moved: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Gesture {
+    Tap { position: Vec2f },
+    Drag { id: u64, position: Vec2f, delta: Vec2f },
+    /// `scale` is the change in finger distance since the last pinch event, above 1 when spreading.
+    Pinch { center: Vec2f, scale: f32 },
+}
+
+fn distance(a: Vec2f, b: Vec2f) -> f32 {
+    let offset = a - b;
+    (offset.x * offset.x + offset.y * offset.y).sqrt()
+}
+
 #[derive(Clone, Debug)]
 pub struct InputManager {
     pub pressed: HashSet<Key>,
     pub down: HashSet<Key>,
     pub released: HashSet<Key>,
 
+    pub mouse_pressed: HashSet<MouseButton>,
+    pub mouse_down: HashSet<MouseButton>,
+    pub mouse_released: HashSet<MouseButton>,
+
     pub mouse_pos: Vec2f,
     prev_mouse_pos: Option<Vec2f>,
+    pub cursor_pos: Vec2f,
+
+    pub touches: HashMap<u64, TouchPoint>,
+    pub touch_pressed: HashSet<u64>,
+    pub touch_released: HashSet<u64>,
+    /// Gestures recognized this frame.
+    pub gestures: Vec<Gesture>,
+
+    /// Text typed or committed through the IME this frame, without control characters.
+    pub text_input: String,
+    /// Text the IME is still composing, shown as a preview until committed.
+    pub ime_preedit: String,
+    /// Byte range of the IME cursor inside `ime_preedit`.
+    pub ime_cursor: Option<(usize, usize)>,
 }
 
 impl InputManager {
@@ -32,20 +81,122 @@ impl InputManager {
         self.released.insert(key_code);
     }
 
+    pub fn process_mouse_press(&mut self, button: MouseButton) {
+        if self.mouse_down.insert(button) {
+            self.mouse_pressed.insert(button);
+        }
+    }
+
+    pub fn process_mouse_release(&mut self, button: MouseButton) {
+        self.mouse_down.remove(&button);
+        self.mouse_released.insert(button);
+    }
+
+    pub fn process_touch(&mut self, touch: Touch) {
+        let position = Vec2f::new([touch.location.x as f32, touch.location.y as f32]);
+        match touch.phase {
+            TouchPhase::Started => {
+                self.touches.insert(
+                    touch.id,
+                    TouchPoint {
+                        id: touch.id,
+                        position,
+                        start_position: position,
+                        started: Instant::now(),
+                        moved: false,
+                    },
+                );
+                self.touch_pressed.insert(touch.id);
+                if self.touches.len() > 1 {
+                    self.touches.values_mut().for_each(|x| x.moved = true);
+                }
+            }
+            TouchPhase::Moved => self.process_touch_move(touch.id, position),
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                let Some(point) = self.touches.remove(&touch.id) else {
+                    return;
+                };
+                self.touch_released.insert(touch.id);
+                let tap = touch.phase == TouchPhase::Ended
+                    && !point.moved
+                    && point.started.elapsed().as_secs_f32() <= TAP_MAX_SECONDS;
+                if tap {
+                    self.gestures.push(Gesture::Tap { position });
+                }
+            }
+        }
+    }
+
+    fn process_touch_move(&mut self, id: u64, position: Vec2f) {
+        let Some(point) = self.touches.get(&id).copied() else {
+            return;
+        };
+
+        if self.touches.len() == 2 {
+            let other = self.touches.values().find(|x| x.id != id).unwrap().position;
+            let before = distance(point.position, other);
+            let after = distance(position, other);
+            if before > 0.0 {
+                self.gestures.push(Gesture::Pinch {
+                    center: (position + other) * 0.5,
+                    scale: after / before,
+                });
+            }
+        }
+
+        let single = self.touches.len() == 1;
+        let point = self.touches.get_mut(&id).unwrap();
+        let delta = position - point.position;
+        point.position = position;
+        point.moved |= distance(position, point.start_position) > TAP_MAX_DISTANCE;
+        if single && point.moved {
+            self.gestures.push(Gesture::Drag { id, position, delta });
+        }
+    }
+
+    pub fn process_text(&mut self, text: &str) {
+        // While composing, the IME reports the result through `process_ime_commit` instead.
+        if !self.ime_preedit.is_empty() {
+            return;
+        }
+        self.text_input.extend(text.chars().filter(|x| !x.is_control()));
+    }
+
+    pub fn process_ime_preedit(&mut self, text: String, cursor: Option<(usize, usize)>) {
+        self.ime_preedit = text;
+        self.ime_cursor = cursor;
+    }
+
+    pub fn process_ime_commit(&mut self, text: &str) {
+        self.ime_preedit.clear();
+        self.ime_cursor = None;
+        self.text_input.push_str(text);
+    }
+
+    pub fn touch_count(&self) -> usize {
+        self.touches.len()
+    }
+
     pub fn get_mouse_delta(&self) -> Vec2f {
-        if self.prev_mouse_pos.is_none() {
-            Vec2f::new([0.0, 0.0])
-        } else {
+        if let Some(prev_mouse_pos) = self.prev_mouse_pos {
             Vec2f::new([
-                self.mouse_pos.x - self.prev_mouse_pos.unwrap().x,
-                self.mouse_pos.y - self.prev_mouse_pos.unwrap().y,
+                self.mouse_pos.x - prev_mouse_pos.x,
+                self.mouse_pos.y - prev_mouse_pos.y,
             ])
+        } else {
+            Vec2f::new([0.0, 0.0])
         }
     }
 
     pub fn clear_temp(&mut self) {
         self.pressed.clear();
         self.released.clear();
+        self.mouse_pressed.clear();
+        self.mouse_released.clear();
+        self.touch_pressed.clear();
+        self.touch_released.clear();
+        self.gestures.clear();
+        self.text_input.clear();
         self.prev_mouse_pos = Some(self.mouse_pos);
     }
 
@@ -54,8 +205,19 @@ impl InputManager {
             pressed: HashSet::new(),
             down: HashSet::new(),
             released: HashSet::new(),
+            mouse_pressed: HashSet::new(),
+            mouse_down: HashSet::new(),
+            mouse_released: HashSet::new(),
             mouse_pos: Vec2f::new([0.0, 0.0]),
             prev_mouse_pos: None,
+            cursor_pos: Vec2f::new([0.0, 0.0]),
+            touches: HashMap::new(),
+            touch_pressed: HashSet::new(),
+            touch_released: HashSet::new(),
+            gestures: Vec::new(),
+            text_input: String::new(),
+            ime_preedit: String::new(),
+            ime_cursor: None,
         }
     }
 }
@@ -66,7 +228,7 @@ impl Default for InputManager {
     }
 }
 
-struct InputManagerUpdater {}
+pub struct InputManagerUpdater {}
 
 impl System for InputManagerUpdater {
     fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
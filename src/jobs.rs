@@ -0,0 +1,307 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferExecFuture, CommandBufferUsage};
+use vulkano::sync::future::{FenceSignalFuture, NowFuture};
+use vulkano::sync::{now, GpuFuture};
+
+use crate::asset_library::{AssetKind, AssetLibrary};
+use crate::ecs::{System, World};
+use crate::rendering::Renderer;
+use crate::state::State;
+#[cfg(feature = "gltf")]
+use crate::types::gltf;
+use crate::types::mesh::Mesh;
+use crate::types::texture::{self, DecodedImage, Texture};
+
+pub const DEFAULT_UPLOAD_BUDGET: u64 = 16 * 1024 * 1024;
+
+pub enum DecodedAsset {
+    Texture { name: String, srgb: bool, image: DecodedImage },
+    Meshes(Vec<Mesh>),
+}
+
+impl DecodedAsset {
+    fn size(&self) -> u64 {
+        match self {
+            DecodedAsset::Texture { image, .. } => image.size(),
+            DecodedAsset::Meshes(meshes) => meshes
+                .iter()
+                .map(|x| (std::mem::size_of_val(x.vertices.as_slice()) + std::mem::size_of_val(x.indices.as_slice())) as u64)
+                .sum(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetJobEvent {
+    Loaded { kind: AssetKind, name: String },
+    Failed { job: String, error: String },
+}
+
+pub type JobId = u64;
+/// Assets a finished job added to the library, or why it failed.
+pub type JobOutcome = Result<Vec<(AssetKind, String)>, String>;
+
+type Job = Box<dyn FnOnce() -> Result<DecodedAsset, String> + Send>;
+type UploadFence = FenceSignalFuture<CommandBufferExecFuture<NowFuture>>;
+
+struct UploadBatch {
+    textures: Vec<(JobId, Texture, usize)>,
+    fence: UploadFence,
+}
+
+// Decoding runs on worker threads. Results are uploaded by `AsyncAssetLoader`, at most
+// `upload_budget` bytes per frame, and only added to the asset library once the GPU copy is done.
+// Spawn entities using an asset after its `AssetJobEvent::Loaded`.
+pub struct Jobs {
+    sender: Option<Sender<(JobId, String, Job)>>,
+    result_sender: Sender<(JobId, String, Result<DecodedAsset, String>)>,
+    results: Receiver<(JobId, String, Result<DecodedAsset, String>)>,
+    workers: Vec<JoinHandle<()>>,
+    next_id: JobId,
+    outstanding: usize,
+    pending: VecDeque<(JobId, DecodedAsset)>,
+    in_flight: Option<UploadBatch>,
+    events: Vec<AssetJobEvent>,
+    watched: HashSet<JobId>,
+    outcomes: HashMap<JobId, JobOutcome>,
+    pub upload_budget: u64,
+}
+
+impl Jobs {
+    pub fn new() -> Jobs {
+        let (result_sender, results) = mpsc::channel();
+        Jobs {
+            sender: None,
+            result_sender,
+            results,
+            workers: Vec::new(),
+            next_id: 0,
+            outstanding: 0,
+            pending: VecDeque::new(),
+            in_flight: None,
+            events: Vec::new(),
+            watched: HashSet::new(),
+            outcomes: HashMap::new(),
+            upload_budget: DEFAULT_UPLOAD_BUDGET,
+        }
+    }
+
+    fn start_workers(&mut self) -> &Sender<(JobId, String, Job)> {
+        if self.sender.is_none() {
+            let (sender, receiver) = mpsc::channel::<(JobId, String, Job)>();
+            let receiver = Arc::new(Mutex::new(receiver));
+            let count = thread::available_parallelism().map_or(1, |x| x.get().saturating_sub(1)).clamp(1, 4);
+            for i in 0..count {
+                let receiver = receiver.clone();
+                let results = self.result_sender.clone();
+                let worker = thread::Builder::new()
+                    .name(format!("asset worker {i}"))
+                    .spawn(move || loop {
+                        let Ok((id, name, job)) = receiver.lock().unwrap().recv() else {
+                            return;
+                        };
+                        if results.send((id, name, job())).is_err() {
+                            return;
+                        }
+                    })
+                    .unwrap();
+                self.workers.push(worker);
+            }
+            self.sender = Some(sender);
+        }
+        self.sender.as_ref().unwrap()
+    }
+
+    // `name` identifies the job in failure events and logs.
+    pub fn spawn(&mut self, name: &str, job: impl FnOnce() -> Result<DecodedAsset, String> + Send + 'static) -> JobId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.outstanding += 1;
+        self.start_workers().send((id, name.to_string(), Box::new(job))).unwrap();
+        id
+    }
+
+    pub fn load_texture(&mut self, renderer: &Renderer, name: &str, srgb: bool) -> JobId {
+        let supports_format = renderer.texture_format_support();
+        let texture_name = name.to_string();
+        self.spawn(name, move || {
+            let image = texture::decode_texture(&texture_name, srgb, supports_format)?;
+            Ok(DecodedAsset::Texture { name: texture_name, srgb, image })
+        })
+    }
+
+    #[cfg(feature = "gltf")]
+    pub fn load_gltf(&mut self, path: &str, material: &str) -> JobId {
+        let (gltf_path, material) = (path.to_string(), material.to_string());
+        self.spawn(path, move || Ok(DecodedAsset::Meshes(gltf::load_gltf(&gltf_path, &material)?)))
+    }
+
+    /// Keeps the outcome of `job` around for `take_outcome`. Unwatched outcomes are only reported
+    /// as events.
+    pub fn watch(&mut self, job: JobId) {
+        self.watched.insert(job);
+    }
+
+    pub fn take_outcome(&mut self, job: JobId) -> Option<JobOutcome> {
+        let outcome = self.outcomes.remove(&job)?;
+        self.watched.remove(&job);
+        Some(outcome)
+    }
+
+    /// Stops keeping the outcome of `job`, e.g. once it is no longer needed.
+    pub fn unwatch(&mut self, job: JobId) {
+        self.watched.remove(&job);
+        self.outcomes.remove(&job);
+    }
+
+    fn loaded(&mut self, job: JobId, kind: AssetKind, name: &str) {
+        self.events.push(AssetJobEvent::Loaded { kind, name: name.to_string() });
+        if self.watched.contains(&job) {
+            if let Ok(assets) = self.outcomes.entry(job).or_insert(Ok(Vec::new())) {
+                assets.push((kind, name.to_string()));
+            }
+        }
+    }
+
+    fn failed(&mut self, job: JobId, name: String, error: String) {
+        log::error!("asset job {} failed: {}", name, error);
+        if self.watched.contains(&job) {
+            self.outcomes.insert(job, Err(error.clone()));
+        }
+        self.events.push(AssetJobEvent::Failed { job: name, error });
+    }
+
+    /// True once every spawned job has been decoded and uploaded.
+    pub fn is_idle(&self) -> bool {
+        self.outstanding == 0 && self.pending.is_empty() && self.in_flight.is_none()
+    }
+
+    pub fn drain_events(&mut self) -> Vec<AssetJobEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Jobs {
+    fn drop(&mut self) {
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn finish_batch(assets: &mut AssetLibrary, state: &mut State) {
+    let Some(batch) = state.jobs.in_flight.as_ref() else {
+        return;
+    };
+    if !batch.fence.is_signaled().unwrap_or(false) {
+        return;
+    }
+
+    let batch = state.jobs.in_flight.take().unwrap();
+    for (job, mut texture, mip_levels) in batch.textures {
+        texture.finish_upload(&state.renderer, mip_levels);
+        state.jobs.loaded(job, AssetKind::Texture, &texture.name);
+        assets.textures.retain(|x| x.name != texture.name);
+        assets.textures.push(texture);
+    }
+    state.renderer.command_buffer_outdated = true;
+}
+
+fn start_batch(assets: &mut AssetLibrary, state: &mut State) {
+    if state.jobs.in_flight.is_some() || state.jobs.pending.is_empty() {
+        return;
+    }
+
+    let renderer = &mut state.renderer;
+    let queue = renderer.transfer_queue.as_ref().or(renderer.queue.as_ref()).unwrap().clone();
+    let queue_families: Vec<u32> = [renderer.queue.as_ref(), renderer.transfer_queue.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|x| x.queue_family_index())
+        .collect();
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(renderer.device.as_ref().unwrap().clone(), Default::default());
+    let mut builder =
+        AutoCommandBufferBuilder::primary(&command_buffer_allocator, queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit)
+            .unwrap();
+
+    // Always takes at least one asset, so a single large one cannot stall the queue.
+    let mut spent = 0;
+    let mut textures = Vec::new();
+    while spent == 0 || spent < state.jobs.upload_budget {
+        let Some((job, asset)) = state.jobs.pending.pop_front() else {
+            break;
+        };
+        spent += asset.size().max(1);
+        match asset {
+            DecodedAsset::Texture { name, srgb, image } => {
+                let mut texture = if srgb { Texture::new(name) } else { Texture::linear(name) };
+                let mip_levels = image.levels.len();
+                texture.record_upload(&mut builder, renderer, image, &queue_families);
+                textures.push((job, texture, mip_levels));
+            }
+            // Mesh buffers are host visible, so they are usable right away.
+            DecodedAsset::Meshes(meshes) => {
+                if meshes.is_empty() && state.jobs.watched.contains(&job) {
+                    state.jobs.outcomes.insert(job, Ok(Vec::new()));
+                }
+                for mut mesh in meshes {
+                    mesh.load(renderer);
+                    state.jobs.loaded(job, AssetKind::Mesh, &mesh.name);
+                    assets.meshes.retain(|x| x.name != mesh.name);
+                    assets.meshes.push(mesh);
+                }
+                renderer.command_buffer_outdated = true;
+            }
+        }
+    }
+
+    if textures.is_empty() {
+        return;
+    }
+    let fence = now(renderer.device.as_ref().unwrap().clone())
+        .then_execute(queue, builder.build().unwrap())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap();
+    state.jobs.in_flight = Some(UploadBatch { textures, fence });
+}
+
+pub struct AsyncAssetLoader {}
+
+impl System for AsyncAssetLoader {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        while let Ok((job, name, result)) = state.jobs.results.try_recv() {
+            state.jobs.outstanding -= 1;
+            match result {
+                Ok(asset) => state.jobs.pending.push_back((job, asset)),
+                Err(error) => state.jobs.failed(job, name, error),
+            }
+        }
+
+        finish_batch(assets, state);
+        start_batch(assets, state);
+    }
+
+    fn on_device_restored(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        // Waiting on a fence of the lost device fails, so it is leaked like the frame fences.
+        if let Some(batch) = state.jobs.in_flight.take() {
+            for (job, texture, _) in batch.textures.iter() {
+                state.jobs.failed(*job, texture.name.clone(), "device lost during upload".to_string());
+            }
+            std::mem::forget(batch.fence);
+        }
+    }
+}
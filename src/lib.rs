@@ -1,116 +1,397 @@
+extern crate self as simple_engine;
+
 pub mod asset_library;
+pub mod builtin_shaders;
+pub mod capture;
+pub mod clusters;
+pub mod crash;
+pub mod debug_labels;
+pub mod depth_pass;
+pub mod display;
 pub mod ecs;
 pub mod input;
+pub mod jobs;
+pub mod lightmapper;
+pub mod logging;
+pub mod memory_stats;
+#[cfg(feature = "network")]
+pub mod network;
+#[cfg(feature = "physics2d")]
+pub mod physics2d;
+pub mod post_process;
+pub mod prelude;
+pub mod profiler;
+pub mod ray_tracing;
+pub mod reflect;
+pub mod reflections;
+#[cfg(feature = "regression")]
+pub mod regression;
+pub mod render_callbacks;
 pub mod rendering;
+pub mod replay;
+pub mod shadows;
+pub mod skinning;
 pub mod state;
+pub mod streaming;
+pub mod stress;
+pub mod timers;
 pub mod types;
 pub mod utility;
+pub mod watchdog;
+
+pub use simple_engine_derive::{Component, Reflect};
 
 use std::time::Instant;
 
 use asset_library::AssetLibrary;
 use ecs::World;
-use input::InputManager;
-use rendering::{EventLoop, Renderer, RendererHandler, Window};
+use input::{InputManager, InputManagerUpdater};
+use jobs::{AsyncAssetLoader, Jobs};
+use logging::LoggerSettings;
+#[cfg(feature = "network")]
+use network::{Network, NetworkUpdater};
+#[cfg(feature = "physics2d")]
+use physics2d::{Physics2d, Physics2dUpdater};
+use profiler::Profiler;
+use replay::Replay;
+use rendering::{EventLoop, Renderer, RendererHandler, RendererSettings, Window};
 use state::State;
-use types::camera::CameraUpdater;
+use streaming::{LevelStreamer, LevelStreaming};
+use timers::{Timers, TimersUpdater};
+use types::animation::AnimatorUpdater;
+use types::behavior::{BehaviorUpdater, Behaviors};
+use types::camera::{Camera, CameraUpdater};
+use types::light::PointLight;
+use types::debug_overlay::{DebugOverlay, DebugOverlayUpdater};
+use types::gizmo::GizmoUpdater;
+#[cfg(feature = "ui")]
+use types::inspector::InspectorUpdater;
+use types::lod::LodUpdater;
 use types::mesh::{DynamicMeshLoader, MeshLoader};
 use types::shader::ShaderLoader;
+use types::noise::Rng;
+use types::spline::FollowSplineUpdater;
+use types::sprite_animation::SpriteAnimationUpdater;
+use types::terrain::TerrainUpdater;
+use types::tilemap::TilemapUpdater;
 use types::texture::TextureLoader;
-use types::transform::TransformUpdater;
-
-use types::vectors::Vec2f;
-use winit::event::DeviceEvent::MouseMotion;
-use winit::event::WindowEvent::KeyboardInput;
-use winit::event::{ElementState, Event, KeyEvent, WindowEvent};
-use winit::event_loop::ControlFlow;
-
-pub fn run(mut world: World, mut assets: AssetLibrary) {
-    let event_loop = EventLoop::new();
-    let timer = Instant::now();
-    let mut state = State {
-        window: Window::new(&event_loop),
-        input: InputManager::new(),
-        renderer: Renderer::new(),
-        time: 0.0,
-        delta_time: 0.0
-    };
-    
-    rendering::init(&mut state);
-    
-    world.add_system(TransformUpdater {});
-    world.add_system(CameraUpdater {});
-    world.add_system(MeshLoader {});
-    world.add_system(DynamicMeshLoader {});
-    world.add_system(ShaderLoader {});
-    world.add_system(TextureLoader {});
-    world.add_system(RendererHandler {});
-    world.start(&mut assets, &mut state);
+use types::static_mesh::StaticMesh;
+use types::transform::{Transform, TransformUpdater};
+use types::trail::TrailUpdater;
+use types::tween::{TweenUpdater, Tweens};
+#[cfg(feature = "ui")]
+use types::ui::{UiBatcher, UiState, UiUpdater};
+#[cfg(feature = "ui")]
+use types::ui_widgets::UiWidgetUpdater;
+use types::visibility::Visibility;
 
-    event_loop.event_loop.set_control_flow(ControlFlow::Poll);
-    event_loop
-        .event_loop
-        .run(move |event, elwt| match event {
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            } => {
-                println!("Close requested!");
-                elwt.exit();
+use types::vectors::{Vec2f, Vec3d};
+use winit::application::ApplicationHandler;
+use winit::event::{DeviceEvent, DeviceId, ElementState, Ime, KeyEvent, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow};
+use winit::window::WindowId;
+
+struct App {
+    world: World,
+    assets: AssetLibrary,
+    state: Option<State>,
+    settings: RendererSettings,
+    timer: Instant,
+    last_time: f64,
+    paused: bool,
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(state) = self.state.as_mut() {
+            if self.paused {
+                self.paused = false;
+                // The old native window was destroyed on suspend.
+                if cfg!(target_os = "android") {
+                    state.window = Window::new(event_loop, &self.settings.window);
+                    rendering::restore_surface(state);
+                }
+                self.world.resume(&mut self.assets, state);
             }
-            Event::WindowEvent {
-                event: WindowEvent::Resized(_),
-                ..
-            } => {
-                println!("Resizing!");
+            return;
+        }
+
+        let mut state = State {
+            window: Window::new(event_loop, &self.settings.window),
+            input: InputManager::new(),
+            renderer: Renderer::with_settings(self.settings.clone()),
+            time: 0.0,
+            delta_time: 0.0,
+            debug_overlay: DebugOverlay::new(),
+            profiler: Profiler::new(),
+            replay: Replay::new(),
+            #[cfg(feature = "network")]
+            network: Network::new(),
+            timers: Timers::new(),
+            tweens: Tweens::new(),
+            rng: Rng::from_time(),
+            behaviors: Behaviors::new(),
+            #[cfg(feature = "ui")]
+            ui: UiState::new(),
+            #[cfg(feature = "physics2d")]
+            physics2d: Physics2d::new(),
+            jobs: Jobs::new(),
+            streaming: LevelStreaming::new(),
+            exit_requested: false,
+            origin_offset: Vec3d::new([0.0, 0.0, 0.0]),
+        };
+
+        log::info!("random seed {}", state.rng.seed());
+        rendering::init(&mut state);
+        self.world.start(&mut self.assets, &mut state);
+        self.state = Some(state);
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
+
+        if state.replay.is_playing() && matches!(
+            event,
+            WindowEvent::KeyboardInput { .. }
+                | WindowEvent::CursorMoved { .. }
+                | WindowEvent::MouseInput { .. }
+                | WindowEvent::Touch(_)
+                | WindowEvent::Ime(_)
+        ) {
+            return;
+        }
+
+        match event {
+            WindowEvent::CloseRequested => {
+                log::info!("Close requested!");
+                event_loop.exit();
+            }
+            WindowEvent::Resized(_) => {
+                log::debug!("Resizing!");
+                state.renderer.window_resized = true;
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                log::debug!("Scale factor changed to {scale_factor}!");
                 state.renderer.window_resized = true;
             }
-            Event::WindowEvent {
+            WindowEvent::KeyboardInput {
                 event:
-                    KeyboardInput {
-                        event:
-                            KeyEvent {
-                                logical_key: key_code,
-                                state: ElementState::Pressed,
-                                ..
-                            },
+                    KeyEvent {
+                        logical_key: key_code,
+                        state: ElementState::Pressed,
+                        text,
                         ..
                     },
                 ..
             } => {
                 state.input.process_key_press(key_code);
+                if let Some(text) = text {
+                    state.input.process_text(&text);
+                }
             }
-            Event::WindowEvent {
+            WindowEvent::KeyboardInput {
                 event:
-                    KeyboardInput {
-                        event:
-                            KeyEvent {
-                                logical_key: key_code,
-                                state: ElementState::Released,
-                                ..
-                            },
+                    KeyEvent {
+                        logical_key: key_code,
+                        state: ElementState::Released,
                         ..
                     },
                 ..
             } => {
                 state.input.process_key_release(key_code);
             }
-            Event::DeviceEvent {
-                event: MouseMotion { delta: (x, y) },
+            WindowEvent::CursorMoved { position, .. } => {
+                state.input.cursor_pos = Vec2f::new([position.x as f32, position.y as f32]);
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button,
                 ..
             } => {
-                state.input.mouse_pos += Vec2f::new([x as f32, y as f32]);
+                state.input.process_mouse_press(button);
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button,
+                ..
+            } => {
+                state.input.process_mouse_release(button);
+            }
+            WindowEvent::Touch(touch) => {
+                state.input.process_touch(touch);
+            }
+            WindowEvent::Ime(Ime::Preedit(text, cursor)) => {
+                state.input.process_ime_preedit(text, cursor);
+            }
+            WindowEvent::Ime(Ime::Commit(text)) => {
+                state.input.process_ime_commit(&text);
+            }
+            WindowEvent::Ime(Ime::Disabled) => {
+                state.input.process_ime_preedit(String::new(), None);
+            }
+            _ => (),
+        }
+    }
+
+    fn device_event(&mut self, _event_loop: &ActiveEventLoop, _device_id: DeviceId, event: DeviceEvent) {
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
+
+        if state.replay.is_playing() {
+            return;
+        }
+
+        if let DeviceEvent::MouseMotion { delta: (x, y) } = event {
+            state.input.mouse_pos += Vec2f::new([x as f32, y as f32]);
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
+
+        if event_loop.exiting() || self.paused {
+            return;
+        }
+
+        let current_time = (self.timer.elapsed().as_millis() as f64) / 1000.0;
+        let mut delta_time = current_time - self.last_time;
+        self.last_time = current_time;
+
+        if state.replay.is_playing() {
+            match state.replay.play(&mut state.input) {
+                Some(recorded_delta_time) => delta_time = recorded_delta_time,
+                None => {
+                    log::info!("Replay finished!");
+                    if state.replay.exit_on_finish {
+                        event_loop.exit();
+                        return;
+                    }
+                }
             }
-            Event::AboutToWait => {
-                let current_time = (timer.elapsed().as_millis() as f64) / 1000.0;
-                state.delta_time = current_time - state.time;
-                state.time = current_time;
+        }
+        state.replay.record(&state.input, delta_time);
+
+        state.delta_time = delta_time;
+        state.time += delta_time;
 
-                world.update(&mut assets, &mut state);
+        self.world.update(&mut self.assets, state);
+        state.window.apply_pending_cursor(event_loop);
+        if state.exit_requested {
+            event_loop.exit();
+            return;
+        }
 
-                state.input.clear_temp();
+        if state.renderer.device_lost {
+            log::warn!("Device lost, reinitializing renderer!");
+            rendering::recover_device(state);
+            self.world.device_restored(&mut self.assets, state);
+        }
+    }
+
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
+
+        if !self.paused {
+            self.paused = true;
+            self.world.pause(&mut self.assets, state);
+            if cfg!(target_os = "android") {
+                rendering::release_surface(state);
             }
-            _ => (),
-        })
-        .unwrap();
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(mut state) = self.state.take() {
+            if let Err(e) = state.replay.stop_recording() {
+                log::error!("failed to save replay: {e}");
+            }
+            self.world.exit(&mut self.assets, &mut state);
+            rendering::shutdown(&mut state);
+        }
+    }
+}
+
+pub fn run(world: World, assets: AssetLibrary) {
+    run_with_settings(world, assets, RendererSettings::default());
+}
+
+pub fn run_with_settings(world: World, assets: AssetLibrary, settings: RendererSettings) {
+    run_on(EventLoop::new, world, assets, settings);
+}
+
+// Call from the `android_main` entry point.
+#[cfg(target_os = "android")]
+pub fn run_android(
+    app: winit::platform::android::activity::AndroidApp,
+    world: World,
+    assets: AssetLibrary,
+    settings: RendererSettings,
+) {
+    run_on(|| EventLoop::with_android_app(app), world, assets, settings);
+}
+
+fn run_on(event_loop: impl FnOnce() -> EventLoop, mut world: World, assets: AssetLibrary, settings: RendererSettings) {
+    let _ = logging::init(LoggerSettings::from_env());
+    crash::install(settings.crash_report_dir.clone());
+    let event_loop = event_loop();
+
+    world.register::<Transform>();
+    world.register::<StaticMesh>();
+    world.register::<Visibility>();
+    world.register::<Camera>();
+    world.register::<PointLight>();
+
+    #[cfg(feature = "network")]
+    world.add_system(NetworkUpdater {});
+    world.add_system(TimersUpdater {});
+    world.add_system(TweenUpdater::<Transform>::new());
+    world.add_system(FollowSplineUpdater {});
+    world.add_system(BehaviorUpdater {});
+    world.add_system(AnimatorUpdater {});
+    world.add_system(SpriteAnimationUpdater {});
+    #[cfg(feature = "physics2d")]
+    world.add_system(Physics2dUpdater {});
+    world.add_system(TransformUpdater::new());
+    world.add_system(CameraUpdater {});
+    world.add_system(MeshLoader {});
+    world.add_system(DynamicMeshLoader {});
+    world.add_system(ShaderLoader {});
+    world.add_system(TextureLoader {});
+    world.add_system(AsyncAssetLoader {});
+    world.add_system(LevelStreamer {});
+    world.add_system(TerrainUpdater::new());
+    world.add_system(TilemapUpdater {});
+    world.add_system(LodUpdater {});
+    world.add_system(GizmoUpdater {});
+    world.add_system(TrailUpdater {});
+    #[cfg(feature = "ui")]
+    {
+        world.add_system(UiUpdater {});
+        world.add_system(UiWidgetUpdater {});
+        world.add_system(InspectorUpdater {});
+        world.add_system(UiBatcher {});
+    }
+    world.add_system(RendererHandler {});
+    world.add_system(DebugOverlayUpdater {});
+    world.add_system(InputManagerUpdater {});
+
+    let mut app = App {
+        world,
+        assets,
+        state: None,
+        settings,
+        timer: Instant::now(),
+        last_time: 0.0,
+        paused: false,
+    };
+
+    event_loop.event_loop.set_control_flow(ControlFlow::Poll);
+    event_loop.event_loop.run_app(&mut app).unwrap();
 }
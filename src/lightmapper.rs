@@ -0,0 +1,446 @@
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+use crate::asset_library::AssetLibrary;
+use crate::capture::{self, CapturedFrame};
+use crate::ecs::World;
+use crate::types::color::Color;
+use crate::types::light::PointLight;
+use crate::types::matrices::Matrix4f;
+use crate::types::static_mesh::StaticMesh;
+use crate::types::texture::Texture;
+use crate::types::transform::Transform;
+use crate::types::vectors::Vec3f;
+
+// Lightmaps are saved here and loaded like any other texture, as `lightmaps/{mesh}`.
+pub const LIGHTMAP_DIR: &str = "assets/textures/lightmaps";
+
+#[derive(Clone, Debug)]
+pub struct LightmapSettings {
+    pub resolution: u32,
+    /// Hemisphere rays per texel for indirect light.
+    pub samples: u32,
+    /// Indirect bounces, 0 bakes direct light and `ambient` only.
+    pub bounces: u32,
+    /// Radiance of rays that leave the scene.
+    pub ambient: Color,
+    /// Texels every empty texel is filled from, hides seams when bilinear filtering reaches outside
+    /// the charts.
+    pub dilation: u32,
+}
+
+impl Default for LightmapSettings {
+    fn default() -> Self {
+        LightmapSettings {
+            resolution: 256,
+            samples: 64,
+            bounces: 1,
+            ambient: Color::rgb(0.03, 0.03, 0.03),
+            dilation: 2,
+        }
+    }
+}
+
+// Linear irradiance over pi, the lightmapped shader multiplies it by the albedo.
+#[derive(Clone, Debug)]
+pub struct Lightmap {
+    pub mesh_name: String,
+    pub width: u32,
+    pub height: u32,
+    pub texels: Vec<[f32; 3]>,
+}
+
+impl Lightmap {
+    pub fn texture_name(&self) -> String {
+        format!("lightmaps/{}", self.mesh_name)
+    }
+
+    // sRGB encoded, matching how textures are sampled by default.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        self.texels
+            .iter()
+            .flat_map(|[r, g, b]| {
+                let srgb = Color::rgb(r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0)).to_srgb();
+                [srgb.r, srgb.g, srgb.b, 1.0].map(|x| (x * 255.0).round() as u8)
+            })
+            .collect()
+    }
+
+    // Writes the PNG `Texture::new(texture_name())` loads, returning that name.
+    pub fn save(&self) -> Result<String, String> {
+        let path = format!("{LIGHTMAP_DIR}/{}.png", self.mesh_name);
+        // Mesh names may contain directories.
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+        }
+        let frame = CapturedFrame {
+            width: self.width,
+            height: self.height,
+            rgba: self.to_rgba8(),
+        };
+        capture::save_png(&frame, &path)?;
+        Ok(self.texture_name())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Triangle {
+    a: Vec3f,
+    edge1: Vec3f,
+    edge2: Vec3f,
+    colors: [Color; 3],
+}
+
+struct Instance {
+    center: Vec3f,
+    radius: f32,
+    triangles: Vec<Triangle>,
+}
+
+struct Light {
+    position: Vec3f,
+    light: PointLight,
+}
+
+struct Scene {
+    instances: Vec<Instance>,
+    lights: Vec<Light>,
+}
+
+struct Hit {
+    t: f32,
+    normal: Vec3f,
+    albedo: Color,
+}
+
+// Möller-Trumbore, returns the distance and barycentrics of `b` and `c`.
+fn intersect(triangle: &Triangle, origin: Vec3f, mut direction: Vec3f, max_t: f32) -> Option<(f32, f32, f32)> {
+    let mut p = direction.cross(triangle.edge2);
+    let determinant = p.dot(triangle.edge1);
+    if determinant.abs() < 1e-8 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let mut s = origin - triangle.a;
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(triangle.edge1);
+    let v = direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let mut edge2 = triangle.edge2;
+    let t = edge2.dot(q) * inverse;
+    (t > 0.0 && t < max_t).then_some((t, u, v))
+}
+
+fn sphere_hit(center: Vec3f, radius: f32, origin: Vec3f, direction: Vec3f, max_t: f32) -> bool {
+    let mut to_center = center - origin;
+    let along = to_center.dot(direction);
+    let closest = to_center.length_sqr() - along * along;
+    closest <= radius * radius && along + radius >= 0.0 && along - radius <= max_t
+}
+
+impl Scene {
+    fn occluded(&self, origin: Vec3f, direction: Vec3f, max_t: f32) -> bool {
+        self.instances.iter().any(|instance| {
+            sphere_hit(instance.center, instance.radius, origin, direction, max_t)
+                && instance.triangles.iter().any(|x| intersect(x, origin, direction, max_t).is_some())
+        })
+    }
+
+    fn closest(&self, origin: Vec3f, direction: Vec3f) -> Option<Hit> {
+        let mut closest: Option<(f32, f32, f32, &Triangle)> = None;
+        for instance in self.instances.iter() {
+            let max_t = closest.map_or(f32::MAX, |x| x.0);
+            if !sphere_hit(instance.center, instance.radius, origin, direction, max_t) {
+                continue;
+            }
+            for triangle in instance.triangles.iter() {
+                let max_t = closest.map_or(f32::MAX, |x| x.0);
+                if let Some((t, u, v)) = intersect(triangle, origin, direction, max_t) {
+                    closest = Some((t, u, v, triangle));
+                }
+            }
+        }
+        let (t, u, v, triangle) = closest?;
+        let mut edge1 = triangle.edge1;
+        let mut normal = edge1.cross(triangle.edge2).normalize();
+        if normal.dot(direction) > 0.0 {
+            normal *= -1.0;
+        }
+        let [a, b, c] = triangle.colors;
+        let albedo = Color::rgb(
+            a.r * (1.0 - u - v) + b.r * u + c.r * v,
+            a.g * (1.0 - u - v) + b.g * u + c.g * v,
+            a.b * (1.0 - u - v) + b.b * u + c.b * v,
+        );
+        Some(Hit { t, normal, albedo })
+    }
+
+    // Irradiance over pi from every light, attenuated like the built-in lit shader.
+    fn direct(&self, position: Vec3f, mut normal: Vec3f) -> Vec3f {
+        let mut sum = Vec3f::new([0.0, 0.0, 0.0]);
+        for light in self.lights.iter() {
+            let mut to_light = light.position - position;
+            let distance = to_light.length();
+            if distance >= light.light.range || distance <= 0.0 {
+                continue;
+            }
+            let l = to_light / distance;
+            let n_dot_l = normal.dot(l);
+            if n_dot_l <= 0.0 || self.occluded(position + normal * 1e-3, l, distance) {
+                continue;
+            }
+            let window = (1.0 - (distance / light.light.range).powi(4)).clamp(0.0, 1.0);
+            let attenuation = window * window / (distance * distance + 1.0);
+            let color = light.light.color;
+            sum += Vec3f::new([color.r, color.g, color.b]) * (light.light.intensity * attenuation * n_dot_l / PI);
+        }
+        sum
+    }
+
+    // Radiance arriving along `direction`, one path per call. Surfaces are dark once the bounces
+    // run out, only the ambient reaches further.
+    fn incoming(&self, origin: Vec3f, direction: Vec3f, bounces: u32, settings: &LightmapSettings, rng: &mut Rng) -> Vec3f {
+        let Some(hit) = self.closest(origin, direction) else {
+            let ambient = settings.ambient;
+            return Vec3f::new([ambient.r, ambient.g, ambient.b]);
+        };
+        if bounces == 0 {
+            return Vec3f::new([0.0, 0.0, 0.0]);
+        }
+        let position = origin + direction * hit.t;
+        let next = cosine_sample(hit.normal, rng);
+        let irradiance = self.direct(position, hit.normal) + self.incoming(position + hit.normal * 1e-3, next, bounces - 1, settings, rng);
+        irradiance * Vec3f::new([hit.albedo.r, hit.albedo.g, hit.albedo.b])
+    }
+}
+
+// xorshift32, enough for sampling directions.
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+fn cosine_sample(mut normal: Vec3f, rng: &mut Rng) -> Vec3f {
+    let (u, v) = (rng.next(), rng.next());
+    let radius = u.sqrt();
+    let angle = 2.0 * PI * v;
+    let helper = if normal.x.abs() > 0.9 { Vec3f::new([0.0, 1.0, 0.0]) } else { Vec3f::new([1.0, 0.0, 0.0]) };
+    let tangent = normal.cross(helper).normalize();
+    let bitangent = normal.cross(tangent);
+    tangent * (radius * angle.cos()) + bitangent * (radius * angle.sin()) + normal * (1.0 - u).max(0.0).sqrt()
+}
+
+fn model_matrix(transform: &Transform) -> Matrix4f {
+    Matrix4f::compose(transform.position.to_vec3f(), transform.rotation, transform.scale)
+}
+
+fn build_scene(world: &World, assets: &AssetLibrary) -> Scene {
+    let mut instances = Vec::new();
+    if let (Some(static_meshes), Some(transforms)) =
+        (world.borrow_component_vec_mut::<StaticMesh>(), world.borrow_component_vec_mut::<Transform>())
+    {
+        for (static_mesh, transform) in static_meshes.iter().zip(transforms.iter()) {
+            let (Some(static_mesh), Some(transform)) = (static_mesh, transform) else {
+                continue;
+            };
+            let Some(mesh) = assets.meshes.iter().find(|x| x.name == static_mesh.mesh_name) else {
+                continue;
+            };
+            let model = model_matrix(transform);
+            let positions: Vec<Vec3f> = mesh.vertices.iter().map(|x| model.transform_point(x.position)).collect();
+            let triangles: Vec<Triangle> = mesh
+                .indices
+                .chunks_exact(3)
+                .map(|x| {
+                    let [a, b, c] = [x[0], x[1], x[2]].map(|i| i as usize);
+                    Triangle {
+                        a: positions[a],
+                        edge1: positions[b] - positions[a],
+                        edge2: positions[c] - positions[a],
+                        colors: [a, b, c].map(|i| mesh.vertices[i].color),
+                    }
+                })
+                .collect();
+            if triangles.is_empty() {
+                continue;
+            }
+            let center = positions.iter().fold(Vec3f::new([0.0, 0.0, 0.0]), |a, b| a + *b) / positions.len() as f32;
+            let radius = positions.iter().map(|x| (*x - center).length()).fold(0.0, f32::max);
+            instances.push(Instance { center, radius, triangles });
+        }
+    }
+
+    let mut lights = Vec::new();
+    if let (Some(point_lights), Some(transforms)) =
+        (world.borrow_component_vec_mut::<PointLight>(), world.borrow_component_vec_mut::<Transform>())
+    {
+        for (light, transform) in point_lights.iter().zip(transforms.iter()) {
+            if let (Some(light), Some(transform)) = (light, transform) {
+                lights.push(Light {
+                    position: transform.position.to_vec3f(),
+                    light: *light,
+                });
+            }
+        }
+    }
+    Scene { instances, lights }
+}
+
+// World space position and normal of every texel a triangle covers in the second UV set.
+fn rasterize(world: &World, assets: &AssetLibrary, entity: usize, resolution: u32) -> Vec<Option<(Vec3f, Vec3f)>> {
+    let static_meshes = world.borrow_component_vec_mut::<StaticMesh>().unwrap();
+    let transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+    let mesh_name = &static_meshes[entity].as_ref().unwrap().mesh_name;
+    let mesh = assets.meshes.iter().find(|x| x.name == *mesh_name).unwrap();
+    let model = model_matrix(transforms[entity].as_ref().unwrap());
+
+    let size = resolution as f32;
+    let mut texels = vec![None; (resolution * resolution) as usize];
+    for triangle in mesh.indices.chunks_exact(3) {
+        let vertices = [triangle[0], triangle[1], triangle[2]].map(|i| mesh.vertices[i as usize]);
+        let uvs = vertices.map(|x| [x.uv2.x * size, x.uv2.y * size]);
+        let area = (uvs[1][0] - uvs[0][0]) * (uvs[2][1] - uvs[0][1]) - (uvs[2][0] - uvs[0][0]) * (uvs[1][1] - uvs[0][1]);
+        if area.abs() < 1e-8 {
+            continue;
+        }
+        let min_x = uvs.iter().map(|x| x[0]).fold(f32::MAX, f32::min).floor().max(0.0) as u32;
+        let max_x = uvs.iter().map(|x| x[0]).fold(f32::MIN, f32::max).ceil().min(size) as u32;
+        let min_y = uvs.iter().map(|x| x[1]).fold(f32::MAX, f32::min).floor().max(0.0) as u32;
+        let max_y = uvs.iter().map(|x| x[1]).fold(f32::MIN, f32::max).ceil().min(size) as u32;
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let edge = |a: [f32; 2], b: [f32; 2]| ((b[0] - a[0]) * (py - a[1]) - (px - a[0]) * (b[1] - a[1])) / area;
+                let weights = [edge(uvs[1], uvs[2]), edge(uvs[2], uvs[0]), edge(uvs[0], uvs[1])];
+                if weights.iter().any(|w| *w < -1e-4) {
+                    continue;
+                }
+                let local = vertices[0].position * weights[0] + vertices[1].position * weights[1] + vertices[2].position * weights[2];
+                let local_normal = vertices[0].normal * weights[0] + vertices[1].normal * weights[1] + vertices[2].normal * weights[2];
+                let position = model.transform_point(local);
+                let mut normal = model.transform_point(local + local_normal) - position;
+                if normal.length_sqr() > 0.0 {
+                    texels[(y * resolution + x) as usize] = Some((position, normal.normalize()));
+                }
+            }
+        }
+    }
+    texels
+}
+
+fn dilate(texels: &mut [Option<[f32; 3]>], resolution: u32) {
+    let size = resolution as i32;
+    let previous = texels.to_vec();
+    for y in 0..size {
+        for x in 0..size {
+            if previous[(y * size + x) as usize].is_some() {
+                continue;
+            }
+            let neighbors: Vec<[f32; 3]> = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .filter(|(dx, dy)| (0..size).contains(&(x + dx)) && (0..size).contains(&(y + dy)))
+                .filter_map(|(dx, dy)| previous[((y + dy) * size + x + dx) as usize])
+                .collect();
+            if !neighbors.is_empty() {
+                let sum = neighbors.iter().fold([0.0; 3], |a, b| [a[0] + b[0], a[1] + b[1], a[2] + b[2]]);
+                texels[(y * size + x) as usize] = Some(sum.map(|x| x / neighbors.len() as f32));
+            }
+        }
+    }
+}
+
+// Path traces static lighting from every `PointLight` into a lightmap per static mesh, laid out by
+// the second UV set. Runs on the CPU without a window or device, so it works from a build script
+// or tool before the engine starts. A mesh used by several entities is baked for the first one.
+// The lightmaps are only returned, see `bake_to_assets` to write them to disk.
+pub fn bake(world: &World, assets: &AssetLibrary, settings: &LightmapSettings) -> Vec<Lightmap> {
+    let scene = build_scene(world, assets);
+    let Some(static_meshes) = world.borrow_component_vec_mut::<StaticMesh>() else {
+        return Vec::new();
+    };
+    let has_transform = |entity: usize| world.borrow_component_vec_mut::<Transform>().is_some_and(|x| x.get(entity).is_some_and(|x| x.is_some()));
+    let mut entities: Vec<(usize, String)> = Vec::new();
+    for (entity, static_mesh) in static_meshes.iter().enumerate() {
+        let Some(static_mesh) = static_mesh else {
+            continue;
+        };
+        if !has_transform(entity) || !assets.meshes.iter().any(|x| x.name == static_mesh.mesh_name) {
+            continue;
+        }
+        if entities.iter().any(|(_, name)| *name == static_mesh.mesh_name) {
+            log::warn!("mesh {} is used by several entities, baking the first", static_mesh.mesh_name);
+            continue;
+        }
+        entities.push((entity, static_mesh.mesh_name.clone()));
+    }
+    drop(static_meshes);
+
+    let resolution = settings.resolution.max(1);
+    let threads = thread::available_parallelism().map_or(1, |x| x.get());
+    entities
+        .into_iter()
+        .map(|(entity, mesh_name)| {
+            let surface = rasterize(world, assets, entity, resolution);
+            let mut texels: Vec<Option<[f32; 3]>> = vec![None; surface.len()];
+            let chunk = surface.len().div_ceil(threads);
+            thread::scope(|scope| {
+                for (i, (output, input)) in texels.chunks_mut(chunk).zip(surface.chunks(chunk)).enumerate() {
+                    let scene = &scene;
+                    scope.spawn(move || {
+                        let mut rng = Rng(0x9e37_79b9 ^ (entity as u32).wrapping_mul(0x85eb_ca6b) ^ (i as u32 + 1));
+                        for (output, input) in output.iter_mut().zip(input.iter()) {
+                            let Some((position, normal)) = input else {
+                                continue;
+                            };
+                            let mut value = scene.direct(*position, *normal);
+                            if settings.samples > 0 {
+                                let mut indirect = Vec3f::new([0.0, 0.0, 0.0]);
+                                for _ in 0..settings.samples {
+                                    let direction = cosine_sample(*normal, &mut rng);
+                                    indirect += scene.incoming(*position + *normal * 1e-3, direction, settings.bounces, settings, &mut rng);
+                                }
+                                value += indirect / settings.samples as f32;
+                            }
+                            *output = Some([value.x, value.y, value.z]);
+                        }
+                    });
+                }
+            });
+            for _ in 0..settings.dilation {
+                dilate(&mut texels, resolution);
+            }
+            Lightmap {
+                mesh_name,
+                width: resolution,
+                height: resolution,
+                texels: texels.into_iter().map(|x| x.unwrap_or([0.0; 3])).collect(),
+            }
+        })
+        .collect()
+}
+
+// Bakes every lightmap and saves it to `LIGHTMAP_DIR`, so later runs load it from disk without
+// baking. The textures are added to `assets` for this run, returns their names for
+// `Material::lightmapped`.
+pub fn bake_to_assets(world: &World, assets: &mut AssetLibrary, settings: &LightmapSettings) -> Result<Vec<String>, String> {
+    let names: Vec<String> = bake(world, assets, settings).iter().map(Lightmap::save).collect::<Result<_, _>>()?;
+    for name in names.iter() {
+        if !assets.textures.iter().any(|x| x.name == *name) {
+            assets.textures.push(Texture::new(name.clone()));
+        }
+    }
+    Ok(names)
+}
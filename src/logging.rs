@@ -0,0 +1,177 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    str::FromStr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+pub const LOG_ENV: &str = "SIMPLE_ENGINE_LOG";
+
+#[derive(Clone, Debug)]
+pub struct FileSink {
+    pub path: String,
+    pub max_size: u64,
+    pub max_files: usize,
+}
+
+impl FileSink {
+    pub fn new(path: &str) -> FileSink {
+        FileSink {
+            path: path.to_string(),
+            max_size: 10 * 1024 * 1024,
+            max_files: 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LoggerSettings {
+    pub level: LevelFilter,
+    pub module_filters: Vec<(String, LevelFilter)>,
+    pub console: bool,
+    pub file: Option<FileSink>,
+}
+
+impl LoggerSettings {
+    pub fn from_env() -> LoggerSettings {
+        let mut settings = LoggerSettings::default();
+        if let Ok(filters) = env::var(LOG_ENV) {
+            settings.parse_filters(&filters);
+        }
+        settings
+    }
+
+    pub fn parse_filters(&mut self, filters: &str) {
+        for filter in filters.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            match filter.split_once('=') {
+                Some((module, level)) => {
+                    if let Ok(level) = LevelFilter::from_str(level) {
+                        self.module_filters.push((module.to_string(), level));
+                    }
+                }
+                None => {
+                    if let Ok(level) = LevelFilter::from_str(filter) {
+                        self.level = level;
+                    }
+                }
+            }
+        }
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.module_filters
+            .iter()
+            .filter(|(module, _)| {
+                target == module || target.strip_prefix(module.as_str()).is_some_and(|x| x.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.module_filters
+            .iter()
+            .map(|x| x.1)
+            .fold(self.level, |a, b| a.max(b))
+    }
+}
+
+impl Default for LoggerSettings {
+    fn default() -> Self {
+        LoggerSettings {
+            level: LevelFilter::Info,
+            module_filters: Vec::new(),
+            console: true,
+            file: None,
+        }
+    }
+}
+
+struct FileOutput {
+    sink: FileSink,
+    file: File,
+    size: u64,
+}
+
+impl FileOutput {
+    fn open(sink: FileSink) -> Option<FileOutput> {
+        let file = OpenOptions::new().create(true).append(true).open(&sink.path).ok()?;
+        let size = file.metadata().map_or(0, |x| x.len());
+        Some(FileOutput { sink, file, size })
+    }
+
+    fn write(&mut self, line: &str) {
+        if self.size + line.len() as u64 > self.sink.max_size {
+            self.rotate();
+        }
+        if self.file.write_all(line.as_bytes()).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+
+    fn rotate(&mut self) {
+        for i in (1..self.sink.max_files).rev() {
+            let from = if i == 1 {
+                self.sink.path.clone()
+            } else {
+                format!("{}.{}", self.sink.path, i - 1)
+            };
+            let _ = fs::rename(from, format!("{}.{}", self.sink.path, i));
+        }
+        if let Ok(file) = File::create(&self.sink.path) {
+            self.file = file;
+            self.size = 0;
+        }
+    }
+}
+
+struct Logger {
+    settings: LoggerSettings,
+    file: Option<Mutex<FileOutput>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.settings.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let line = format!(
+            "[{}.{:03} {:5} {}] {}\n",
+            timestamp.as_secs(),
+            timestamp.subsec_millis(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if self.settings.console {
+            eprint!("{}", line);
+        }
+        if let Some(file) = self.file.as_ref() {
+            file.lock().unwrap().write(&line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.as_ref() {
+            let _ = file.lock().unwrap().file.flush();
+        }
+    }
+}
+
+pub fn init(settings: LoggerSettings) -> Result<(), SetLoggerError> {
+    let file = settings.file.clone().and_then(FileOutput::open).map(Mutex::new);
+    let max_level = settings.max_level();
+    log::set_boxed_logger(Box::new(Logger { settings, file }))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
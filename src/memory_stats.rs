@@ -0,0 +1,247 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferMemory};
+use vulkano::image::{Image, ImageMemory};
+use vulkano::memory::{MemoryHeapFlags, ResourceMemory};
+
+use crate::asset_library::AssetLibrary;
+use crate::ecs::World;
+use crate::skinning;
+use crate::state::State;
+use crate::types::mesh::DynamicMesh;
+use crate::types::morph::MorphWeights;
+use crate::types::point_cloud::PointCloud;
+use crate::types::transform::Transform;
+
+pub const MEMORY_CHECK_INTERVAL: f64 = 5.0;
+
+#[derive(Clone, Debug, Default)]
+pub struct HeapUsage {
+    pub index: u32,
+    pub size: u64,
+    pub device_local: bool,
+    pub used: u64,
+    pub reserved: u64,
+    pub resources: usize,
+    pub allocations: usize,
+}
+
+impl HeapUsage {
+    pub fn usage(&self) -> f32 {
+        if self.size == 0 {
+            return 0.0;
+        }
+        self.reserved as f32 / self.size as f32
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryFootprint {
+    pub bytes: u64,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MemoryReport {
+    pub heaps: Vec<HeapUsage>,
+    pub meshes: MemoryFootprint,
+    pub textures: MemoryFootprint,
+    pub render_targets: MemoryFootprint,
+    pub frame_buffers: MemoryFootprint,
+    pub storage_buffers: MemoryFootprint,
+}
+
+impl MemoryReport {
+    pub fn total(&self) -> u64 {
+        self.heaps.iter().map(|x| x.used).sum()
+    }
+
+    pub fn summary(&self) -> String {
+        let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        let heaps = self
+            .heaps
+            .iter()
+            .filter(|x| x.reserved > 0)
+            .map(|x| format!("heap{} {:.0}/{:.0} MB", x.index, mb(x.reserved), mb(x.size)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "mem {:.1} MB (mesh {:.1} tex {:.1} rt {:.1}) {}",
+            mb(self.total()),
+            mb(self.meshes.bytes),
+            mb(self.textures.bytes),
+            mb(self.render_targets.bytes),
+            heaps,
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MemoryMonitor {
+    pub last_report: Option<MemoryReport>,
+    last_check: f64,
+    warned: Vec<bool>,
+}
+
+struct Collector<'a> {
+    state: &'a State,
+    report: MemoryReport,
+    seen: HashSet<usize>,
+    blocks: HashSet<usize>,
+}
+
+impl Collector<'_> {
+    fn add_memory(&mut self, memory: &ResourceMemory, footprint: &mut MemoryFootprint) {
+        let physical_device = self.state.renderer.physical_device().unwrap();
+        let device_memory = memory.device_memory();
+        let heap_index = physical_device.memory_properties().memory_types[device_memory.memory_type_index() as usize].heap_index;
+        let heap = &mut self.report.heaps[heap_index as usize];
+        heap.used += memory.size();
+        heap.resources += 1;
+        if self.blocks.insert(Arc::as_ptr(device_memory) as usize) {
+            heap.reserved += device_memory.allocation_size();
+            heap.allocations += 1;
+        }
+        footprint.bytes += memory.size();
+    }
+
+    fn add_buffer(&mut self, buffer: &Arc<Buffer>, mut footprint: MemoryFootprint) -> MemoryFootprint {
+        if !self.seen.insert(Arc::as_ptr(buffer) as usize) {
+            return footprint;
+        }
+        if let BufferMemory::Normal(memory) = buffer.memory() {
+            self.add_memory(memory, &mut footprint);
+            footprint.count += 1;
+        }
+        footprint
+    }
+
+    fn add_image(&mut self, image: &Arc<Image>, mut footprint: MemoryFootprint) -> MemoryFootprint {
+        if !self.seen.insert(Arc::as_ptr(image) as usize) {
+            return footprint;
+        }
+        if let ImageMemory::Normal(memory) = image.memory() {
+            for memory in memory.iter() {
+                self.add_memory(memory, &mut footprint);
+            }
+            footprint.count += 1;
+        }
+        footprint
+    }
+}
+
+pub fn memory_report(world: &World, assets: &AssetLibrary, state: &State) -> MemoryReport {
+    let Some(physical_device) = state.renderer.physical_device() else {
+        return MemoryReport::default();
+    };
+    let heaps = physical_device
+        .memory_properties()
+        .memory_heaps
+        .iter()
+        .enumerate()
+        .map(|(i, heap)| HeapUsage {
+            index: i as u32,
+            size: heap.size,
+            device_local: heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL),
+            ..Default::default()
+        })
+        .collect();
+    let mut collector = Collector {
+        state,
+        report: MemoryReport { heaps, ..Default::default() },
+        seen: HashSet::new(),
+        blocks: HashSet::new(),
+    };
+
+    let mut meshes = MemoryFootprint::default();
+    for mesh in assets.meshes.iter() {
+        if let Some(buffer) = mesh.vertex_buffer.as_ref() {
+            meshes = collector.add_buffer(buffer.buffer(), meshes);
+        }
+        if let Some(buffer) = mesh.index_buffer.as_ref() {
+            meshes = collector.add_buffer(buffer.buffer(), meshes);
+        }
+    }
+    if let Some(dynamic_meshes) = world.borrow_component_vec_mut::<DynamicMesh>() {
+        for buffers in dynamic_meshes.iter().flatten().filter_map(|x| x.buffers.as_ref()) {
+            for buffer in buffers.vertex.iter() {
+                meshes = collector.add_buffer(buffer.buffer(), meshes);
+            }
+            for buffer in buffers.index.iter() {
+                meshes = collector.add_buffer(buffer.buffer(), meshes);
+            }
+        }
+    }
+
+    let mut textures = MemoryFootprint::default();
+    for image in assets.textures.iter().filter_map(|x| x.image.as_ref()) {
+        textures = collector.add_image(image, textures);
+    }
+
+    let mut render_targets = MemoryFootprint::default();
+    for image in state.renderer.render_target_images() {
+        render_targets = collector.add_image(&image, render_targets);
+    }
+
+    let mut frame_buffers = MemoryFootprint::default();
+    let mut uniforms = state.renderer.uniform_buffers();
+    uniforms.extend(assets.materials.iter().filter_map(|x| x.buffer.as_ref()).flat_map(|x| x.buffers.iter().map(|x| x.buffer().clone())));
+    if let Some(transforms) = world.borrow_component_vec_mut::<Transform>() {
+        uniforms.extend(transforms.iter().flatten().filter_map(|x| x.buffer.as_ref()).flat_map(|x| x.buffers.iter().map(|x| x.buffer().clone())));
+    }
+    for buffer in uniforms.iter() {
+        frame_buffers = collector.add_buffer(buffer, frame_buffers);
+    }
+
+    let mut storage_buffers = MemoryFootprint::default();
+    let mut storage = state.renderer.clusters.storage_buffers();
+    if let Some(point_clouds) = world.borrow_component_vec_mut::<PointCloud>() {
+        storage.extend(point_clouds.iter().flatten().flat_map(|x| x.buffers()));
+    }
+    storage.extend(skinning::storage_buffers(world));
+    storage.extend(assets.meshes.iter().filter_map(|x| x.morph_buffer.as_ref()).map(|x| x.buffer().clone()));
+    if let Some(morph_weights) = world.borrow_component_vec_mut::<MorphWeights>() {
+        storage.extend(morph_weights.iter().flatten().filter_map(|x| x.buffers.as_ref()).flat_map(|x| x.weights.iter().map(|x| x.buffer().clone())));
+    }
+    for buffer in storage.iter() {
+        storage_buffers = collector.add_buffer(buffer, storage_buffers);
+    }
+
+    let mut report = collector.report;
+    report.meshes = meshes;
+    report.textures = textures;
+    report.render_targets = render_targets;
+    report.frame_buffers = frame_buffers;
+    report.storage_buffers = storage_buffers;
+    report
+}
+
+pub fn check_memory_budget(world: &World, assets: &AssetLibrary, state: &mut State) {
+    let Some(threshold) = state.renderer.settings.memory_warning_threshold else {
+        return;
+    };
+    if state.time - state.renderer.memory.last_check < MEMORY_CHECK_INTERVAL {
+        return;
+    }
+
+    let report = memory_report(world, assets, state);
+    let monitor = &mut state.renderer.memory;
+    monitor.last_check = state.time;
+    monitor.warned.resize(report.heaps.len(), false);
+    for (heap, warned) in report.heaps.iter().zip(monitor.warned.iter_mut()) {
+        let over = heap.usage() >= threshold;
+        if over && !*warned {
+            log::warn!(
+                "memory heap {} is {:.0}% full ({} of {} bytes in {} allocations)",
+                heap.index,
+                heap.usage() * 100.0,
+                heap.reserved,
+                heap.size,
+                heap.allocations,
+            );
+        }
+        *warned = over;
+    }
+    monitor.last_report = Some(report);
+}
@@ -0,0 +1,296 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+    types::{transform::Transform, vectors::*},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Replicated {
+    pub network_id: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NetworkEvent {
+    pub name: String,
+    pub payload: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum NetworkMessage {
+    Transform {
+        network_id: u64,
+        position: [f64; 3],
+        scale: [f32; 3],
+        rotation: [f32; 3],
+    },
+    Event(NetworkEvent),
+}
+
+#[derive(Debug)]
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> std::io::Result<Connection> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+            closed: false,
+        })
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.stream.peer_addr().ok()
+    }
+
+    fn send(&mut self, message: &NetworkMessage) {
+        serde_json::to_writer(&mut self.outgoing, message).unwrap();
+        self.outgoing.push(b'\n');
+    }
+
+    fn flush(&mut self) {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => {
+                    self.closed = true;
+                    return;
+                }
+                Ok(n) => {
+                    self.outgoing.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(_) => {
+                    self.closed = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Vec<NetworkMessage> {
+        let mut buffer = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(n) => self.incoming.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+
+        let mut messages = Vec::new();
+        while let Some(end) = self.incoming.iter().position(|x| *x == b'\n') {
+            let line: Vec<u8> = self.incoming.drain(..=end).collect();
+            match serde_json::from_slice(&line) {
+                Ok(message) => messages.push(message),
+                Err(e) => log::warn!("dropping malformed network message: {e}"),
+            }
+        }
+        messages
+    }
+}
+
+#[derive(Debug, Default)]
+pub enum NetworkRole {
+    #[default]
+    Offline,
+    Server {
+        listener: TcpListener,
+        clients: Vec<Connection>,
+    },
+    Client {
+        server: Connection,
+    },
+}
+
+#[derive(Debug)]
+pub struct Network {
+    pub role: NetworkRole,
+    pub send_interval: f64,
+    events: Vec<NetworkEvent>,
+    outgoing_events: Vec<NetworkEvent>,
+    last_send: f64,
+}
+
+impl Network {
+    pub fn new() -> Network {
+        Network {
+            role: NetworkRole::Offline,
+            send_interval: 1.0 / 30.0,
+            events: Vec::new(),
+            outgoing_events: Vec::new(),
+            last_send: 0.0,
+        }
+    }
+
+    pub fn host(&mut self, address: impl ToSocketAddrs) -> std::io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        self.role = NetworkRole::Server {
+            listener,
+            clients: Vec::new(),
+        };
+        Ok(())
+    }
+
+    pub fn connect(&mut self, address: impl ToSocketAddrs) -> std::io::Result<()> {
+        let server = Connection::new(TcpStream::connect(address)?)?;
+        self.role = NetworkRole::Client { server };
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self) {
+        self.role = NetworkRole::Offline;
+    }
+
+    pub fn is_server(&self) -> bool {
+        matches!(self.role, NetworkRole::Server { .. })
+    }
+
+    pub fn send_event(&mut self, name: &str, payload: &str) {
+        self.outgoing_events.push(NetworkEvent {
+            name: name.to_string(),
+            payload: payload.to_string(),
+        });
+    }
+
+    pub fn drain_events(&mut self) -> Vec<NetworkEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn connections(&mut self) -> Vec<&mut Connection> {
+        match &mut self.role {
+            NetworkRole::Offline => Vec::new(),
+            NetworkRole::Server { clients, .. } => clients.iter_mut().collect(),
+            NetworkRole::Client { server } => vec![server],
+        }
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct NetworkUpdater {}
+
+impl System for NetworkUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let network = &mut state.network;
+        if let NetworkRole::Server { listener, clients } = &mut network.role {
+            while let Ok((stream, address)) = listener.accept() {
+                match Connection::new(stream) {
+                    Ok(connection) => {
+                        log::info!("Client connected from {address}");
+                        clients.push(connection);
+                    }
+                    Err(e) => log::warn!("failed to accept client: {e}"),
+                }
+            }
+        }
+
+        let mut incoming = Vec::new();
+        for connection in network.connections() {
+            incoming.extend(connection.receive());
+        }
+
+        let mut relayed = Vec::new();
+        {
+            let replicated = world.borrow_component_vec_mut::<Replicated>();
+            let mut transforms = world.borrow_component_vec_mut::<Transform>();
+            for message in incoming {
+                match message {
+                    NetworkMessage::Transform { network_id, position, scale, rotation } => {
+                        let (Some(replicated), Some(transforms)) = (replicated.as_ref(), transforms.as_mut()) else {
+                            continue;
+                        };
+                        let entity = replicated.iter().position(|x| x.is_some_and(|x| x.network_id == network_id));
+                        let Some(entity) = entity else {
+                            continue;
+                        };
+                        if let Some(Some(transform)) = transforms.get_mut(entity) {
+                            transform.position = Vec3d::new(position);
+                            transform.scale = Vec3f::new(scale);
+                            transform.rotation = Vec3f::new(rotation);
+                            world.mark_changed::<Transform>(entity);
+                        }
+                    }
+                    NetworkMessage::Event(event) => {
+                        if network.is_server() {
+                            relayed.push(event.clone());
+                        }
+                        network.events.push(event);
+                    }
+                }
+            }
+        }
+
+        let mut outgoing: Vec<NetworkMessage> = network
+            .outgoing_events
+            .drain(..)
+            .chain(relayed)
+            .map(NetworkMessage::Event)
+            .collect();
+        if network.is_server() && state.time - network.last_send >= network.send_interval {
+            network.last_send = state.time;
+            if let (Some(replicated), Some(transforms)) = (
+                world.borrow_component_vec_mut::<Replicated>(),
+                world.borrow_component_vec_mut::<Transform>(),
+            ) {
+                let zip = replicated.iter().zip(transforms.iter());
+                for (replicated, transform) in zip.filter_map(|(x, y)| Some((x.as_ref()?, y.as_ref()?))) {
+                    outgoing.push(NetworkMessage::Transform {
+                        network_id: replicated.network_id,
+                        position: [transform.position.x, transform.position.y, transform.position.z],
+                        scale: [transform.scale.x, transform.scale.y, transform.scale.z],
+                        rotation: [transform.rotation.x, transform.rotation.y, transform.rotation.z],
+                    });
+                }
+            }
+        }
+
+        for connection in network.connections() {
+            for message in outgoing.iter() {
+                connection.send(message);
+            }
+            connection.flush();
+        }
+
+        match &mut network.role {
+            NetworkRole::Server { clients, .. } => clients.retain(|x| {
+                if x.closed {
+                    log::info!("Client disconnected");
+                }
+                !x.closed
+            }),
+            NetworkRole::Client { server } if server.closed => {
+                log::warn!("Disconnected from server");
+                network.role = NetworkRole::Offline;
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use crate::asset_library::AssetLibrary;
+use crate::ecs::{System, World};
+use crate::state::State;
+use crate::types::bvh::{Aabb, Bvh};
+use crate::types::tilemap::Tilemap;
+use crate::types::transform::Transform;
+use crate::types::vectors::{Vec2f, Vec3d, Vec3f};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BodyType {
+    #[default]
+    Dynamic,
+    /// Moved only by its velocity, pushes dynamic bodies without being pushed back.
+    Kinematic,
+}
+
+// Simulated on the XY plane of the entity's `Transform`, which needs a `Collider2d` as well.
+// Colliders without a body are static.
+#[derive(Clone, Copy, Debug)]
+pub struct RigidBody2d {
+    pub body_type: BodyType,
+    pub velocity: Vec2f,
+    pub mass: f32,
+    pub gravity_scale: f32,
+    /// Fraction of velocity lost per second.
+    pub linear_damping: f32,
+    // Accumulated by `add_force`, cleared every step.
+    force: Vec2f,
+}
+
+impl RigidBody2d {
+    pub fn dynamic(mass: f32) -> RigidBody2d {
+        RigidBody2d {
+            body_type: BodyType::Dynamic,
+            velocity: Vec2f::new([0.0, 0.0]),
+            mass: mass.max(f32::EPSILON),
+            gravity_scale: 1.0,
+            linear_damping: 0.0,
+            force: Vec2f::new([0.0, 0.0]),
+        }
+    }
+
+    pub fn kinematic() -> RigidBody2d {
+        RigidBody2d {
+            body_type: BodyType::Kinematic,
+            ..RigidBody2d::dynamic(1.0)
+        }
+    }
+
+    // Applied over the next step.
+    pub fn add_force(&mut self, force: Vec2f) {
+        self.force += force;
+    }
+
+    pub fn add_impulse(&mut self, impulse: Vec2f) {
+        if self.body_type == BodyType::Dynamic {
+            self.velocity += impulse / self.mass;
+        }
+    }
+
+    fn inverse_mass(&self) -> f32 {
+        match self.body_type {
+            BodyType::Dynamic => 1.0 / self.mass,
+            BodyType::Kinematic => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Shape2d {
+    /// Axis aligned, rotation is ignored.
+    Box { half_extents: Vec2f },
+    Circle { radius: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Collider2d {
+    pub shape: Shape2d,
+    /// From the transform's position.
+    pub offset: Vec2f,
+    /// Reports contacts without pushing anything.
+    pub sensor: bool,
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+impl Collider2d {
+    pub fn new(shape: Shape2d) -> Collider2d {
+        Collider2d {
+            shape,
+            offset: Vec2f::new([0.0, 0.0]),
+            sensor: false,
+            restitution: 0.0,
+            friction: 0.5,
+        }
+    }
+
+    pub fn rect(width: f32, height: f32) -> Collider2d {
+        Collider2d::new(Shape2d::Box {
+            half_extents: Vec2f::new([width * 0.5, height * 0.5]),
+        })
+    }
+
+    pub fn circle(radius: f32) -> Collider2d {
+        Collider2d::new(Shape2d::Circle { radius })
+    }
+
+    pub fn with_offset(mut self, offset: Vec2f) -> Collider2d {
+        self.offset = offset;
+        self
+    }
+
+    pub fn as_sensor(mut self) -> Collider2d {
+        self.sensor = true;
+        self
+    }
+
+    pub fn with_restitution(mut self, restitution: f32) -> Collider2d {
+        self.restitution = restitution;
+        self
+    }
+}
+
+// `normal` points from `a` to `b`.
+#[derive(Clone, Copy, Debug)]
+pub struct Contact2d {
+    pub a: usize,
+    pub b: usize,
+    pub normal: Vec2f,
+    pub depth: f32,
+    pub sensor: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Physics2d {
+    pub enabled: bool,
+    pub gravity: Vec2f,
+    /// Fixed step in seconds, frames run as many steps as fit in their delta time.
+    pub timestep: f64,
+    /// Steps per frame at most, the simulation slows down rather than spiral on long frames.
+    pub max_steps: u32,
+    /// Contacts of the last step.
+    pub contacts: Vec<Contact2d>,
+    accumulator: f64,
+}
+
+impl Physics2d {
+    pub fn new() -> Physics2d {
+        Physics2d {
+            enabled: true,
+            gravity: Vec2f::new([0.0, -9.81]),
+            timestep: 1.0 / 60.0,
+            max_steps: 4,
+            contacts: Vec::new(),
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn contacts_of(&self, entity: usize) -> impl Iterator<Item = &Contact2d> {
+        self.contacts.iter().filter(move |x| x.a == entity || x.b == entity)
+    }
+}
+
+impl Default for Physics2d {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Body {
+    entity: usize,
+    position: Vec2f,
+    collider: Collider2d,
+    scale: Vec2f,
+    inverse_mass: f32,
+    velocity: Vec2f,
+}
+
+impl Body {
+    fn center(&self) -> Vec2f {
+        self.position + self.collider.offset * self.scale
+    }
+
+    fn half_extents(&self) -> Vec2f {
+        match self.collider.shape {
+            Shape2d::Box { half_extents } => half_extents * self.scale,
+            Shape2d::Circle { radius } => Vec2f::new([radius, radius]) * self.scale.x.max(self.scale.y),
+        }
+    }
+
+    fn bounds(&self) -> Aabb {
+        let (center, half) = (self.center(), self.half_extents());
+        Aabb::new(
+            Vec3f::new([center.x - half.x, center.y - half.y, 0.0]),
+            Vec3f::new([center.x + half.x, center.y + half.y, 0.0]),
+        )
+    }
+}
+
+fn box_box(a: Vec2f, a_half: Vec2f, b: Vec2f, b_half: Vec2f) -> Option<(Vec2f, f32)> {
+    let d = b - a;
+    let overlap_x = a_half.x + b_half.x - d.x.abs();
+    let overlap_y = a_half.y + b_half.y - d.y.abs();
+    if overlap_x <= 0.0 || overlap_y <= 0.0 {
+        return None;
+    }
+    if overlap_x < overlap_y {
+        Some((Vec2f::new([d.x.signum(), 0.0]), overlap_x))
+    } else {
+        Some((Vec2f::new([0.0, d.y.signum()]), overlap_y))
+    }
+}
+
+fn circle_circle(a: Vec2f, a_radius: f32, b: Vec2f, b_radius: f32) -> Option<(Vec2f, f32)> {
+    let d = b - a;
+    let distance = (d.x * d.x + d.y * d.y).sqrt();
+    let depth = a_radius + b_radius - distance;
+    if depth <= 0.0 {
+        return None;
+    }
+    let normal = if distance > f32::EPSILON { d * (1.0 / distance) } else { Vec2f::new([0.0, 1.0]) };
+    Some((normal, depth))
+}
+
+fn box_circle(a: Vec2f, a_half: Vec2f, b: Vec2f, radius: f32) -> Option<(Vec2f, f32)> {
+    let d = b - a;
+    let closest = Vec2f::new([d.x.clamp(-a_half.x, a_half.x), d.y.clamp(-a_half.y, a_half.y)]);
+    // Center inside the box, push out along the nearest face.
+    if closest.x == d.x && closest.y == d.y {
+        let (normal, depth) = box_box(a, a_half, b, Vec2f::new([0.0, 0.0]))?;
+        return Some((normal, depth + radius));
+    }
+    let (normal, depth) = circle_circle(a + closest, 0.0, b, radius)?;
+    Some((normal, depth))
+}
+
+fn collide(a: &Body, b: &Body) -> Option<(Vec2f, f32)> {
+    let (a_center, b_center) = (a.center(), b.center());
+    let (a_half, b_half) = (a.half_extents(), b.half_extents());
+    match (a.collider.shape, b.collider.shape) {
+        (Shape2d::Box { .. }, Shape2d::Box { .. }) => box_box(a_center, a_half, b_center, b_half),
+        (Shape2d::Circle { .. }, Shape2d::Circle { .. }) => circle_circle(a_center, a_half.x, b_center, b_half.x),
+        (Shape2d::Box { .. }, Shape2d::Circle { .. }) => box_circle(a_center, a_half, b_center, b_half.x),
+        (Shape2d::Circle { .. }, Shape2d::Box { .. }) => {
+            box_circle(b_center, b_half, a_center, a_half.x).map(|(normal, depth)| (normal * -1.0, depth))
+        }
+    }
+}
+
+fn step(physics: &mut Physics2d, bodies: &mut [Body], broad_phase: &mut Bvh, rigid_bodies: &mut [Option<RigidBody2d>], dt: f32) {
+    for body in bodies.iter_mut() {
+        let Some(rigid_body) = rigid_bodies.get_mut(body.entity).and_then(|x| x.as_mut()) else {
+            continue;
+        };
+        if rigid_body.body_type == BodyType::Dynamic {
+            let acceleration = physics.gravity * rigid_body.gravity_scale + rigid_body.force * (1.0 / rigid_body.mass);
+            body.velocity = (body.velocity + acceleration * dt) * (1.0 / (1.0 + rigid_body.linear_damping * dt));
+        }
+        rigid_body.force = Vec2f::new([0.0, 0.0]);
+        body.position += body.velocity * dt;
+    }
+
+    // Only bodies whose bounds overlap are tested, each pair once.
+    broad_phase.refit(&bodies.iter().map(Body::bounds).collect::<Vec<_>>());
+    physics.contacts.clear();
+    let mut candidates = Vec::new();
+    for a in 0..bodies.len() {
+        candidates.clear();
+        broad_phase.traverse_aabb(&bodies[a].bounds(), |b| {
+            if b > a {
+                candidates.push(b);
+            }
+        });
+        candidates.sort_unstable();
+        for &b in candidates.iter() {
+            let inverse_mass = bodies[a].inverse_mass + bodies[b].inverse_mass;
+            let sensor = bodies[a].collider.sensor || bodies[b].collider.sensor;
+            if inverse_mass == 0.0 && !sensor {
+                continue;
+            }
+            let Some((normal, depth)) = collide(&bodies[a], &bodies[b]) else {
+                continue;
+            };
+            physics.contacts.push(Contact2d {
+                a: bodies[a].entity,
+                b: bodies[b].entity,
+                normal,
+                depth,
+                sensor,
+            });
+            if sensor || inverse_mass == 0.0 {
+                continue;
+            }
+
+            // Push apart in proportion to the inverse masses, then remove the closing velocity.
+            let correction = normal * (depth / inverse_mass);
+            bodies[a].position -= correction * bodies[a].inverse_mass;
+            bodies[b].position += correction * bodies[b].inverse_mass;
+
+            let relative = bodies[b].velocity - bodies[a].velocity;
+            let closing = relative.x * normal.x + relative.y * normal.y;
+            if closing >= 0.0 {
+                continue;
+            }
+            let restitution = bodies[a].collider.restitution.max(bodies[b].collider.restitution);
+            let impulse = -(1.0 + restitution) * closing / inverse_mass;
+            let tangent = relative - normal * closing;
+            let tangent_speed = (tangent.x * tangent.x + tangent.y * tangent.y).sqrt();
+            let friction = (bodies[a].collider.friction * bodies[b].collider.friction).sqrt();
+            // Coulomb friction, never more than stops the sliding.
+            let friction_impulse = if tangent_speed > f32::EPSILON {
+                tangent * (-(friction * impulse).min(tangent_speed / inverse_mass) / tangent_speed)
+            } else {
+                Vec2f::new([0.0, 0.0])
+            };
+            let total = normal * impulse + friction_impulse;
+            bodies[a].velocity -= total * bodies[a].inverse_mass;
+            bodies[b].velocity += total * bodies[b].inverse_mass;
+        }
+    }
+}
+
+pub struct Physics2dUpdater {}
+
+impl System for Physics2dUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let physics = &mut state.physics2d;
+        if !physics.enabled {
+            return;
+        }
+        let Some(colliders) = world.borrow_component_vec_mut::<Collider2d>() else {
+            return;
+        };
+        let mut transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+        let mut rigid_bodies = world.borrow_component_vec_mut::<RigidBody2d>();
+
+        physics.accumulator += state.delta_time;
+        let steps = ((physics.accumulator / physics.timestep) as u32).min(physics.max_steps);
+        physics.accumulator = (physics.accumulator - steps as f64 * physics.timestep).min(physics.timestep);
+        if steps == 0 {
+            return;
+        }
+
+        // Transforms are read every frame, so moving an entity by hand teleports its body.
+        let mut bodies: Vec<Body> = colliders
+            .iter()
+            .enumerate()
+            .filter_map(|(entity, collider)| {
+                let transform = transforms.get(entity)?.as_ref()?;
+                let rigid_body = rigid_bodies.as_ref().and_then(|x| x.get(entity)?.as_ref());
+                Some(Body {
+                    entity,
+                    position: Vec2f::new([transform.position.x as f32, transform.position.y as f32]),
+                    collider: (*collider)?,
+                    scale: Vec2f::new([transform.scale.x.abs(), transform.scale.y.abs()]),
+                    inverse_mass: rigid_body.map_or(0.0, |x| x.inverse_mass()),
+                    velocity: rigid_body.map_or(Vec2f::new([0.0, 0.0]), |x| x.velocity),
+                })
+            })
+            .collect();
+        // Built once per frame, the steps only refit it since bodies move little in between.
+        let mut broad_phase = Bvh::build(&bodies.iter().map(Body::bounds).collect::<Vec<_>>());
+        let mut no_bodies = Vec::new();
+        let rigid_body_slots = rigid_bodies.as_deref_mut().unwrap_or(&mut no_bodies);
+        for _ in 0..steps {
+            step(physics, &mut bodies, &mut broad_phase, rigid_body_slots, physics.timestep as f32);
+        }
+
+        for body in bodies.iter() {
+            let Some(rigid_body) = rigid_body_slots.get_mut(body.entity).and_then(|x| x.as_mut()) else {
+                continue;
+            };
+            rigid_body.velocity = body.velocity;
+            let transform = transforms[body.entity].as_mut().unwrap();
+            transform.position = Vec3d::new([body.position.x as f64, body.position.y as f64, transform.position.z]);
+            world.mark_changed::<Transform>(body.entity);
+        }
+    }
+}
+
+// Spawns static box colliders for the solid tiles of a tilemap spawned at `position`, see
+// `Tilemap::collision_rects`.
+pub fn spawn_tilemap_colliders(world: &mut World, tilemap: &Tilemap, position: Vec3d) -> Vec<usize> {
+    tilemap
+        .collision_rects()
+        .into_iter()
+        .map(|rect| {
+            let entity = world.new_entity();
+            let center = (rect.min + rect.max) * 0.5;
+            world.add_component(entity, Transform::new(
+                position + Vec3d::new([center.x as f64, center.y as f64, 0.0]),
+                Vec3f::new([1.0, 1.0, 1.0]),
+                Vec3f::new([0.0, 0.0, 0.0]),
+            ));
+            world.add_component(entity, Collider2d::rect(rect.max.x - rect.min.x, rect.max.y - rect.min.y));
+            entity
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_resting_on_static_ground_stays_put() {
+        let body = |entity: usize, y: f32, collider: Collider2d, inverse_mass: f32| Body {
+            entity,
+            position: Vec2f::new([0.0, y]),
+            collider,
+            scale: Vec2f::new([1.0, 1.0]),
+            inverse_mass,
+            velocity: Vec2f::new([0.0, 0.0]),
+        };
+        let mut bodies = vec![body(0, 0.0, Collider2d::rect(20.0, 1.0), 0.0), body(1, 1.0, Collider2d::rect(1.0, 1.0), 1.0)];
+        let mut rigid_bodies = vec![None, Some(RigidBody2d::dynamic(1.0))];
+        let mut physics = Physics2d::new();
+        let mut broad_phase = Bvh::build(&bodies.iter().map(Body::bounds).collect::<Vec<_>>());
+        let dt = physics.timestep as f32;
+
+        for _ in 0..600 {
+            step(&mut physics, &mut bodies, &mut broad_phase, &mut rigid_bodies, dt);
+        }
+        let resting = bodies[1];
+        assert!((resting.position.y - 1.0).abs() < 0.01, "box moved to y {}", resting.position.y);
+        assert!(resting.position.x.abs() < 1e-5, "box slid to x {}", resting.position.x);
+        assert!(resting.velocity.y.abs() < 0.5, "box still moving at {}", resting.velocity.y);
+        assert_eq!(bodies[0].position.y, 0.0);
+        assert_eq!(physics.contacts.len(), 1);
+    }
+}
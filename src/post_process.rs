@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, BlitImageInfo, CommandBufferUsage, CopyBufferToImageInfo, CopyImageInfo,
+    PrimaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::{Format, NumericFormat};
+use vulkano::image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo};
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::compute::ComputePipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo};
+use vulkano::sync::{self, GpuFuture};
+
+use crate::asset_library::AssetLibrary;
+use crate::builtin_shaders;
+use crate::ecs::World;
+use crate::rendering::VPData;
+use crate::state::State;
+use crate::types::buffers::UpdatableBuffer;
+use crate::types::camera::{Camera, LensEffects};
+use crate::types::color_lut::ColorLut;
+use crate::types::matrices::Matrix4f;
+use crate::types::vectors::Vec2f;
+
+pub const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+pub const WORKGROUP_SIZE: u32 = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PostEffect {
+    DepthOfField {
+        shader: String,
+        focus_distance: f32,
+        focus_range: f32,
+        max_radius: f32,
+    },
+    MotionBlur {
+        shader: String,
+        strength: f32,
+        samples: u32,
+    },
+    /// Looks colors up in `ColorLut::load(lut)`, bound at binding 4. Goes after tonemapping, the
+    /// table is indexed with sRGB encoded colors like most authored LUTs expect.
+    ColorGrading {
+        shader: String,
+        lut: String,
+        strength: f32,
+    },
+    /// Vignette, chromatic aberration and film grain in one pass, parameterized by the
+    /// `LensEffects` of the rendering camera. Does nothing for cameras without one.
+    Lens {
+        shader: String,
+    },
+    Custom {
+        shader: String,
+        params: [f32; 4],
+    },
+}
+
+impl PostEffect {
+    pub fn depth_of_field(focus_distance: f32, focus_range: f32) -> PostEffect {
+        PostEffect::DepthOfField {
+            shader: "depth_of_field".to_string(),
+            focus_distance,
+            focus_range,
+            max_radius: 8.0,
+        }
+    }
+
+    pub fn motion_blur(strength: f32) -> PostEffect {
+        PostEffect::MotionBlur {
+            shader: "motion_blur".to_string(),
+            strength,
+            samples: 8,
+        }
+    }
+
+    // Uses the built-in shader, see `builtin_shaders::load`.
+    pub fn color_grading(lut: &str) -> PostEffect {
+        PostEffect::ColorGrading {
+            shader: builtin_shaders::COLOR_GRADING_CS.to_string(),
+            lut: lut.to_string(),
+            strength: 1.0,
+        }
+    }
+
+    // Uses the built-in shader, see `builtin_shaders::load`.
+    pub fn lens() -> PostEffect {
+        PostEffect::Lens {
+            shader: builtin_shaders::LENS_CS.to_string(),
+        }
+    }
+
+    pub fn shader(&self) -> &str {
+        match self {
+            PostEffect::DepthOfField { shader, .. } => shader,
+            PostEffect::MotionBlur { shader, .. } => shader,
+            PostEffect::ColorGrading { shader, .. } => shader,
+            PostEffect::Lens { shader } => shader,
+            PostEffect::Custom { shader, .. } => shader,
+        }
+    }
+
+    pub fn params(&self) -> [f32; 4] {
+        match self {
+            PostEffect::DepthOfField { focus_distance, focus_range, max_radius, .. } => {
+                [*focus_distance, *focus_range, *max_radius, 0.0]
+            }
+            PostEffect::MotionBlur { strength, samples, .. } => [*strength, *samples as f32, 0.0, 0.0],
+            PostEffect::ColorGrading { strength, .. } => [*strength, 0.0, 0.0, 0.0],
+            PostEffect::Lens { .. } => [0.0; 4],
+            PostEffect::Custom { params, .. } => *params,
+        }
+    }
+}
+
+#[derive(Pod, Zeroable, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct PostData {
+    pub view: Matrix4f,
+    pub projection: Matrix4f,
+    pub previous_view: Matrix4f,
+    pub previous_projection: Matrix4f,
+    pub params: [f32; 4],
+    pub resolution: Vec2f,
+    pub delta_time: f32,
+    /// Seconds since start, wraps every hour to keep precision for animated noise.
+    pub time: f32,
+}
+
+#[derive(Clone, Default)]
+pub struct PostProcessing {
+    pub scene: Option<Arc<ImageView>>,
+    pub depth: Option<Arc<ImageView>>,
+    targets: Vec<Arc<ImageView>>,
+    sampler: Option<Arc<Sampler>>,
+    depth_sampler: Option<Arc<Sampler>>,
+    pipelines: HashMap<String, Arc<ComputePipeline>>,
+    buffers: Vec<UpdatableBuffer<PostData>>,
+    previous_vp: Option<VPData>,
+    gamma_target: Option<Arc<Image>>,
+    // Kept after a swap so switching back does not reload the file.
+    luts: HashMap<String, Arc<ImageView>>,
+}
+
+impl PostProcessing {
+    pub(crate) fn images(&self) -> Vec<Arc<Image>> {
+        self.scene
+            .iter()
+            .chain(self.depth.iter())
+            .chain(self.targets.iter())
+            .map(|x| x.image().clone())
+            .chain(self.gamma_target.iter().cloned())
+            .chain(self.luts.values().map(|x| x.image().clone()))
+            .collect()
+    }
+
+    pub(crate) fn uniform_buffers(&self) -> Vec<Arc<Buffer>> {
+        self.buffers.iter().flat_map(|x| x.buffers.iter().map(|x| x.buffer().clone())).collect()
+    }
+}
+
+fn create_target(state: &State, extent: [u32; 3], usage: ImageUsage) -> Arc<ImageView> {
+    ImageView::new_default(
+        Image::new(
+            state.renderer.memeory_allocator.as_ref().unwrap().clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: HDR_FORMAT,
+                extent,
+                usage: usage | ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+fn srgb_counterpart(format: Format) -> Option<Format> {
+    match format {
+        Format::B8G8R8A8_UNORM => Some(Format::B8G8R8A8_SRGB),
+        Format::R8G8B8A8_UNORM => Some(Format::R8G8B8A8_SRGB),
+        Format::A8B8G8R8_UNORM_PACK32 => Some(Format::A8B8G8R8_SRGB_PACK32),
+        _ => None,
+    }
+}
+
+// Blitting into an sRGB image encodes the linear scene, and copying that into a UNORM swapchain
+// image keeps the encoded bytes, so gamma is applied even without an sRGB surface format.
+fn create_gamma_target(state: &State, extent: [u32; 3]) -> Option<Arc<Image>> {
+    let format = state.renderer.swapchain.as_ref().unwrap().image_format();
+    if format.numeric_format_color() == Some(NumericFormat::SRGB) {
+        return None;
+    }
+    let Some(srgb_format) = srgb_counterpart(format) else {
+        log::warn!("Swapchain format {:?} has no sRGB counterpart, output is not gamma corrected", format);
+        return None;
+    };
+    Some(
+        Image::new(
+            state.renderer.memeory_allocator.as_ref().unwrap().clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: srgb_format,
+                extent,
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap(),
+    )
+}
+
+pub fn init(state: &mut State) {
+    let create_sampler = |filter: Filter| {
+        Sampler::new(
+            state.renderer.device.as_ref().unwrap().clone(),
+            SamplerCreateInfo {
+                mag_filter: filter,
+                min_filter: filter,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    state.renderer.post.sampler = Some(create_sampler(Filter::Linear));
+    state.renderer.post.depth_sampler = Some(create_sampler(Filter::Nearest));
+}
+
+pub fn create_targets(state: &mut State, extent: [u32; 3], depth: Arc<ImageView>) {
+    state.renderer.post.scene = Some(create_target(state, extent, ImageUsage::COLOR_ATTACHMENT));
+    state.renderer.post.targets = (0..2)
+        .map(|_| create_target(state, extent, ImageUsage::STORAGE))
+        .collect();
+    state.renderer.post.depth = Some(depth);
+    state.renderer.post.gamma_target = create_gamma_target(state, extent);
+}
+
+fn create_pipeline(state: &State, assets: &AssetLibrary, name: &str) -> Arc<ComputePipeline> {
+    let device = state.renderer.device.as_ref().unwrap().clone();
+    let shader = assets
+        .shaders
+        .iter()
+        .find(|x| x.name == name)
+        .unwrap_or_else(|| panic!("post effect shader {name} not loaded"));
+    let stage = PipelineShaderStageCreateInfo::new(shader.module.as_ref().unwrap().entry_point("main").unwrap());
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+
+    ComputePipeline::new(device, None, ComputePipelineCreateInfo::stage_layout(stage, layout)).unwrap()
+}
+
+fn upload_lut(state: &State, lut: &ColorLut) -> Arc<ImageView> {
+    let renderer = &state.renderer;
+    let image = Image::new(
+        renderer.memeory_allocator.as_ref().unwrap().clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim3d,
+            format: Format::A2B10G10R10_UNORM_PACK32,
+            extent: [lut.size; 3],
+            usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    let staging = Buffer::from_iter(
+        renderer.memeory_allocator.as_ref().unwrap().clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        lut.packed(),
+    )
+    .unwrap();
+
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(renderer.device.as_ref().unwrap().clone(), Default::default());
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &command_buffer_allocator,
+        renderer.queue.as_ref().unwrap().queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(staging, image.clone()))
+        .unwrap();
+    sync::now(renderer.device.as_ref().unwrap().clone())
+        .then_execute(renderer.queue.as_ref().unwrap().clone(), builder.build().unwrap())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+    ImageView::new_default(image).unwrap()
+}
+
+pub fn prepare(assets: &AssetLibrary, state: &mut State) {
+    let effects = state.renderer.settings.post_effects.clone();
+    for effect in effects.iter() {
+        if !state.renderer.post.pipelines.contains_key(effect.shader()) {
+            let pipeline = create_pipeline(state, assets, effect.shader());
+            state.renderer.post.pipelines.insert(effect.shader().to_string(), pipeline);
+        }
+        if let PostEffect::ColorGrading { lut, .. } = effect {
+            if !state.renderer.post.luts.contains_key(lut) {
+                let view = match ColorLut::load(lut) {
+                    Ok(table) => upload_lut(state, &table),
+                    Err(e) => {
+                        log::error!("failed to load LUT: {e}");
+                        upload_lut(state, &ColorLut::identity(2))
+                    }
+                };
+                state.renderer.post.luts.insert(lut.clone(), view);
+            }
+        }
+    }
+    while state.renderer.post.buffers.len() < effects.len() {
+        let buffer = UpdatableBuffer::new(&state.renderer, BufferUsage::UNIFORM_BUFFER);
+        state.renderer.post.buffers.push(buffer);
+    }
+}
+
+// The first camera's, the one `CameraUpdater` renders with.
+fn lens_params(world: &World) -> [f32; 4] {
+    let (Some(cameras), Some(lenses)) = (world.borrow_component_vec_mut::<Camera>(), world.borrow_component_vec_mut::<LensEffects>()) else {
+        return [0.0; 4];
+    };
+    let Some(camera) = cameras.iter().position(|x| x.is_some()) else {
+        return [0.0; 4];
+    };
+    lenses.get(camera).copied().flatten().map_or([0.0; 4], |x| x.to_params())
+}
+
+pub fn write_post_data(world: &World, state: &mut State) {
+    let vp_data = state.renderer.vp_data;
+    let previous_vp = state.renderer.post.previous_vp.unwrap_or(vp_data);
+    let extent = state.renderer.viewport.as_ref().unwrap().extent;
+
+    let effects = &state.renderer.settings.post_effects;
+    for (effect, buffer) in effects.iter().zip(state.renderer.post.buffers.iter()) {
+        buffer.write(
+            state,
+            PostData {
+                view: vp_data.view,
+                projection: vp_data.projection,
+                previous_view: previous_vp.view,
+                previous_projection: previous_vp.projection,
+                params: match effect {
+                    PostEffect::ColorGrading { lut, .. } => {
+                        let size = state.renderer.post.luts.get(lut).map_or(2, |x| x.image().extent()[0]);
+                        [effect.params()[0], size as f32, 0.0, 0.0]
+                    }
+                    PostEffect::Lens { .. } => lens_params(world),
+                    _ => effect.params(),
+                },
+                resolution: Vec2f::new(extent),
+                delta_time: state.delta_time as f32,
+                time: (state.time % 3600.0) as f32,
+            },
+        );
+    }
+    state.renderer.post.previous_vp = Some(vp_data);
+}
+
+pub fn record(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    state: &State,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    frame_i: usize,
+    output: Arc<Image>,
+) {
+    let post = &state.renderer.post;
+    let mut input = post.scene.as_ref().unwrap().clone();
+    let [width, height, _] = input.image().extent();
+
+    for (i, effect) in state.renderer.settings.post_effects.iter().enumerate() {
+        let pipeline = post.pipelines.get(effect.shader()).unwrap().clone();
+        let layout = pipeline.layout().set_layouts().first().unwrap().clone();
+        let target = post.targets[i % 2].clone();
+
+        let mut writes = vec![
+            WriteDescriptorSet::image_view_sampler(0, input.clone(), post.sampler.as_ref().unwrap().clone()),
+            WriteDescriptorSet::image_view(2, target.clone()),
+        ];
+        if layout.bindings().contains_key(&1) {
+            writes.push(WriteDescriptorSet::image_view_sampler(
+                1,
+                post.depth.as_ref().unwrap().clone(),
+                post.depth_sampler.as_ref().unwrap().clone(),
+            ));
+        }
+        if layout.bindings().contains_key(&3) {
+            writes.push(WriteDescriptorSet::buffer(3, post.buffers[i].buffer(frame_i)));
+        }
+        if let (PostEffect::ColorGrading { lut, .. }, true) = (effect, layout.bindings().contains_key(&4)) {
+            writes.push(WriteDescriptorSet::image_view_sampler(
+                4,
+                post.luts.get(lut).unwrap().clone(),
+                post.sampler.as_ref().unwrap().clone(),
+            ));
+        }
+        let set = PersistentDescriptorSet::new(descriptor_set_allocator, layout, writes, []).unwrap();
+
+        builder
+            .bind_pipeline_compute(pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline.layout().clone(), 0, set)
+            .unwrap()
+            .dispatch([width.div_ceil(WORKGROUP_SIZE), height.div_ceil(WORKGROUP_SIZE), 1])
+            .unwrap();
+        input = target;
+    }
+
+    match post.gamma_target.as_ref() {
+        Some(gamma_target) => {
+            builder
+                .blit_image(BlitImageInfo {
+                    filter: Filter::Nearest,
+                    ..BlitImageInfo::images(input.image().clone(), gamma_target.clone())
+                })
+                .unwrap()
+                .copy_image(CopyImageInfo::images(gamma_target.clone(), output))
+                .unwrap();
+        }
+        None => {
+            builder
+                .blit_image(BlitImageInfo {
+                    filter: Filter::Nearest,
+                    ..BlitImageInfo::images(input.image().clone(), output)
+                })
+                .unwrap();
+        }
+    }
+}
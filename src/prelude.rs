@@ -0,0 +1,27 @@
+// Everything a typical game needs, `use simple_engine::prelude::*;`. Subsystems like streaming,
+// networking or the UI are used through their modules.
+
+pub use crate::asset_library::AssetLibrary;
+pub use crate::builtin_shaders;
+pub use crate::ecs::{Added, Bundle, Changed, Component, System, World};
+pub use crate::input::InputManager;
+pub use crate::rendering::{CursorImage, DepthMode, RendererSettings, VertexData, WindowSettings};
+pub use crate::state::State;
+pub use crate::types::bundles::{CameraBundle, DynamicMeshBundle, LightBundle, MeshBundle};
+pub use crate::types::camera::{Camera, LensEffects};
+pub use crate::types::color::Color;
+pub use crate::types::light::PointLight;
+pub use crate::types::material::{Attachment, BlendMode, Material, RenderQueue, RenderState};
+pub use crate::types::material_override::MaterialOverride;
+pub use crate::types::matrices::Matrix4f;
+pub use crate::types::mesh::{DynamicMesh, Mesh};
+pub use crate::types::quaternion::Quaternion;
+pub use crate::types::shader::{Shader, ShaderType};
+pub use crate::types::static_mesh::StaticMesh;
+pub use crate::types::texture::Texture;
+pub use crate::types::transform::Transform;
+pub use crate::types::vectors::{Vec2d, Vec2f, Vec3d, Vec3f};
+pub use crate::types::visibility::Visibility;
+pub use crate::{run, run_with_settings, Reflect};
+// The derive macro, next to the trait of the same name.
+pub use simple_engine_derive::Component;
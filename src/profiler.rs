@@ -0,0 +1,220 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    mem,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+
+use crate::memory_stats::MemoryReport;
+use crate::rendering::{MaterialStats, RenderStats};
+
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub name: String,
+    pub depth: usize,
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+// A frame kept in `Profiler::history`, with the renderer's counters as they were at its end.
+#[derive(Clone, Debug)]
+pub struct FrameRecord {
+    pub frame: u64,
+    pub delta_time: f64,
+    pub gpu_time: Option<Duration>,
+    pub spans: Vec<Span>,
+    pub draw_calls: usize,
+    pub triangles: usize,
+    /// Device memory allocations and bytes in use over all heaps, from the latest memory report.
+    pub allocations: usize,
+    pub allocated_bytes: u64,
+    pub materials: Vec<(String, MaterialStats)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Profiler {
+    pub enabled: bool,
+    pub last_frame: Vec<Span>,
+    /// Frames kept for `save_history`.
+    pub history_len: usize,
+    history: VecDeque<FrameRecord>,
+    frame: u64,
+    epoch: Instant,
+    open: Vec<(String, Instant)>,
+    spans: Vec<Span>,
+    capture: Option<Vec<Span>>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            enabled: true,
+            last_frame: Vec::new(),
+            history_len: 300,
+            history: VecDeque::new(),
+            frame: 0,
+            epoch: Instant::now(),
+            open: Vec::new(),
+            spans: Vec::new(),
+            capture: None,
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.open.clear();
+        self.spans.clear();
+    }
+
+    pub fn end_frame(&mut self) {
+        while !self.open.is_empty() {
+            self.end_span();
+        }
+        if let Some(capture) = self.capture.as_mut() {
+            capture.extend(self.spans.iter().cloned());
+        }
+        self.last_frame = mem::take(&mut self.spans);
+    }
+
+    pub fn begin_span(&mut self, name: &str) {
+        if self.enabled {
+            self.open.push((name.to_string(), Instant::now()));
+        }
+    }
+
+    pub fn end_span(&mut self) {
+        let Some((name, start)) = self.open.pop() else {
+            return;
+        };
+        self.spans.push(Span {
+            name,
+            depth: self.open.len(),
+            start: start - self.epoch,
+            duration: start.elapsed(),
+        });
+    }
+
+    pub fn record_frame(&mut self, delta_time: f64, stats: &RenderStats, memory: Option<&MemoryReport>) {
+        self.frame += 1;
+        if !self.enabled || self.history_len == 0 {
+            return;
+        }
+        let mut materials: Vec<(String, MaterialStats)> =
+            stats.materials.iter().map(|(name, x)| (name.clone(), x.clone())).collect();
+        materials.sort_by(|a, b| a.0.cmp(&b.0));
+        self.history.push_back(FrameRecord {
+            frame: self.frame,
+            delta_time,
+            gpu_time: stats.gpu_time,
+            spans: self.last_frame.clone(),
+            draw_calls: stats.draw_calls,
+            triangles: stats.triangles,
+            allocations: memory.map_or(0, |x| x.heaps.iter().map(|x| x.allocations).sum()),
+            allocated_bytes: memory.map_or(0, |x| x.total()),
+            materials,
+        });
+        while self.history.len() > self.history_len {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &FrameRecord> {
+        self.history.iter()
+    }
+
+    // Writes the last `frames` recorded frames as JSON, spans nested under the span they ran in.
+    pub fn save_history(&self, frames: usize, path: &str) -> Result<(), String> {
+        let frames: Vec<Value> = self
+            .history
+            .iter()
+            .skip(self.history.len().saturating_sub(frames))
+            .map(|frame| {
+                let materials: serde_json::Map<String, Value> = frame
+                    .materials
+                    .iter()
+                    .map(|(name, x)| {
+                        (name.clone(), json!({
+                            "submitted": x.submitted,
+                            "culled": x.culled,
+                            "occluded": x.occluded,
+                            "triangles": x.triangles,
+                            "uploaded_bytes": x.uploaded_bytes,
+                        }))
+                    })
+                    .collect();
+                json!({
+                    "frame": frame.frame,
+                    "cpu_ms": frame.delta_time * 1000.0,
+                    "gpu_ms": frame.gpu_time.map(|x| x.as_secs_f64() * 1000.0),
+                    "draw_calls": frame.draw_calls,
+                    "triangles": frame.triangles,
+                    "allocations": frame.allocations,
+                    "allocated_bytes": frame.allocated_bytes,
+                    "spans": span_tree(&frame.spans),
+                    "materials": materials,
+                })
+            })
+            .collect();
+        let file = File::create(path).map_err(|e| format!("{path}: {e}"))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &json!({ "frames": frames }))
+            .map_err(|e| format!("{path}: {e}"))
+    }
+
+    pub fn system_timings(&self) -> impl Iterator<Item = (&str, Duration)> {
+        self.last_frame
+            .iter()
+            .filter(|x| x.depth == 0)
+            .map(|x| (x.name.as_str(), x.duration))
+    }
+
+    pub fn start_capture(&mut self) {
+        self.capture = Some(Vec::new());
+    }
+
+    pub fn stop_capture(&mut self) -> Vec<Span> {
+        self.capture.take().unwrap_or_default()
+    }
+
+    pub fn save_chrome_trace(spans: &[Span], path: &str) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "[")?;
+        for (i, span) in spans.iter().enumerate() {
+            let separator = if i + 1 < spans.len() { "," } else { "" };
+            writeln!(
+                file,
+                "{{\"name\":\"{}\",\"ph\":\"X\",\"pid\":0,\"tid\":0,\"ts\":{},\"dur\":{}}}{}",
+                span.name.replace('\\', "\\\\").replace('"', "\\\""),
+                span.start.as_micros(),
+                span.duration.as_micros(),
+                separator,
+            )?;
+        }
+        writeln!(file, "]")?;
+        file.flush()
+    }
+}
+
+// Spans end children first, so every span adopts the deeper ones pending before it.
+fn span_tree(spans: &[Span]) -> Vec<Value> {
+    let mut pending: Vec<(usize, Value)> = Vec::new();
+    for span in spans.iter() {
+        let first_child = pending.iter().position(|x| x.0 > span.depth).unwrap_or(pending.len());
+        let mut children: Vec<Value> = pending.drain(first_child..).map(|x| x.1).collect();
+        children.sort_by(|a, b| a["start_ms"].as_f64().unwrap().total_cmp(&b["start_ms"].as_f64().unwrap()));
+        pending.push((span.depth, json!({
+            "name": span.name,
+            "start_ms": span.start.as_secs_f64() * 1000.0,
+            "ms": span.duration.as_secs_f64() * 1000.0,
+            "children": children,
+        })));
+    }
+    pending.into_iter().map(|x| x.1).collect()
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
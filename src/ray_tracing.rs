@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::Arc;
+
+use smallvec::smallvec;
+use vulkano::acceleration_structure::{
+    AccelerationStructure, AccelerationStructureBuildGeometryInfo, AccelerationStructureBuildRangeInfo,
+    AccelerationStructureBuildType, AccelerationStructureCreateInfo, AccelerationStructureGeometries,
+    AccelerationStructureGeometryInstancesData, AccelerationStructureGeometryInstancesDataType,
+    AccelerationStructureGeometryTrianglesData, AccelerationStructureInstance, AccelerationStructureType,
+    BuildAccelerationStructureFlags, BuildAccelerationStructureMode, GeometryFlags,
+};
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, IndexBuffer, Subbuffer};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer};
+use vulkano::descriptor_set::layout::DescriptorSetLayout;
+use vulkano::descriptor_set::WriteDescriptorSet;
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::{DeviceExtensions, Features};
+use vulkano::format::Format;
+use vulkano::memory::allocator::{AllocationCreateInfo, DeviceLayout, MemoryTypeFilter};
+use vulkano::sync::{self, GpuFuture};
+use vulkano::{DeviceSize, Version};
+
+use crate::asset_library::AssetLibrary;
+use crate::builtin_shaders;
+use crate::ecs::World;
+use crate::rendering::{Renderer, VertexData};
+use crate::state::State;
+use crate::types::matrices::Matrix4f;
+use crate::types::mesh::Mesh;
+use crate::types::shader::Shader;
+use crate::types::static_mesh::StaticMesh;
+use crate::types::transform::Transform;
+
+// Frame set binding of the scene's top-level acceleration structure.
+pub const TLAS_BINDING: u32 = 8;
+
+// Ray queried shadows for the built-in lit shader, see `RendererSettings::ray_traced_shadows`. Only
+// static meshes are in the scene, rebuilt whenever the command buffers are.
+#[derive(Clone, Default)]
+pub struct RayTracing {
+    pub enabled: bool,
+    blases: HashMap<String, Arc<AccelerationStructure>>,
+    tlas: Option<Arc<AccelerationStructure>>,
+    // Instance data and scratch memory, kept alive until the next build.
+    build_buffers: Vec<Subbuffer<[u8]>>,
+    /// `builtin_shaders::LIT_FS` with ray queried shadows, swapped in for mesh pipelines.
+    pub(crate) lit_shader: Option<Arc<Shader>>,
+}
+
+pub(crate) fn supported(physical_device: &PhysicalDevice) -> bool {
+    let extensions = physical_device.supported_extensions();
+    let features = physical_device.supported_features();
+    physical_device.api_version() >= Version::V1_2
+        && extensions.khr_acceleration_structure
+        && extensions.khr_ray_query
+        && extensions.khr_deferred_host_operations
+        && features.acceleration_structure
+        && features.ray_query
+        && features.buffer_device_address
+}
+
+pub(crate) fn device_extensions(enabled: bool) -> DeviceExtensions {
+    DeviceExtensions {
+        khr_acceleration_structure: enabled,
+        khr_ray_query: enabled,
+        khr_deferred_host_operations: enabled,
+        ..Default::default()
+    }
+}
+
+pub(crate) fn device_features(enabled: bool) -> Features {
+    Features {
+        acceleration_structure: enabled,
+        ray_query: enabled,
+        ..Features::empty()
+    }
+}
+
+// Mesh buffers need these to be read by acceleration structure builds.
+pub(crate) fn mesh_buffer_usage(renderer: &Renderer) -> BufferUsage {
+    if renderer.ray_tracing.enabled {
+        BufferUsage::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY | BufferUsage::SHADER_DEVICE_ADDRESS
+    } else {
+        BufferUsage::empty()
+    }
+}
+
+// Creates the ray query shader, turning ray tracing off if it is unavailable.
+pub(crate) fn init(state: &mut State) {
+    if !state.renderer.ray_tracing.enabled {
+        return;
+    }
+    match builtin_shaders::lit_ray_query() {
+        Ok(mut shader) => {
+            shader.load(&mut state.renderer);
+            state.renderer.ray_tracing.lit_shader = Some(Arc::new(shader));
+        }
+        Err(e) => {
+            log::warn!("ray traced shadows unavailable, using shadow maps: {e}");
+            state.renderer.ray_tracing.enabled = false;
+        }
+    }
+}
+
+fn device_buffer(renderer: &Renderer, usage: BufferUsage, size: DeviceSize) -> Subbuffer<[u8]> {
+    Buffer::new_slice::<u8>(
+        renderer.memeory_allocator.as_ref().unwrap().clone(),
+        BufferCreateInfo {
+            usage: usage | BufferUsage::SHADER_DEVICE_ADDRESS,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+        size.max(1),
+    )
+    .unwrap()
+}
+
+// Buffers only guarantee 64 byte alignment, scratch memory often needs more.
+fn scratch_buffer(renderer: &Renderer, size: DeviceSize) -> Subbuffer<[u8]> {
+    let alignment = renderer
+        .physical_device()
+        .unwrap()
+        .properties()
+        .min_acceleration_structure_scratch_offset_alignment
+        .unwrap_or(1) as DeviceSize;
+    let buffer = Subbuffer::new(
+        Buffer::new(
+            renderer.memeory_allocator.as_ref().unwrap().clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::SHADER_DEVICE_ADDRESS,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            DeviceLayout::from_size_alignment(size + alignment, 1).unwrap(),
+        )
+        .unwrap(),
+    );
+    let address = buffer.device_address().unwrap().get();
+    let offset = (alignment - address % alignment) % alignment;
+    buffer.slice(offset..offset + size)
+}
+
+fn build(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    renderer: &mut Renderer,
+    ty: AccelerationStructureType,
+    geometries: AccelerationStructureGeometries,
+    primitive_count: u32,
+) -> Arc<AccelerationStructure> {
+    let device = renderer.device.as_ref().unwrap().clone();
+    let mut info = AccelerationStructureBuildGeometryInfo {
+        flags: BuildAccelerationStructureFlags::PREFER_FAST_TRACE,
+        mode: BuildAccelerationStructureMode::Build,
+        ..AccelerationStructureBuildGeometryInfo::new(geometries)
+    };
+    let sizes = device
+        .acceleration_structure_build_sizes(AccelerationStructureBuildType::Device, &info, &[primitive_count])
+        .unwrap();
+
+    let storage = device_buffer(renderer, BufferUsage::ACCELERATION_STRUCTURE_STORAGE, sizes.acceleration_structure_size);
+    let acceleration_structure = unsafe {
+        AccelerationStructure::new(
+            device,
+            AccelerationStructureCreateInfo {
+                ty,
+                ..AccelerationStructureCreateInfo::new(storage)
+            },
+        )
+    }
+    .unwrap();
+    let scratch = scratch_buffer(renderer, sizes.build_scratch_size);
+    renderer.ray_tracing.build_buffers.push(scratch.clone());
+
+    info.dst_acceleration_structure = Some(acceleration_structure.clone());
+    info.scratch_data = Some(scratch);
+    let range = AccelerationStructureBuildRangeInfo {
+        primitive_count,
+        primitive_offset: 0,
+        first_vertex: 0,
+        transform_offset: 0,
+    };
+    unsafe { builder.build_acceleration_structure(info, smallvec![range]) }.unwrap();
+    acceleration_structure
+}
+
+fn build_blas(builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, renderer: &mut Renderer, mesh: &Mesh) -> Arc<AccelerationStructure> {
+    let triangles = AccelerationStructureGeometryTrianglesData {
+        flags: GeometryFlags::OPAQUE,
+        vertex_data: Some(mesh.vertex_buffer.clone().unwrap().into_bytes()),
+        vertex_stride: size_of::<VertexData>() as u32,
+        max_vertex: mesh.vertices.len().saturating_sub(1) as u32,
+        index_data: Some(IndexBuffer::U32(mesh.index_buffer.clone().unwrap())),
+        ..AccelerationStructureGeometryTrianglesData::new(Format::R32G32B32_SFLOAT)
+    };
+    build(
+        builder,
+        renderer,
+        AccelerationStructureType::BottomLevel,
+        AccelerationStructureGeometries::Triangles(vec![triangles]),
+        (mesh.indices.len() / 3) as u32,
+    )
+}
+
+// Row-major 3x4, the bottom row of the model matrix is dropped.
+fn instance_transform(matrix: Matrix4f) -> [[f32; 4]; 3] {
+    let m = matrix.to_array();
+    [0, 1, 2].map(|row| [m[0][row], m[1][row], m[2][row], m[3][row]])
+}
+
+// Builds missing bottom-level structures and the top-level one over every static mesh entity.
+// Runs before the command buffers are recorded.
+pub(crate) fn prepare(world: &World, assets: &AssetLibrary, state: &mut State) {
+    if !state.renderer.ray_tracing.enabled {
+        return;
+    }
+    let (Some(static_meshes), Some(transforms)) = (
+        world.borrow_component_vec_mut::<StaticMesh>(),
+        world.borrow_component_vec_mut::<Transform>(),
+    ) else {
+        return;
+    };
+
+    let renderer = &mut state.renderer;
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(renderer.device.as_ref().unwrap().clone(), Default::default());
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &command_buffer_allocator,
+        renderer.queue.as_ref().unwrap().queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    renderer.ray_tracing.build_buffers.clear();
+
+    let mut instances = Vec::new();
+    for (static_mesh, transform) in static_meshes.iter().zip(transforms.iter()) {
+        let (Some(static_mesh), Some(transform)) = (static_mesh, transform) else {
+            continue;
+        };
+        let Some(mesh) = assets.meshes.iter().find(|x| x.name == static_mesh.mesh_name) else {
+            continue;
+        };
+        if mesh.indices.is_empty() || mesh.vertex_buffer.is_none() {
+            continue;
+        }
+        if !renderer.ray_tracing.blases.contains_key(&mesh.name) {
+            let blas = build_blas(&mut builder, renderer, mesh);
+            renderer.ray_tracing.blases.insert(mesh.name.clone(), blas);
+        }
+        let model = Matrix4f::compose(renderer.render_space(transform.position), transform.rotation, transform.scale);
+        instances.push(AccelerationStructureInstance {
+            transform: instance_transform(model),
+            acceleration_structure_reference: renderer.ray_tracing.blases[&mesh.name].device_address().get(),
+            ..Default::default()
+        });
+    }
+    // The bottom-level builds have to finish before the top-level one reads them.
+    if !renderer.ray_tracing.blases.is_empty() {
+        submit(renderer, builder);
+        builder = AutoCommandBufferBuilder::primary(
+            &command_buffer_allocator,
+            renderer.queue.as_ref().unwrap().queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+    }
+
+    let instance_count = instances.len() as u32;
+    // An empty scene still gets a structure, the shader binding must not be left unwritten.
+    if instances.is_empty() {
+        instances.push(AccelerationStructureInstance::default());
+    }
+    let instance_buffer = Buffer::from_iter(
+        renderer.memeory_allocator.as_ref().unwrap().clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY | BufferUsage::SHADER_DEVICE_ADDRESS,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        instances,
+    )
+    .unwrap();
+    renderer.ray_tracing.build_buffers.push(instance_buffer.clone().into_bytes());
+    let geometries = AccelerationStructureGeometries::Instances(AccelerationStructureGeometryInstancesData::new(
+        AccelerationStructureGeometryInstancesDataType::Values(Some(instance_buffer)),
+    ));
+    let tlas = build(&mut builder, renderer, AccelerationStructureType::TopLevel, geometries, instance_count);
+    submit(renderer, builder);
+    renderer.ray_tracing.tlas = Some(tlas);
+}
+
+fn submit(renderer: &Renderer, builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+    sync::now(renderer.device.as_ref().unwrap().clone())
+        .then_execute(renderer.queue.as_ref().unwrap().clone(), builder.build().unwrap())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+}
+
+pub(crate) fn descriptor_writes(state: &State, layout: &DescriptorSetLayout) -> Vec<WriteDescriptorSet> {
+    match state.renderer.ray_tracing.tlas.as_ref() {
+        Some(tlas) if layout.bindings().contains_key(&TLAS_BINDING) => {
+            vec![WriteDescriptorSet::acceleration_structure(TLAS_BINDING, tlas.clone())]
+        }
+        _ => Vec::new(),
+    }
+}
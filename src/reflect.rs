@@ -0,0 +1,165 @@
+use std::fmt;
+
+use crate::types::{
+    color::Color,
+    vectors::{Vec3d, Vec3f},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Vector([f64; 3]),
+    Color([f32; 4]),
+}
+
+impl FieldValue {
+    // Parses `text` as the same kind of value as `self`, e.g. "1 2 3" for vectors.
+    pub fn parse_like(&self, text: &str) -> Option<FieldValue> {
+        let text = text.trim();
+        let numbers = || -> Option<Vec<f64>> { text.split([' ', ',']).filter(|x| !x.is_empty()).map(|x| x.parse().ok()).collect() };
+        match self {
+            FieldValue::Bool(_) => text.parse().ok().map(FieldValue::Bool),
+            FieldValue::Int(_) => text.parse().ok().map(FieldValue::Int),
+            FieldValue::Float(_) => text.parse().ok().map(FieldValue::Float),
+            FieldValue::Text(_) => Some(FieldValue::Text(text.to_string())),
+            FieldValue::Vector(_) => match numbers()?.as_slice() {
+                [x, y, z] => Some(FieldValue::Vector([*x, *y, *z])),
+                _ => None,
+            },
+            FieldValue::Color(_) => match numbers()?.as_slice() {
+                [r, g, b] => Some(FieldValue::Color([*r as f32, *g as f32, *b as f32, 1.0])),
+                [r, g, b, a] => Some(FieldValue::Color([*r as f32, *g as f32, *b as f32, *a as f32])),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Bool(x) => write!(f, "{x}"),
+            FieldValue::Int(x) => write!(f, "{x}"),
+            FieldValue::Float(x) => write!(f, "{x:.3}"),
+            FieldValue::Text(x) => write!(f, "{x}"),
+            FieldValue::Vector([x, y, z]) => write!(f, "{x:.3} {y:.3} {z:.3}"),
+            FieldValue::Color([r, g, b, a]) => write!(f, "{r:.3} {g:.3} {b:.3} {a:.3}"),
+        }
+    }
+}
+
+// Field types a reflected component can expose.
+pub trait ReflectValue: Sized {
+    fn to_field(&self) -> FieldValue;
+    fn from_field(value: &FieldValue) -> Option<Self>;
+}
+
+impl ReflectValue for bool {
+    fn to_field(&self) -> FieldValue {
+        FieldValue::Bool(*self)
+    }
+    fn from_field(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Bool(x) => Some(*x),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! reflect_int {
+    ($($t:ty),+) => {$(
+        impl ReflectValue for $t {
+            fn to_field(&self) -> FieldValue {
+                FieldValue::Int(*self as i64)
+            }
+            fn from_field(value: &FieldValue) -> Option<Self> {
+                match value {
+                    FieldValue::Int(x) => (*x).try_into().ok(),
+                    _ => None,
+                }
+            }
+        }
+    )+};
+}
+
+reflect_int!(i32, i64, u32, u64, usize);
+
+macro_rules! reflect_float {
+    ($($t:ty),+) => {$(
+        impl ReflectValue for $t {
+            fn to_field(&self) -> FieldValue {
+                FieldValue::Float(*self as f64)
+            }
+            fn from_field(value: &FieldValue) -> Option<Self> {
+                match value {
+                    FieldValue::Float(x) => Some(*x as $t),
+                    FieldValue::Int(x) => Some(*x as $t),
+                    _ => None,
+                }
+            }
+        }
+    )+};
+}
+
+reflect_float!(f32, f64);
+
+impl ReflectValue for String {
+    fn to_field(&self) -> FieldValue {
+        FieldValue::Text(self.clone())
+    }
+    fn from_field(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Text(x) => Some(x.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl ReflectValue for Vec3f {
+    fn to_field(&self) -> FieldValue {
+        FieldValue::Vector([self.x as f64, self.y as f64, self.z as f64])
+    }
+    fn from_field(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Vector([x, y, z]) => Some(Vec3f::new([*x as f32, *y as f32, *z as f32])),
+            _ => None,
+        }
+    }
+}
+
+impl ReflectValue for Vec3d {
+    fn to_field(&self) -> FieldValue {
+        FieldValue::Vector([self.x, self.y, self.z])
+    }
+    fn from_field(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Vector(x) => Some(Vec3d::new(*x)),
+            _ => None,
+        }
+    }
+}
+
+impl ReflectValue for Color {
+    fn to_field(&self) -> FieldValue {
+        FieldValue::Color([self.r, self.g, self.b, self.a])
+    }
+    fn from_field(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Color([r, g, b, a]) => Some(Color::rgba(*r, *g, *b, *a)),
+            _ => None,
+        }
+    }
+}
+
+pub type ReflectedFields = Vec<(&'static str, FieldValue)>;
+
+// Field level access to a component, used by the inspector. Usually derived, see
+// `simple_engine::Reflect`. Register with `World::register_reflect`.
+pub trait Reflect {
+    fn fields(&self) -> ReflectedFields;
+    /// False if there is no such field or the value has the wrong kind.
+    fn set_field(&mut self, name: &str, value: &FieldValue) -> bool;
+}
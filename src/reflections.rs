@@ -0,0 +1,332 @@
+use std::sync::Arc;
+
+use vulkano::buffer::BufferUsage;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::format::Format;
+use vulkano::image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo};
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount};
+use vulkano::memory::allocator::AllocationCreateInfo;
+use vulkano::pipeline::graphics::rasterization::FrontFace;
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo};
+
+use crate::asset_library::AssetLibrary;
+use crate::ecs::World;
+use crate::post_process::HDR_FORMAT;
+use crate::rendering::{bind_mesh_descriptor_sets, pipeline_for_key, PipelineVariant, VPData};
+use crate::state::State;
+use crate::types::buffers::UpdatableBuffer;
+use crate::types::material::PipelineKey;
+use crate::types::material_override::MaterialOverride;
+use crate::types::matrices::Matrix4f;
+use crate::types::mesh::DynamicMesh;
+use crate::types::morph::MorphWeights;
+use crate::types::planar_reflection::PlanarReflection;
+use crate::types::skin::SkinnedMesh;
+use crate::types::static_mesh::StaticMesh;
+use crate::types::texture::Texture;
+use crate::types::transform::Transform;
+use crate::types::visibility::Visibility;
+
+#[derive(Clone)]
+pub struct ReflectionTarget {
+    pub entity: usize,
+    pub texture: String,
+    pub extent: [u32; 3],
+    pub framebuffer: Arc<Framebuffer>,
+    pub vp_buffer: UpdatableBuffer<VPData>,
+}
+
+#[derive(Clone, Default)]
+pub struct PlanarReflections {
+    pub targets: Vec<ReflectionTarget>,
+    sampler: Option<Arc<Sampler>>,
+}
+
+// Mirroring flips the triangle winding, so reflections are drawn with the opposite front face.
+pub fn mirrored_key(key: &PipelineKey) -> PipelineKey {
+    let mut key = key.clone();
+    key.2.front_face = if key.2.front_face == FrontFace::Clockwise {
+        FrontFace::CounterClockwise
+    } else {
+        FrontFace::Clockwise
+    };
+    key
+}
+
+fn create_attachment(state: &State, extent: [u32; 3], format: Format, usage: ImageUsage, samples: SampleCount) -> Arc<ImageView> {
+    ImageView::new_default(
+        Image::new(
+            state.renderer.memeory_allocator.as_ref().unwrap().clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent,
+                usage,
+                samples,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+// Uses the main render pass, so the regular material pipelines are compatible with it.
+fn create_target(state: &State, entity: usize, texture: &str, extent: [u32; 3]) -> (ReflectionTarget, Arc<ImageView>) {
+    let inter = create_attachment(state, extent, HDR_FORMAT, ImageUsage::COLOR_ATTACHMENT, SampleCount::Sample8);
+    let color = create_attachment(state, extent, HDR_FORMAT, ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED, SampleCount::Sample1);
+    let depth = create_attachment(state, extent, Format::D32_SFLOAT, ImageUsage::DEPTH_STENCIL_ATTACHMENT, SampleCount::Sample8);
+    let framebuffer = Framebuffer::new(
+        state.renderer.render_pass.as_ref().unwrap().clone(),
+        FramebufferCreateInfo {
+            attachments: vec![inter, color.clone(), depth],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let target = ReflectionTarget {
+        entity,
+        texture: texture.to_string(),
+        extent,
+        framebuffer,
+        vp_buffer: UpdatableBuffer::new(&state.renderer, BufferUsage::UNIFORM_BUFFER),
+    };
+    (target, color)
+}
+
+pub fn prepare_reflections(world: &World, assets: &mut AssetLibrary, state: &mut State) {
+    let reflections: Vec<(usize, String)> = match (
+        world.borrow_component_vec_mut::<PlanarReflection>(),
+        world.borrow_component_vec_mut::<Transform>(),
+    ) {
+        (Some(reflections), Some(transforms)) => reflections
+            .iter()
+            .zip(transforms.iter())
+            .enumerate()
+            .filter_map(|(entity, (reflection, transform))| {
+                transform.as_ref()?;
+                Some((entity, reflection.as_ref()?.texture.clone()))
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let [width, height] = state.renderer.viewport.as_ref().unwrap().extent;
+    let extent = [width as u32, height as u32, 1];
+    let current: Vec<(usize, String)> = state
+        .renderer
+        .reflections
+        .targets
+        .iter()
+        .filter(|x| x.extent == extent)
+        .map(|x| (x.entity, x.texture.clone()))
+        .collect();
+    if reflections == current && current.len() == state.renderer.reflections.targets.len() {
+        return;
+    }
+
+    if state.renderer.reflections.sampler.is_none() {
+        state.renderer.reflections.sampler = Some(
+            Sampler::new(
+                state.renderer.device.as_ref().unwrap().clone(),
+                SamplerCreateInfo {
+                    mag_filter: Filter::Linear,
+                    min_filter: Filter::Linear,
+                    address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+        );
+    }
+    let sampler = state.renderer.reflections.sampler.as_ref().unwrap().clone();
+
+    let mut targets = Vec::new();
+    for (entity, texture) in reflections {
+        let existing = state
+            .renderer
+            .reflections
+            .targets
+            .iter()
+            .find(|x| x.entity == entity && x.texture == texture && x.extent == extent);
+        if let Some(target) = existing {
+            targets.push(target.clone());
+            continue;
+        }
+
+        let (target, view) = create_target(state, entity, &texture, extent);
+        assets.textures.retain(|x| x.name != texture);
+        assets.textures.push(Texture::from_view(&texture, view, sampler.clone()));
+        targets.push(target);
+    }
+    state.renderer.reflections.targets = targets;
+    state.renderer.command_buffer_outdated = true;
+}
+
+pub fn write_reflection_data(world: &World, state: &mut State) {
+    let (Some(reflections), Some(transforms)) = (
+        world.borrow_component_vec_mut::<PlanarReflection>(),
+        world.borrow_component_vec_mut::<Transform>(),
+    ) else {
+        return;
+    };
+
+    let vp_data = state.renderer.vp_data;
+    for target in state.renderer.reflections.targets.iter() {
+        let (Some(Some(reflection)), Some(Some(transform))) = (reflections.get(target.entity), transforms.get(target.entity)) else {
+            continue;
+        };
+        let mut normal = reflection.normal;
+        let normal = normal.normalize();
+        let mut point = state.renderer.render_space(transform.position);
+        let d = -point.dot(normal);
+
+        let view = vp_data.view * Matrix4f::reflection(normal, d);
+        let plane = [normal.x, normal.y, normal.z, d + reflection.clip_offset];
+        let clip_plane = view.transform_plane(plane);
+        let projection = if state.renderer.settings.depth_mode.is_reversed() {
+            vp_data.projection.oblique_near_plane_reversed(clip_plane)
+        } else {
+            vp_data.projection.oblique_near_plane(clip_plane)
+        };
+        // The oblique projection does the clipping, the plane is also passed to shaders that use
+        // clip distances, in place of the camera's last one.
+        let mut clip_planes = vp_data.clip_planes;
+        clip_planes.rotate_right(1);
+        clip_planes[0] = plane;
+        target.vp_buffer.write(
+            state,
+            VPData {
+                view,
+                projection,
+                clip_planes,
+                ..vp_data
+            },
+        );
+    }
+}
+
+pub fn prepare_pipelines(assets: &AssetLibrary, state: &mut State) {
+    if state.renderer.reflections.targets.is_empty() {
+        return;
+    }
+    for material in assets.materials.iter() {
+        let key = mirrored_key(&material.pipeline_key());
+        if !state.renderer.pipelines.contains_key(&key) {
+            let pipeline = pipeline_for_key(state, assets, &key, PipelineVariant::Mesh);
+            state.renderer.pipelines.insert(key, pipeline);
+        }
+    }
+}
+
+pub fn record_reflection_passes(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    world: &World,
+    assets: &AssetLibrary,
+    state: &State,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    frame_i: usize,
+) {
+    let reflections = &state.renderer.reflections;
+    if reflections.targets.is_empty() {
+        return;
+    }
+
+    let transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+    let static_meshes = world.borrow_component_vec_mut::<StaticMesh>();
+    let dynamic_meshes = world.borrow_component_vec_mut::<DynamicMesh>();
+    let skinned_meshes = world.borrow_component_vec_mut::<SkinnedMesh>();
+    let morph_weights = world.borrow_component_vec_mut::<MorphWeights>();
+    let material_overrides = world.borrow_component_vec_mut::<MaterialOverride>();
+    let reflectors = world.borrow_component_vec_mut::<PlanarReflection>();
+    let visibilities = world.borrow_component_vec_mut::<Visibility>();
+    let is_visible = |entity: usize| {
+        visibilities.as_ref().and_then(|x| x[entity]).is_none_or(|x| x.visible)
+    };
+    let clear_color = state.renderer.settings.clear_color.to_array();
+    let clear_depth = state.renderer.settings.depth_mode.clear_value();
+
+    for target in reflections.targets.iter() {
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some(clear_color.into()), Some(clear_color.into()), Some(clear_depth.into())],
+                    ..RenderPassBeginInfo::framebuffer(target.framebuffer.clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        for (entity, transform) in transforms.iter().enumerate() {
+            let Some(transform) = transform.as_ref() else {
+                continue;
+            };
+            // Reflecting surfaces would only occlude their own reflection.
+            let is_reflector = reflectors.as_ref().is_some_and(|x| x.get(entity).is_some_and(|x| x.is_some()));
+            if !is_visible(entity) || is_reflector {
+                continue;
+            }
+
+            let static_mesh = static_meshes.as_ref().and_then(|x| x.get(entity)?.as_ref());
+            let dynamic_mesh = dynamic_meshes.as_ref().and_then(|x| x.get(entity)?.as_ref());
+            let skinned_mesh = skinned_meshes.as_ref().and_then(|x| x.get(entity)?.as_ref());
+            let morph = morph_weights.as_ref().and_then(|x| x.get(entity)?.as_ref());
+            let (material, index_buffer, vertex_buffer, index_count, morph) = if let Some(static_mesh) = static_mesh {
+                let mesh = assets.meshes.iter().find(|x| x.name == static_mesh.mesh_name).unwrap();
+                let index_buffer = mesh.index_buffer.as_ref().unwrap().clone();
+                let index_count = index_buffer.len() as u32;
+                (&mesh.material, index_buffer, mesh.vertex_buffer.as_ref().unwrap().clone(), index_count, morph)
+            } else if let Some(dynamic_mesh) = dynamic_mesh {
+                let buffers = dynamic_mesh.buffers.as_ref().unwrap();
+                (
+                    &dynamic_mesh.material,
+                    buffers.index[frame_i].clone(),
+                    buffers.vertex[frame_i].clone(),
+                    dynamic_mesh.indices.len() as u32,
+                    None,
+                )
+            } else if let Some(skinned_mesh) = skinned_mesh {
+                let mesh = assets.meshes.iter().find(|x| x.name == skinned_mesh.mesh_name).unwrap();
+                let index_buffer = mesh.index_buffer.as_ref().unwrap().clone();
+                let index_count = index_buffer.len() as u32;
+                let vertex_buffer = skinned_mesh.buffers.as_ref().unwrap().output[frame_i].clone();
+                (&mesh.material, index_buffer, vertex_buffer, index_count, morph)
+            } else {
+                continue;
+            };
+
+            let material = assets.materials.iter().find(|x| x.name == *material).unwrap();
+            let pipeline = state.renderer.pipelines.get(&mirrored_key(&material.pipeline_key())).unwrap().clone();
+            builder.bind_pipeline_graphics(pipeline.clone()).unwrap();
+            bind_mesh_descriptor_sets(
+                builder,
+                descriptor_set_allocator,
+                assets,
+                state,
+                &pipeline,
+                transform,
+                material,
+                morph,
+                material_overrides.as_ref().and_then(|x| x.get(entity)?.as_ref()?.texture.as_deref()),
+                frame_i,
+                target.vp_buffer.buffer(frame_i),
+            );
+            builder
+                .bind_index_buffer(index_buffer)
+                .unwrap()
+                .bind_vertex_buffers(0, vertex_buffer)
+                .unwrap()
+                .draw_indexed(index_count, 1, 0, 0, 0)
+                .unwrap();
+        }
+
+        builder.end_render_pass(Default::default()).unwrap();
+    }
+}
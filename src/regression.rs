@@ -0,0 +1,181 @@
+use std::{
+    cell::Cell,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    asset_library::AssetLibrary,
+    capture::{self, CapturedFrame},
+    ecs::{System, World},
+    rendering::RendererSettings,
+    run_with_settings,
+    state::State,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    /// Largest difference of a single channel that still counts as equal.
+    pub per_channel: u8,
+    /// Fraction of pixels allowed to differ by more than `per_channel`.
+    pub max_differing_fraction: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            per_channel: 2,
+            max_differing_fraction: 0.001,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    pub max_difference: u8,
+    pub passed: bool,
+}
+
+// Differing pixels are red in the returned image, matching ones are a dimmed copy of `golden`.
+pub fn compare(actual: &CapturedFrame, golden: &CapturedFrame, tolerance: Tolerance) -> Result<(Comparison, CapturedFrame), String> {
+    if actual.width != golden.width || actual.height != golden.height {
+        return Err(format!(
+            "size mismatch, rendered {}x{} but the golden image is {}x{}",
+            actual.width, actual.height, golden.width, golden.height
+        ));
+    }
+
+    let mut differing_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff = Vec::with_capacity(golden.rgba.len());
+    for (a, g) in actual.rgba.chunks_exact(4).zip(golden.rgba.chunks_exact(4)) {
+        let difference = a.iter().zip(g).map(|(a, g)| a.abs_diff(*g)).max().unwrap();
+        max_difference = max_difference.max(difference);
+        if difference > tolerance.per_channel {
+            differing_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            diff.extend_from_slice(&[g[0] / 4, g[1] / 4, g[2] / 4, 255]);
+        }
+    }
+
+    let total_pixels = (golden.width * golden.height) as usize;
+    let comparison = Comparison {
+        differing_pixels,
+        total_pixels,
+        max_difference,
+        passed: differing_pixels as f32 <= tolerance.max_differing_fraction * total_pixels as f32,
+    };
+    let diff = CapturedFrame {
+        width: golden.width,
+        height: golden.height,
+        rgba: diff,
+    };
+    Ok((comparison, diff))
+}
+
+// Compares against the golden file, writing it instead when it does not exist yet or
+// SIMPLE_ENGINE_UPDATE_GOLDEN is set. A failed comparison leaves `<golden>.actual.png` and
+// `<golden>.diff.png` next to the golden file.
+pub fn check_golden(actual: &CapturedFrame, golden_path: &str, tolerance: Tolerance) -> Result<Comparison, String> {
+    let update = std::env::var_os("SIMPLE_ENGINE_UPDATE_GOLDEN").is_some();
+    if update || !Path::new(golden_path).exists() {
+        if let Some(dir) = Path::new(golden_path).parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        }
+        capture::save_png(actual, golden_path)?;
+        log::info!("golden image written to {golden_path}");
+        let total_pixels = (actual.width * actual.height) as usize;
+        return Ok(Comparison {
+            differing_pixels: 0,
+            total_pixels,
+            max_difference: 0,
+            passed: true,
+        });
+    }
+
+    let golden = capture::load_png(golden_path)?;
+    let stem = golden_path.strip_suffix(".png").unwrap_or(golden_path);
+    let (comparison, diff) = match compare(actual, &golden, tolerance) {
+        Ok(x) => x,
+        Err(e) => {
+            capture::save_png(actual, &format!("{stem}.actual.png"))?;
+            return Err(format!("{golden_path}: {e}"));
+        }
+    };
+    if !comparison.passed {
+        capture::save_png(actual, &format!("{stem}.actual.png"))?;
+        capture::save_png(&diff, &format!("{stem}.diff.png"))?;
+    }
+    Ok(comparison)
+}
+
+#[derive(Clone, Debug)]
+pub struct RegressionTest {
+    pub golden_path: String,
+    /// Frames rendered before capturing, so loaders and streaming have settled.
+    pub warmup_frames: u32,
+    pub tolerance: Tolerance,
+}
+
+impl RegressionTest {
+    pub fn new(golden_path: &str) -> Self {
+        Self {
+            golden_path: golden_path.to_string(),
+            warmup_frames: 10,
+            tolerance: Tolerance::default(),
+        }
+    }
+}
+
+type Outcome = Arc<Mutex<Option<Result<Comparison, String>>>>;
+
+struct RegressionRunner {
+    test: RegressionTest,
+    frame: Cell<u32>,
+    outcome: Outcome,
+}
+
+impl System for RegressionRunner {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let frame = self.frame.get();
+        self.frame.set(frame + 1);
+
+        // Requested in the warmup's last frame, the renderer copies it out at the end of it.
+        if frame + 1 == self.test.warmup_frames.max(1) {
+            state.renderer.capture.request();
+            return;
+        }
+
+        if frame < self.test.warmup_frames {
+            return;
+        }
+
+        let result = match state.renderer.capture.take() {
+            Some(actual) => check_golden(&actual, &self.test.golden_path, self.test.tolerance),
+            None => Err("the frame could not be captured".to_string()),
+        };
+        *self.outcome.lock().unwrap() = Some(result);
+        state.exit_requested = true;
+    }
+}
+
+// Renders the scene in `world` and compares it against the test's golden image. winit only allows
+// one event loop per process, so every reference scene needs its own test binary or process.
+// There is no headless mode, the frame is copied out of the window's swapchain.
+pub fn run_regression(mut world: World, assets: AssetLibrary, settings: RendererSettings, test: RegressionTest) -> Result<Comparison, String> {
+    let outcome: Outcome = Arc::new(Mutex::new(None));
+    world.add_system(RegressionRunner {
+        test,
+        frame: Cell::new(0),
+        outcome: outcome.clone(),
+    });
+    run_with_settings(world, assets, settings);
+
+    let result = outcome.lock().unwrap().take();
+    result.unwrap_or_else(|| Err("the application exited before the frame was captured".to_string()))
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::image::Image;
+use vulkano::render_pass::Framebuffer;
+
+use crate::asset_library::AssetLibrary;
+use crate::ecs::World;
+use crate::state::State;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RenderStage {
+    /// After the shadow passes, before the main render pass begins.
+    BeforeScene,
+    /// Inside the main render pass, after every engine draw including the UI.
+    Ui,
+    /// After the main render pass, before the post-processing chain reads the scene.
+    AfterScene,
+    /// After the final image has been written to the swapchain image.
+    AfterPost,
+}
+
+pub struct RenderContext<'a> {
+    pub world: &'a World,
+    pub assets: &'a AssetLibrary,
+    pub state: &'a State,
+    pub descriptor_set_allocator: &'a StandardDescriptorSetAllocator,
+    pub frame_i: usize,
+    pub framebuffer: &'a Arc<Framebuffer>,
+    pub swapchain_image: &'a Arc<Image>,
+}
+
+pub type RenderCallback = Arc<dyn Fn(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, &RenderContext)>;
+
+pub fn record(
+    stage: RenderStage,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    context: &RenderContext,
+) {
+    for (_, callback) in context.state.renderer.render_callbacks.iter().filter(|x| x.0 == stage) {
+        callback(builder, context);
+    }
+}
@@ -1,57 +1,87 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use bytemuck::{Pod, Zeroable};
-use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
 use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
 use vulkano::command_buffer::{
-    AutoCommandBufferBuilder, CommandBufferExecFuture, CommandBufferUsage, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
+    AutoCommandBufferBuilder, CommandBufferUsage, DrawIndexedIndirectCommand, PrimaryAutoCommandBuffer, RenderPassBeginInfo,
+    SubpassBeginInfo, SubpassContents,
 };
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::layout::DescriptorSetLayout;
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
 use vulkano::device::{
-    Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
+    Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags,
 };
-use vulkano::format::Format;
+use vulkano::format::{Format, FormatFeatures};
 use vulkano::image::view::ImageView;
 use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount};
 use vulkano::instance::{Instance, InstanceCreateInfo};
-use vulkano::memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator};
-use vulkano::pipeline::graphics::color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState, ColorComponents};
-use vulkano::pipeline::graphics::depth_stencil::{DepthState, DepthStencilState};
-use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState, ColorComponents};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::{InputAssemblyState, PrimitiveTopology};
 use vulkano::pipeline::graphics::multisample::MultisampleState;
-use vulkano::pipeline::graphics::rasterization::RasterizationState;
-use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::rasterization::{CullMode, DepthBiasState, PolygonMode, RasterizationState};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition, VertexInputState};
 use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
 use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
 use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
 use vulkano::pipeline::{
     GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo,
 };
+use vulkano::query::{QueryControlFlags, QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType};
 use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
 use vulkano::swapchain::{
-    self, PresentFuture, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo,
-    SwapchainPresentInfo,
+    self, CompositeAlpha, PresentFuture, PresentMode, Surface, SurfaceTransform, Swapchain,
+    SwapchainCreateInfo, SwapchainPresentInfo,
 };
-use vulkano::sync::future::{FenceSignalFuture, JoinFuture};
-use vulkano::sync::{self, GpuFuture};
-use vulkano::{Validated, VulkanError, VulkanLibrary};
-use winit::window::WindowBuilder;
+use vulkano::sync::future::FenceSignalFuture;
+use vulkano::sync::{self, GpuFuture, PipelineStage};
+use vulkano::{Validated, Version, VulkanError, VulkanLibrary};
+use winit::event_loop::ActiveEventLoop;
+use winit::window::{CursorGrabMode, CursorIcon, CustomCursor, WindowLevel};
 
 use crate::asset_library::AssetLibrary;
+use crate::builtin_shaders;
+use crate::capture::{self, FrameCapture};
+use crate::clusters::{self, LightClusters};
+use crate::crash;
+use crate::debug_labels::{self, BatchLabels};
+use crate::depth_pass::{self, DepthDraw};
 use crate::ecs::{System, World};
+use crate::memory_stats::{self, MemoryMonitor};
+use crate::reflections::{self, PlanarReflections};
+use crate::render_callbacks::{self, RenderCallback, RenderContext, RenderStage};
+use crate::post_process::{self, PostEffect, PostProcessing, HDR_FORMAT};
+use crate::ray_tracing::{self, RayTracing};
+use crate::shadows::{self, ShadowMaps};
+use crate::skinning::{self, Skinning};
+use crate::watchdog::{self, FrameWatchdog};
 use crate::state::State;
 use crate::types::buffers::*;
+use crate::types::bvh::Aabb;
 use crate::types::camera::Camera;
-use crate::types::material::Attachment;
+use crate::types::color::Color;
+use crate::types::compressed_texture;
+use crate::types::fog::{Fog, FogData};
+use crate::types::material::{Attachment, Material, PipelineKey, RenderQueue, RenderState};
+use crate::types::material_override::MaterialOverride;
 use crate::types::matrices::*;
 use crate::types::mesh::DynamicMesh;
+use crate::types::mesh_arena::MeshArena;
+use crate::types::meshlet;
+use crate::types::morph::{self, MorphWeights};
+use crate::types::point_cloud;
 use crate::types::shader::Shader;
+use crate::types::skin::SkinnedMesh;
 use crate::types::static_mesh::StaticMesh;
 use crate::types::transform::Transform;
 use crate::types::vectors::*;
+use crate::types::visibility::Visibility;
 
 #[derive(BufferContents, Vertex, Clone, Copy, Debug)]
 #[repr(C)]
@@ -62,13 +92,60 @@ pub struct VertexData {
     pub uv: Vec2f,
     #[format(R32G32B32_SFLOAT)]
     pub normal: Vec3f,
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: Color,
+    #[format(R32G32_SFLOAT)]
+    pub uv2: Vec2f,
+}
+
+impl VertexData {
+    pub fn new(position: Vec3f, uv: Vec2f, normal: Vec3f) -> VertexData {
+        VertexData {
+            position,
+            uv,
+            normal,
+            color: Color::WHITE,
+            uv2: uv,
+        }
+    }
+
+    pub fn with_uv2(mut self, uv2: Vec2f) -> VertexData {
+        self.uv2 = uv2;
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> VertexData {
+        self.color = color;
+        self
+    }
 }
 
+pub const MAX_CLIP_PLANES: usize = 4;
+/// Keeps everything, unused clip plane slots are set to this.
+pub const NO_CLIP_PLANE: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+// `clip_planes` are in render space (see `Renderer::render_space`). Vertex shaders that support
+// them write `gl_ClipDistance[i] = dot(clip_planes[i], vec4(position, 1.0))` for every slot, which
+// needs the shader_clip_distance feature.
 #[derive(Pod, Zeroable, Clone, Copy, Debug)]
 #[repr(C)]
 pub struct VPData {
     pub view: Matrix4f,
     pub projection: Matrix4f,
+    pub clip_planes: [[f32; 4]; MAX_CLIP_PLANES],
+    /// Logical pixels with 0,0 at the top-left corner of the window, see `Window::logical_size`.
+    pub ui_projection: Matrix4f,
+}
+
+impl VPData {
+    pub fn new(view: Matrix4f, projection: Matrix4f) -> VPData {
+        VPData {
+            view,
+            projection,
+            clip_planes: [NO_CLIP_PLANE; MAX_CLIP_PLANES],
+            ui_projection: Matrix4f::indentity(),
+        }
+    }
 }
 
 
@@ -78,17 +155,277 @@ pub struct ModelData {
     pub translation: Matrix4f,
 }
 
+// Reversed-Z maps the near plane to depth 1 and the far plane to 0, which evens out float depth
+// precision over distance. Shadow maps always use the standard range. Post effects reading the
+// depth buffer see the reversed values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DepthMode {
+    #[default]
+    Standard,
+    Reversed,
+    /// Reversed with the far plane at infinity, the camera's `far` is ignored.
+    ReversedInfinite,
+}
+
+impl DepthMode {
+    pub fn is_reversed(&self) -> bool {
+        *self != DepthMode::Standard
+    }
+
+    pub fn projection(&self, fovy: f32, aspect: f32, near: f32, far: f32) -> Matrix4f {
+        match self {
+            DepthMode::Standard => Matrix4f::perspective(fovy, aspect, near, far),
+            DepthMode::Reversed => Matrix4f::perspective_reversed(fovy, aspect, near, far),
+            DepthMode::ReversedInfinite => Matrix4f::perspective_infinite_reversed(fovy, aspect, near),
+        }
+    }
+
+    pub fn compare_op(&self) -> CompareOp {
+        if self.is_reversed() {
+            CompareOp::Greater
+        } else {
+            CompareOp::Less
+        }
+    }
+
+    pub fn clear_value(&self) -> f32 {
+        if self.is_reversed() {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RendererSettings {
+    pub frames_in_flight: usize,
+    pub clear_color: Color,
+    pub fog: Fog,
+    pub post_effects: Vec<PostEffect>,
+    pub light_clusters: [u32; 3],
+    pub memory_warning_threshold: Option<f32>,
+    pub swapchain_image_count: Option<u32>,
+    pub surface_formats: Vec<Format>,
+    /// In order of preference, falls back to `Fifo` which every surface supports.
+    pub present_modes: Vec<PresentMode>,
+    pub debug_labels: bool,
+    /// Directory crash reports are written to when the engine panics.
+    pub crash_report_dir: Option<String>,
+    pub depth_mode: DepthMode,
+    /// Renders relative to an origin that follows the camera in steps of this many units, 0
+    /// follows it exactly. Keeps f32 matrices precise far from the world origin, every model
+    /// matrix is rewritten when the origin moves.
+    pub camera_relative: Option<f64>,
+    /// Clamps depth instead of clipping at the near and far planes, needs the depth_clamp feature.
+    pub depth_clamp: bool,
+    pub window: WindowSettings,
+    /// Runs compute work like skinning on a dedicated compute queue when the device has one,
+    /// overlapping it with the graphics queue. Read when the device is created.
+    pub async_compute: bool,
+    /// Draws static meshes meshlet by meshlet, skipping those outside the frustum or facing away
+    /// from the camera. Needs the multi_draw_indirect feature.
+    pub cluster_culling: bool,
+    /// Skips meshes whose entity an occlusion query found hidden, tested with its bounds against
+    /// the depth of the opaque queues. Needs the built-in shaders, see `builtin_shaders::load`.
+    pub occlusion_culling: bool,
+    /// Shadows the built-in lit shader with ray queries against the static meshes, softened by a
+    /// few jittered rays per light. Needs the ray query extensions, read when the device is created,
+    /// without them lights fall back to their shadow maps.
+    pub ray_traced_shadows: bool,
+    /// Frames whose GPU time or fence wait exceeds this are reported by `FrameWatchdog`.
+    pub frame_watchdog: Option<Duration>,
+    /// How long to wait for a swapchain image before skipping the render, updates keep running.
+    /// `None` waits forever, which locks up the application while the compositor stalls.
+    pub acquire_timeout: Option<Duration>,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        RendererSettings {
+            frames_in_flight: 2,
+            clear_color: Color::BLACK,
+            fog: Fog::default(),
+            post_effects: Vec::new(),
+            light_clusters: [16, 9, 24],
+            memory_warning_threshold: Some(0.9),
+            swapchain_image_count: Some(3),
+            surface_formats: vec![Format::B8G8R8A8_SRGB, Format::R8G8B8A8_SRGB],
+            present_modes: vec![PresentMode::Fifo],
+            debug_labels: cfg!(debug_assertions),
+            crash_report_dir: None,
+            depth_mode: DepthMode::Standard,
+            camera_relative: None,
+            depth_clamp: false,
+            window: WindowSettings::default(),
+            async_compute: true,
+            cluster_culling: true,
+            occlusion_culling: false,
+            ray_traced_shadows: false,
+            frame_watchdog: None,
+            acquire_timeout: Some(Duration::from_millis(100)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WindowSettings {
+    /// Lets the desktop show through where the frame's alpha is below 1, clear with a transparent
+    /// `clear_color`. Shaders have to output premultiplied alpha on most platforms.
+    pub transparent: bool,
+    pub always_on_top: bool,
+    /// Windows only, X11 gets a utility window instead which most window managers keep off the
+    /// taskbar.
+    pub skip_taskbar: bool,
+}
+
+// Draw counters are from the last time the command buffers were recorded, uploads are per frame.
+#[derive(Clone, Debug, Default)]
+pub struct MaterialStats {
+    pub submitted: usize,
+    /// Hidden through `Visibility`, by frustum culling or otherwise.
+    pub culled: usize,
+    /// Skipped because the entity's occlusion query found nothing visible.
+    pub occluded: usize,
+    pub triangles: usize,
+    pub uploaded_bytes: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    pub occlusion_samples: Vec<Option<u64>>,
+    pub occluded: usize,
+    pub draw_calls: usize,
+    pub triangles: usize,
+    pub materials: HashMap<String, MaterialStats>,
+    /// Meshlets skipped by cluster culling in the last frame.
+    pub culled_meshlets: usize,
+    /// Time the graphics command buffer of the last finished frame spent on the GPU, if the queue
+    /// supports timestamps.
+    pub gpu_time: Option<Duration>,
+    /// The same split by `GPU_PASSES`, empty without timestamps.
+    pub gpu_passes: Vec<(&'static str, Duration)>,
+    /// Time the CPU spent waiting for the next frame's fence, long waits mean the GPU is behind.
+    pub fence_wait: Duration,
+    /// Frames not rendered because no swapchain image was available in time, see
+    /// `RendererSettings::acquire_timeout`.
+    pub skipped_frames: usize,
+    /// Whether the last frame was one of them.
+    pub frame_skipped: bool,
+}
+
+// Passes timed on the GPU, in recording order. Skinning is on the compute queue with
+// `RendererSettings::async_compute` and takes no time here.
+pub const GPU_PASSES: [&str; 5] = ["skinning", "shadows", "reflections", "scene", "post processing"];
+
+#[derive(Clone, Debug)]
+pub struct CursorImage {
+    /// Tightly packed RGBA8, not premultiplied.
+    pub rgba: Vec<u8>,
+    pub width: u16,
+    pub height: u16,
+    pub hotspot: [u16; 2],
+}
+
 #[derive(Clone, Debug)]
 pub struct Window {
     pub window_handle: Arc<winit::window::Window>,
+    // Custom cursors need the event loop, they are created before the next frame.
+    pending_cursor: Option<CursorImage>,
 }
 
 impl Window {
-    pub fn new(event_loop: &EventLoop) -> Window {
+    pub fn new(event_loop: &ActiveEventLoop, settings: &WindowSettings) -> Window {
+        let mut attributes = winit::window::Window::default_attributes().with_transparent(settings.transparent);
+        if settings.always_on_top {
+            attributes = attributes.with_window_level(WindowLevel::AlwaysOnTop);
+        }
+        if settings.skip_taskbar {
+            attributes = skip_taskbar(attributes);
+        }
         Window {
-            window_handle: Arc::new(WindowBuilder::new().build(&event_loop.event_loop).unwrap()),
+            window_handle: Arc::new(event_loop.create_window(attributes).unwrap()),
+            pending_cursor: None,
         }
     }
+
+    // Only changes what the window manager shows, the swapchain keeps its composite alpha.
+    pub fn set_transparent(&self, transparent: bool) {
+        self.window_handle.set_transparent(transparent);
+    }
+
+    pub fn set_always_on_top(&self, always_on_top: bool) {
+        let level = if always_on_top { WindowLevel::AlwaysOnTop } else { WindowLevel::Normal };
+        self.window_handle.set_window_level(level);
+    }
+
+    pub fn set_cursor_icon(&mut self, icon: CursorIcon) {
+        self.pending_cursor = None;
+        self.window_handle.set_cursor(icon);
+    }
+
+    // Shown from the next frame on.
+    pub fn set_cursor_image(&mut self, image: CursorImage) -> Result<(), String> {
+        if image.rgba.len() != image.width as usize * image.height as usize * 4 {
+            return Err(format!("cursor image is {} bytes, expected {}x{} RGBA", image.rgba.len(), image.width, image.height));
+        }
+        if image.hotspot[0] >= image.width || image.hotspot[1] >= image.height {
+            return Err(format!("cursor hotspot {:?} is outside the image", image.hotspot));
+        }
+        self.pending_cursor = Some(image);
+        Ok(())
+    }
+
+    pub fn set_cursor_visible(&self, visible: bool) {
+        self.window_handle.set_cursor_visible(visible);
+    }
+
+    // Keeps the cursor inside the window, or locks it in place where confining is not supported.
+    pub fn set_cursor_grab(&self, grab: bool) -> Result<(), String> {
+        if !grab {
+            return self.window_handle.set_cursor_grab(CursorGrabMode::None).map_err(|e| e.to_string());
+        }
+        self.window_handle
+            .set_cursor_grab(CursorGrabMode::Confined)
+            .or_else(|_| self.window_handle.set_cursor_grab(CursorGrabMode::Locked))
+            .map_err(|e| e.to_string())
+    }
+
+    pub(crate) fn apply_pending_cursor(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(image) = self.pending_cursor.take() else {
+            return;
+        };
+        let [x, y] = image.hotspot;
+        match CustomCursor::from_rgba(image.rgba, image.width, image.height, x, y) {
+            Ok(source) => self.window_handle.set_cursor(event_loop.create_custom_cursor(source)),
+            Err(e) => log::error!("failed to create cursor: {e}"),
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn skip_taskbar(attributes: winit::window::WindowAttributes) -> winit::window::WindowAttributes {
+    use winit::platform::windows::WindowAttributesExtWindows;
+    attributes.with_skip_taskbar(true)
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd", target_os = "openbsd"))]
+fn skip_taskbar(attributes: winit::window::WindowAttributes) -> winit::window::WindowAttributes {
+    use winit::platform::x11::{WindowAttributesExtX11, WindowType};
+    attributes.with_x11_window_type(vec![WindowType::Utility])
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn skip_taskbar(attributes: winit::window::WindowAttributes) -> winit::window::WindowAttributes {
+    log::warn!("skip_taskbar is not supported on this platform");
+    attributes
 }
 
 pub struct EventLoop {
@@ -101,6 +438,15 @@ impl EventLoop {
             event_loop: winit::event_loop::EventLoop::new().unwrap(),
         }
     }
+
+    // Android needs the activity handed to `android_main`.
+    #[cfg(target_os = "android")]
+    pub fn with_android_app(app: winit::platform::android::activity::AndroidApp) -> EventLoop {
+        use winit::platform::android::EventLoopBuilderExtAndroid;
+        EventLoop {
+            event_loop: winit::event_loop::EventLoop::builder().with_android_app(app).build().unwrap(),
+        }
+    }
 }
 
 impl Default for EventLoop {
@@ -109,7 +455,8 @@ impl Default for EventLoop {
     }
 }
             
-type Fence = Option<Arc<FenceSignalFuture<PresentFuture<CommandBufferExecFuture<JoinFuture<Box<dyn GpuFuture>, SwapchainAcquireFuture>>>>>>;
+type Fence = Option<Arc<FenceSignalFuture<PresentFuture<Box<dyn GpuFuture>>>>>;
+type ProxyCube = (Subbuffer<[VertexData]>, Subbuffer<[u32]>);
 
 #[derive(Clone)]
 pub struct Renderer {
@@ -119,26 +466,83 @@ pub struct Renderer {
     physical_device: Option<Arc<PhysicalDevice>>,
     queue_family_index: Option<u32>,
     pub device: Option<Arc<Device>>,
+    pub enabled_features: Features,
+    pub debug_utils: bool,
     pub queue: Option<Arc<Queue>>,
+    /// Queue from a dedicated transfer family, if the device has one.
+    pub transfer_queue: Option<Arc<Queue>>,
+    /// Queue from a dedicated compute family, only with `RendererSettings::async_compute`.
+    pub compute_queue: Option<Arc<Queue>>,
     pub memeory_allocator: Option<Arc<StandardMemoryAllocator>>,
     pub render_pass: Option<Arc<RenderPass>>,
     pub swapchain: Option<Arc<Swapchain>>,
     pub vp_data: VPData,
     pub vp_pos: Vec3d,
+    /// Subtracted from every position before it is converted to f32 for the GPU.
+    pub render_origin: Vec3d,
     pub vp_buffer: Option<UpdatableBuffer<VPData>>,
+    pub fog_buffer: Option<UpdatableBuffer<FogData>>,
+    pub shadows: ShadowMaps,
+    pub clusters: LightClusters,
+    pub post: PostProcessing,
     images: Option<Vec<Arc<Image>>>,
     framebuffers: Option<Vec<Arc<Framebuffer>>>,
     pub viewport: Option<Viewport>,
     pub command_buffers: Option<Vec<Arc<PrimaryAutoCommandBuffer>>>,
+    /// One per frame in flight, submitted to `compute_queue` before the graphics work.
+    pub compute_command_buffers: Option<Vec<Arc<PrimaryAutoCommandBuffer>>>,
     pub window_resized: bool,
+    pub device_lost: bool,
     pub command_buffer_outdated: bool,
     pub recreate_swapchain: bool,
+    pub settings: RendererSettings,
     pub frames_in_flight: usize,
+    pub current_frame: usize,
     pub fences: Option<Vec<Fence>>,
     pub previous_fence: usize,
-    pub pipelines: HashMap<(String, String), Arc<GraphicsPipeline>>,
+    submitted_command_buffers: Vec<Option<usize>>,
+    pub pipelines: HashMap<PipelineKey, Arc<GraphicsPipeline>>,
+    pub occlusion_pipeline: Option<Arc<GraphicsPipeline>>,
+    /// Unit cube every occlusion proxy is drawn from, scaled to the entity's bounds.
+    pub occlusion_cube: Option<ProxyCube>,
+    pub point_pipelines: HashMap<PipelineKey, Arc<GraphicsPipeline>>,
+    pub occlusion_query_pools: Option<Vec<Arc<QueryPool>>>,
+    /// Per command buffer, a timestamp at its start and one after each of `GPU_PASSES`.
+    pub timestamp_query_pools: Option<Vec<Arc<QueryPool>>>,
+    pub occluded: Vec<bool>,
+    /// Per command buffer, see `OcclusionDraws`.
+    pub(crate) occlusion_draws: Vec<Option<OcclusionDraws>>,
+    /// Per frame in flight, what depth-only passes draw, see `depth_pass::gather`.
+    pub depth_draws: Vec<Vec<DepthDraw>>,
+    pub stats: RenderStats,
+    /// Indirect draws per frame in flight for static mesh entities, see `meshlet::cull_clusters`.
+    pub(crate) cluster_draws: HashMap<usize, Vec<Subbuffer<[DrawIndexedIndirectCommand]>>>,
+    pub memory: MemoryMonitor,
+    pub mesh_arena: MeshArena,
+    pub reflections: PlanarReflections,
+    pub skinning: Skinning,
+    pub ray_tracing: RayTracing,
+    pub watchdog: FrameWatchdog,
+    pub render_callbacks: Vec<(RenderStage, RenderCallback)>,
+    pub capture: FrameCapture,
 }
 
+const REQUIRED_FEATURES: Features = Features::empty();
+
+const OPTIONAL_FEATURES: Features = Features {
+    fill_mode_non_solid: true,
+    wide_lines: true,
+    depth_clamp: true,
+    depth_bias_clamp: true,
+    sampler_anisotropy: true,
+    occlusion_query_precise: true,
+    shader_clip_distance: true,
+    texture_compression_bc: true,
+    multi_draw_indirect: true,
+    buffer_device_address: true,
+    ..Features::empty()
+};
+
 fn select_physical_device(state: &mut State, device_extensions: &DeviceExtensions) {
     let (physical_device, queue_family_index) = state
         .renderer
@@ -148,6 +552,7 @@ fn select_physical_device(state: &mut State, device_extensions: &DeviceExtension
         .enumerate_physical_devices()
         .expect("failed to enumerate physical devices")
         .filter(|p| p.supported_extensions().contains(device_extensions))
+        .filter(|p| p.supported_features().contains(&REQUIRED_FEATURES))
         .filter_map(|p| {
             p.queue_family_properties()
                 .iter()
@@ -178,13 +583,13 @@ fn get_render_pass(state: &mut State) {
         state.renderer.device.as_ref().unwrap().clone(),
         attachments: {
             inter: {
-                format: state.renderer.swapchain.as_ref().unwrap().image_format(),
+                format: HDR_FORMAT,
                 samples: 8,
                 load_op: Clear,
                 store_op: Store,
             },
             color: {
-                format: state.renderer.swapchain.as_ref().unwrap().image_format(),
+                format: HDR_FORMAT,
                 samples: 1,
                 load_op: Clear,
                 store_op: Store,
@@ -193,7 +598,7 @@ fn get_render_pass(state: &mut State) {
                 format: Format::D32_SFLOAT,
                 samples: 8,
                 load_op: Clear,
-                store_op: DontCare,
+                store_op: Store,
             }
         },
         pass: {
@@ -218,7 +623,7 @@ fn get_framebuffers(state: &mut State) {
                 image_type: ImageType::Dim2d,
                 format: Format::D32_SFLOAT,
                 extent: state.renderer.images.as_ref().unwrap()[0].extent(),
-                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED,
                 samples: SampleCount::Sample8,
                 ..Default::default()
             },
@@ -227,6 +632,10 @@ fn get_framebuffers(state: &mut State) {
         .unwrap(),
     )
     .unwrap();
+    debug_labels::name_object(&state.renderer, depth_buffer.image().as_ref(), "scene depth");
+    let extent = state.renderer.images.as_ref().unwrap()[0].extent();
+    post_process::create_targets(state, extent, depth_buffer.clone());
+    let scene = state.renderer.post.scene.as_ref().unwrap().clone();
 
     state.renderer.framebuffers = Some(
         state
@@ -236,13 +645,12 @@ fn get_framebuffers(state: &mut State) {
             .unwrap()
             .iter()
             .map(|image| {
-                let view = ImageView::new_default(image.clone()).unwrap();
                 let inter = ImageView::new_default(
                     Image::new(
                         memory_allocator.clone(),
                         ImageCreateInfo {
                             image_type: ImageType::Dim2d,
-                            format: image.format(),
+                            format: HDR_FORMAT,
                             extent: image.extent(),
                             usage: ImageUsage::COLOR_ATTACHMENT,
                             samples: SampleCount::Sample8,
@@ -253,11 +661,12 @@ fn get_framebuffers(state: &mut State) {
                     .unwrap(),
                 )
                 .unwrap();
+                debug_labels::name_object(&state.renderer, inter.image().as_ref(), "scene msaa color");
 
                 Framebuffer::new(
                     state.renderer.render_pass.as_ref().unwrap().clone(),
                     FramebufferCreateInfo {
-                        attachments: vec![inter, view, depth_buffer.clone()],
+                        attachments: vec![inter, scene.clone(), depth_buffer.clone()],
                         ..Default::default()
                     },
                 )
@@ -267,13 +676,118 @@ fn get_framebuffers(state: &mut State) {
     )
 }
 
-pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader) -> Arc<GraphicsPipeline> {
+// Meshes of one command buffer under occlusion culling, drawn indirectly so a visibility flip only
+// rewrites instance counts, see `write_occlusion_draws`.
+#[derive(Clone)]
+pub(crate) struct OcclusionDraws {
+    commands: Subbuffer<[DrawIndexedIndirectCommand]>,
+    // Entity, material and, unless drawn through `meshlet::cull_clusters`, the command's index count.
+    slots: Vec<(usize, String, Option<u32>)>,
+}
+
+struct MeshDraw<'a> {
+    entity: usize,
+    transform: &'a Transform,
+    material: &'a Material,
+    vertices: &'a [VertexData],
+    vertex_buffer: Subbuffer<[VertexData]>,
+    index_buffer: Subbuffer<[u32]>,
+    index_count: u32,
+    morph: Option<&'a MorphWeights>,
+    /// From the entity's `MaterialOverride`.
+    texture_override: Option<&'a str>,
+    distance: f64,
+    clusters: Option<Subbuffer<[DrawIndexedIndirectCommand]>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PipelineVariant {
+    Mesh,
+    Points,
+}
+
+pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader, render_state: &RenderState) -> Arc<GraphicsPipeline> {
+    create_pipeline(state, vs, fs, render_state, PipelineVariant::Mesh)
+}
+
+pub fn pipeline_for_key(state: &State, assets: &AssetLibrary, key: &PipelineKey, variant: PipelineVariant) -> Arc<GraphicsPipeline> {
+    let ray_query_fs = state.renderer.ray_tracing.lit_shader.as_deref().filter(|_| {
+        key.1 == builtin_shaders::LIT_FS && variant == PipelineVariant::Mesh
+    });
+    create_pipeline(
+        state,
+        assets.shaders.iter().find(|x| x.name == key.0).unwrap(),
+        ray_query_fs.unwrap_or_else(|| assets.shaders.iter().find(|x| x.name == key.1).unwrap()),
+        &key.2,
+        variant,
+    )
+}
+
+pub fn prepare_pipelines(assets: &AssetLibrary, state: &mut State) {
+    for material in assets.materials.iter() {
+        let key = material.pipeline_key();
+        if !state.renderer.pipelines.contains_key(&key) {
+            let pipeline = pipeline_for_key(state, assets, &key, PipelineVariant::Mesh);
+            state.renderer.pipelines.insert(key, pipeline);
+        }
+    }
+}
+
+fn rasterization_state(state: &State, render_state: &RenderState) -> RasterizationState {
+    let features = &state.renderer.enabled_features;
+    let polygon_mode = if render_state.polygon_mode != PolygonMode::Fill && !features.fill_mode_non_solid {
+        log::warn!("non-solid polygon modes are not supported, falling back to fill");
+        PolygonMode::Fill
+    } else {
+        render_state.polygon_mode
+    };
+    let line_width = if render_state.line_width != 1.0 && !features.wide_lines {
+        log::warn!("wide lines are not supported, falling back to a width of 1");
+        1.0
+    } else {
+        render_state.line_width
+    };
+    let depth_bias = render_state.depth_bias.map(|x| DepthBiasState {
+        constant_factor: x.constant_factor,
+        clamp: if features.depth_bias_clamp { x.clamp } else { 0.0 },
+        slope_factor: x.slope_factor,
+    });
+
+    let depth_clamp_enable = state.renderer.settings.depth_clamp && features.depth_clamp;
+    if state.renderer.settings.depth_clamp && !features.depth_clamp {
+        log::warn!("depth clamp is not supported, near and far planes still clip");
+    }
+
+    RasterizationState {
+        depth_clamp_enable,
+        polygon_mode,
+        cull_mode: render_state.cull_mode,
+        front_face: render_state.front_face,
+        depth_bias,
+        line_width,
+        ..Default::default()
+    }
+}
+
+fn create_pipeline(
+    state: &State,
+    vs: &Shader,
+    fs: &Shader,
+    render_state: &RenderState,
+    variant: PipelineVariant,
+) -> Arc<GraphicsPipeline> {
+    let name = format!("{} / {} ({:?})", vs.name, fs.name, variant);
     let vs = vs.module.as_ref().unwrap().entry_point("main").unwrap();
     let fs = fs.module.as_ref().unwrap().entry_point("main").unwrap();
 
-    let vertex_input_state = VertexData::per_vertex()
-        .definition(&vs.info().input_interface)
-        .unwrap();
+    let (vertex_input_state, topology) = if variant == PipelineVariant::Points {
+        (VertexInputState::new(), PrimitiveTopology::PointList)
+    } else {
+        let definition = VertexData::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+        (definition, PrimitiveTopology::TriangleList)
+    };
 
     let stages = [
         PipelineShaderStageCreateInfo::new(vs),
@@ -290,22 +804,32 @@ pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader) -> Arc<GraphicsPipe
 
     let subpass = Subpass::from(state.renderer.render_pass.as_ref().unwrap().clone(), 0).unwrap();
 
-    GraphicsPipeline::new(
+    let pipeline = GraphicsPipeline::new(
         state.renderer.device.as_ref().unwrap().clone(),
         None,
         GraphicsPipelineCreateInfo {
             stages: stages.into_iter().collect(),
             vertex_input_state: Some(vertex_input_state),
-            input_assembly_state: Some(InputAssemblyState::default()),
+            input_assembly_state: Some(InputAssemblyState {
+                topology,
+                ..Default::default()
+            }),
             viewport_state: Some(ViewportState {
                 viewports: [state.renderer.viewport.as_ref().unwrap().clone()]
                     .into_iter()
                     .collect(),
                 ..Default::default()
             }),
-            rasterization_state: Some(RasterizationState::default()),
+            rasterization_state: Some(rasterization_state(state, render_state)),
             depth_stencil_state: Some(DepthStencilState {
-                depth: Some(DepthState::simple()),
+                depth: Some(DepthState {
+                    write_enable: render_state.depth_write,
+                    compare_op: if render_state.depth_test {
+                        state.renderer.settings.depth_mode.compare_op()
+                    } else {
+                        CompareOp::Always
+                    },
+                }),
                 ..Default::default()
             }),
             multisample_state: Some(MultisampleState {
@@ -315,7 +839,7 @@ pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader) -> Arc<GraphicsPipe
             color_blend_state: Some(ColorBlendState::with_attachment_states(
                 subpass.num_color_attachments(),
                 ColorBlendAttachmentState {
-                    blend: Some(AttachmentBlend::alpha()),
+                    blend: render_state.blend_mode.attachment_blend(),
                     color_write_mask: ColorComponents::all(),
                     color_write_enable: true
                 },
@@ -324,7 +848,508 @@ pub fn get_pipeline(state: &State, vs: &Shader, fs: &Shader) -> Arc<GraphicsPipe
             ..GraphicsPipelineCreateInfo::layout(layout)
         },
     )
-    .unwrap()
+    .unwrap();
+    debug_labels::name_object(&state.renderer, pipeline.as_ref(), &name);
+    pipeline
+}
+
+// Vertex only, so the proxy writes neither color nor depth and only counts samples. Both faces are
+// drawn so a camera inside the bounds still sees the entity.
+fn create_occlusion_pipeline(state: &State, vs: &Shader) -> Arc<GraphicsPipeline> {
+    let device = state.renderer.device.as_ref().unwrap().clone();
+    let vs = vs.module.as_ref().unwrap().entry_point("main").unwrap();
+
+    let vertex_input_state = VertexData::per_vertex()
+        .definition(&vs.info().input_interface)
+        .unwrap();
+
+    let stages = [PipelineShaderStageCreateInfo::new(vs)];
+
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+
+    let subpass = Subpass::from(state.renderer.render_pass.as_ref().unwrap().clone(), 0).unwrap();
+
+    let pipeline = GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState {
+                viewports: [state.renderer.viewport.as_ref().unwrap().clone()]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            }),
+            rasterization_state: Some(RasterizationState {
+                polygon_mode: PolygonMode::Fill,
+                cull_mode: CullMode::None,
+                ..Default::default()
+            }),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState {
+                    write_enable: false,
+                    compare_op: state.renderer.settings.depth_mode.compare_op(),
+                }),
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples: SampleCount::Sample8,
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    color_write_mask: ColorComponents::empty(),
+                    ..Default::default()
+                },
+            )),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap();
+    debug_labels::name_object(&state.renderer, pipeline.as_ref(), "occlusion proxy");
+    pipeline
+}
+
+fn create_unit_cube(renderer: &Renderer) -> ProxyCube {
+    let corners = (0..8).map(|i| VertexData {
+        position: Vec3f::new([(i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2 & 1) as f32]),
+        uv: Vec2f::new([0.0, 0.0]),
+        normal: Vec3f::new([0.0, 0.0, 0.0]),
+        color: Color::WHITE,
+        uv2: Vec2f::new([0.0, 0.0]),
+    });
+    let indices: [u32; 36] = [
+        0, 2, 1, 1, 2, 3, 4, 5, 6, 5, 7, 6,
+        0, 1, 4, 1, 5, 4, 2, 6, 3, 3, 6, 7,
+        0, 4, 2, 2, 4, 6, 1, 3, 5, 3, 7, 5,
+    ];
+
+    let allocation_info = AllocationCreateInfo {
+        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+            | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        ..Default::default()
+    };
+    let vertex_buffer = Buffer::from_iter(
+        renderer.memeory_allocator.as_ref().unwrap().clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        },
+        allocation_info.clone(),
+        corners,
+    )
+    .unwrap();
+    let index_buffer = Buffer::from_iter(
+        renderer.memeory_allocator.as_ref().unwrap().clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::INDEX_BUFFER,
+            ..Default::default()
+        },
+        allocation_info,
+        indices,
+    )
+    .unwrap();
+    (vertex_buffer, index_buffer)
+}
+
+fn prepare_occlusion_queries(world: &World, assets: &AssetLibrary, state: &mut State) {
+    let Some(vs) = assets.shaders.iter().find(|x| x.name == builtin_shaders::OCCLUSION_PROXY_VS) else {
+        log::warn!("occlusion culling needs the built-in shaders, see `builtin_shaders::load`");
+        state.renderer.occlusion_query_pools = None;
+        return;
+    };
+    if state.renderer.occlusion_pipeline.is_none() {
+        state.renderer.occlusion_pipeline = Some(create_occlusion_pipeline(state, vs));
+    }
+    if state.renderer.occlusion_cube.is_none() {
+        state.renderer.occlusion_cube = Some(create_unit_cube(&state.renderer));
+    }
+
+    // One query per entity, see `record_occlusion_proxies`. Pools outlive re-recording, results of
+    // the submissions in flight are read from them.
+    let query_count = world.entity_count.max(1) as u32;
+    state.renderer.occluded.resize(world.entity_count, false);
+    let command_buffer_count = state.renderer.frames_in_flight * state.renderer.framebuffers.as_ref().unwrap().len();
+    if state
+        .renderer
+        .occlusion_query_pools
+        .as_ref()
+        .is_some_and(|x| x.len() == command_buffer_count && x[0].query_count() == query_count)
+    {
+        return;
+    }
+    state.renderer.occlusion_query_pools = Some(
+        (0..command_buffer_count)
+            .map(|_| {
+                QueryPool::new(
+                    state.renderer.device.as_ref().unwrap().clone(),
+                    QueryPoolCreateInfo {
+                        query_count,
+                        ..QueryPoolCreateInfo::query_type(QueryType::Occlusion)
+                    },
+                )
+                .unwrap()
+            })
+            .collect(),
+    );
+    // The new pools are reset by their first submission, nothing can be read from them before.
+    state.renderer.submitted_command_buffers.fill(None);
+}
+
+fn prepare_timestamp_queries(state: &mut State) {
+    let queue_family_index = state.renderer.queue.as_ref().unwrap().queue_family_index();
+    let physical_device = state.renderer.physical_device.as_ref().unwrap();
+    if physical_device.queue_family_properties()[queue_family_index as usize].timestamp_valid_bits.is_none() {
+        state.renderer.timestamp_query_pools = None;
+        return;
+    }
+
+    let command_buffer_count = state.renderer.frames_in_flight * state.renderer.framebuffers.as_ref().unwrap().len();
+    state.renderer.timestamp_query_pools = Some(
+        (0..command_buffer_count)
+            .map(|_| {
+                QueryPool::new(
+                    state.renderer.device.as_ref().unwrap().clone(),
+                    QueryPoolCreateInfo {
+                        query_count: GPU_PASSES.len() as u32 + 1,
+                        ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                    },
+                )
+                .unwrap()
+            })
+            .collect(),
+    );
+}
+
+#[derive(Pod, Zeroable, Clone, Copy, Debug)]
+#[repr(C)]
+struct ProxyBounds {
+    min: [f32; 4],
+    extent: [f32; 4],
+}
+
+// Draws the local bounds of every entity in `proxies` against the depth recorded so far, each in
+// the query at the entity's index. An entity with several meshes gets one proxy covering all of
+// them, so its query is begun once.
+fn record_occlusion_proxies(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    state: &State,
+    query_pool: &Arc<QueryPool>,
+    proxies: &[(usize, &Transform, Aabb)],
+    frame_i: usize,
+) {
+    let pipeline = state.renderer.occlusion_pipeline.as_ref().unwrap();
+    let (vertex_buffer, index_buffer) = state.renderer.occlusion_cube.clone().unwrap();
+    let layouts = pipeline.layout().set_layouts();
+    let vp_set = PersistentDescriptorSet::new(
+        descriptor_set_allocator,
+        layouts[0].clone(),
+        [WriteDescriptorSet::buffer(0, state.renderer.vp_buffer.as_ref().unwrap().buffer(frame_i))],
+        [],
+    )
+    .unwrap();
+
+    let index_count = index_buffer.len() as u32;
+    builder
+        .bind_pipeline_graphics(pipeline.clone())
+        .unwrap()
+        .bind_index_buffer(index_buffer)
+        .unwrap()
+        .bind_vertex_buffers(0, vertex_buffer)
+        .unwrap();
+    for (entity, transform, bounds) in proxies.iter() {
+        let m_set = PersistentDescriptorSet::new(
+            descriptor_set_allocator,
+            layouts[1].clone(),
+            [WriteDescriptorSet::buffer(0, transform.buffer.as_ref().unwrap().buffer(frame_i))],
+            [],
+        )
+        .unwrap();
+        // Grown a little so faces lying on the mesh, like those of a box, still pass the depth test.
+        let margin = (bounds.max - bounds.min) * 0.01 + Vec3f::new([1e-3; 3]);
+        let min = bounds.min - margin;
+        let extent = bounds.max + margin - min;
+        builder
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, (vp_set.clone(), m_set))
+            .unwrap()
+            .push_constants(
+                pipeline.layout().clone(),
+                0,
+                ProxyBounds {
+                    min: [min.x, min.y, min.z, 0.0],
+                    extent: [extent.x, extent.y, extent.z, 0.0],
+                },
+            )
+            .unwrap();
+        unsafe { builder.begin_query(query_pool.clone(), *entity as u32, QueryControlFlags::empty()) }.unwrap();
+        builder.draw_indexed(index_count, 1, 0, 0, 0).unwrap();
+        builder.end_query(query_pool.clone(), *entity as u32).unwrap();
+    }
+}
+
+fn update_gpu_time(state: &mut State) {
+    let Some(query_pools) = state.renderer.timestamp_query_pools.as_ref() else {
+        state.renderer.stats.gpu_time = None;
+        state.renderer.stats.gpu_passes.clear();
+        return;
+    };
+    let Some(command_buffer_i) = state.renderer.submitted_command_buffers[state.renderer.current_frame] else {
+        return;
+    };
+
+    let query_count = GPU_PASSES.len() as u32 + 1;
+    let mut results = vec![0u64; query_count as usize * 2];
+    query_pools[command_buffer_i]
+        .get_results(0..query_count, &mut results, QueryResultFlags::WITH_AVAILABILITY)
+        .unwrap();
+    if results.chunks(2).any(|x| x[1] == 0) {
+        return;
+    }
+    let period = state.renderer.physical_device.as_ref().unwrap().properties().timestamp_period as f64;
+    let elapsed = |from: usize, to: usize| {
+        Duration::from_nanos((results[to * 2].wrapping_sub(results[from * 2]) as f64 * period) as u64)
+    };
+    state.renderer.stats.gpu_time = Some(elapsed(0, GPU_PASSES.len()));
+    state.renderer.stats.gpu_passes = GPU_PASSES.iter().enumerate().map(|(i, name)| (*name, elapsed(i, i + 1))).collect();
+}
+
+fn write_pass_timestamp(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    query_pool: &Option<Arc<QueryPool>>,
+    query: u32,
+) {
+    if let Some(query_pool) = query_pool {
+        unsafe { builder.write_timestamp(query_pool.clone(), query, PipelineStage::BottomOfPipe) }.unwrap();
+    }
+}
+
+fn update_occlusion_results(state: &mut State) {
+    let Some(query_pools) = state.renderer.occlusion_query_pools.as_ref() else {
+        return;
+    };
+    let Some(command_buffer_i) = state.renderer.submitted_command_buffers[state.renderer.current_frame] else {
+        return;
+    };
+
+    let query_pool = &query_pools[command_buffer_i];
+    let query_count = query_pool.query_count();
+    let mut results = vec![0u64; query_count as usize * 2];
+    if let Err(e) = query_pool.get_results(0..query_count, &mut results, QueryResultFlags::WITH_AVAILABILITY) {
+        log::warn!("failed to read occlusion queries: {e}");
+        return;
+    }
+
+    let samples: Vec<Option<u64>> = results
+        .chunks(2)
+        .map(|x| (x[1] != 0).then_some(x[0]))
+        .collect();
+
+    for (entity, sample) in samples.iter().enumerate() {
+        if let (Some(sample), Some(occluded)) = (sample, state.renderer.occluded.get_mut(entity)) {
+            *occluded = *sample == 0;
+        }
+    }
+
+    state.renderer.stats.occluded = state.renderer.occluded.iter().filter(|x| **x).count();
+    state.renderer.stats.occlusion_samples = samples;
+}
+
+// Hides the meshes of occluded entities in the command buffer about to be submitted, its previous
+// submission has finished by now.
+fn write_occlusion_draws(state: &mut State, command_buffer_i: usize) {
+    let Some(Some(draws)) = state.renderer.occlusion_draws.get(command_buffer_i) else {
+        return;
+    };
+    for stats in state.renderer.stats.materials.values_mut() {
+        stats.occluded = 0;
+    }
+    let mut commands = draws.commands.write().unwrap();
+    let mut command = commands.iter_mut();
+    for (entity, material, index_count) in draws.slots.iter() {
+        let occluded = state.renderer.occluded.get(*entity).copied().unwrap_or(false);
+        if occluded {
+            state.renderer.stats.materials.entry(material.clone()).or_default().occluded += 1;
+        }
+        if let (Some(index_count), Some(command)) = (index_count, command.next()) {
+            *command = DrawIndexedIndirectCommand {
+                index_count: *index_count,
+                instance_count: !occluded as u32,
+                first_index: 0,
+                vertex_offset: 0,
+                first_instance: 0,
+            };
+        }
+    }
+}
+
+pub(crate) fn frame_descriptor_writes(state: &State, layout: &DescriptorSetLayout, frame_i: usize) -> Vec<WriteDescriptorSet> {
+    frame_descriptor_writes_with_vp(state, layout, frame_i, state.renderer.vp_buffer.as_ref().unwrap().buffer(frame_i))
+}
+
+pub(crate) fn frame_descriptor_writes_with_vp(
+    state: &State,
+    layout: &DescriptorSetLayout,
+    frame_i: usize,
+    vp_buffer: Subbuffer<VPData>,
+) -> Vec<WriteDescriptorSet> {
+    let mut writes = vec![WriteDescriptorSet::buffer(0, vp_buffer)];
+    if layout.bindings().contains_key(&1) {
+        writes.push(WriteDescriptorSet::buffer(
+            1,
+            state.renderer.fog_buffer.as_ref().unwrap().buffer(frame_i),
+        ));
+    }
+    writes.extend(shadows::descriptor_writes(state, layout, frame_i));
+    writes.extend(clusters::descriptor_writes(state, layout, frame_i));
+    writes.extend(ray_tracing::descriptor_writes(state, layout));
+    writes
+}
+
+fn model_descriptor_writes(
+    assets: &AssetLibrary,
+    layout: &DescriptorSetLayout,
+    transform: &Transform,
+    material: &Material,
+    morph: Option<&MorphWeights>,
+    frame_i: usize,
+) -> Vec<WriteDescriptorSet> {
+    let mut writes = vec![WriteDescriptorSet::buffer(
+        0,
+        transform.buffer.as_ref().unwrap().buffer(frame_i),
+    )];
+    if layout.bindings().contains_key(&1) {
+        writes.push(WriteDescriptorSet::buffer(
+            1,
+            material.buffer.as_ref().unwrap().buffer(frame_i),
+        ));
+    }
+    if layout.bindings().contains_key(&2) {
+        let name = material
+            .emissive_texture
+            .as_ref()
+            .unwrap_or_else(|| panic!("material {} has no emissive texture", material.name));
+        let texture = assets.textures.iter().find(|x| x.name == *name).unwrap();
+        writes.push(WriteDescriptorSet::image_view_sampler(
+            2,
+            texture.image_view.as_ref().unwrap().clone(),
+            texture.sampler.as_ref().unwrap().clone(),
+        ));
+    }
+    if layout.bindings().contains_key(&3) {
+        let name = material
+            .lightmap
+            .as_ref()
+            .unwrap_or_else(|| panic!("material {} has no lightmap", material.name));
+        let texture = assets.textures.iter().find(|x| x.name == *name).unwrap();
+        writes.push(WriteDescriptorSet::image_view_sampler(
+            3,
+            texture.image_view.as_ref().unwrap().clone(),
+            texture.sampler.as_ref().unwrap().clone(),
+        ));
+    }
+    if layout.bindings().contains_key(&4) {
+        let buffers = morph
+            .and_then(|x| x.buffers.as_ref())
+            .unwrap_or_else(|| panic!("material {} needs morph weights on the entity", material.name));
+        writes.push(WriteDescriptorSet::buffer(4, buffers.deltas.clone()));
+        writes.push(WriteDescriptorSet::buffer(5, buffers.weights[frame_i].clone()));
+    }
+    writes
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn bind_mesh_descriptor_sets(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    assets: &AssetLibrary,
+    state: &State,
+    pipeline: &Arc<GraphicsPipeline>,
+    transform: &Transform,
+    material: &Material,
+    morph: Option<&MorphWeights>,
+    texture_override: Option<&str>,
+    frame_i: usize,
+    vp_buffer: Subbuffer<VPData>,
+) {
+    let layouts = pipeline.layout().set_layouts();
+    let vp_set = PersistentDescriptorSet::new(
+        descriptor_set_allocator,
+        layouts[0].clone(),
+        frame_descriptor_writes_with_vp(state, &layouts[0], frame_i, vp_buffer),
+        [],
+    )
+    .unwrap();
+    let m_set = PersistentDescriptorSet::new(
+        descriptor_set_allocator,
+        layouts[1].clone(),
+        model_descriptor_writes(assets, &layouts[1], transform, material, morph, frame_i),
+        [],
+    )
+    .unwrap();
+
+    if material.attachments.is_empty() {
+        builder
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, (vp_set, m_set))
+            .unwrap();
+        return;
+    }
+
+    let att_set = PersistentDescriptorSet::new(
+        descriptor_set_allocator,
+        layouts[2].clone(),
+        material
+            .attachments
+            .iter()
+            .enumerate()
+            .map(|(binding, attachment)| {
+                if let Attachment::Texture(tex) = attachment {
+                    let tex = texture_override.filter(|_| binding == 0).unwrap_or(tex);
+                    let texture = assets.textures.iter().find(|x| x.name == *tex).unwrap();
+                    WriteDescriptorSet::image_view_sampler(
+                        binding as u32,
+                        texture.image_view.as_ref().unwrap().clone(),
+                        texture.sampler.as_ref().unwrap().clone(),
+                    )
+                } else {
+                    panic!("not impl");
+                }
+            })
+            .collect::<Vec<_>>(),
+        [],
+    )
+    .unwrap();
+    builder
+        .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, (vp_set, m_set, att_set))
+        .unwrap();
+}
+
+fn prepare_materials(assets: &mut AssetLibrary, state: &mut State, recreate: bool) {
+    let time = state.time as f32;
+    for material in assets.materials.iter_mut() {
+        match material.buffer.as_ref() {
+            Some(buffer) if !recreate => buffer.write(state, material.to_data(time)),
+            _ => {
+                let buffer = UpdatableBuffer::new(&state.renderer, BufferUsage::UNIFORM_BUFFER);
+                for frame in buffer.buffers.iter() {
+                    debug_labels::name_object(&state.renderer, frame.buffer().as_ref(), &format!("material {}", material.name));
+                }
+                buffer.write_all(state, material.to_data(time));
+                material.buffer = Some(buffer);
+                state.renderer.command_buffer_outdated = true;
+            }
+        }
+    }
 }
 
 fn update_command_buffers(world: &World, assets: &AssetLibrary, state: &mut State) {
@@ -337,24 +1362,108 @@ fn update_command_buffers(world: &World, assets: &AssetLibrary, state: &mut Stat
         Default::default(),
     );
 
-    state.renderer.command_buffers = Some(
-        state.renderer.framebuffers.as_ref().unwrap().iter()
-            .map(|framebuffer| {
-                let mut transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
-
+    if state.renderer.settings.occlusion_culling {
+        prepare_occlusion_queries(world, assets, state);
+    } else {
+        state.renderer.occlusion_query_pools = None;
+    }
+    prepare_timestamp_queries(state);
+    prepare_pipelines(assets, state);
+    reflections::prepare_pipelines(assets, state);
+    skinning::prepare_pipeline(world, assets, state);
+    point_cloud::prepare_pipelines(world, assets, state);
+    shadows::prepare_pipelines(world, assets, state);
+    post_process::prepare(assets, state);
+    meshlet::prepare_cluster_draws(world, assets, state);
+    ray_tracing::prepare(world, assets, state);
+
+    let frames_in_flight = state.renderer.frames_in_flight;
+    state.renderer.depth_draws = (0..frames_in_flight).map(|frame_i| depth_pass::gather(world, assets, frame_i)).collect();
+    let async_compute = state.renderer.compute_queue.is_some();
+    state.renderer.compute_command_buffers = state.renderer.compute_queue.as_ref().map(|queue| {
+        (0..frames_in_flight)
+            .map(|frame_i| {
+                let mut builder = AutoCommandBufferBuilder::primary(
+                    &command_buffer_allocator,
+                    queue.queue_family_index(),
+                    CommandBufferUsage::MultipleSubmit,
+                )
+                .unwrap();
+                debug_labels::begin_pass(&mut builder, &state.renderer, "skinning");
+                skinning::record_skinning(&mut builder, world, state, &descriptor_set_allocator, frame_i);
+                debug_labels::end(&mut builder, &state.renderer);
+                builder.build().unwrap()
+            })
+            .collect()
+    });
+    let command_buffers: Vec<_> = (0..frames_in_flight)
+            .flat_map(|frame_i| {
+                let framebuffers = state.renderer.framebuffers.as_ref().unwrap().iter();
+                framebuffers.zip(state.renderer.images.as_ref().unwrap().iter()).map(move |(x, y)| (frame_i, x, y))
+            })
+            .enumerate()
+            .map(|(command_buffer_i, (frame_i, framebuffer, image))| {
                 let mut builder = AutoCommandBufferBuilder::primary(
                     &command_buffer_allocator,
                     state.renderer.queue.as_ref().unwrap().queue_family_index(),
                     CommandBufferUsage::MultipleSubmit,
                 ).unwrap();
+                let timestamp_pool = state.renderer.timestamp_query_pools.as_ref().map(|x| x[command_buffer_i].clone());
+                if let Some(timestamp_pool) = timestamp_pool.as_ref() {
+                    unsafe {
+                        builder.reset_query_pool(timestamp_pool.clone(), 0..timestamp_pool.query_count()).unwrap();
+                        builder.write_timestamp(timestamp_pool.clone(), 0, PipelineStage::TopOfPipe).unwrap();
+                    }
+                }
+                if !async_compute {
+                    debug_labels::begin_pass(&mut builder, &state.renderer, "skinning");
+                    skinning::record_skinning(&mut builder, world, state, &descriptor_set_allocator, frame_i);
+                    debug_labels::end(&mut builder, &state.renderer);
+                }
+                write_pass_timestamp(&mut builder, &timestamp_pool, 1);
+                debug_labels::begin_pass(&mut builder, &state.renderer, "shadows");
+                shadows::record_shadow_passes(&mut builder, state, &descriptor_set_allocator, frame_i);
+                debug_labels::end(&mut builder, &state.renderer);
+                write_pass_timestamp(&mut builder, &timestamp_pool, 2);
+                debug_labels::begin_pass(&mut builder, &state.renderer, "reflections");
+                reflections::record_reflection_passes(&mut builder, world, assets, state, &descriptor_set_allocator, frame_i);
+                debug_labels::end(&mut builder, &state.renderer);
+                write_pass_timestamp(&mut builder, &timestamp_pool, 3);
+                let context = RenderContext {
+                    world,
+                    assets,
+                    state,
+                    descriptor_set_allocator: &descriptor_set_allocator,
+                    frame_i,
+                    framebuffer,
+                    swapchain_image: image,
+                };
+                render_callbacks::record(RenderStage::BeforeScene, &mut builder, &context);
+
+                let transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+                let visibilities = world.borrow_component_vec_mut::<Visibility>();
+                let is_visible = |entity: usize| {
+                    visibilities.as_ref().and_then(|x| x[entity]).is_none_or(|x| x.visible)
+                };
 
+                let mut draw_calls = 0;
+                let mut triangles = 0;
+                let mut material_stats: HashMap<String, MaterialStats> = HashMap::new();
+                let mut batch_labels = BatchLabels::default();
+
+                let query_pool = state.renderer.occlusion_query_pools.as_ref().map(|x| x[command_buffer_i].clone());
+                if let Some(query_pool) = query_pool.as_ref() {
+                    unsafe { builder.reset_query_pool(query_pool.clone(), 0..query_pool.query_count()) }.unwrap();
+                }
+
+                debug_labels::begin_pass(&mut builder, &state.renderer, "scene");
                 builder
                     .begin_render_pass(
                         RenderPassBeginInfo {
                             clear_values: vec![
-                                Some([0.0, 0.0, 0.0, 1.0].into()),
-                                Some([0.0, 0.0, 0.0, 1.0].into()),
-                                Some(1f32.into()),
+                                Some(state.renderer.settings.clear_color.to_array().into()),
+                                Some(state.renderer.settings.clear_color.to_array().into()),
+                                Some(state.renderer.settings.depth_mode.clear_value().into()),
                             ],
                             ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
                         },
@@ -364,198 +1473,226 @@ fn update_command_buffers(world: &World, assets: &AssetLibrary, state: &mut Stat
                         },
                     ).unwrap();
 
-                if let Some(mut static_meshes) = world.borrow_component_vec_mut::<StaticMesh>() {
-                    let static_zip = static_meshes.iter_mut().zip(transforms.iter_mut());
-                    let mut static_vec: Vec<_> = static_zip.filter_map(|(mesh, transform)| Some((mesh.as_mut()?, transform.as_mut()?))).collect();
-                    static_vec.sort_by(|a, b| (a.1.position - state.renderer.vp_pos).length_sqr().total_cmp(&(b.1.position - state.renderer.vp_pos).length_sqr()));
-
-                    for (static_mesh, transform) in static_vec.iter() {
+                let static_meshes = world.borrow_component_vec_mut::<StaticMesh>();
+                let dynamic_meshes = world.borrow_component_vec_mut::<DynamicMesh>();
+                let skinned_meshes = world.borrow_component_vec_mut::<SkinnedMesh>();
+                let morph_weights = world.borrow_component_vec_mut::<MorphWeights>();
+                let material_overrides = world.borrow_component_vec_mut::<MaterialOverride>();
+                let mut draws = Vec::new();
+                for (entity, transform) in transforms.iter().enumerate() {
+                    let Some(transform) = transform.as_ref() else {
+                        continue;
+                    };
+                    let distance = (transform.position - state.renderer.vp_pos).length_sqr();
+                    let morph = morph_weights.as_ref().and_then(|x| x[entity].as_ref());
+                    let texture_override = material_overrides.as_ref().and_then(|x| x.get(entity)?.as_ref()?.texture.as_deref());
+                    if let Some(static_mesh) = static_meshes.as_ref().and_then(|x| x[entity].as_ref()) {
                         let mesh = assets.meshes.iter().find(|x| x.name == static_mesh.mesh_name).unwrap();
-                        let material = assets.materials.iter().find(|x| x.name == mesh.material).unwrap();
-                        let pipeline = state
-                            .renderer
-                            .pipelines
-                            .get(&(material.vertex_shader.clone(), material.fragment_shader.clone()))
-                            .unwrap()
-                            .clone();
-
-                        builder
-                            .bind_pipeline_graphics(pipeline.clone())
-                            .unwrap();
-
-                        let vp_set = PersistentDescriptorSet::new(
-                            &descriptor_set_allocator,
-                            pipeline.layout().set_layouts().first().unwrap().clone(),
-                            [WriteDescriptorSet::buffer(
-                                0,
-                                state
-                                .renderer
-                                .vp_buffer
-                                .as_ref()
-                                .unwrap()
-                                .buffer
-                                .clone(),
-                                )],
-                            [],
-                            )
-                            .unwrap();
-
-                        let m_set = PersistentDescriptorSet::new(
-                            &descriptor_set_allocator,
-                            pipeline.layout().set_layouts().get(1).unwrap().clone(),
-                            [WriteDescriptorSet::buffer(
-                                0,
-                                transform.buffer.as_ref().unwrap().buffer.clone(),
-                                )],
-                            [],
-                            )
-                            .unwrap();
-
-                        if !material.attachments.is_empty() {
-                            let att_set = PersistentDescriptorSet::new(
-                                &descriptor_set_allocator,
-                                pipeline.layout().set_layouts().get(2).unwrap().clone(),
-                                material.attachments.iter().map(
-                                    |attachement| {
-                                        if let Attachment::Texture(tex) = attachement {
-                                            let texture = assets.textures.iter().find(|x| x.name == *tex).unwrap();
-                                            WriteDescriptorSet::image_view_sampler(
-                                                0, 
-                                                texture.image_view.as_ref().unwrap().clone(), 
-                                                texture.sampler.as_ref().unwrap().clone()
-                                                )
-                                        } else {
-                                            panic!("not impl");
-                                        }
-                                    }
-                                    ).collect::<Vec<_>>(), 
-                                [],
-                                ).unwrap();
-
-                            builder.bind_descriptor_sets(
-                                PipelineBindPoint::Graphics,
-                                pipeline.layout().clone(),
-                                0,
-                                (vp_set.clone(), m_set.clone(), att_set.clone()),
-                                ).unwrap();
-                        } else {
-                            builder.bind_descriptor_sets(
-                                PipelineBindPoint::Graphics,
-                                pipeline.layout().clone(),
-                                0,
-                                (vp_set.clone(), m_set.clone()),
-                                ).unwrap();
+                        draws.push(MeshDraw {
+                            entity,
+                            transform,
+                            material: assets.materials.iter().find(|x| x.name == mesh.material).unwrap(),
+                            vertices: &mesh.vertices,
+                            vertex_buffer: mesh.vertex_buffer.as_ref().unwrap().clone(),
+                            index_buffer: mesh.index_buffer.as_ref().unwrap().clone(),
+                            index_count: mesh.index_buffer.as_ref().unwrap().len() as u32,
+                            morph,
+                            texture_override,
+                            distance,
+                            clusters: state.renderer.cluster_draws.get(&entity).map(|x| x[frame_i].clone()),
+                        });
+                    }
+                    if let Some(dynamic_mesh) = dynamic_meshes.as_ref().and_then(|x| x[entity].as_ref()) {
+                        let buffers = dynamic_mesh.buffers.as_ref().unwrap();
+                        draws.push(MeshDraw {
+                            entity,
+                            transform,
+                            material: assets.materials.iter().find(|x| x.name == dynamic_mesh.material).unwrap(),
+                            vertices: &dynamic_mesh.vertices,
+                            vertex_buffer: buffers.vertex[frame_i].clone(),
+                            index_buffer: buffers.index[frame_i].clone(),
+                            index_count: dynamic_mesh.indices.len() as u32,
+                            morph: None,
+                            texture_override,
+                            distance,
+                            clusters: None,
+                        });
+                    }
+                    if let Some(skinned_mesh) = skinned_meshes.as_ref().and_then(|x| x[entity].as_ref()) {
+                        let mesh = assets.meshes.iter().find(|x| x.name == skinned_mesh.mesh_name).unwrap();
+                        draws.push(MeshDraw {
+                            entity,
+                            transform,
+                            material: assets.materials.iter().find(|x| x.name == mesh.material).unwrap(),
+                            vertices: &mesh.vertices,
+                            vertex_buffer: skinned_mesh.buffers.as_ref().unwrap().output[frame_i].clone(),
+                            index_buffer: mesh.index_buffer.as_ref().unwrap().clone(),
+                            index_count: mesh.index_buffer.as_ref().unwrap().len() as u32,
+                            morph,
+                            texture_override,
+                            distance,
+                            clusters: None,
+                        });
+                    }
+                }
+                draws.retain(|x| {
+                    let visible = is_visible(x.entity);
+                    if !visible {
+                        material_stats.entry(x.material.name.clone()).or_default().culled += 1;
+                    }
+                    visible
+                });
+                // Draws are still in entity order, so an entity's meshes are next to each other.
+                let mut proxies: Vec<(usize, &Transform, Aabb)> = Vec::new();
+                if query_pool.is_some() {
+                    for draw in draws.iter() {
+                        let bounds = Aabb::from_points(draw.vertices.iter().map(|x| x.position));
+                        match proxies.last_mut() {
+                            Some((entity, _, proxy)) if *entity == draw.entity => *proxy = proxy.union(&bounds),
+                            _ => proxies.push((draw.entity, draw.transform, bounds)),
                         }
-
-                        builder
-                            .bind_index_buffer(mesh.index_buffer.as_ref().unwrap().clone())
-                            .unwrap()
-                            .bind_vertex_buffers(0, mesh.vertex_buffer.as_ref().unwrap().clone())
-                            .unwrap()
-                            .draw_indexed(
-                                mesh.index_buffer.as_ref().unwrap().len() as u32, 1, 0, 0, 0)
-                            .unwrap();
                     }
-                };
+                }
+                draws.sort_by(|a, b| {
+                    let by_distance = a.distance.total_cmp(&b.distance);
+                    a.material.sort_key().cmp(&b.material.sort_key()).then(match a.material.queue {
+                        RenderQueue::Transparent => by_distance.reverse(),
+                        _ => by_distance,
+                    })
+                });
+
+                let mut occlusion_draws = query_pool.is_some().then(|| OcclusionDraws {
+                    commands: meshlet::indirect_buffer(&state.renderer, draws.iter().filter(|x| x.clusters.is_none()).count().max(1)),
+                    slots: Vec::new(),
+                });
+
+                // Proxies go after the queues that write depth, so they are tested against every
+                // opaque mesh regardless of draw order.
+                let mut proxies_pending = query_pool.is_some();
+                for draw in draws.iter() {
+                    let material = draw.material;
+                    if proxies_pending && matches!(material.queue, RenderQueue::Transparent | RenderQueue::Overlay) {
+                        proxies_pending = false;
+                        batch_labels.finish(&mut builder, &state.renderer);
+                        debug_labels::begin_pass(&mut builder, &state.renderer, "occlusion proxies");
+                        record_occlusion_proxies(&mut builder, &descriptor_set_allocator, state, query_pool.as_ref().unwrap(), &proxies, frame_i);
+                        debug_labels::end(&mut builder, &state.renderer);
+                        draw_calls += proxies.len();
+                        triangles += proxies.len() * 12;
+                    }
 
-                if let Some(mut dynamic_meshes) = world.borrow_component_vec_mut::<DynamicMesh>() {
-                    let dynamic_zip = dynamic_meshes.iter_mut().zip(transforms.iter_mut());
-                    let mut dynamic_vec: Vec<_> = dynamic_zip.filter_map(|(mesh, transform)| Some((mesh.as_mut()?, transform.as_mut()?))).collect();
-                    dynamic_vec.sort_by(|a, b| (a.1.position - state.renderer.vp_pos).length_sqr().total_cmp(&(b.1.position - state.renderer.vp_pos).length_sqr()));
-
-                    for (dynamic_mesh, transform) in dynamic_vec.iter() {
-                        let material = assets.materials.iter().find(|x| x.name == dynamic_mesh.material).unwrap();
-                        let pipeline = state
-                            .renderer
-                            .pipelines
-                            .get(&(material.vertex_shader.clone(), material.fragment_shader.clone()))
-                            .unwrap()
-                            .clone();
-
-                        builder
-                            .bind_pipeline_graphics(pipeline.clone())
-                            .unwrap();
-
-                        let vp_set = PersistentDescriptorSet::new(
-                            &descriptor_set_allocator,
-                            pipeline.layout().set_layouts().first().unwrap().clone(),
-                            [WriteDescriptorSet::buffer(
-                                0,
-                                state
-                                .renderer
-                                .vp_buffer
-                                .as_ref()
-                                .unwrap()
-                                .buffer
-                                .clone(),
-                                )],
-                            [],
-                            )
-                            .unwrap();
-
-                        let m_set = PersistentDescriptorSet::new(
-                            &descriptor_set_allocator,
-                            pipeline.layout().set_layouts().get(1).unwrap().clone(),
-                            [WriteDescriptorSet::buffer(
-                                0,
-                                transform.buffer.as_ref().unwrap().buffer.clone(),
-                                )],
-                            [],
-                            )
-                            .unwrap();
-
-                        if !material.attachments.is_empty() {
-                            let att_set = PersistentDescriptorSet::new(
-                                &descriptor_set_allocator,
-                                pipeline.layout().set_layouts().get(2).unwrap().clone(),
-                                material.attachments.iter().map(
-                                    |attachement| {
-                                        if let Attachment::Texture(tex) = attachement {
-                                            let texture = assets.textures.iter().find(|x| x.name == *tex).unwrap();
-                                            WriteDescriptorSet::image_view_sampler(
-                                                0, 
-                                                texture.image_view.as_ref().unwrap().clone(), 
-                                                texture.sampler.as_ref().unwrap().clone()
-                                                )
-                                        } else {
-                                            panic!("not impl");
-                                        }
-                                    }
-                                    ).collect::<Vec<_>>(), 
-                                [],
-                                ).unwrap();
-
-                            builder.bind_descriptor_sets(
-                                PipelineBindPoint::Graphics,
-                                pipeline.layout().clone(),
-                                0,
-                                (vp_set.clone(), m_set.clone(), att_set.clone()),
-                                ).unwrap();
-                        } else {
-                            builder.bind_descriptor_sets(
-                                PipelineBindPoint::Graphics,
-                                pipeline.layout().clone(),
-                                0,
-                                (vp_set.clone(), m_set.clone()),
-                                ).unwrap();
+                    let stats = material_stats.entry(material.name.clone()).or_default();
+                    stats.submitted += 1;
+                    stats.triangles += draw.index_count as usize / 3;
+                    triangles += draw.index_count as usize / 3;
+                    draw_calls += 1;
+
+                    batch_labels.switch(&mut builder, &state.renderer, &material.name);
+                    let pipeline = state
+                        .renderer
+                        .pipelines
+                        .get(&material.pipeline_key())
+                        .unwrap()
+                        .clone();
+
+                    builder
+                        .bind_pipeline_graphics(pipeline.clone())
+                        .unwrap();
+                    bind_mesh_descriptor_sets(
+                        &mut builder,
+                        &descriptor_set_allocator,
+                        assets,
+                        state,
+                        &pipeline,
+                        draw.transform,
+                        material,
+                        draw.morph,
+                        draw.texture_override,
+                        frame_i,
+                        state.renderer.vp_buffer.as_ref().unwrap().buffer(frame_i),
+                    );
+                    builder
+                        .bind_index_buffer(draw.index_buffer.clone())
+                        .unwrap()
+                        .bind_vertex_buffers(0, draw.vertex_buffer.clone())
+                        .unwrap();
+                    match (draw.clusters.clone(), occlusion_draws.as_mut()) {
+                        (Some(clusters), _) => {
+                            if let Some(occlusion_draws) = occlusion_draws.as_mut() {
+                                occlusion_draws.slots.push((draw.entity, material.name.clone(), None));
+                            }
+                            builder.draw_indexed_indirect(clusters).unwrap()
                         }
-
-                        builder
-                            .bind_index_buffer(dynamic_mesh.index_buffer.as_ref().unwrap().clone())
-                            .unwrap()
-                            .bind_vertex_buffers(0, dynamic_mesh.vertex_buffer.as_ref().unwrap().clone())
-                            .unwrap()
-                            .draw_indexed(
-                                dynamic_mesh.indices.len() as u32, 1, 0, 0, 0)
-                            .unwrap();
-                    }
+                        (None, Some(occlusion_draws)) => {
+                            let i = occlusion_draws.slots.iter().filter(|x| x.2.is_some()).count() as u64;
+                            occlusion_draws.slots.push((draw.entity, material.name.clone(), Some(draw.index_count)));
+                            builder.draw_indexed_indirect(occlusion_draws.commands.clone().slice(i..i + 1)).unwrap()
+                        }
+                        (None, None) => builder.draw_indexed(draw.index_count, 1, 0, 0, 0).unwrap(),
+                    };
+                }
+                batch_labels.finish(&mut builder, &state.renderer);
+                if proxies_pending {
+                    debug_labels::begin_pass(&mut builder, &state.renderer, "occlusion proxies");
+                    record_occlusion_proxies(&mut builder, &descriptor_set_allocator, state, query_pool.as_ref().unwrap(), &proxies, frame_i);
+                    debug_labels::end(&mut builder, &state.renderer);
+                    draw_calls += proxies.len();
+                    triangles += proxies.len() * 12;
                 }
+                drop(static_meshes);
+                drop(dynamic_meshes);
+                drop(skinned_meshes);
+                batch_labels.finish(&mut builder, &state.renderer);
+                draw_calls += point_cloud::record(
+                    &mut builder,
+                    world,
+                    assets,
+                    state,
+                    &descriptor_set_allocator,
+                    frame_i,
+                    &transforms,
+                    &is_visible,
+                );
+                drop(transforms);
+                drop(visibilities);
+                render_callbacks::record(RenderStage::Ui, &mut builder, &context);
 
                 builder.end_render_pass(Default::default()).unwrap();
-                builder.build().unwrap()
+                debug_labels::end(&mut builder, &state.renderer);
+                render_callbacks::record(RenderStage::AfterScene, &mut builder, &context);
+                write_pass_timestamp(&mut builder, &timestamp_pool, 4);
+                debug_labels::begin_pass(&mut builder, &state.renderer, "post processing");
+                post_process::record(&mut builder, state, &descriptor_set_allocator, frame_i, image.clone());
+                debug_labels::end(&mut builder, &state.renderer);
+                render_callbacks::record(RenderStage::AfterPost, &mut builder, &context);
+                write_pass_timestamp(&mut builder, &timestamp_pool, 5);
+                (builder.build().unwrap(), draw_calls, triangles, material_stats, occlusion_draws)
             })
-            .collect(),
-    )
+            .collect();
+
+    if let Some((_, draw_calls, triangles, material_stats, _)) = command_buffers.first() {
+        state.renderer.stats.draw_calls = *draw_calls;
+        state.renderer.stats.triangles = *triangles;
+        for stats in state.renderer.stats.materials.values_mut() {
+            *stats = MaterialStats {
+                uploaded_bytes: stats.uploaded_bytes,
+                ..Default::default()
+            };
+        }
+        for (name, stats) in material_stats.iter() {
+            let entry = state.renderer.stats.materials.entry(name.clone()).or_default();
+            *entry = MaterialStats {
+                uploaded_bytes: entry.uploaded_bytes,
+                ..stats.clone()
+            };
+        }
+    }
+    let (command_buffers, occlusion_draws): (Vec<_>, Vec<_>) = command_buffers.into_iter().map(|x| (x.0, x.4)).unzip();
+    state.renderer.command_buffers = Some(command_buffers);
+    state.renderer.occlusion_draws = occlusion_draws;
+    for command_buffer_i in 0..state.renderer.occlusion_draws.len() {
+        write_occlusion_draws(state, command_buffer_i);
+    }
 }
 
 fn get_swapchain(state: &mut State) {
@@ -571,9 +1708,22 @@ fn get_swapchain(state: &mut State) {
             )
             .expect("failed to get surface capabilities");
 
-        let dimensions = state.window.window_handle.inner_size();
-        let composite_alpha = caps.supported_composite_alpha.into_iter().next().unwrap();
-        let image_format = state
+        let dimensions = caps.current_extent.unwrap_or(state.window.window_handle.inner_size().into());
+        // Android surfaces often only offer Inherit.
+        let composite_alpha = if state.renderer.settings.window.transparent {
+            [CompositeAlpha::PreMultiplied, CompositeAlpha::PostMultiplied, CompositeAlpha::Inherit]
+                .into_iter()
+                .find(|x| caps.supported_composite_alpha.contains_enum(*x))
+                .unwrap_or_else(|| {
+                    log::warn!("surface does not support transparency");
+                    caps.supported_composite_alpha.into_iter().next().unwrap()
+                })
+        } else if caps.supported_composite_alpha.contains_enum(CompositeAlpha::Opaque) {
+            CompositeAlpha::Opaque
+        } else {
+            caps.supported_composite_alpha.into_iter().next().unwrap()
+        };
+        let surface_formats = state
             .renderer
             .physical_device
             .as_ref()
@@ -582,18 +1732,68 @@ fn get_swapchain(state: &mut State) {
                 state.renderer.surface.as_ref().unwrap(),
                 Default::default(),
             )
-            .unwrap()[0]
-            .0;
+            .unwrap();
+        let (image_format, image_color_space) = state
+            .renderer
+            .settings
+            .surface_formats
+            .iter()
+            .find_map(|format| surface_formats.iter().find(|x| x.0 == *format).copied())
+            .unwrap_or_else(|| {
+                log::warn!("No preferred surface format available, using {:?}", surface_formats[0].0);
+                surface_formats[0]
+            });
+
+        let present_modes: Vec<PresentMode> = state
+            .renderer
+            .physical_device
+            .as_ref()
+            .unwrap()
+            .surface_present_modes(state.renderer.surface.as_ref().unwrap(), Default::default())
+            .unwrap()
+            .collect();
+        let present_mode = state
+            .renderer
+            .settings
+            .present_modes
+            .iter()
+            .copied()
+            .find(|x| present_modes.contains(x))
+            .unwrap_or(PresentMode::Fifo);
+        // Rotated mobile displays report a rotated current transform, presenting with identity
+        // lets the compositor rotate instead of the projection.
+        let pre_transform = if caps.supported_transforms.contains_enum(SurfaceTransform::Identity) {
+            SurfaceTransform::Identity
+        } else {
+            caps.current_transform
+        };
+
+        let max_image_count = caps.max_image_count.unwrap_or(u32::MAX);
+        let min_image_count = match state.renderer.settings.swapchain_image_count {
+            Some(count) if count < caps.min_image_count || count > max_image_count => {
+                let clamped = count.clamp(caps.min_image_count, max_image_count);
+                log::info!("Swapchain image count {} unsupported, using {}", count, clamped);
+                clamped
+            }
+            Some(count) => count,
+            None => caps.min_image_count,
+        };
 
         Swapchain::new(
             state.renderer.device.as_ref().unwrap().clone(),
             state.renderer.surface.as_ref().unwrap().clone(),
             SwapchainCreateInfo {
-                min_image_count: caps.min_image_count,
+                min_image_count,
                 image_format,
-                image_extent: dimensions.into(),
-                image_usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_DST,
+                image_color_space,
+                image_extent: dimensions,
+                // Transfer source allows frame captures.
+                image_usage: ImageUsage::COLOR_ATTACHMENT
+                    | ImageUsage::TRANSFER_DST
+                    | (caps.supported_usage_flags & ImageUsage::TRANSFER_SRC),
                 composite_alpha,
+                pre_transform,
+                present_mode,
                 ..Default::default()
             },
         )
@@ -603,12 +1803,39 @@ fn get_swapchain(state: &mut State) {
     state.renderer.images = Some(images);
 }
 
+// The surface's extent when it has one, mobile surfaces swap width and height on rotation before
+// the window reports a resize.
+fn surface_extent(state: &State) -> [u32; 2] {
+    let window_extent = state.window.window_handle.inner_size().into();
+    state
+        .renderer
+        .physical_device
+        .as_ref()
+        .unwrap()
+        .surface_capabilities(state.renderer.surface.as_ref().unwrap(), Default::default())
+        .ok()
+        .and_then(|x| x.current_extent)
+        .unwrap_or(window_extent)
+}
+
+fn update_ui_projection(state: &mut State, extent: [u32; 2]) {
+    let scale = state.window.scale_factor() as f32;
+    let [width, height] = extent.map(|x| x as f32 / scale);
+    state.renderer.vp_data.ui_projection = Matrix4f::orthographic(0.0, width, 0.0, height, -1.0, 1.0);
+}
+
 fn handle_possible_resize(world: &World, assets: &AssetLibrary, state: &mut State) {
     if state.renderer.window_resized || state.renderer.recreate_swapchain {
+        // Minimized, or the surface is gone until the app resumes.
+        let extent = surface_extent(state);
+        if extent.contains(&0) {
+            return;
+        }
+        wait_for_idle(&mut state.renderer);
         state.renderer.recreate_swapchain = false;
         state.renderer.window_resized = false;
 
-        let new_dimensions = state.window.window_handle.inner_size();
+        let new_dimensions = winit::dpi::PhysicalSize::new(extent[0], extent[1]);
 
         let (new_swapchain, new_images) = state
             .renderer
@@ -631,7 +1858,7 @@ fn handle_possible_resize(world: &World, assets: &AssetLibrary, state: &mut Stat
         let mut iter =
             zip.filter_map(|(camera, transform)| Some((camera.as_ref()?, transform.as_ref()?)));
         let (camera_data, _) = iter.next().unwrap();
-        state.renderer.vp_data.projection = Matrix4f::perspective(
+        state.renderer.vp_data.projection = state.renderer.settings.depth_mode.projection(
             camera_data.vfov.to_radians(),
             (new_dimensions.width as f32) / (new_dimensions.height as f32),
             camera_data.near,
@@ -639,25 +1866,17 @@ fn handle_possible_resize(world: &World, assets: &AssetLibrary, state: &mut Stat
         );
 
         state.renderer.viewport.as_mut().unwrap().extent = new_dimensions.into();
-        let iter: Vec<(String, String)> =
+        update_ui_projection(state, extent);
+        let iter: Vec<PipelineKey> =
             state.renderer.pipelines.keys().cloned().collect();
-        for pipeline in iter.iter() {
-            state.renderer.pipelines.insert(
-                pipeline.clone(),
-                get_pipeline(
-                    state,
-                    assets
-                        .shaders
-                        .iter().find(|x| x.name == pipeline.0)
-                        .unwrap(),
-                    assets
-                        .shaders
-                        .iter().find(|x| x.name == pipeline.1)
-                        .unwrap(),
-                ),
-            );
+        for key in iter.iter() {
+            let pipeline = pipeline_for_key(state, assets, key, PipelineVariant::Mesh);
+            state.renderer.pipelines.insert(key.clone(), pipeline);
         }
 
+        state.renderer.occlusion_pipeline = None;
+        state.renderer.point_pipelines.clear();
+
         drop(camera);
         drop(transform);
         update_command_buffers(world, assets, state);
@@ -668,19 +1887,71 @@ fn handle_possible_resize(world: &World, assets: &AssetLibrary, state: &mut Stat
     }
 }
 
+// Public for the benchmarks, which run it on a device from `init_device_only`.
+pub fn prepare_dynamic_meshes(world: &World, renderer: &mut Renderer) {
+    let Some(mut dynamic_meshes) = world.borrow_component_vec_mut::<DynamicMesh>() else {
+        return;
+    };
+
+    renderer.mesh_arena.advance_frame();
+    for dynamic_mesh in dynamic_meshes.iter_mut().flatten() {
+        if dynamic_mesh.buffers.is_none() {
+            dynamic_mesh.load(renderer);
+            renderer.command_buffer_outdated = true;
+        } else if !dynamic_mesh.fits_buffers() {
+            wait_for_idle(renderer);
+            dynamic_mesh.load(renderer);
+            renderer.command_buffer_outdated = true;
+        }
+    }
+}
+
+pub fn upload_dynamic_meshes(world: &World, renderer: &mut Renderer, frame: usize) {
+    renderer.stats.materials.values_mut().for_each(|x| x.uploaded_bytes = 0);
+    let Some(mut dynamic_meshes) = world.borrow_component_vec_mut::<DynamicMesh>() else {
+        return;
+    };
+
+    for dynamic_mesh in dynamic_meshes.iter_mut().flatten() {
+        let bytes = dynamic_mesh.upload(frame);
+        if bytes > 0 {
+            renderer.stats.materials.entry(dynamic_mesh.material.clone()).or_default().uploaded_bytes += bytes;
+        }
+    }
+}
+
 #[allow(clippy::arc_with_non_send_sync)]
-fn render(state: &mut State) {
+fn render(world: &World, state: &mut State) {
+    // Zero sized surface, nothing to present until it is recreated.
+    if state.renderer.framebuffers.is_none() || state.renderer.window_resized || state.renderer.recreate_swapchain {
+        return;
+    }
+    crash::begin_frame();
+    let frame_i = state.renderer.current_frame;
+    let was_skipped = std::mem::take(&mut state.renderer.stats.frame_skipped);
     let (image_i, suboptimal, acquire_future) = match swapchain::acquire_next_image(
         state.renderer.swapchain.as_ref().unwrap().clone(),
-        None,
+        state.renderer.settings.acquire_timeout,
     )
     .map_err(Validated::unwrap)
     {
         Ok(r) => r,
+        Err(VulkanError::Timeout | VulkanError::NotReady) => {
+            if !was_skipped {
+                log::warn!("no swapchain image within the acquire timeout, skipping frames");
+            }
+            state.renderer.stats.skipped_frames += 1;
+            state.renderer.stats.frame_skipped = true;
+            return;
+        }
         Err(VulkanError::OutOfDate) => {
             state.renderer.recreate_swapchain = true;
             return;
         }
+        Err(VulkanError::DeviceLost) => {
+            state.renderer.device_lost = true;
+            return;
+        }
         Err(e) => panic!("failed to acquire next image: {e}"),
     };
 
@@ -688,9 +1959,10 @@ fn render(state: &mut State) {
         state.renderer.recreate_swapchain = true;
     }
 
-    if let Some(image_fence) = &state.renderer.fences.as_ref().unwrap()[image_i as usize] {
-        image_fence.wait(None).unwrap();
-    }
+    upload_dynamic_meshes(world, &mut state.renderer, frame_i);
+
+    let command_buffer_i = frame_i * state.renderer.images.as_ref().unwrap().len() + image_i as usize;
+    write_occlusion_draws(state, command_buffer_i);
 
     let previous_future =
         match state.renderer.fences.as_ref().unwrap()[state.renderer.previous_fence].clone() {
@@ -701,14 +1973,40 @@ fn render(state: &mut State) {
             }
             Some(fence) => fence.boxed(),
         };
+    // Flushed right away so the compute queue works while the image is acquired, the graphics
+    // submission waits on the semaphore.
+    let previous_future = match state.renderer.compute_command_buffers.as_ref() {
+        Some(compute_command_buffers) => {
+            let compute = previous_future
+                .then_execute(state.renderer.compute_queue.as_ref().unwrap().clone(), compute_command_buffers[frame_i].clone())
+                .unwrap()
+                .then_signal_semaphore_and_flush();
+            match compute.map_err(Validated::unwrap) {
+                Ok(x) => x.boxed(),
+                Err(VulkanError::DeviceLost) => {
+                    state.renderer.device_lost = true;
+                    return;
+                }
+                Err(e) => panic!("failed to submit compute work: {e}"),
+            }
+        }
+        None => previous_future,
+    };
 
-    let future = previous_future 
+    let image = state.renderer.images.as_ref().unwrap()[image_i as usize].clone();
+    let capture_copy = capture::record_copy(&mut state.renderer, image);
+    let future = previous_future
         .join(acquire_future)
         .then_execute(
             state.renderer.queue.as_ref().unwrap().clone(),
-            state.renderer.command_buffers.as_ref().unwrap()[image_i as usize].clone(),
+            state.renderer.command_buffers.as_ref().unwrap()[command_buffer_i].clone(),
         )
-        .unwrap()
+        .unwrap();
+    let future = match capture_copy {
+        Some(copy) => future.then_execute(state.renderer.queue.as_ref().unwrap().clone(), copy).unwrap().boxed(),
+        None => future.boxed(),
+    };
+    let future = future
         .then_swapchain_present(
             state.renderer.queue.as_ref().unwrap().clone(),
             SwapchainPresentInfo::swapchain_image_index(
@@ -718,7 +2016,7 @@ fn render(state: &mut State) {
         )
         .then_signal_fence_and_flush();
 
-    state.renderer.fences.as_mut().unwrap()[image_i as usize] =
+    state.renderer.fences.as_mut().unwrap()[frame_i] =
         match future.map_err(Validated::unwrap) {
             Ok(value) => {
                 Some(Arc::new(value))
@@ -727,29 +2025,143 @@ fn render(state: &mut State) {
                 state.renderer.recreate_swapchain = true;
                 None
             }
+            Err(VulkanError::DeviceLost) => {
+                state.renderer.device_lost = true;
+                None
+            }
             Err(e) => {
-                println!("failed to flush future: {e}");
+                log::error!("failed to flush future: {e}");
                 None
             }
         };
-    state.renderer.previous_fence = image_i as usize;
+    state.renderer.submitted_command_buffers[frame_i] = Some(command_buffer_i);
+    if let Some(fence) = state.renderer.fences.as_ref().unwrap()[frame_i].as_ref() {
+        if capture::is_pending(&state.renderer) && fence.wait(None).is_ok() {
+            capture::read_back(&mut state.renderer);
+        }
+    }
+    state.renderer.previous_fence = frame_i;
+    state.renderer.current_frame = (frame_i + 1) % state.renderer.frames_in_flight;
+
+    let wait_start = Instant::now();
+    if let Some(frame_fence) = &state.renderer.fences.as_ref().unwrap()[state.renderer.current_frame] {
+        match frame_fence.wait(None).map_err(Validated::unwrap) {
+            Ok(()) => {}
+            Err(VulkanError::DeviceLost) => state.renderer.device_lost = true,
+            Err(e) => panic!("failed to wait for fence: {e}"),
+        }
+    }
+    state.renderer.stats.fence_wait = wait_start.elapsed();
 }
 
-fn wait_for_idle(state: &mut State) {
-    for fence in state.renderer.fences.as_mut().unwrap().iter_mut() {
-        if let Some(val) = fence.as_mut() {
-            val.wait(None).unwrap()
-        };
+fn wait_for_idle(renderer: &mut Renderer) {
+    let Some(fences) = renderer.fences.as_ref() else {
+        return;
+    };
+
+    for fence in fences.iter().flatten() {
+        match fence.wait(None).map_err(Validated::unwrap) {
+            Ok(()) => {}
+            Err(VulkanError::DeviceLost) => renderer.device_lost = true,
+            Err(e) => panic!("failed to wait for fence: {e}"),
+        }
     }
 }
 
+// Android destroys the native window when the app is suspended, the swapchain and surface have to go
+// with it. The device and everything uploaded to it stay.
+pub fn release_surface(state: &mut State) {
+    wait_for_idle(&mut state.renderer);
+    state.renderer.command_buffers = None;
+    state.renderer.framebuffers = None;
+    state.renderer.images = None;
+    state.renderer.swapchain = None;
+    state.renderer.surface = None;
+}
+
+// Recreates the surface for `state.window`, the framebuffers and command buffers follow on the next
+// frame.
+pub fn restore_surface(state: &mut State) {
+    let surface = Surface::from_window(
+        state.renderer.instance.as_ref().unwrap().clone(),
+        state.window.window_handle.clone(),
+    )
+    .unwrap();
+    let supported = state
+        .renderer
+        .physical_device
+        .as_ref()
+        .unwrap()
+        .surface_support(state.renderer.queue_family_index.unwrap(), &surface)
+        .unwrap_or(false);
+    if !supported {
+        panic!("new surface is not supported by the graphics queue");
+    }
+    state.renderer.surface = Some(surface);
+    get_swapchain(state);
+    state.renderer.window_resized = true;
+}
+
+pub fn recover_device(state: &mut State) {
+    // Fence futures wait on drop, which fails on a lost device, so they are leaked instead.
+    if let Some(fences) = state.renderer.fences.take() {
+        std::mem::forget(fences);
+    }
+
+    // What the application registered on the renderer outlives the device.
+    let settings = state.renderer.settings.clone();
+    let render_callbacks = std::mem::take(&mut state.renderer.render_callbacks);
+    let watchdog = std::mem::take(&mut state.renderer.watchdog);
+    let capture = std::mem::take(&mut state.renderer.capture).without_device();
+    state.renderer = Renderer::with_settings(settings);
+    state.renderer.render_callbacks = render_callbacks;
+    state.renderer.watchdog = watchdog;
+    state.renderer.capture = capture;
+    init(state);
+}
+
+pub fn shutdown(state: &mut State) {
+    wait_for_idle(&mut state.renderer);
+    if let Some(device) = state.renderer.device.as_ref() {
+        unsafe { device.wait_idle() }.unwrap();
+    }
+    crash::set_device(None);
+
+    state.renderer.command_buffers = None;
+    state.renderer.compute_command_buffers = None;
+    state.renderer.fences = None;
+    state.renderer.pipelines.clear();
+    state.renderer.occlusion_pipeline = None;
+    state.renderer.occlusion_cube = None;
+    state.renderer.point_pipelines.clear();
+    state.renderer.occlusion_query_pools = None;
+    state.renderer.occlusion_draws.clear();
+    state.renderer.timestamp_query_pools = None;
+    state.renderer.framebuffers = None;
+    state.renderer.images = None;
+    state.renderer.swapchain = None;
+    state.renderer.vp_buffer = None;
+    state.renderer.fog_buffer = None;
+    state.renderer.shadows = ShadowMaps::default();
+    state.renderer.clusters = LightClusters::default();
+    state.renderer.post = PostProcessing::default();
+    state.renderer.mesh_arena = MeshArena::new();
+    state.renderer.reflections = PlanarReflections::default();
+    state.renderer.skinning = Skinning::default();
+    state.renderer.render_pass = None;
+}
+
 pub fn init(state: &mut State) {
     state.renderer.library = Some(VulkanLibrary::new().expect("Vulkan library not found"));
+    let mut enabled_extensions = Surface::required_extensions(&state.window.window_handle);
+    state.renderer.debug_utils = state.renderer.settings.debug_labels
+        && state.renderer.library.as_ref().unwrap().supported_extensions().ext_debug_utils;
+    enabled_extensions.ext_debug_utils = state.renderer.debug_utils;
     state.renderer.instance = Some(
         Instance::new(
             state.renderer.library.as_ref().unwrap().clone(),
             InstanceCreateInfo {
-                enabled_extensions: Surface::required_extensions(&state.window.window_handle),
+                enabled_extensions,
                 ..Default::default()
             },
         )
@@ -769,27 +2181,94 @@ pub fn init(state: &mut State) {
             ..Default::default()
         },
     );
+    let supported_features = state.renderer.physical_device.as_ref().unwrap().supported_features();
+    let missing_features = OPTIONAL_FEATURES.difference(supported_features);
+    if missing_features != Features::empty() {
+        log::info!("Optional device features unavailable: {:?}", missing_features);
+    }
+    state.renderer.enabled_features = REQUIRED_FEATURES.union(&OPTIONAL_FEATURES.intersection(supported_features));
+    // Core in 1.2, older devices would need the extension enabled as well.
+    if state.renderer.physical_device.as_ref().unwrap().api_version() < Version::V1_2 {
+        state.renderer.enabled_features.buffer_device_address = false;
+    }
+    let ray_tracing = state.renderer.settings.ray_traced_shadows;
+    state.renderer.ray_tracing.enabled = ray_tracing && ray_tracing::supported(state.renderer.physical_device.as_ref().unwrap());
+    if ray_tracing && !state.renderer.ray_tracing.enabled {
+        log::warn!("ray queries unavailable, ray traced shadows fall back to shadow maps");
+    }
+    state.renderer.enabled_features = state
+        .renderer
+        .enabled_features
+        .union(&ray_tracing::device_features(state.renderer.ray_tracing.enabled));
+
+    // Copy engines run uploads alongside rendering, so prefer a family that can do nothing else.
+    let transfer_family = state
+        .renderer
+        .physical_device
+        .as_ref()
+        .unwrap()
+        .queue_family_properties()
+        .iter()
+        .enumerate()
+        .filter(|(_, q)| {
+            q.queue_flags.contains(QueueFlags::TRANSFER) && !q.queue_flags.intersects(QueueFlags::GRAPHICS)
+        })
+        .min_by_key(|(_, q)| q.queue_flags.intersects(QueueFlags::COMPUTE))
+        .map(|(i, _)| i as u32);
+    // Async compute wants a family that does not share hardware with graphics.
+    let compute_family = state
+        .renderer
+        .physical_device
+        .as_ref()
+        .unwrap()
+        .queue_family_properties()
+        .iter()
+        .enumerate()
+        .find(|(i, q)| {
+            q.queue_flags.contains(QueueFlags::COMPUTE)
+                && !q.queue_flags.intersects(QueueFlags::GRAPHICS)
+                && Some(*i as u32) != transfer_family
+        })
+        .map(|(i, _)| i as u32)
+        .filter(|_| state.renderer.settings.async_compute);
+    let mut queue_create_infos = vec![QueueCreateInfo {
+        queue_family_index: *state.renderer.queue_family_index.as_ref().unwrap(),
+        ..Default::default()
+    }];
+    for queue_family_index in [transfer_family, compute_family].into_iter().flatten() {
+        queue_create_infos.push(QueueCreateInfo {
+            queue_family_index,
+            ..Default::default()
+        });
+    }
+
     let (device, mut queues) = Device::new(
         state.renderer.physical_device.as_ref().unwrap().clone(),
         DeviceCreateInfo {
-            queue_create_infos: vec![QueueCreateInfo {
-                queue_family_index: *state.renderer.queue_family_index.as_ref().unwrap(),
-                ..Default::default()
-            }],
+            queue_create_infos,
             enabled_extensions: DeviceExtensions {
                 khr_swapchain: true,
-                ..Default::default()
+                ..ray_tracing::device_extensions(state.renderer.ray_tracing.enabled)
             },
+            enabled_features: state.renderer.enabled_features,
             ..Default::default()
         },
     )
     .unwrap();
     state.renderer.queue = Some(queues.next().unwrap());
+    state.renderer.transfer_queue = transfer_family.and_then(|_| queues.next());
+    state.renderer.compute_queue = compute_family.and_then(|_| queues.next());
+    if state.renderer.settings.async_compute && state.renderer.compute_queue.is_none() {
+        log::info!("No dedicated compute queue, compute work runs on the graphics queue");
+    }
+    crash::set_device(Some(device.clone()));
     state.renderer.device = Some(device);
     state.renderer.memeory_allocator = Some(Arc::new(StandardMemoryAllocator::new_default(
         state.renderer.device.as_ref().unwrap().clone(),
     )));
     get_swapchain(state);
+    post_process::init(state);
+    ray_tracing::init(state);
     get_render_pass(state);
     get_framebuffers(state);
     state.renderer.viewport = Some(Viewport {
@@ -797,16 +2276,171 @@ pub fn init(state: &mut State) {
         extent: state.window.window_handle.inner_size().into(),
         depth_range: 0.0..=1.0,
     });
-    state.renderer.frames_in_flight = state.renderer.images.as_ref().unwrap().len();
+    update_ui_projection(state, state.window.physical_size());
+    state.renderer.frames_in_flight = state.renderer.settings.frames_in_flight.clamp(1, 3);
     state.renderer.fences = Some(vec![None; state.renderer.frames_in_flight]);
+    state.renderer.submitted_command_buffers = vec![None; state.renderer.frames_in_flight];
     state.renderer.vp_buffer = Some(UpdatableBuffer::new(
         &state.renderer,
         BufferUsage::UNIFORM_BUFFER,
     ));
+    state.renderer.fog_buffer = Some(UpdatableBuffer::new(
+        &state.renderer,
+        BufferUsage::UNIFORM_BUFFER,
+    ));
+    shadows::init(state);
+    clusters::init(state);
+}
+
+// A device and allocator without window, surface or swapchain, enough to create and upload buffers.
+// Used by benchmarks and tools, nothing can be presented with it.
+pub fn init_device_only(renderer: &mut Renderer) -> Result<(), String> {
+    let library = VulkanLibrary::new().map_err(|e| e.to_string())?;
+    let instance = Instance::new(library.clone(), InstanceCreateInfo::default()).map_err(|e| e.to_string())?;
+    let (physical_device, queue_family_index) = instance
+        .enumerate_physical_devices()
+        .map_err(|e| e.to_string())?
+        .filter_map(|p| {
+            p.queue_family_properties()
+                .iter()
+                .position(|q| q.queue_flags.contains(QueueFlags::GRAPHICS))
+                .map(|q| (p, q as u32))
+        })
+        .min_by_key(|(p, _)| match p.properties().device_type {
+            PhysicalDeviceType::DiscreteGpu => 0,
+            PhysicalDeviceType::IntegratedGpu => 1,
+            PhysicalDeviceType::VirtualGpu => 2,
+            PhysicalDeviceType::Cpu => 3,
+            _ => 4,
+        })
+        .ok_or("no device available")?;
+
+    let (device, mut queues) = Device::new(
+        physical_device.clone(),
+        DeviceCreateInfo {
+            queue_create_infos: vec![QueueCreateInfo {
+                queue_family_index,
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    renderer.library = Some(library);
+    renderer.instance = Some(instance);
+    renderer.physical_device = Some(physical_device);
+    renderer.queue_family_index = Some(queue_family_index);
+    renderer.queue = queues.next();
+    renderer.memeory_allocator = Some(Arc::new(StandardMemoryAllocator::new_default(device.clone())));
+    renderer.device = Some(device);
+    renderer.frames_in_flight = renderer.settings.frames_in_flight.clamp(1, 3);
+    Ok(())
 }
 
 impl Renderer {
     pub fn new() -> Renderer {
+        Renderer::with_settings(RendererSettings::default())
+    }
+
+    // Queue families a resource written by compute work and read by graphics work is shared by.
+    pub(crate) fn compute_queue_families(&self) -> Vec<u32> {
+        [self.queue.as_ref(), self.compute_queue.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|x| x.queue_family_index())
+            .collect()
+    }
+
+    // World position to the space matrices and light data are written in, subtracting in f64.
+    pub fn render_space(&self, position: Vec3d) -> Vec3f {
+        (position - self.render_origin).to_vec3f()
+    }
+
+    pub fn physical_device(&self) -> Option<&Arc<PhysicalDevice>> {
+        self.physical_device.as_ref()
+    }
+
+    // Callbacks run when the command buffers are recorded, which only happens when they are outdated.
+    pub fn add_render_callback(
+        &mut self,
+        stage: RenderStage,
+        callback: impl Fn(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, &RenderContext) + 'static,
+    ) {
+        self.render_callbacks.push((stage, Arc::new(callback)));
+        self.command_buffer_outdated = true;
+    }
+
+    pub(crate) fn render_target_images(&self) -> Vec<Arc<Image>> {
+        let mut images: Vec<Arc<Image>> = self
+            .framebuffers
+            .iter()
+            .flatten()
+            .flat_map(|x| x.attachments().iter().map(|x| x.image().clone()))
+            .collect();
+        images.extend(self.post.images());
+        images.extend(self.reflections.targets.iter().flat_map(|x| x.framebuffer.attachments().iter().map(|x| x.image().clone())));
+        images.extend(self.shadows.maps.iter().map(|x| x.cube_view.image().clone()));
+        images.extend(self.shadows.dummy_view.iter().map(|x| x.image().clone()));
+        images
+    }
+
+    pub(crate) fn uniform_buffers(&self) -> Vec<Arc<Buffer>> {
+        let mut buffers: Vec<Arc<Buffer>> = self
+            .vp_buffer
+            .iter()
+            .flat_map(|x| x.buffers.iter().map(|x| x.buffer().clone()))
+            .chain(self.fog_buffer.iter().flat_map(|x| x.buffers.iter().map(|x| x.buffer().clone())))
+            .chain(self.shadows.lights_buffer.iter().flat_map(|x| x.buffers.iter().map(|x| x.buffer().clone())))
+            .collect();
+        for map in self.shadows.maps.iter() {
+            buffers.extend(map.vp_buffers.iter().flat_map(|x| x.buffers.iter().map(|x| x.buffer().clone())));
+        }
+        buffers.extend(self.clusters.uniform_buffers());
+        buffers.extend(self.post.uniform_buffers());
+        buffers
+    }
+
+    pub fn supports_texture_format(&self, format: Format) -> bool {
+        self.texture_format_support()(format)
+    }
+
+    // Same check as `supports_texture_format`, usable from worker threads.
+    pub fn texture_format_support(&self) -> impl Fn(Format) -> bool + Send + 'static {
+        let physical_device = self.physical_device.clone();
+        let texture_compression_bc = self.enabled_features.texture_compression_bc;
+        move |format| {
+            if compressed_texture::is_block_compressed(format) && !texture_compression_bc {
+                return false;
+            }
+            physical_device
+                .as_ref()
+                .and_then(|x| x.format_properties(format).ok())
+                .is_some_and(|x| x.optimal_tiling_features.intersects(FormatFeatures::SAMPLED_IMAGE))
+        }
+    }
+
+    pub fn set_clear_color(&mut self, color: Color) {
+        self.settings.clear_color = color;
+        self.command_buffer_outdated = true;
+    }
+
+    pub fn set_post_effects(&mut self, effects: Vec<PostEffect>) {
+        self.settings.post_effects = effects;
+        self.command_buffer_outdated = true;
+    }
+
+    // Swaps the table of every color grading effect, loaded before the next frame.
+    pub fn set_color_lut(&mut self, name: &str) {
+        for effect in self.settings.post_effects.iter_mut() {
+            if let PostEffect::ColorGrading { lut, .. } = effect {
+                *lut = name.to_string();
+                self.command_buffer_outdated = true;
+            }
+        }
+    }
+
+    pub fn with_settings(settings: RendererSettings) -> Renderer {
         Renderer {
             library: None,
             instance: None,
@@ -814,7 +2448,11 @@ impl Renderer {
             physical_device: None,
             queue_family_index: None,
             device: None,
+            enabled_features: Features::empty(),
+            debug_utils: false,
             queue: None,
+            transfer_queue: None,
+            compute_queue: None,
             memeory_allocator: None,
             render_pass: None,
             swapchain: None,
@@ -822,19 +2460,44 @@ impl Renderer {
             framebuffers: None,
             viewport: None,
             command_buffers: None,
+            compute_command_buffers: None,
             window_resized: false,
+            device_lost: false,
             command_buffer_outdated: false,
             recreate_swapchain: false,
+            settings,
             frames_in_flight: 0,
+            current_frame: 0,
             fences: None,
             previous_fence: 0,
-            vp_data: VPData {
-                view: Matrix4f::indentity(),
-                projection: Matrix4f::indentity(),
-            },
+            submitted_command_buffers: Vec::new(),
+            vp_data: VPData::new(Matrix4f::indentity(), Matrix4f::indentity()),
             vp_pos: Vec3d::new([0.0, 0.0, 0.0]),
+            render_origin: Vec3d::new([0.0, 0.0, 0.0]),
             vp_buffer: None,
+            fog_buffer: None,
+            shadows: ShadowMaps::default(),
+            clusters: LightClusters::default(),
+            post: PostProcessing::default(),
             pipelines: HashMap::new(),
+            occlusion_pipeline: None,
+            occlusion_cube: None,
+            point_pipelines: HashMap::new(),
+            occlusion_query_pools: None,
+            timestamp_query_pools: None,
+            depth_draws: Vec::new(),
+            occluded: Vec::new(),
+            occlusion_draws: Vec::new(),
+            stats: RenderStats::default(),
+            cluster_draws: HashMap::new(),
+            memory: MemoryMonitor::default(),
+            mesh_arena: MeshArena::new(),
+            reflections: PlanarReflections::default(),
+            skinning: Skinning::default(),
+            ray_tracing: RayTracing::default(),
+            watchdog: FrameWatchdog::default(),
+            render_callbacks: Vec::new(),
+            capture: FrameCapture::default(),
         }
     }
 }
@@ -850,12 +2513,42 @@ pub struct RendererHandler {}
 impl System for RendererHandler {
     fn on_start(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
         state.renderer.vp_buffer.as_ref().unwrap().write_all(state, state.renderer.vp_data);
+        state.renderer.fog_buffer.as_ref().unwrap().write_all(state, state.renderer.settings.fog.to_data());
+        prepare_materials(assets, state, true);
+        skinning::prepare_skinned_meshes(world, assets, state);
+        morph::prepare_morph_weights(world, assets, state);
         update_command_buffers(world, assets, state);
     }
 
+    fn on_device_restored(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        skinning::release_skinned_meshes(world);
+        morph::release_morph_weights(world);
+        self.on_start(world, assets, state);
+    }
+
+    fn on_despawn(&self, _world: &World, _assets: &mut AssetLibrary, state: &mut State, _entity_id: usize) {
+        state.renderer.command_buffer_outdated = true;
+    }
+
     fn on_update(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        state.renderer.fog_buffer.as_ref().unwrap().write(state, state.renderer.settings.fog.to_data());
+        prepare_dynamic_meshes(world, &mut state.renderer);
+        skinning::prepare_skinned_meshes(world, assets, state);
+        morph::prepare_morph_weights(world, assets, state);
+        prepare_materials(assets, state, false);
+        point_cloud::prepare_point_clouds(world, state);
+        shadows::prepare_shadow_maps(world, state);
+        let lights = shadows::write_light_data(world, state);
+        clusters::write_cluster_data(world, state, &lights);
+        reflections::prepare_reflections(world, assets, state);
+        reflections::write_reflection_data(world, state);
         handle_possible_resize(world, assets, state);
-        render(state);
-        wait_for_idle(state);
+        post_process::write_post_data(world, state);
+        meshlet::cull_clusters(world, assets, state);
+        render(world, state);
+        update_occlusion_results(state);
+        update_gpu_time(state);
+        watchdog::check_frame(state);
+        memory_stats::check_memory_budget(world, assets, state);
     }
 }
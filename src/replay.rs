@@ -0,0 +1,149 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+};
+
+use serde::{Deserialize, Serialize};
+use winit::{event::MouseButton, keyboard::Key};
+
+use crate::{input::InputManager, types::vectors::Vec2f};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    pub delta_time: f64,
+    pub pressed: Vec<Key>,
+    pub released: Vec<Key>,
+    pub mouse_pressed: Vec<MouseButton>,
+    pub mouse_released: Vec<MouseButton>,
+    pub mouse_pos: [f32; 2],
+    pub cursor_pos: [f32; 2],
+}
+
+impl ReplayFrame {
+    fn capture(input: &InputManager, delta_time: f64) -> ReplayFrame {
+        ReplayFrame {
+            delta_time,
+            pressed: input.pressed.iter().cloned().collect(),
+            released: input.released.iter().cloned().collect(),
+            mouse_pressed: input.mouse_pressed.iter().copied().collect(),
+            mouse_released: input.mouse_released.iter().copied().collect(),
+            mouse_pos: [input.mouse_pos.x, input.mouse_pos.y],
+            cursor_pos: [input.cursor_pos.x, input.cursor_pos.y],
+        }
+    }
+
+    fn apply(&self, input: &mut InputManager) {
+        input.pressed.clear();
+        input.released.clear();
+        input.mouse_pressed.clear();
+        input.mouse_released.clear();
+        for key in self.pressed.iter() {
+            input.down.insert(key.clone());
+            input.pressed.insert(key.clone());
+        }
+        for key in self.released.iter() {
+            input.down.remove(key);
+            input.released.insert(key.clone());
+        }
+        for button in self.mouse_pressed.iter() {
+            input.process_mouse_press(*button);
+        }
+        for button in self.mouse_released.iter() {
+            input.process_mouse_release(*button);
+        }
+        input.mouse_pos = Vec2f::new(self.mouse_pos);
+        input.cursor_pos = Vec2f::new(self.cursor_pos);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum ReplayMode {
+    #[default]
+    Off,
+    Recording {
+        path: String,
+        frames: Vec<ReplayFrame>,
+    },
+    Playing {
+        frames: Vec<ReplayFrame>,
+        index: usize,
+    },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Replay {
+    pub mode: ReplayMode,
+    pub exit_on_finish: bool,
+}
+
+impl Replay {
+    pub fn new() -> Replay {
+        Replay::default()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.mode, ReplayMode::Recording { .. })
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.mode, ReplayMode::Playing { .. })
+    }
+
+    pub fn start_recording(&mut self, path: &str) {
+        self.mode = ReplayMode::Recording {
+            path: path.to_string(),
+            frames: Vec::new(),
+        };
+    }
+
+    pub fn stop_recording(&mut self) -> std::io::Result<()> {
+        let ReplayMode::Recording { path, frames } = std::mem::take(&mut self.mode) else {
+            return Ok(());
+        };
+        save(&path, &frames)
+    }
+
+    pub fn start_playback(&mut self, path: &str) -> std::io::Result<()> {
+        self.mode = ReplayMode::Playing {
+            frames: load(path)?,
+            index: 0,
+        };
+        Ok(())
+    }
+
+    pub fn record(&mut self, input: &InputManager, delta_time: f64) {
+        if let ReplayMode::Recording { frames, .. } = &mut self.mode {
+            frames.push(ReplayFrame::capture(input, delta_time));
+        }
+    }
+
+    pub fn play(&mut self, input: &mut InputManager) -> Option<f64> {
+        let ReplayMode::Playing { frames, index } = &mut self.mode else {
+            return None;
+        };
+        let Some(frame) = frames.get(*index) else {
+            self.mode = ReplayMode::Off;
+            return None;
+        };
+        frame.apply(input);
+        *index += 1;
+        Some(frame.delta_time)
+    }
+}
+
+pub fn save(path: &str, frames: &[ReplayFrame]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    for frame in frames.iter() {
+        serde_json::to_writer(&mut file, frame)?;
+        writeln!(file)?;
+    }
+    file.flush()
+}
+
+pub fn load(path: &str) -> std::io::Result<Vec<ReplayFrame>> {
+    let file = BufReader::new(File::open(path)?);
+    file.lines()
+        .filter(|x| x.as_ref().is_ok_and(|x| !x.trim().is_empty()))
+        .map(|x| Ok(serde_json::from_str(&x?)?))
+        .collect()
+}
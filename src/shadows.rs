@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bytemuck::Zeroable;
+use vulkano::buffer::BufferUsage;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::layout::DescriptorSetLayout;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::Format;
+use vulkano::image::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo};
+use vulkano::image::view::{ImageView, ImageViewCreateInfo, ImageViewType};
+use vulkano::image::{Image, ImageAspects, ImageCreateFlags, ImageCreateInfo, ImageSubresourceRange, ImageType, ImageUsage};
+use vulkano::memory::allocator::AllocationCreateInfo;
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::{DepthBiasState, RasterizationState};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
+
+use crate::asset_library::AssetLibrary;
+use crate::ecs::World;
+use crate::rendering::{VPData, VertexData};
+use crate::state::State;
+use crate::types::buffers::UpdatableBuffer;
+use crate::types::light::{LightsData, PointLight, PointLightData, MAX_POINT_LIGHTS};
+use crate::types::matrices::Matrix4f;
+use crate::types::mesh::DynamicMesh;
+use crate::types::shader::Shader;
+use crate::types::skin::SkinnedMesh;
+use crate::types::static_mesh::StaticMesh;
+use crate::types::transform::Transform;
+use crate::types::vectors::*;
+
+pub const MAX_SHADOW_CASTERS: usize = 4;
+pub const SHADOW_NEAR: f32 = 0.05;
+
+// Faces follow the Vulkan cube layer order: +X, -X, +Y, -Y, +Z, -Z.
+const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+#[derive(Clone)]
+pub struct ShadowMap {
+    pub entity: usize,
+    pub resolution: u32,
+    pub cube_view: Arc<ImageView>,
+    pub framebuffers: Vec<Arc<Framebuffer>>,
+    pub vp_buffers: Vec<UpdatableBuffer<VPData>>,
+}
+
+#[derive(Clone, Default)]
+pub struct ShadowMaps {
+    pub render_pass: Option<Arc<RenderPass>>,
+    pub sampler: Option<Arc<Sampler>>,
+    pub dummy_view: Option<Arc<ImageView>>,
+    pub pipelines: HashMap<String, Arc<GraphicsPipeline>>,
+    pub maps: Vec<ShadowMap>,
+    pub lights_buffer: Option<UpdatableBuffer<LightsData>>,
+}
+
+fn create_cube_image(state: &State, resolution: u32) -> Arc<Image> {
+    Image::new(
+        state.renderer.memeory_allocator.as_ref().unwrap().clone(),
+        ImageCreateInfo {
+            flags: ImageCreateFlags::CUBE_COMPATIBLE,
+            image_type: ImageType::Dim2d,
+            format: Format::D32_SFLOAT,
+            extent: [resolution, resolution, 1],
+            array_layers: 6,
+            usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap()
+}
+
+fn create_cube_view(image: &Arc<Image>) -> Arc<ImageView> {
+    ImageView::new(
+        image.clone(),
+        ImageViewCreateInfo {
+            view_type: ImageViewType::Cube,
+            ..ImageViewCreateInfo::from_image(image)
+        },
+    )
+    .unwrap()
+}
+
+fn create_shadow_map(state: &State, entity: usize, resolution: u32) -> ShadowMap {
+    let shadows = &state.renderer.shadows;
+    let image = create_cube_image(state, resolution);
+
+    let framebuffers = (0..6)
+        .map(|face| {
+            let view = ImageView::new(
+                image.clone(),
+                ImageViewCreateInfo {
+                    view_type: ImageViewType::Dim2d,
+                    subresource_range: ImageSubresourceRange {
+                        aspects: ImageAspects::DEPTH,
+                        mip_levels: 0..1,
+                        array_layers: face..face + 1,
+                    },
+                    ..ImageViewCreateInfo::from_image(&image)
+                },
+            )
+            .unwrap();
+            Framebuffer::new(
+                shadows.render_pass.as_ref().unwrap().clone(),
+                FramebufferCreateInfo {
+                    attachments: vec![view],
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        })
+        .collect();
+
+    ShadowMap {
+        entity,
+        resolution,
+        cube_view: create_cube_view(&image),
+        framebuffers,
+        vp_buffers: (0..6)
+            .map(|_| UpdatableBuffer::new(&state.renderer, BufferUsage::UNIFORM_BUFFER))
+            .collect(),
+    }
+}
+
+pub fn init(state: &mut State) {
+    let device = state.renderer.device.as_ref().unwrap().clone();
+    state.renderer.shadows.render_pass = Some(
+        vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                depth: {
+                    format: Format::D32_SFLOAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                }
+            },
+            pass: {
+                color: [],
+                depth_stencil: {depth},
+            },
+        )
+        .unwrap(),
+    );
+    state.renderer.shadows.sampler = Some(
+        Sampler::new(
+            device,
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                compare: Some(CompareOp::LessOrEqual),
+                ..Default::default()
+            },
+        )
+        .unwrap(),
+    );
+    state.renderer.shadows.dummy_view = Some(create_cube_view(&create_cube_image(state, 1)));
+    state.renderer.shadows.lights_buffer = Some(UpdatableBuffer::new(
+        &state.renderer,
+        BufferUsage::UNIFORM_BUFFER,
+    ));
+}
+
+fn create_shadow_pipeline(state: &State, vs: &Shader) -> Arc<GraphicsPipeline> {
+    let device = state.renderer.device.as_ref().unwrap().clone();
+    let vs = vs.module.as_ref().unwrap().entry_point("main").unwrap();
+
+    let vertex_input_state = VertexData::per_vertex()
+        .definition(&vs.info().input_interface)
+        .unwrap();
+
+    let stages = [PipelineShaderStageCreateInfo::new(vs)];
+
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+
+    let subpass = Subpass::from(state.renderer.shadows.render_pass.as_ref().unwrap().clone(), 0).unwrap();
+
+    GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState {
+                depth_bias: Some(DepthBiasState {
+                    constant_factor: 1.25,
+                    clamp: 0.0,
+                    slope_factor: 1.75,
+                }),
+                ..Default::default()
+            }),
+            depth_stencil_state: Some(DepthStencilState {
+                depth: Some(DepthState::simple()),
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState::default()),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+pub fn prepare_pipelines(world: &World, assets: &AssetLibrary, state: &mut State) {
+    if state.renderer.shadows.maps.is_empty() {
+        return;
+    }
+
+    let mut vertex_shaders = Vec::new();
+    if let Some(static_meshes) = world.borrow_component_vec_mut::<StaticMesh>() {
+        for static_mesh in static_meshes.iter().flatten() {
+            let mesh = assets.meshes.iter().find(|x| x.name == static_mesh.mesh_name).unwrap();
+            vertex_shaders.push(mesh.material.clone());
+        }
+    }
+    if let Some(dynamic_meshes) = world.borrow_component_vec_mut::<DynamicMesh>() {
+        for dynamic_mesh in dynamic_meshes.iter().flatten() {
+            vertex_shaders.push(dynamic_mesh.material.clone());
+        }
+    }
+    if let Some(skinned_meshes) = world.borrow_component_vec_mut::<SkinnedMesh>() {
+        for skinned_mesh in skinned_meshes.iter().flatten() {
+            let mesh = assets.meshes.iter().find(|x| x.name == skinned_mesh.mesh_name).unwrap();
+            vertex_shaders.push(mesh.material.clone());
+        }
+    }
+
+    for material in vertex_shaders.iter() {
+        let material = assets.materials.iter().find(|x| x.name == *material).unwrap();
+        if !state.renderer.shadows.pipelines.contains_key(&material.vertex_shader) {
+            let shader = assets.shaders.iter().find(|x| x.name == material.vertex_shader).unwrap();
+            let pipeline = create_shadow_pipeline(state, shader);
+            state.renderer.shadows.pipelines.insert(material.vertex_shader.clone(), pipeline);
+        }
+    }
+}
+
+pub fn prepare_shadow_maps(world: &World, state: &mut State) {
+    let casters: Vec<(usize, u32)> = match (
+        world.borrow_component_vec_mut::<PointLight>(),
+        world.borrow_component_vec_mut::<Transform>(),
+    ) {
+        (Some(lights), Some(transforms)) => lights
+            .iter()
+            .zip(transforms.iter())
+            .enumerate()
+            .filter_map(|(entity, (light, transform))| {
+                let light = light.as_ref()?;
+                transform.as_ref()?;
+                light.cast_shadows.then_some((entity, light.shadow_resolution))
+            })
+            .take(MAX_SHADOW_CASTERS)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let current: Vec<(usize, u32)> = state
+        .renderer
+        .shadows
+        .maps
+        .iter()
+        .map(|x| (x.entity, x.resolution))
+        .collect();
+    if casters == current {
+        return;
+    }
+
+    let maps = casters
+        .iter()
+        .map(|(entity, resolution)| {
+            match state.renderer.shadows.maps.iter().find(|x| x.entity == *entity && x.resolution == *resolution) {
+                Some(map) => map.clone(),
+                None => create_shadow_map(state, *entity, *resolution),
+            }
+        })
+        .collect();
+    state.renderer.shadows.maps = maps;
+    state.renderer.command_buffer_outdated = true;
+}
+
+pub fn write_light_data(world: &World, state: &mut State) -> Vec<PointLightData> {
+    let mut all_lights = Vec::new();
+
+    if let (Some(lights), Some(transforms)) = (
+        world.borrow_component_vec_mut::<PointLight>(),
+        world.borrow_component_vec_mut::<Transform>(),
+    ) {
+        let zip = lights.iter().zip(transforms.iter()).enumerate();
+        for (entity, (light, transform)) in zip.filter_map(|(i, (x, y))| Some((i, (x.as_ref()?, y.as_ref()?)))) {
+            let position = state.renderer.render_space(transform.position);
+            let shadow_index = state.renderer.shadows.maps.iter().position(|x| x.entity == entity);
+
+            if let Some(map) = shadow_index.map(|x| &state.renderer.shadows.maps[x]) {
+                let projection = Matrix4f::perspective(90f32.to_radians(), 1.0, SHADOW_NEAR, light.range);
+                for (face, (dir, up)) in CUBE_FACES.iter().enumerate() {
+                    map.vp_buffers[face].write(
+                        state,
+                        VPData::new(Matrix4f::look_at(position, Vec3f::new(*dir), Vec3f::new(*up)), projection),
+                    );
+                }
+            }
+
+            all_lights.push(light.to_data(position, shadow_index));
+        }
+    }
+
+    let mut data = LightsData {
+        lights: [PointLightData::zeroed(); MAX_POINT_LIGHTS],
+        count: all_lights.len().min(MAX_POINT_LIGHTS) as u32,
+        _padding: [0; 3],
+    };
+    for (slot, light) in data.lights.iter_mut().zip(all_lights.iter()) {
+        *slot = *light;
+    }
+    state.renderer.shadows.lights_buffer.as_ref().unwrap().write(state, data);
+    all_lights
+}
+
+// Every map and face draws the shared `Renderer::depth_draws`, model descriptor sets are made
+// once per draw rather than per face.
+pub fn record_shadow_passes(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    state: &State,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    frame_i: usize,
+) {
+    let shadows = &state.renderer.shadows;
+    if shadows.maps.is_empty() {
+        return;
+    }
+
+    let draws: Vec<_> = state.renderer.depth_draws[frame_i]
+        .iter()
+        .map(|draw| {
+            let pipeline = shadows.pipelines.get(&draw.vertex_shader).unwrap().clone();
+            let m_set = PersistentDescriptorSet::new(
+                descriptor_set_allocator,
+                pipeline.layout().set_layouts()[1].clone(),
+                [WriteDescriptorSet::buffer(0, draw.model.clone())],
+                [],
+            )
+            .unwrap();
+            (draw, pipeline, m_set)
+        })
+        .collect();
+
+    for map in shadows.maps.iter() {
+        for (face, framebuffer) in map.framebuffers.iter().enumerate() {
+            builder
+                .begin_render_pass(
+                    RenderPassBeginInfo {
+                        clear_values: vec![Some(1f32.into())],
+                        ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+                    },
+                    SubpassBeginInfo {
+                        contents: SubpassContents::Inline,
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+                .set_viewport(
+                    0,
+                    [Viewport {
+                        offset: [0.0, 0.0],
+                        extent: [map.resolution as f32, map.resolution as f32],
+                        depth_range: 0.0..=1.0,
+                    }]
+                    .into_iter()
+                    .collect(),
+                )
+                .unwrap();
+
+            let mut bound: Option<(&str, Arc<PersistentDescriptorSet>)> = None;
+            for (draw, pipeline, m_set) in draws.iter() {
+                // Draws are sorted by vertex shader, so pipelines and the view set change rarely.
+                if bound.as_ref().is_none_or(|x| x.0 != draw.vertex_shader) {
+                    let vp_set = PersistentDescriptorSet::new(
+                        descriptor_set_allocator,
+                        pipeline.layout().set_layouts()[0].clone(),
+                        [WriteDescriptorSet::buffer(0, map.vp_buffers[face].buffer(frame_i))],
+                        [],
+                    )
+                    .unwrap();
+                    builder.bind_pipeline_graphics(pipeline.clone()).unwrap();
+                    bound = Some((&draw.vertex_shader, vp_set));
+                }
+                let vp_set = bound.as_ref().unwrap().1.clone();
+
+                builder
+                    .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, (vp_set, m_set.clone()))
+                    .unwrap()
+                    .bind_index_buffer(draw.index_buffer.clone())
+                    .unwrap()
+                    .bind_vertex_buffers(0, draw.vertex_buffer.clone())
+                    .unwrap()
+                    .draw_indexed(draw.index_count, 1, 0, 0, 0)
+                    .unwrap();
+            }
+
+            builder.end_render_pass(Default::default()).unwrap();
+        }
+    }
+}
+
+pub fn descriptor_writes(state: &State, layout: &DescriptorSetLayout, frame_i: usize) -> Vec<WriteDescriptorSet> {
+    let shadows = &state.renderer.shadows;
+    let mut writes = Vec::new();
+    if let Some(binding) = layout.bindings().get(&2) {
+        let sampler = shadows.sampler.as_ref().unwrap();
+        let views = (0..binding.descriptor_count as usize).map(|i| {
+            let view = shadows
+                .maps
+                .get(i)
+                .map_or(shadows.dummy_view.as_ref().unwrap(), |x| &x.cube_view);
+            (view.clone() as _, sampler.clone())
+        });
+        writes.push(WriteDescriptorSet::image_view_sampler_array(2, 0, views));
+    }
+    if layout.bindings().contains_key(&3) {
+        writes.push(WriteDescriptorSet::buffer(3, shadows.lights_buffer.as_ref().unwrap().buffer(frame_i)));
+    }
+    writes
+}
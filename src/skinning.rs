@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use vulkano::buffer::Buffer;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::pipeline::compute::ComputePipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo};
+
+use crate::asset_library::AssetLibrary;
+use crate::ecs::World;
+use crate::state::State;
+use crate::types::skin::SkinnedMesh;
+
+pub const SKINNING_SHADER: &str = "skinning";
+pub const SKINNING_WORKGROUP_SIZE: u32 = 64;
+
+#[derive(Clone, Default)]
+pub struct Skinning {
+    pipeline: Option<Arc<ComputePipeline>>,
+}
+
+pub fn prepare_skinned_meshes(world: &World, assets: &AssetLibrary, state: &mut State) {
+    let Some(mut skinned_meshes) = world.borrow_component_vec_mut::<SkinnedMesh>() else {
+        return;
+    };
+
+    for skinned_mesh in skinned_meshes.iter_mut().flatten() {
+        let outdated = skinned_mesh.buffers.is_none() || skinned_mesh.joint_count() != skinned_mesh.joint_matrices.len();
+        if outdated {
+            let mesh = assets.meshes.iter().find(|x| x.name == skinned_mesh.mesh_name).unwrap();
+            skinned_mesh.load(&state.renderer, mesh);
+            state.renderer.command_buffer_outdated = true;
+        }
+        skinned_mesh.write_joints(state.renderer.current_frame);
+    }
+}
+
+// Buffers were created on the previous device.
+pub fn release_skinned_meshes(world: &World) {
+    if let Some(mut skinned_meshes) = world.borrow_component_vec_mut::<SkinnedMesh>() {
+        for skinned_mesh in skinned_meshes.iter_mut().flatten() {
+            skinned_mesh.buffers = None;
+        }
+    }
+}
+
+pub fn prepare_pipeline(world: &World, assets: &AssetLibrary, state: &mut State) {
+    let needed = world
+        .borrow_component_vec_mut::<SkinnedMesh>()
+        .is_some_and(|x| x.iter().any(|x| x.is_some()));
+    if !needed || state.renderer.skinning.pipeline.is_some() {
+        return;
+    }
+
+    let device = state.renderer.device.as_ref().unwrap().clone();
+    let shader = assets
+        .shaders
+        .iter()
+        .find(|x| x.name == SKINNING_SHADER)
+        .unwrap_or_else(|| panic!("skinning shader {SKINNING_SHADER} not loaded"));
+    let stage = PipelineShaderStageCreateInfo::new(shader.module.as_ref().unwrap().entry_point("main").unwrap());
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+
+    state.renderer.skinning.pipeline =
+        Some(ComputePipeline::new(device, None, ComputePipelineCreateInfo::stage_layout(stage, layout)).unwrap());
+}
+
+// Runs before the shadow passes so every pass of the frame reads the same skinned vertices.
+pub fn record_skinning(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    world: &World,
+    state: &State,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    frame_i: usize,
+) {
+    let Some(pipeline) = state.renderer.skinning.pipeline.as_ref() else {
+        return;
+    };
+    let Some(skinned_meshes) = world.borrow_component_vec_mut::<SkinnedMesh>() else {
+        return;
+    };
+
+    builder.bind_pipeline_compute(pipeline.clone()).unwrap();
+    for buffers in skinned_meshes.iter().flatten().filter_map(|x| x.buffers.as_ref()) {
+        let set = PersistentDescriptorSet::new(
+            descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, buffers.source.clone()),
+                WriteDescriptorSet::buffer(1, buffers.weights.clone()),
+                WriteDescriptorSet::buffer(2, buffers.joints[frame_i].clone()),
+                WriteDescriptorSet::buffer(3, buffers.output[frame_i].clone()),
+            ],
+            [],
+        )
+        .unwrap();
+
+        builder
+            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline.layout().clone(), 0, set)
+            .unwrap()
+            .dispatch([(buffers.source.len() as u32).div_ceil(SKINNING_WORKGROUP_SIZE), 1, 1])
+            .unwrap();
+    }
+}
+
+pub(crate) fn storage_buffers(world: &World) -> Vec<Arc<Buffer>> {
+    let Some(skinned_meshes) = world.borrow_component_vec_mut::<SkinnedMesh>() else {
+        return Vec::new();
+    };
+    skinned_meshes
+        .iter()
+        .flatten()
+        .filter_map(|x| x.buffers.as_ref())
+        .flat_map(|x| {
+            [x.source.buffer().clone(), x.weights.buffer().clone()]
+                .into_iter()
+                .chain(x.joints.iter().map(|x| x.buffer().clone()))
+                .chain(x.output.iter().map(|x| x.buffer().clone()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
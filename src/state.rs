@@ -1,12 +1,48 @@
 use crate::{
     input::InputManager,
+    jobs::Jobs,
+    profiler::Profiler,
+    replay::Replay,
+    streaming::LevelStreaming,
+    timers::Timers,
+    types::{behavior::Behaviors, noise::Rng, tween::Tweens},
     rendering::{Renderer, Window},
+    types::debug_overlay::DebugOverlay,
+    types::vectors::Vec3d,
 };
+#[cfg(feature = "network")]
+use crate::network::Network;
+#[cfg(feature = "physics2d")]
+use crate::physics2d::Physics2d;
+#[cfg(feature = "ui")]
+use crate::types::ui::UiState;
 
 pub struct State {
     pub window: Window,
     pub input: InputManager,
     pub renderer: Renderer,
     pub time: f64,
-    pub delta_time: f64
+    pub delta_time: f64,
+    pub debug_overlay: DebugOverlay,
+    pub profiler: Profiler,
+    pub replay: Replay,
+    #[cfg(feature = "network")]
+    pub network: Network,
+    pub timers: Timers,
+    pub tweens: Tweens,
+    /// Shared by gameplay code, seeded from the clock. Reseed it in `on_start` for runs that must
+    /// repeat, e.g. with replays. Register it with `World::register_persistent_resource` for
+    /// snapshots to carry it.
+    pub rng: Rng,
+    pub behaviors: Behaviors,
+    #[cfg(feature = "ui")]
+    pub ui: UiState,
+    #[cfg(feature = "physics2d")]
+    pub physics2d: Physics2d,
+    pub jobs: Jobs,
+    pub streaming: LevelStreaming,
+    /// Set to close the application after the current frame.
+    pub exit_requested: bool,
+    /// Total shift applied by `FloatingOrigin`, world coordinates are positions plus this.
+    pub origin_offset: Vec3d,
 }
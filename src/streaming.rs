@@ -0,0 +1,243 @@
+use std::sync::Arc;
+
+use crate::asset_library::{AssetKind, AssetLibrary};
+use crate::ecs::{System, World};
+use crate::jobs::JobId;
+use crate::state::State;
+use crate::types::{transform::Transform, vectors::Vec3d};
+
+pub type CellSpawnCallback = Arc<dyn Fn(&mut World, &mut AssetLibrary) -> Vec<usize>>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ManifestEntry {
+    Texture { name: String, srgb: bool },
+    #[cfg(feature = "gltf")]
+    Gltf { path: String, material: String },
+}
+
+// Assets are loaded on the job system. Once all are in the library `spawn` creates the cell's
+// entities, which are despawned again together with the assets when the cell is unloaded.
+#[derive(Clone)]
+pub struct LevelCell {
+    pub name: String,
+    pub center: Vec3d,
+    pub manifest: Vec<ManifestEntry>,
+    pub spawn: CellSpawnCallback,
+}
+
+impl LevelCell {
+    pub fn new(name: &str, center: Vec3d, spawn: impl Fn(&mut World, &mut AssetLibrary) -> Vec<usize> + 'static) -> LevelCell {
+        LevelCell {
+            name: name.to_string(),
+            center,
+            manifest: Vec::new(),
+            spawn: Arc::new(spawn),
+        }
+    }
+
+    pub fn with_texture(mut self, name: &str, srgb: bool) -> LevelCell {
+        self.manifest.push(ManifestEntry::Texture { name: name.to_string(), srgb });
+        self
+    }
+
+    #[cfg(feature = "gltf")]
+    pub fn with_gltf(mut self, path: &str, material: &str) -> LevelCell {
+        self.manifest.push(ManifestEntry::Gltf {
+            path: path.to_string(),
+            material: material.to_string(),
+        });
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CellState {
+    Unloaded,
+    Loading,
+    Spawning,
+    Loaded,
+    /// A manifest asset failed to load. The cell is not retried until `retry` is called.
+    Failed,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellEvent {
+    pub cell: String,
+    pub state: CellState,
+}
+
+struct CellEntry {
+    cell: LevelCell,
+    state: CellState,
+    // Pending jobs and loaded assets, by manifest entry index.
+    jobs: Vec<(usize, JobId)>,
+    entities: Vec<usize>,
+    assets: Vec<(usize, AssetKind, String)>,
+}
+
+pub struct LevelStreaming {
+    cells: Vec<CellEntry>,
+    events: Vec<CellEvent>,
+    pub load_radius: f64,
+    /// Larger than `load_radius` so cells on the border do not load and unload every frame.
+    pub unload_radius: f64,
+}
+
+impl LevelStreaming {
+    pub fn new() -> LevelStreaming {
+        LevelStreaming {
+            cells: Vec::new(),
+            events: Vec::new(),
+            load_radius: 200.0,
+            unload_radius: 250.0,
+        }
+    }
+
+    pub fn add_cell(&mut self, cell: LevelCell) {
+        if self.cells.iter().any(|x| x.cell.name == cell.name) {
+            log::warn!("level cell {} already exists", cell.name);
+            return;
+        }
+        self.cells.push(CellEntry {
+            cell,
+            state: CellState::Unloaded,
+            jobs: Vec::new(),
+            entities: Vec::new(),
+            assets: Vec::new(),
+        });
+    }
+
+    pub fn cell_state(&self, name: &str) -> Option<&CellState> {
+        self.cells.iter().find(|x| x.cell.name == name).map(|x| &x.state)
+    }
+
+    pub fn retry(&mut self, name: &str) {
+        if let Some(entry) = self.cells.iter_mut().find(|x| x.cell.name == name && x.state == CellState::Failed) {
+            entry.state = CellState::Unloaded;
+        }
+    }
+
+    pub fn drain_events(&mut self) -> Vec<CellEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn set_state(&mut self, cell: usize, state: CellState) {
+        let entry = &mut self.cells[cell];
+        entry.state = state.clone();
+        self.events.push(CellEvent {
+            cell: entry.cell.name.clone(),
+            state,
+        });
+    }
+
+    // Manifest entries still needed by another cell which is not unloaded.
+    fn shared_entry(&self, cell: usize, entry: &ManifestEntry) -> bool {
+        self.cells.iter().enumerate().any(|(i, x)| {
+            i != cell && !matches!(x.state, CellState::Unloaded | CellState::Failed) && x.cell.manifest.contains(entry)
+        })
+    }
+}
+
+impl Default for LevelStreaming {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn start_loading(state: &mut State, cell: usize) {
+    let manifest = state.streaming.cells[cell].cell.manifest.clone();
+    let jobs = manifest
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let job = match entry {
+                ManifestEntry::Texture { name, srgb } => state.jobs.load_texture(&state.renderer, name, *srgb),
+                #[cfg(feature = "gltf")]
+                ManifestEntry::Gltf { path, material } => state.jobs.load_gltf(path, material),
+            };
+            state.jobs.watch(job);
+            (index, job)
+        })
+        .collect();
+    state.streaming.cells[cell].jobs = jobs;
+    state.streaming.set_state(cell, CellState::Loading);
+}
+
+fn poll_loading(world: &World, assets: &mut AssetLibrary, state: &mut State, cell: usize) {
+    let mut failed = false;
+    for (index, job) in std::mem::take(&mut state.streaming.cells[cell].jobs) {
+        match state.jobs.take_outcome(job) {
+            None => state.streaming.cells[cell].jobs.push((index, job)),
+            Some(Ok(loaded)) => {
+                let entry = &mut state.streaming.cells[cell];
+                entry.assets.extend(loaded.into_iter().map(|(kind, name)| (index, kind, name)));
+            }
+            Some(Err(_)) => failed = true,
+        }
+    }
+
+    // Jobs still running are forgotten, their assets stay in the library.
+    if failed {
+        for (_, job) in std::mem::take(&mut state.streaming.cells[cell].jobs) {
+            state.jobs.unwatch(job);
+        }
+        release_assets(assets, state, cell);
+        state.streaming.set_state(cell, CellState::Failed);
+    } else if state.streaming.cells[cell].jobs.is_empty() {
+        state.streaming.set_state(cell, CellState::Spawning);
+        let spawn = state.streaming.cells[cell].cell.spawn.clone();
+        world.defer(move |world, assets, state| {
+            let entities = spawn(world, assets);
+            // Cells are placed in world coordinates, see `FloatingOrigin`.
+            if let Some(mut transforms) = world.borrow_component_vec_mut::<Transform>() {
+                for entity in entities.iter() {
+                    if let Some(Some(transform)) = transforms.get_mut(*entity) {
+                        transform.position -= state.origin_offset;
+                    }
+                }
+            }
+            state.streaming.cells[cell].entities = entities;
+            state.streaming.set_state(cell, CellState::Loaded);
+            state.renderer.command_buffer_outdated = true;
+        });
+    }
+}
+
+// Assets shared with another cell stay, anything else is released.
+fn release_assets(assets: &mut AssetLibrary, state: &mut State, cell: usize) {
+    for (index, kind, name) in std::mem::take(&mut state.streaming.cells[cell].assets) {
+        let entry = &state.streaming.cells[cell].cell.manifest[index];
+        if !state.streaming.shared_entry(cell, entry) {
+            assets.unload(state, kind, &name);
+        }
+    }
+}
+
+fn unload(world: &World, assets: &mut AssetLibrary, state: &mut State, cell: usize) {
+    for entity in std::mem::take(&mut state.streaming.cells[cell].entities) {
+        world.despawn(entity);
+    }
+    release_assets(assets, state, cell);
+    state.streaming.set_state(cell, CellState::Unloaded);
+}
+
+pub struct LevelStreamer {}
+
+impl System for LevelStreamer {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        let camera = state.renderer.vp_pos + state.origin_offset;
+        let load_radius = state.streaming.load_radius.powi(2);
+        let unload_radius = state.streaming.unload_radius.powi(2).max(load_radius);
+
+        for cell in 0..state.streaming.cells.len() {
+            let distance = (state.streaming.cells[cell].cell.center - camera).length_sqr();
+            match state.streaming.cells[cell].state {
+                CellState::Unloaded if distance <= load_radius => start_loading(state, cell),
+                CellState::Loading => poll_loading(world, assets, state, cell),
+                CellState::Loaded if distance > unload_radius => unload(world, assets, state, cell),
+                _ => (),
+            }
+        }
+    }
+}
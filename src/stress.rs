@@ -0,0 +1,100 @@
+use crate::{
+    ecs::World,
+    rendering::VertexData,
+    types::{
+        bundles::DynamicMeshBundle,
+        color::Color,
+        mesh::DynamicMesh,
+        noise::Rng,
+        transform::Transform,
+        vectors::{Vec2f, Vec3d, Vec3f},
+    },
+};
+
+// Synthetic scene for benchmarks and profiling: `count` dynamic meshes on a square grid, each a
+// subdivided plane with `subdivisions`² quads. The same seed always gives the same scene.
+#[derive(Clone, Debug)]
+pub struct StressScene {
+    pub count: usize,
+    pub subdivisions: u32,
+    pub spacing: f64,
+    pub material: String,
+    pub seed: u64,
+}
+
+impl StressScene {
+    pub fn new(count: usize, material: &str) -> StressScene {
+        StressScene {
+            count,
+            subdivisions: 4,
+            spacing: 2.0,
+            material: material.to_string(),
+            seed: 1,
+        }
+    }
+
+    pub fn with_subdivisions(mut self, subdivisions: u32) -> StressScene {
+        self.subdivisions = subdivisions.max(1);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> StressScene {
+        self.seed = seed;
+        self
+    }
+
+    pub fn mesh(&self, rng: &mut Rng) -> DynamicMesh {
+        let n = self.subdivisions;
+        let color = Color::rgb(rng.next_f32(), rng.next_f32(), rng.next_f32());
+        let mut vertices = Vec::with_capacity(((n + 1) * (n + 1)) as usize);
+        for z in 0..=n {
+            for x in 0..=n {
+                let u = x as f32 / n as f32;
+                let v = z as f32 / n as f32;
+                let height = (rng.next_f32() - 0.5) * 0.1;
+                let mut vertex = VertexData::new(
+                    Vec3f::new([u - 0.5, height, v - 0.5]),
+                    Vec2f::new([u, v]),
+                    Vec3f::new([0.0, 1.0, 0.0]),
+                );
+                vertex.color = color;
+                vertices.push(vertex);
+            }
+        }
+
+        let mut indices = Vec::with_capacity((n * n * 6) as usize);
+        for z in 0..n {
+            for x in 0..n {
+                let i = z * (n + 1) + x;
+                indices.extend_from_slice(&[i, i + n + 1, i + 1, i + 1, i + n + 1, i + n + 2]);
+            }
+        }
+
+        DynamicMesh {
+            vertices,
+            indices,
+            material: self.material.clone(),
+            buffers: None,
+        }
+    }
+
+    // Returns the spawned entities.
+    pub fn spawn(&self, world: &mut World) -> Vec<usize> {
+        let mut rng = Rng::new(self.seed);
+
+        let side = (self.count as f64).sqrt().ceil().max(1.0) as usize;
+        let offset = (side - 1) as f64 * self.spacing * 0.5;
+        (0..self.count)
+            .map(|i| {
+                let position = Vec3d::new([
+                    (i % side) as f64 * self.spacing - offset,
+                    0.0,
+                    (i / side) as f64 * self.spacing - offset,
+                ]);
+                let rotation = Vec3f::new([0.0, rng.next_f32() * std::f32::consts::TAU, 0.0]);
+                let transform = Transform::new(position, Vec3f::new([1.0, 1.0, 1.0]), rotation);
+                world.spawn_bundle(DynamicMeshBundle::new(self.mesh(&mut rng), transform))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,128 @@
+use std::mem;
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+    types::easing::Easing,
+};
+
+pub type TimerCallback = Box<dyn FnMut(&World, &mut State)>;
+pub type ProgressCallback = Box<dyn FnMut(&World, &mut State, f32)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimerHandle(u64);
+
+enum TaskKind {
+    After(TimerCallback),
+    Every(TimerCallback),
+    Over(Easing, ProgressCallback),
+}
+
+struct Task {
+    handle: TimerHandle,
+    duration: f64,
+    elapsed: f64,
+    kind: TaskKind,
+}
+
+#[derive(Default)]
+pub struct Timers {
+    next_handle: u64,
+    tasks: Vec<Task>,
+    cancelled: Vec<TimerHandle>,
+}
+
+impl Timers {
+    pub fn new() -> Timers {
+        Timers::default()
+    }
+
+    fn push(&mut self, duration: f64, kind: TaskKind) -> TimerHandle {
+        let handle = TimerHandle(self.next_handle);
+        self.next_handle += 1;
+        self.tasks.push(Task {
+            handle,
+            duration,
+            elapsed: 0.0,
+            kind,
+        });
+        handle
+    }
+
+    pub fn after(&mut self, seconds: f64, callback: impl FnMut(&World, &mut State) + 'static) -> TimerHandle {
+        self.push(seconds, TaskKind::After(Box::new(callback)))
+    }
+
+    pub fn every(&mut self, seconds: f64, callback: impl FnMut(&World, &mut State) + 'static) -> TimerHandle {
+        self.push(seconds, TaskKind::Every(Box::new(callback)))
+    }
+
+    pub fn over(
+        &mut self,
+        seconds: f64,
+        easing: Easing,
+        callback: impl FnMut(&World, &mut State, f32) + 'static,
+    ) -> TimerHandle {
+        self.push(seconds, TaskKind::Over(easing, Box::new(callback)))
+    }
+
+    pub fn cancel(&mut self, handle: TimerHandle) {
+        self.cancelled.push(handle);
+    }
+
+    pub fn is_active(&self, handle: TimerHandle) -> bool {
+        !self.cancelled.contains(&handle) && self.tasks.iter().any(|x| x.handle == handle)
+    }
+}
+
+pub struct TimersUpdater {}
+
+impl System for TimersUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let mut tasks = mem::take(&mut state.timers.tasks);
+        let delta_time = state.delta_time;
+
+        tasks.retain_mut(|task| {
+            if state.timers.cancelled.contains(&task.handle) {
+                return false;
+            }
+            task.elapsed += delta_time;
+            match &mut task.kind {
+                TaskKind::After(callback) => {
+                    if task.elapsed < task.duration {
+                        return true;
+                    }
+                    callback(world, state);
+                    false
+                }
+                TaskKind::Every(callback) => {
+                    if task.duration <= 0.0 {
+                        callback(world, state);
+                        return true;
+                    }
+                    while task.elapsed >= task.duration && !state.timers.cancelled.contains(&task.handle) {
+                        task.elapsed -= task.duration;
+                        callback(world, state);
+                    }
+                    true
+                }
+                TaskKind::Over(easing, callback) => {
+                    let progress = if task.duration > 0.0 {
+                        (task.elapsed / task.duration).min(1.0) as f32
+                    } else {
+                        1.0
+                    };
+                    callback(world, state, easing.apply(progress));
+                    progress < 1.0
+                }
+            }
+        });
+
+        tasks.append(&mut state.timers.tasks);
+        let cancelled = mem::take(&mut state.timers.cancelled);
+        tasks.retain(|x| !cancelled.contains(&x.handle));
+        state.timers.tasks = tasks;
+    }
+}
@@ -8,3 +8,47 @@ pub mod shader;
 pub mod mesh;
 pub mod material;
 pub mod texture;
+pub mod visibility;
+pub mod frustum;
+pub mod terrain;
+pub mod lod;
+pub mod color;
+pub mod fog;
+pub mod ray;
+pub mod gizmo;
+pub mod debug_overlay;
+pub mod easing;
+pub mod tween;
+#[cfg(feature = "ui")]
+pub mod ui;
+#[cfg(feature = "ui")]
+pub mod ui_widgets;
+pub mod atlas;
+pub mod compressed_texture;
+pub mod light;
+#[cfg(feature = "gltf")]
+pub mod gltf;
+pub mod point_cloud;
+pub mod mesh_arena;
+pub mod trail;
+pub mod planar_reflection;
+pub mod behavior;
+pub mod skin;
+pub mod animation;
+pub mod morph;
+pub mod bundles;
+#[cfg(feature = "ui")]
+pub mod inspector;
+pub(crate) mod simd;
+pub mod quaternion;
+pub mod floating_origin;
+pub mod gpu_ptr;
+pub mod color_lut;
+pub mod meshlet;
+pub mod shader_graph;
+pub mod sprite_animation;
+pub mod tilemap;
+pub mod material_override;
+pub mod bvh;
+pub mod spline;
+pub mod noise;
@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+};
+
+use super::{matrices::Matrix4f, morph::MorphWeights, skin::SkinnedMesh, vectors::*};
+
+#[derive(Clone, Copy, Debug)]
+pub struct JointPose {
+    pub translation: Vec3f,
+    pub rotation: Vec3f,
+    pub scale: Vec3f,
+}
+
+impl JointPose {
+    pub fn new(translation: Vec3f, rotation: Vec3f, scale: Vec3f) -> JointPose {
+        JointPose { translation, rotation, scale }
+    }
+
+    pub fn lerp(&self, other: &JointPose, t: f32) -> JointPose {
+        JointPose {
+            translation: self.translation + (other.translation - self.translation) * t,
+            rotation: self.rotation + (other.rotation - self.rotation) * t,
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+
+    // Same composition as `Transform`.
+    pub fn matrix(&self) -> Matrix4f {
+        Matrix4f::compose(self.translation, self.rotation, self.scale)
+    }
+}
+
+impl Default for JointPose {
+    fn default() -> Self {
+        JointPose::new(Vec3f::new([0.0; 3]), Vec3f::new([0.0; 3]), Vec3f::new([1.0; 3]))
+    }
+}
+
+// Joints are ordered so that every parent comes before its children.
+#[derive(Clone, Debug)]
+pub struct Skeleton {
+    pub name: String,
+    pub parents: Vec<Option<usize>>,
+    pub inverse_bind: Vec<Matrix4f>,
+    pub bind_pose: Vec<JointPose>,
+}
+
+impl Skeleton {
+    pub fn new(name: &str, parents: Vec<Option<usize>>, inverse_bind: Vec<Matrix4f>, bind_pose: Vec<JointPose>) -> Skeleton {
+        assert!(parents.len() == inverse_bind.len() && parents.len() == bind_pose.len());
+        Skeleton {
+            name: name.to_string(),
+            parents,
+            inverse_bind,
+            bind_pose,
+        }
+    }
+
+    pub fn joint_count(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub fn joint_matrices(&self, pose: &[JointPose]) -> Vec<Matrix4f> {
+        let mut world: Vec<Matrix4f> = Vec::with_capacity(self.joint_count());
+        for (joint, parent) in self.parents.iter().enumerate() {
+            let local = pose[joint].matrix();
+            world.push(match parent {
+                Some(parent) => world[*parent] * local,
+                None => local,
+            });
+        }
+        world.iter().zip(self.inverse_bind.iter()).map(|(world, inverse_bind)| *world * *inverse_bind).collect()
+    }
+
+    // Full weight for `root` and everything below it, e.g. the spine for an upper body layer.
+    pub fn mask(&self, root: usize) -> Vec<f32> {
+        let mut mask = vec![0.0; self.joint_count()];
+        mask[root] = 1.0;
+        for joint in root + 1..self.joint_count() {
+            if self.parents[joint].is_some_and(|x| mask[x] > 0.0) {
+                mask[joint] = 1.0;
+            }
+        }
+        mask
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub pose: JointPose,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MorphKeyframe {
+    pub time: f32,
+    pub weight: f32,
+}
+
+// One channel of keyframes per joint, sorted by time. Joints with an empty channel keep the
+// skeleton's bind pose. `morph_channels` work the same way per morph target, missing ones sample
+// as zero.
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32,
+    pub channels: Vec<Vec<Keyframe>>,
+    pub morph_channels: Vec<Vec<MorphKeyframe>>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, duration: f32, channels: Vec<Vec<Keyframe>>) -> AnimationClip {
+        AnimationClip {
+            name: name.to_string(),
+            duration,
+            channels,
+            morph_channels: Vec::new(),
+        }
+    }
+
+    pub fn with_morph_channels(mut self, morph_channels: Vec<Vec<MorphKeyframe>>) -> AnimationClip {
+        self.morph_channels = morph_channels;
+        self
+    }
+
+    pub fn sample_morph(&self, time: f32, target: usize) -> f32 {
+        let Some(keyframes) = self.morph_channels.get(target).filter(|x| !x.is_empty()) else {
+            return 0.0;
+        };
+        let next = keyframes.partition_point(|x| x.time <= time);
+        if next == 0 {
+            return keyframes[0].weight;
+        }
+        if next == keyframes.len() {
+            return keyframes[next - 1].weight;
+        }
+        let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+        let t = (time - a.time) / (b.time - a.time).max(f32::EPSILON);
+        a.weight + (b.weight - a.weight) * t
+    }
+
+    pub fn sample(&self, time: f32, joint: usize, bind_pose: &JointPose) -> JointPose {
+        let Some(keyframes) = self.channels.get(joint).filter(|x| !x.is_empty()) else {
+            return *bind_pose;
+        };
+        let next = keyframes.partition_point(|x| x.time <= time);
+        if next == 0 {
+            return keyframes[0].pose;
+        }
+        if next == keyframes.len() {
+            return keyframes[next - 1].pose;
+        }
+        let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+        let t = (time - a.time) / (b.time - a.time).max(f32::EPSILON);
+        a.pose.lerp(&b.pose, t)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum BlendNode {
+    Clip(String),
+    /// Children sorted by threshold. The two around the parameter are blended linearly.
+    Blend1D { parameter: String, children: Vec<(f32, BlendNode)> },
+    /// Children weighted by inverse squared distance to the (x, y) parameter point.
+    Blend2D { x: String, y: String, children: Vec<(Vec2f, BlendNode)> },
+}
+
+impl BlendNode {
+    pub fn clip(name: &str) -> BlendNode {
+        BlendNode::Clip(name.to_string())
+    }
+
+    pub fn blend_1d(parameter: &str, children: Vec<(f32, BlendNode)>) -> BlendNode {
+        BlendNode::Blend1D {
+            parameter: parameter.to_string(),
+            children,
+        }
+    }
+
+    pub fn blend_2d(x: &str, y: &str, children: Vec<(Vec2f, BlendNode)>) -> BlendNode {
+        BlendNode::Blend2D {
+            x: x.to_string(),
+            y: y.to_string(),
+            children,
+        }
+    }
+
+    fn clip_weights(&self, parameters: &HashMap<String, f32>, weight: f32, out: &mut Vec<(String, f32)>) {
+        if weight <= 0.0 {
+            return;
+        }
+        let parameter = |name: &String| parameters.get(name).copied().unwrap_or(0.0);
+        match self {
+            BlendNode::Clip(name) => out.push((name.clone(), weight)),
+            BlendNode::Blend1D { parameter: name, children } => {
+                if children.is_empty() {
+                    return;
+                }
+                let value = parameter(name);
+                let next = children.partition_point(|x| x.0 <= value);
+                if next == 0 || next == children.len() {
+                    let child = &children[next.min(children.len() - 1)];
+                    child.1.clip_weights(parameters, weight, out);
+                    return;
+                }
+                let (a, b) = (&children[next - 1], &children[next]);
+                let t = (value - a.0) / (b.0 - a.0).max(f32::EPSILON);
+                a.1.clip_weights(parameters, weight * (1.0 - t), out);
+                b.1.clip_weights(parameters, weight * t, out);
+            }
+            BlendNode::Blend2D { x, y, children } => {
+                let point = Vec2f::new([parameter(x), parameter(y)]);
+                let distances: Vec<f32> = children
+                    .iter()
+                    .map(|x| {
+                        let offset = x.0 - point;
+                        offset.x * offset.x + offset.y * offset.y
+                    })
+                    .collect();
+                if let Some(exact) = distances.iter().position(|x| *x < 1e-6) {
+                    children[exact].1.clip_weights(parameters, weight, out);
+                    return;
+                }
+                let total: f32 = distances.iter().map(|x| 1.0 / x).sum();
+                for (child, distance) in children.iter().zip(distances.iter()) {
+                    child.1.clip_weights(parameters, weight / distance / total, out);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BlendLayer {
+    pub root: BlendNode,
+    pub weight: f32,
+    /// Per joint weight, see `Skeleton::mask`. `None` affects every joint.
+    pub mask: Option<Vec<f32>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlendTree {
+    pub name: String,
+    pub layers: Vec<BlendLayer>,
+}
+
+impl BlendTree {
+    pub fn new(name: &str, root: BlendNode) -> BlendTree {
+        BlendTree {
+            name: name.to_string(),
+            layers: vec![BlendLayer {
+                root,
+                weight: 1.0,
+                mask: None,
+            }],
+        }
+    }
+
+    pub fn with_layer(mut self, root: BlendNode, weight: f32, mask: Option<Vec<f32>>) -> BlendTree {
+        self.layers.push(BlendLayer { root, weight, mask });
+        self
+    }
+
+    // Clips in a layer are played in sync by normalized time, so a walk and a run blend without
+    // their footsteps drifting apart. `phases` holds one normalized time per layer. Returns the
+    // pose and the morph target weights.
+    fn evaluate(
+        &self,
+        assets: &AssetLibrary,
+        skeleton: &Skeleton,
+        parameters: &HashMap<String, f32>,
+        phases: &mut Vec<f32>,
+        delta_time: f32,
+    ) -> (Vec<JointPose>, Vec<f32>) {
+        phases.resize(self.layers.len(), 0.0);
+        let mut pose = skeleton.bind_pose.clone();
+        let mut morph_weights = Vec::new();
+
+        for (layer, phase) in self.layers.iter().zip(phases.iter_mut()) {
+            let mut weights = Vec::new();
+            layer.root.clip_weights(parameters, 1.0, &mut weights);
+            let clips: Vec<(&AnimationClip, f32)> = weights
+                .iter()
+                .filter_map(|(name, weight)| {
+                    let clip = assets.animations.iter().find(|x| x.name == *name);
+                    if clip.is_none() {
+                        log::warn!("blend tree {} uses missing clip {}", self.name, name);
+                    }
+                    Some((clip?, *weight))
+                })
+                .collect();
+            let total: f32 = clips.iter().map(|x| x.1).sum();
+            if total <= 0.0 {
+                continue;
+            }
+
+            let duration: f32 = clips.iter().map(|(clip, weight)| clip.duration * weight / total).sum();
+            if duration > 0.0 {
+                *phase = (*phase + delta_time / duration).rem_euclid(1.0);
+            }
+
+            for (joint, joint_pose) in pose.iter_mut().enumerate() {
+                let bind_pose = &skeleton.bind_pose[joint];
+                let mut blended = JointPose::new(Vec3f::new([0.0; 3]), Vec3f::new([0.0; 3]), Vec3f::new([0.0; 3]));
+                for (clip, weight) in clips.iter() {
+                    let sample = clip.sample(*phase * clip.duration, joint, bind_pose);
+                    let weight = weight / total;
+                    blended.translation += sample.translation * weight;
+                    blended.rotation += sample.rotation * weight;
+                    blended.scale += sample.scale * weight;
+                }
+                let mask = layer.mask.as_ref().map_or(1.0, |x| x.get(joint).copied().unwrap_or(0.0));
+                *joint_pose = joint_pose.lerp(&blended, layer.weight * mask);
+            }
+
+            let morph_count = clips.iter().map(|x| x.0.morph_channels.len()).max().unwrap_or(0);
+            if morph_weights.len() < morph_count {
+                morph_weights.resize(morph_count, 0.0);
+            }
+            for (target, morph_weight) in morph_weights.iter_mut().enumerate().take(morph_count) {
+                let blended: f32 = clips
+                    .iter()
+                    .map(|(clip, weight)| clip.sample_morph(*phase * clip.duration, target) * weight / total)
+                    .sum();
+                *morph_weight += (blended - *morph_weight) * layer.weight;
+            }
+        }
+        (pose, morph_weights)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Crossfade {
+    tree: String,
+    phases: Vec<f32>,
+    duration: f32,
+    elapsed: f32,
+}
+
+// Drives the `SkinnedMesh` and `MorphWeights` on the same entity.
+#[derive(Clone, Debug)]
+pub struct Animator {
+    pub skeleton: String,
+    pub tree: String,
+    pub parameters: HashMap<String, f32>,
+    pub speed: f32,
+    phases: Vec<f32>,
+    crossfade: Option<Crossfade>,
+}
+
+impl Animator {
+    pub fn new(skeleton: &str, tree: &str) -> Animator {
+        Animator {
+            skeleton: skeleton.to_string(),
+            tree: tree.to_string(),
+            parameters: HashMap::new(),
+            speed: 1.0,
+            phases: Vec::new(),
+            crossfade: None,
+        }
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: f32) {
+        self.parameters.insert(name.to_string(), value);
+    }
+
+    pub fn parameter(&self, name: &str) -> f32 {
+        self.parameters.get(name).copied().unwrap_or(0.0)
+    }
+
+    // The current tree keeps playing and fades out over `duration` seconds.
+    pub fn crossfade(&mut self, tree: &str, duration: f32) {
+        if self.tree == tree {
+            return;
+        }
+        self.crossfade = Some(Crossfade {
+            tree: std::mem::replace(&mut self.tree, tree.to_string()),
+            phases: std::mem::take(&mut self.phases),
+            duration,
+            elapsed: 0.0,
+        });
+    }
+
+    pub fn is_crossfading(&self) -> bool {
+        self.crossfade.is_some()
+    }
+
+    fn evaluate(&mut self, assets: &AssetLibrary, skeleton: &Skeleton, delta_time: f32) -> Option<(Vec<JointPose>, Vec<f32>)> {
+        let delta_time = delta_time * self.speed;
+        let tree = assets.blend_trees.iter().find(|x| x.name == self.tree)?;
+        let (pose, mut morph_weights) = tree.evaluate(assets, skeleton, &self.parameters, &mut self.phases, delta_time);
+
+        let Some(crossfade) = self.crossfade.as_mut() else {
+            return Some((pose, morph_weights));
+        };
+        crossfade.elapsed += delta_time;
+        let t = (crossfade.elapsed / crossfade.duration.max(f32::EPSILON)).min(1.0);
+        let Some(from) = assets.blend_trees.iter().find(|x| x.name == crossfade.tree) else {
+            self.crossfade = None;
+            return Some((pose, morph_weights));
+        };
+        let (from_pose, mut from_morph_weights) = from.evaluate(assets, skeleton, &self.parameters, &mut crossfade.phases, delta_time);
+        if t >= 1.0 {
+            self.crossfade = None;
+        }
+        let morph_count = morph_weights.len().max(from_morph_weights.len());
+        morph_weights.resize(morph_count, 0.0);
+        from_morph_weights.resize(morph_count, 0.0);
+        Some((
+            from_pose.iter().zip(pose.iter()).map(|(from, to)| from.lerp(to, t)).collect(),
+            from_morph_weights.iter().zip(morph_weights.iter()).map(|(from, to)| from + (to - from) * t).collect(),
+        ))
+    }
+}
+
+pub struct AnimatorUpdater {}
+
+impl System for AnimatorUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        let Some(mut animators) = world.borrow_component_vec_mut::<Animator>() else {
+            return;
+        };
+        let mut skinned_meshes = world.borrow_component_vec_mut::<SkinnedMesh>();
+        let mut morph_weights = world.borrow_component_vec_mut::<MorphWeights>();
+
+        for (entity, animator) in animators.iter_mut().enumerate() {
+            let Some(animator) = animator.as_mut() else {
+                continue;
+            };
+            let Some(skeleton) = assets.skeletons.iter().find(|x| x.name == animator.skeleton) else {
+                continue;
+            };
+            let Some((pose, weights)) = animator.evaluate(assets, skeleton, state.delta_time as f32) else {
+                continue;
+            };
+            if let Some(skinned_mesh) = skinned_meshes.as_mut().and_then(|x| x.get_mut(entity)?.as_mut()) {
+                skinned_mesh.joint_matrices = skeleton.joint_matrices(&pose);
+            }
+            // Targets past the last animated channel keep their manually set weight.
+            if let Some(morph_weights) = morph_weights.as_mut().and_then(|x| x.get_mut(entity)?.as_mut()) {
+                let len = weights.len().min(morph_weights.weights.len());
+                morph_weights.weights[..len].copy_from_slice(&weights[..len]);
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::{collections::HashMap, fs::File, io::BufReader};
+
+use serde::Deserialize;
+
+use super::vectors::Vec2f;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AtlasRegion {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TextureAtlas {
+    #[serde(skip)]
+    pub name: String,
+    pub texture: String,
+    pub width: u32,
+    pub height: u32,
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    pub fn load(name: &str) -> TextureAtlas {
+        let file = File::open(format!("assets/atlases/{}.json", name)).unwrap();
+        let mut atlas: TextureAtlas = serde_json::from_reader(BufReader::new(file)).unwrap();
+        atlas.name = name.to_string();
+        atlas
+    }
+
+    pub fn region(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).copied()
+    }
+
+    pub fn uv(&self, x: f32, y: f32) -> Vec2f {
+        Vec2f::new([x / self.width as f32, y / self.height as f32])
+    }
+
+    pub fn region_uv(&self, name: &str) -> Option<(Vec2f, Vec2f)> {
+        let region = self.region(name)?;
+        Some((
+            self.uv(region.x as f32, region.y as f32),
+            self.uv((region.x + region.w) as f32, (region.y + region.h) as f32),
+        ))
+    }
+}
@@ -0,0 +1,303 @@
+use std::sync::Arc;
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+};
+
+pub type StateCallback = Arc<dyn Fn(&World, &mut State, usize)>;
+pub type StateUpdateCallback = Arc<dyn Fn(&World, &mut State, usize, f64) -> Option<String>>;
+pub type ConditionCallback = Arc<dyn Fn(&World, &mut State, usize) -> bool>;
+pub type ActionCallback = Arc<dyn Fn(&World, &mut State, usize) -> BehaviorStatus>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateTransition {
+    pub entity: usize,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Behaviors {
+    transitions: Vec<StateTransition>,
+}
+
+impl Behaviors {
+    pub fn new() -> Behaviors {
+        Behaviors::default()
+    }
+
+    pub fn drain_transitions(&mut self) -> Vec<StateTransition> {
+        std::mem::take(&mut self.transitions)
+    }
+}
+
+#[derive(Clone)]
+struct MachineState {
+    name: String,
+    on_enter: Option<StateCallback>,
+    on_update: Option<StateUpdateCallback>,
+    on_exit: Option<StateCallback>,
+}
+
+#[derive(Clone)]
+pub struct StateMachine {
+    states: Vec<MachineState>,
+    current: String,
+    pending: Option<String>,
+    entered: bool,
+    pub time_in_state: f64,
+}
+
+impl StateMachine {
+    pub fn new(initial: &str) -> StateMachine {
+        StateMachine {
+            states: Vec::new(),
+            current: initial.to_string(),
+            pending: None,
+            entered: false,
+            time_in_state: 0.0,
+        }
+    }
+
+    fn state_mut(&mut self, name: &str) -> &mut MachineState {
+        if let Some(i) = self.states.iter().position(|x| x.name == name) {
+            return &mut self.states[i];
+        }
+        self.states.push(MachineState {
+            name: name.to_string(),
+            on_enter: None,
+            on_update: None,
+            on_exit: None,
+        });
+        self.states.last_mut().unwrap()
+    }
+
+    // The update callback gets the time spent in the state and returns the state to switch to.
+    pub fn with_state(
+        mut self,
+        name: &str,
+        on_update: impl Fn(&World, &mut State, usize, f64) -> Option<String> + 'static,
+    ) -> StateMachine {
+        self.state_mut(name).on_update = Some(Arc::new(on_update));
+        self
+    }
+
+    pub fn with_enter(mut self, name: &str, on_enter: impl Fn(&World, &mut State, usize) + 'static) -> StateMachine {
+        self.state_mut(name).on_enter = Some(Arc::new(on_enter));
+        self
+    }
+
+    pub fn with_exit(mut self, name: &str, on_exit: impl Fn(&World, &mut State, usize) + 'static) -> StateMachine {
+        self.state_mut(name).on_exit = Some(Arc::new(on_exit));
+        self
+    }
+
+    pub fn current(&self) -> &str {
+        &self.current
+    }
+
+    pub fn has_state(&self, name: &str) -> bool {
+        self.states.iter().any(|x| x.name == name)
+    }
+
+    // Applied on the next update, before the current state's update callback runs.
+    pub fn request(&mut self, name: &str) {
+        self.pending = Some(name.to_string());
+    }
+
+    fn state(&self, name: &str) -> Option<&MachineState> {
+        self.states.iter().find(|x| x.name == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BehaviorStatus {
+    Success,
+    Failure,
+    Running,
+}
+
+#[derive(Clone)]
+pub enum BehaviorNode {
+    /// Runs children in order until one fails or is still running.
+    Sequence(Vec<BehaviorNode>),
+    /// Runs children in order until one succeeds or is still running.
+    Selector(Vec<BehaviorNode>),
+    Inverter(Box<BehaviorNode>),
+    Condition(ConditionCallback),
+    Action(ActionCallback),
+}
+
+impl BehaviorNode {
+    pub fn sequence(children: Vec<BehaviorNode>) -> BehaviorNode {
+        BehaviorNode::Sequence(children)
+    }
+
+    pub fn selector(children: Vec<BehaviorNode>) -> BehaviorNode {
+        BehaviorNode::Selector(children)
+    }
+
+    pub fn inverter(child: BehaviorNode) -> BehaviorNode {
+        BehaviorNode::Inverter(Box::new(child))
+    }
+
+    pub fn condition(condition: impl Fn(&World, &mut State, usize) -> bool + 'static) -> BehaviorNode {
+        BehaviorNode::Condition(Arc::new(condition))
+    }
+
+    pub fn action(action: impl Fn(&World, &mut State, usize) -> BehaviorStatus + 'static) -> BehaviorNode {
+        BehaviorNode::Action(Arc::new(action))
+    }
+
+    pub fn tick(&self, world: &World, state: &mut State, entity: usize) -> BehaviorStatus {
+        match self {
+            BehaviorNode::Sequence(children) => {
+                for child in children.iter() {
+                    let status = child.tick(world, state, entity);
+                    if status != BehaviorStatus::Success {
+                        return status;
+                    }
+                }
+                BehaviorStatus::Success
+            }
+            BehaviorNode::Selector(children) => {
+                for child in children.iter() {
+                    let status = child.tick(world, state, entity);
+                    if status != BehaviorStatus::Failure {
+                        return status;
+                    }
+                }
+                BehaviorStatus::Failure
+            }
+            BehaviorNode::Inverter(child) => match child.tick(world, state, entity) {
+                BehaviorStatus::Success => BehaviorStatus::Failure,
+                BehaviorStatus::Failure => BehaviorStatus::Success,
+                BehaviorStatus::Running => BehaviorStatus::Running,
+            },
+            BehaviorNode::Condition(condition) => match condition(world, state, entity) {
+                true => BehaviorStatus::Success,
+                false => BehaviorStatus::Failure,
+            },
+            BehaviorNode::Action(action) => action(world, state, entity),
+        }
+    }
+}
+
+// The tree is evaluated from the root on every tick, so higher priority branches can interrupt
+// running actions.
+#[derive(Clone)]
+pub struct BehaviorTree {
+    pub root: BehaviorNode,
+    pub interval: f64,
+    pub last_status: Option<BehaviorStatus>,
+    elapsed: f64,
+}
+
+impl BehaviorTree {
+    pub fn new(root: BehaviorNode) -> BehaviorTree {
+        BehaviorTree {
+            root,
+            interval: 0.0,
+            last_status: None,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn with_interval(mut self, interval: f64) -> BehaviorTree {
+        self.interval = interval;
+        self
+    }
+}
+
+// Callbacks may borrow any component, including the machine or tree being evaluated, so the
+// component is cloned out and its borrow released before they run.
+fn update_state_machine(world: &World, state: &mut State, entity: usize) {
+    let machine = {
+        let Some(mut machines) = world.borrow_component_vec_mut::<StateMachine>() else {
+            return;
+        };
+        let Some(machine) = machines.get_mut(entity).and_then(|x| x.as_mut()) else {
+            return;
+        };
+        let snapshot = machine.clone();
+        machine.pending = None;
+        snapshot
+    };
+
+    let mut current = machine.current.clone();
+    let mut time_in_state = machine.time_in_state + state.delta_time;
+    if !machine.entered {
+        if let Some(on_enter) = machine.state(&current).and_then(|x| x.on_enter.clone()) {
+            on_enter(world, state, entity);
+        }
+    }
+
+    let next = machine.pending.clone().or_else(|| {
+        let on_update = machine.state(&current).and_then(|x| x.on_update.clone())?;
+        on_update(world, state, entity, time_in_state)
+    });
+    if let Some(next) = next.filter(|x| *x != current) {
+        if machine.has_state(&next) {
+            if let Some(on_exit) = machine.state(&current).and_then(|x| x.on_exit.clone()) {
+                on_exit(world, state, entity);
+            }
+            if let Some(on_enter) = machine.state(&next).and_then(|x| x.on_enter.clone()) {
+                on_enter(world, state, entity);
+            }
+            state.behaviors.transitions.push(StateTransition {
+                entity,
+                from: current,
+                to: next.clone(),
+            });
+            current = next;
+            time_in_state = 0.0;
+        } else {
+            log::warn!("entity {entity} requested unknown state {next}");
+        }
+    }
+
+    let mut machines = world.borrow_component_vec_mut::<StateMachine>().unwrap();
+    if let Some(machine) = machines.get_mut(entity).and_then(|x| x.as_mut()) {
+        machine.current = current;
+        machine.time_in_state = time_in_state;
+        machine.entered = true;
+    }
+}
+
+fn update_behavior_tree(world: &World, state: &mut State, entity: usize) {
+    let root = {
+        let Some(mut trees) = world.borrow_component_vec_mut::<BehaviorTree>() else {
+            return;
+        };
+        let Some(tree) = trees.get_mut(entity).and_then(|x| x.as_mut()) else {
+            return;
+        };
+        tree.elapsed += state.delta_time;
+        if tree.last_status.is_some() && tree.elapsed < tree.interval {
+            return;
+        }
+        tree.elapsed = 0.0;
+        tree.root.clone()
+    };
+
+    let status = root.tick(world, state, entity);
+
+    let mut trees = world.borrow_component_vec_mut::<BehaviorTree>().unwrap();
+    if let Some(tree) = trees.get_mut(entity).and_then(|x| x.as_mut()) {
+        tree.last_status = Some(status);
+    }
+}
+
+pub struct BehaviorUpdater {}
+
+impl System for BehaviorUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        for entity in 0..world.entity_count {
+            update_state_machine(world, state, entity);
+            update_behavior_tree(world, state, entity);
+        }
+    }
+}
@@ -5,9 +5,9 @@ use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
 use crate::rendering::Renderer;
 use crate::state::State;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct UpdatableBuffer<DataType> {
-    pub buffer: Subbuffer<DataType>,
+    pub buffers: Vec<Subbuffer<DataType>>,
 }
 
 impl<DataType> UpdatableBuffer<DataType>
@@ -16,29 +16,43 @@ where
 {
     pub fn new(renderer: &Renderer, buffer_usage: BufferUsage) -> UpdatableBuffer<DataType> {
         let updatable_buffer = UpdatableBuffer::<DataType> { 
-            buffer:
-                Buffer::new_sized(
-                    renderer.memeory_allocator.as_ref().unwrap().clone(), 
-                    BufferCreateInfo {
-                        usage: buffer_usage | BufferUsage::TRANSFER_DST,
-                        ..Default::default()
-                    }, 
-                    AllocationCreateInfo {
-                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                            | MemoryTypeFilter::HOST_RANDOM_ACCESS,
-                        ..Default::default()
-                    }
-                ).unwrap(),
+            buffers: (0..renderer.frames_in_flight.max(1))
+                .map(|_| {
+                    Buffer::new_sized(
+                        renderer.memeory_allocator.as_ref().unwrap().clone(), 
+                        BufferCreateInfo {
+                            usage: buffer_usage | BufferUsage::TRANSFER_DST,
+                            ..Default::default()
+                        }, 
+                        AllocationCreateInfo {
+                            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                            ..Default::default()
+                        }
+                    ).unwrap()
+                })
+                .collect(),
         };
         updatable_buffer
     }
 
-    pub fn write(&self, _state: &State, data: DataType) {
-        let mut content = self.buffer.write().unwrap();
+    pub fn buffer(&self, frame: usize) -> Subbuffer<DataType> {
+        self.buffers[frame].clone()
+    }
+
+    pub fn write(&self, state: &State, data: DataType) {
+        self.write_frame(state.renderer.current_frame, data);
+    }
+
+    pub fn write_frame(&self, frame: usize, data: DataType) {
+        let mut content = self.buffers[frame].write().unwrap();
         *content = data;
     }
     
     pub fn write_all(&self, _state: &State, data: DataType) {
-        self.write(_state, data);
+        for buffer in self.buffers.iter() {
+            let mut content = buffer.write().unwrap();
+            *content = data;
+        }
     }
 }
@@ -0,0 +1,124 @@
+use crate::ecs::{Bundle, World};
+
+use super::{
+    camera::Camera,
+    light::PointLight,
+    mesh::DynamicMesh,
+    static_mesh::StaticMesh,
+    transform::Transform,
+    vectors::{Vec3d, Vec3f},
+    visibility::Visibility,
+};
+
+fn transform_at(position: Vec3d) -> Transform {
+    Transform::new(position, Vec3f::new([1.0, 1.0, 1.0]), Vec3f::new([0.0, 0.0, 0.0]))
+}
+
+// Everything the renderer needs to draw a mesh from the asset library. The material is the one
+// the mesh was loaded with.
+#[derive(Clone)]
+pub struct MeshBundle {
+    pub mesh: StaticMesh,
+    pub transform: Transform,
+    pub visibility: Visibility,
+}
+
+impl MeshBundle {
+    pub fn new(mesh_name: &str, transform: Transform) -> MeshBundle {
+        MeshBundle {
+            mesh: StaticMesh {
+                mesh_name: mesh_name.to_string(),
+            },
+            transform,
+            visibility: Visibility::default(),
+        }
+    }
+
+    pub fn at(mesh_name: &str, position: Vec3d) -> MeshBundle {
+        MeshBundle::new(mesh_name, transform_at(position))
+    }
+
+    pub fn hidden(mut self) -> MeshBundle {
+        self.visibility = Visibility::new(false);
+        self
+    }
+}
+
+impl Bundle for MeshBundle {
+    fn insert(self, world: &mut World, entity_id: usize) {
+        world.add_component(entity_id, self.mesh);
+        world.add_component(entity_id, self.transform);
+        world.add_component(entity_id, self.visibility);
+    }
+}
+
+#[derive(Clone)]
+pub struct DynamicMeshBundle {
+    pub mesh: DynamicMesh,
+    pub transform: Transform,
+    pub visibility: Visibility,
+}
+
+impl DynamicMeshBundle {
+    pub fn new(mesh: DynamicMesh, transform: Transform) -> DynamicMeshBundle {
+        DynamicMeshBundle {
+            mesh,
+            transform,
+            visibility: Visibility::default(),
+        }
+    }
+}
+
+impl Bundle for DynamicMeshBundle {
+    fn insert(self, world: &mut World, entity_id: usize) {
+        world.add_component(entity_id, self.mesh);
+        world.add_component(entity_id, self.transform);
+        world.add_component(entity_id, self.visibility);
+    }
+}
+
+// The first entity with both components is the active camera.
+#[derive(Clone)]
+pub struct CameraBundle {
+    pub camera: Camera,
+    pub transform: Transform,
+}
+
+impl CameraBundle {
+    pub fn new(camera: Camera, transform: Transform) -> CameraBundle {
+        CameraBundle { camera, transform }
+    }
+
+    pub fn at(position: Vec3d, vfov: f32) -> CameraBundle {
+        CameraBundle::new(Camera::new(vfov, 0.1, 1000.0), transform_at(position))
+    }
+}
+
+impl Bundle for CameraBundle {
+    fn insert(self, world: &mut World, entity_id: usize) {
+        world.add_component(entity_id, self.camera);
+        world.add_component(entity_id, self.transform);
+    }
+}
+
+#[derive(Clone)]
+pub struct LightBundle {
+    pub light: PointLight,
+    pub transform: Transform,
+}
+
+impl LightBundle {
+    pub fn new(light: PointLight, position: Vec3d) -> LightBundle {
+        LightBundle {
+            light,
+            transform: transform_at(position),
+        }
+    }
+}
+
+impl Bundle for LightBundle {
+    fn insert(self, world: &mut World, entity_id: usize) {
+        world.add_component(entity_id, self.light);
+        world.add_component(entity_id, self.transform);
+    }
+}
@@ -0,0 +1,281 @@
+use super::frustum::Frustum;
+use super::ray::Ray;
+use super::vectors::Vec3f;
+
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3f, max: Vec3f) -> Aabb {
+        Aabb { min, max }
+    }
+
+    // Contains nothing, the start for `union` and `grow`.
+    pub fn empty() -> Aabb {
+        Aabb::new(Vec3f::new([f32::MAX; 3]), Vec3f::new([f32::MIN; 3]))
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3f>) -> Aabb {
+        points.into_iter().fold(Aabb::empty(), |x, point| x.grow(point))
+    }
+
+    pub fn grow(&self, point: Vec3f) -> Aabb {
+        self.union(&Aabb::new(point, point))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Vec3f::new([self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)]),
+            Vec3f::new([self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)]),
+        )
+    }
+
+    pub fn center(&self) -> Vec3f {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    bounds: Aabb,
+    /// Index of the first child for inner nodes, the second follows it. First entry of `items`
+    /// for leaves.
+    first: u32,
+    /// Items of a leaf, 0 for inner nodes.
+    count: u32,
+}
+
+// Bounding volume hierarchy over boxes given by index, e.g. triangles, entities or physics bodies.
+// Nodes are split at the median of the longest axis. Moving boxes can be refit instead of rebuilt,
+// which is faster but loosens the tree as they drift apart.
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    items: Vec<u32>,
+    /// Per item, so queries test the items of a leaf exactly.
+    bounds: Vec<Aabb>,
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(bounds.len() / LEAF_SIZE * 2 + 1),
+            items: (0..bounds.len() as u32).collect(),
+            bounds: bounds.to_vec(),
+        };
+        if !bounds.is_empty() {
+            bvh.nodes.push(Node { bounds: Aabb::empty(), first: 0, count: 0 });
+            bvh.split(0, 0, bounds.len(), bounds);
+        }
+        bvh
+    }
+
+    fn split(&mut self, node: usize, start: usize, end: usize, bounds: &[Aabb]) {
+        let node_bounds = self.items[start..end].iter().fold(Aabb::empty(), |x, item| x.union(&bounds[*item as usize]));
+        self.nodes[node] = Node { bounds: node_bounds, first: start as u32, count: (end - start) as u32 };
+        if end - start <= LEAF_SIZE {
+            return;
+        }
+
+        let extent = node_bounds.max - node_bounds.min;
+        let center = |item: u32| {
+            let center = bounds[item as usize].center();
+            if extent.x >= extent.y && extent.x >= extent.z {
+                center.x
+            } else if extent.y >= extent.z {
+                center.y
+            } else {
+                center.z
+            }
+        };
+        let middle = (start + end) / 2;
+        self.items[start..end].select_nth_unstable_by(middle - start, |a, b| center(*a).total_cmp(&center(*b)));
+
+        // Children always come after their parent, which `refit` relies on.
+        let first = self.nodes.len();
+        self.nodes[node].first = first as u32;
+        self.nodes[node].count = 0;
+        self.nodes.push(self.nodes[node]);
+        self.nodes.push(self.nodes[node]);
+        self.split(first, start, middle, bounds);
+        self.split(first + 1, middle, end, bounds);
+    }
+
+    // Updates the node bounds for moved boxes, `bounds` holds the same items as for `build`.
+    pub fn refit(&mut self, bounds: &[Aabb]) {
+        assert_eq!(bounds.len(), self.items.len(), "refit needs the boxes the tree was built from");
+        self.bounds.copy_from_slice(bounds);
+        for node in (0..self.nodes.len()).rev() {
+            let Node { first, count, .. } = self.nodes[node];
+            self.nodes[node].bounds = if count == 0 {
+                self.nodes[first as usize].bounds.union(&self.nodes[first as usize + 1].bounds)
+            } else {
+                self.items[first as usize..(first + count) as usize]
+                    .iter()
+                    .fold(Aabb::empty(), |x, item| x.union(&bounds[*item as usize]))
+            };
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|x| x.bounds)
+    }
+
+    // Visits every item whose box passes `test`, skipping whole subtrees whose node fails it.
+    fn traverse(&self, mut test: impl FnMut(&Aabb) -> bool, mut visit: impl FnMut(usize)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if !test(&node.bounds) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.extend([node.first as usize, node.first as usize + 1]);
+            } else {
+                for item in self.items[node.first as usize..(node.first + node.count) as usize].iter() {
+                    if test(&self.bounds[*item as usize]) {
+                        visit(*item as usize);
+                    }
+                }
+            }
+        }
+    }
+
+    // The closest item `hit` reports a distance for. `hit` gets the item and the distance to beat
+    // and returns its own if closer, boxes farther than the closest hit so far are skipped.
+    pub fn traverse_ray(&self, ray: &Ray, max_distance: f32, mut hit: impl FnMut(usize, f32) -> Option<f32>) -> Option<(usize, f32)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut closest: Option<(usize, f32)> = None;
+        // Nearer children are visited first so far ones are more often skipped.
+        let mut stack = vec![(0, 0.0)];
+        while let Some((node, entry)) = stack.pop() {
+            let limit = closest.map_or(max_distance, |x| x.1);
+            if entry > limit {
+                continue;
+            }
+            let node = &self.nodes[node];
+            if node.count > 0 {
+                for item in self.items[node.first as usize..(node.first + node.count) as usize].iter() {
+                    let limit = closest.map_or(max_distance, |x| x.1);
+                    let bounds = &self.bounds[*item as usize];
+                    if ray.intersect_aabb(bounds.min, bounds.max).is_none_or(|x| x > limit) {
+                        continue;
+                    }
+                    if let Some(distance) = hit(*item as usize, limit).filter(|x| *x <= limit) {
+                        closest = Some((*item as usize, distance));
+                    }
+                }
+                continue;
+            }
+            let children = [node.first as usize, node.first as usize + 1]
+                .map(|x| (x, ray.intersect_aabb(self.nodes[x].bounds.min, self.nodes[x].bounds.max)));
+            let [near, far] = if children[1].1.unwrap_or(f32::MAX) < children[0].1.unwrap_or(f32::MAX) {
+                [children[1], children[0]]
+            } else {
+                children
+            };
+            for (child, entry) in [far, near] {
+                if let Some(entry) = entry.filter(|x| *x <= limit) {
+                    stack.push((child, entry));
+                }
+            }
+        }
+        closest
+    }
+
+    // Items whose box is at least partly inside the frustum.
+    pub fn traverse_frustum(&self, frustum: &Frustum, visit: impl FnMut(usize)) {
+        self.traverse(|x| frustum.intersects_aabb(x.min, x.max), visit);
+    }
+
+    // Items whose box overlaps `aabb`, e.g. for broad phase collision.
+    pub fn traverse_aabb(&self, aabb: &Aabb, visit: impl FnMut(usize)) {
+        self.traverse(|x| x.intersects(aabb), visit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::matrices::Matrix4f;
+    use crate::types::noise::Rng;
+
+    fn boxes(rng: &mut Rng, count: usize) -> Vec<Aabb> {
+        (0..count)
+            .map(|_| {
+                let min = Vec3f::new([rng.range_f32(-50.0, 50.0), rng.range_f32(-50.0, 50.0), rng.range_f32(-50.0, 50.0)]);
+                let size = Vec3f::new([rng.range_f32(0.1, 4.0), rng.range_f32(0.1, 4.0), rng.range_f32(0.1, 4.0)]);
+                Aabb::new(min, min + size)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ray_traversal_matches_brute_force() {
+        let mut rng = Rng::new(1);
+        let bounds = boxes(&mut rng, 500);
+        let bvh = Bvh::build(&bounds);
+        let mut hits = 0;
+        for _ in 0..200 {
+            let origin = Vec3f::new([rng.range_f32(-60.0, 60.0), rng.range_f32(-60.0, 60.0), rng.range_f32(-60.0, 60.0)]);
+            let ray = Ray::new(origin, rng.unit_vec3());
+            let max_distance = rng.range_f32(10.0, 150.0);
+            let expected = bounds
+                .iter()
+                .filter_map(|x| ray.intersect_aabb(x.min, x.max))
+                .filter(|x| *x <= max_distance)
+                .fold(None, |a: Option<f32>, b| Some(a.map_or(b, |a| a.min(b))));
+            let found = bvh.traverse_ray(&ray, max_distance, |item, _| ray.intersect_aabb(bounds[item].min, bounds[item].max));
+            assert_eq!(found.map(|x| x.1), expected);
+            hits += found.is_some() as usize;
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    fn frustum_traversal_matches_brute_force() {
+        let mut rng = Rng::new(2);
+        let bounds = boxes(&mut rng, 500);
+        let bvh = Bvh::build(&bounds);
+        let mut visible = 0;
+        for _ in 0..50 {
+            let eye = Vec3f::new([rng.range_f32(-60.0, 60.0), rng.range_f32(-60.0, 60.0), rng.range_f32(-60.0, 60.0)]);
+            let view = Matrix4f::look_at(eye, rng.unit_vec3(), Vec3f::new([0.0, 1.0, 0.0]));
+            let frustum = Frustum::from_matrix(Matrix4f::perspective(1.2, 1.5, 0.1, 60.0) * view);
+            let expected: Vec<usize> = (0..bounds.len()).filter(|x| frustum.intersects_aabb(bounds[*x].min, bounds[*x].max)).collect();
+            let mut found = Vec::new();
+            bvh.traverse_frustum(&frustum, |x| found.push(x));
+            found.sort_unstable();
+            assert_eq!(found, expected);
+            visible += found.len();
+        }
+        assert!(visible > 0);
+    }
+}
@@ -1,29 +1,137 @@
-use crate::{asset_library::AssetLibrary, ecs::{System, World}, state::State};
+use serde::{Deserialize, Serialize};
+use simple_engine_derive::{Component, Reflect};
 
-use super::{matrices::Matrix4f, transform::Transform, vectors::Vec3f};
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    rendering::{MAX_CLIP_PLANES, NO_CLIP_PLANE},
+    state::State,
+};
 
-#[derive(Clone, Copy)]
+use super::{
+    matrices::Matrix4f,
+    transform::Transform,
+    vectors::{Vec3d, Vec3f},
+};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Component, Reflect)]
+#[component(persistent, reflect)]
 pub struct Camera {
     pub vfov: f32,
     pub near: f32,
     pub far: f32,
+    /// World space planes `[a, b, c, d]`, geometry with `a*x + b*y + c*z + d < 0` is clipped by
+    /// shaders that write clip distances, see `VPData`.
+    #[serde(default)]
+    #[reflect(skip)]
+    pub clip_planes: [Option<[f32; 4]>; MAX_CLIP_PLANES],
+}
+
+impl Camera {
+    pub fn new(vfov: f32, near: f32, far: f32) -> Camera {
+        Camera {
+            vfov,
+            near,
+            far,
+            clip_planes: [None; MAX_CLIP_PLANES],
+        }
+    }
+
+    // Panics if all slots are taken.
+    pub fn with_clip_plane(mut self, plane: [f32; 4]) -> Camera {
+        let slot = self.clip_planes.iter_mut().find(|x| x.is_none()).expect("too many clip planes");
+        *slot = Some(plane);
+        self
+    }
+}
+
+// Parameters for `PostEffect::lens`, read from the camera that renders. Zero turns an effect off.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Component, Reflect)]
+#[component(persistent, reflect)]
+pub struct LensEffects {
+    /// How much the corners darken, 0..1.
+    pub vignette: f32,
+    /// Fraction of the screen the vignette fades over.
+    pub vignette_softness: f32,
+    /// Red and blue channel offset at the corners, in pixels.
+    pub chromatic_aberration: f32,
+    /// Strength of the animated noise, 0..1.
+    pub grain: f32,
+}
+
+impl Default for LensEffects {
+    fn default() -> Self {
+        LensEffects {
+            vignette: 0.3,
+            vignette_softness: 0.5,
+            chromatic_aberration: 0.0,
+            grain: 0.0,
+        }
+    }
+}
+
+impl LensEffects {
+    pub fn to_params(&self) -> [f32; 4] {
+        [self.vignette, self.vignette_softness, self.chromatic_aberration, self.grain]
+    }
+}
+
+// Moves the render origin with the first camera, see `RendererSettings::camera_relative`. Runs
+// before the transforms are written, true if the origin moved.
+pub(crate) fn update_render_origin(world: &World, state: &mut State) -> bool {
+    let Some(step) = state.renderer.settings.camera_relative else {
+        return false;
+    };
+    let (Some(cameras), Some(transforms)) = (world.borrow_component_vec_mut::<Camera>(), world.borrow_component_vec_mut::<Transform>()) else {
+        return false;
+    };
+    let Some(position) = cameras
+        .iter()
+        .zip(transforms.iter())
+        .find_map(|(camera, transform)| camera.and(transform.as_ref()).map(|x| x.position))
+    else {
+        return false;
+    };
+
+    let snap = |x: f64| if step > 0.0 { (x / step).round() * step } else { x };
+    let origin = Vec3d::new([snap(position.x), snap(position.y), snap(position.z)]);
+    let current = state.renderer.render_origin;
+    if origin.x == current.x && origin.y == current.y && origin.z == current.z {
+        return false;
+    }
+    state.renderer.render_origin = origin;
+    true
 }
 
 pub struct CameraUpdater {}
 
 impl System for CameraUpdater {
-    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_start(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(cameras) = world.borrow_component_vec_mut::<Camera>() else {
+            return;
+        };
+        let clipping = cameras.iter().flatten().any(|x| x.clip_planes.iter().any(|x| x.is_some()));
+        if clipping && !state.renderer.enabled_features.shader_clip_distance {
+            log::warn!("clip planes need the shader_clip_distance feature");
+        }
+    }
     fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
         let mut camera = world.borrow_component_vec_mut::<Camera>().unwrap();
         let mut transform = world.borrow_component_vec_mut::<Transform>().unwrap();
         let zip = camera.iter_mut().zip(transform.iter_mut());
         let mut iter =
             zip.filter_map(|(camera, transform)| Some((camera.as_mut()?, transform.as_mut()?)));
-        let (_, transform_data) = iter.next().unwrap();
+        let (camera_data, transform_data) = iter.next().unwrap();
+        // Moved into render space: a*(x + o.x) + b*(y + o.y) + c*(z + o.z) + d.
+        let origin = state.renderer.render_origin;
+        state.renderer.vp_data.clip_planes = camera_data.clip_planes.map(|x| match x {
+            Some([a, b, c, d]) => [a, b, c, (d as f64 + a as f64 * origin.x + b as f64 * origin.y + c as f64 * origin.z) as f32],
+            None => NO_CLIP_PLANE,
+        });
         let cam_rot = Matrix4f::rotation_xzy(transform_data.rotation);
         state.renderer.vp_pos = transform_data.position;
         state.renderer.vp_data.view = Matrix4f::look_at(
-            transform_data.position.to_vec3f(),
+            state.renderer.render_space(transform_data.position),
             cam_rot.vec_mul(Vec3f::new([1.0, 0.0, 0.0])),
             cam_rot.vec_mul(Vec3f::new([0.0, 1.0, 0.0])),
         );
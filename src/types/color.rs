@@ -0,0 +1,97 @@
+use bytemuck::{Pod, Zeroable};
+
+/// A linear RGBA color. The renderer works in linear space and only encodes to sRGB when presenting,
+/// so values picked in an sRGB color picker should go through `from_srgb` or `from_srgb8`.
+#[derive(Clone, Copy, Pod, Zeroable, Debug, PartialEq)]
+#[repr(C)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl Color {
+    pub const TRANSPARENT: Color = Color::rgba(0.0, 0.0, 0.0, 0.0);
+    pub const BLACK: Color = Color::rgba(0.0, 0.0, 0.0, 1.0);
+    pub const WHITE: Color = Color::rgba(1.0, 1.0, 1.0, 1.0);
+    pub const GRAY: Color = Color::rgba(0.5, 0.5, 0.5, 1.0);
+    pub const RED: Color = Color::rgba(1.0, 0.0, 0.0, 1.0);
+    pub const GREEN: Color = Color::rgba(0.0, 1.0, 0.0, 1.0);
+    pub const BLUE: Color = Color::rgba(0.0, 0.0, 1.0, 1.0);
+    pub const YELLOW: Color = Color::rgba(1.0, 1.0, 0.0, 1.0);
+    pub const CYAN: Color = Color::rgba(0.0, 1.0, 1.0, 1.0);
+    pub const MAGENTA: Color = Color::rgba(1.0, 0.0, 1.0, 1.0);
+
+    pub fn new(val: [f32; 4]) -> Color {
+        Color::rgba(val[0], val[1], val[2], val[3])
+    }
+
+    pub const fn rgba(r: f32, g: f32, b: f32, a: f32) -> Color {
+        Color { r, g, b, a }
+    }
+
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Color {
+        Color::rgba(r, g, b, 1.0)
+    }
+
+    /// Normalizes the bytes without any color space conversion.
+    pub fn from_rgba8(val: [u8; 4]) -> Color {
+        Color::new(val.map(|x| x as f32 / 255.0))
+    }
+
+    pub fn from_srgb8(val: [u8; 4]) -> Color {
+        Color::from_rgba8(val).to_linear()
+    }
+
+    pub fn from_srgb(val: [f32; 4]) -> Color {
+        Color::new(val).to_linear()
+    }
+
+    pub fn to_linear(&self) -> Color {
+        Color::rgba(srgb_to_linear(self.r), srgb_to_linear(self.g), srgb_to_linear(self.b), self.a)
+    }
+
+    pub fn to_srgb(&self) -> Color {
+        Color::rgba(linear_to_srgb(self.r), linear_to_srgb(self.g), linear_to_srgb(self.b), self.a)
+    }
+
+    pub fn with_alpha(&self, a: f32) -> Color {
+        Color::rgba(self.r, self.g, self.b, a)
+    }
+
+    pub fn lerp(&self, other: Color, t: f32) -> Color {
+        Color::rgba(
+            self.r + (other.r - self.r) * t,
+            self.g + (other.g - self.g) * t,
+            self.b + (other.b - self.b) * t,
+            self.a + (other.a - self.a) * t,
+        )
+    }
+
+    pub fn to_array(&self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::BLACK
+    }
+}
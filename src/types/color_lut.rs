@@ -0,0 +1,130 @@
+use std::fs::{self, File};
+
+// A 3D color lookup table, `data` is indexed red fastest, then green, then blue.
+#[derive(Clone, Debug)]
+pub struct ColorLut {
+    pub name: String,
+    pub size: u32,
+    pub data: Vec<[f32; 3]>,
+}
+
+impl ColorLut {
+    // Identity table, grading with it leaves colors unchanged.
+    pub fn identity(size: u32) -> ColorLut {
+        let scale = 1.0 / (size - 1) as f32;
+        let mut data = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.push([r as f32 * scale, g as f32 * scale, b as f32 * scale]);
+                }
+            }
+        }
+        ColorLut {
+            name: "identity".to_string(),
+            size,
+            data,
+        }
+    }
+
+    // Looks for `assets/luts/{name}.cube`, then a strip `assets/luts/{name}.png`.
+    pub fn load(name: &str) -> Result<ColorLut, String> {
+        let cube = format!("assets/luts/{}.cube", name);
+        if let Ok(text) = fs::read_to_string(&cube) {
+            return ColorLut::from_cube(name, &text).map_err(|e| format!("{}: {}", cube, e));
+        }
+        let png = format!("assets/luts/{}.png", name);
+        let file = File::open(&png).map_err(|e| format!("no LUT named {}: {}", name, e))?;
+        ColorLut::from_strip_png(name, file).map_err(|e| format!("{}: {}", png, e))
+    }
+
+    // Adobe/Resolve .cube, only 3D tables with the default 0..1 domain.
+    pub fn from_cube(name: &str, text: &str) -> Result<ColorLut, String> {
+        let mut size = None;
+        let mut data = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with("TITLE") {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let first = words.next().unwrap();
+            match first {
+                "LUT_3D_SIZE" => {
+                    let value = words.next().ok_or("missing LUT_3D_SIZE value")?;
+                    size = Some(value.parse::<u32>().map_err(|e| format!("bad LUT_3D_SIZE: {e}"))?);
+                }
+                "LUT_1D_SIZE" => return Err("1D tables are not supported".to_string()),
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let expected = if first == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                    if words.any(|x| x.parse::<f32>() != Ok(expected)) {
+                        return Err(format!("unsupported {first}, only 0..1 is"));
+                    }
+                }
+                _ => {
+                    let values: Vec<f32> = line
+                        .split_whitespace()
+                        .map(|x| x.parse::<f32>().map_err(|e| format!("bad entry {line:?}: {e}")))
+                        .collect::<Result<_, _>>()?;
+                    let [r, g, b] = values[..] else {
+                        return Err(format!("bad entry {line:?}"));
+                    };
+                    data.push([r, g, b]);
+                }
+            }
+        }
+
+        let size = size.ok_or("missing LUT_3D_SIZE")?;
+        if size < 2 || data.len() != (size * size * size) as usize {
+            return Err(format!("expected {} entries, found {}", size * size * size, data.len()));
+        }
+        Ok(ColorLut {
+            name: name.to_string(),
+            size,
+            data,
+        })
+    }
+
+    // N*N by N strip of N slices side by side: blue picks the slice, red the column within it and
+    // green the row.
+    pub fn from_strip_png(name: &str, reader: impl std::io::Read) -> Result<ColorLut, String> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).map_err(|e| e.to_string())?;
+
+        let size = info.height;
+        if size < 2 || info.width != size * size {
+            return Err(format!("expected a strip of {size} slices, found {}x{}", info.width, info.height));
+        }
+        let channels = match info.color_type {
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            color_type => return Err(format!("unsupported color type {color_type:?}")),
+        };
+
+        let mut data = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let i = ((g * info.width + b * size + r) * channels) as usize;
+                    data.push([pixels[i], pixels[i + 1], pixels[i + 2]].map(|x| x as f32 / 255.0));
+                }
+            }
+        }
+        Ok(ColorLut {
+            name: name.to_string(),
+            size,
+            data,
+        })
+    }
+
+    // A2B10G10R10, 10 bits per channel and guaranteed to support linear filtering.
+    pub(crate) fn packed(&self) -> Vec<u32> {
+        let pack = |x: f32| (x.clamp(0.0, 1.0) * 1023.0).round() as u32;
+        self.data
+            .iter()
+            .map(|[r, g, b]| (3 << 30) | (pack(*b) << 20) | (pack(*g) << 10) | pack(*r))
+            .collect()
+    }
+}
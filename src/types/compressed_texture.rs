@@ -0,0 +1,156 @@
+use vulkano::format::Format;
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n'];
+
+#[derive(Clone, Debug)]
+pub struct CompressedImage {
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+fn format_from_vk(vk_format: u32) -> Option<Format> {
+    Some(match vk_format {
+        37 => Format::R8G8B8A8_UNORM,
+        43 => Format::R8G8B8A8_SRGB,
+        131 => Format::BC1_RGB_UNORM_BLOCK,
+        132 => Format::BC1_RGB_SRGB_BLOCK,
+        133 => Format::BC1_RGBA_UNORM_BLOCK,
+        134 => Format::BC1_RGBA_SRGB_BLOCK,
+        135 => Format::BC2_UNORM_BLOCK,
+        136 => Format::BC2_SRGB_BLOCK,
+        137 => Format::BC3_UNORM_BLOCK,
+        138 => Format::BC3_SRGB_BLOCK,
+        139 => Format::BC4_UNORM_BLOCK,
+        140 => Format::BC4_SNORM_BLOCK,
+        141 => Format::BC5_UNORM_BLOCK,
+        142 => Format::BC5_SNORM_BLOCK,
+        143 => Format::BC6H_UFLOAT_BLOCK,
+        144 => Format::BC6H_SFLOAT_BLOCK,
+        145 => Format::BC7_UNORM_BLOCK,
+        146 => Format::BC7_SRGB_BLOCK,
+        _ => return None,
+    })
+}
+
+fn format_from_dxgi(dxgi_format: u32) -> Option<Format> {
+    Some(match dxgi_format {
+        28 => Format::R8G8B8A8_UNORM,
+        29 => Format::R8G8B8A8_SRGB,
+        71 => Format::BC1_RGBA_UNORM_BLOCK,
+        72 => Format::BC1_RGBA_SRGB_BLOCK,
+        74 => Format::BC2_UNORM_BLOCK,
+        75 => Format::BC2_SRGB_BLOCK,
+        77 => Format::BC3_UNORM_BLOCK,
+        78 => Format::BC3_SRGB_BLOCK,
+        80 => Format::BC4_UNORM_BLOCK,
+        81 => Format::BC4_SNORM_BLOCK,
+        83 => Format::BC5_UNORM_BLOCK,
+        84 => Format::BC5_SNORM_BLOCK,
+        95 => Format::BC6H_UFLOAT_BLOCK,
+        96 => Format::BC6H_SFLOAT_BLOCK,
+        98 => Format::BC7_UNORM_BLOCK,
+        99 => Format::BC7_SRGB_BLOCK,
+        _ => return None,
+    })
+}
+
+fn format_from_four_cc(four_cc: &[u8]) -> Option<Format> {
+    Some(match four_cc {
+        b"DXT1" => Format::BC1_RGBA_UNORM_BLOCK,
+        b"DXT3" => Format::BC2_UNORM_BLOCK,
+        b"DXT5" => Format::BC3_UNORM_BLOCK,
+        b"ATI1" | b"BC4U" => Format::BC4_UNORM_BLOCK,
+        b"BC4S" => Format::BC4_SNORM_BLOCK,
+        b"ATI2" | b"BC5U" => Format::BC5_UNORM_BLOCK,
+        b"BC5S" => Format::BC5_SNORM_BLOCK,
+        _ => return None,
+    })
+}
+
+pub fn level_size(format: Format, width: u32, height: u32, level: u32) -> usize {
+    let [block_width, block_height, _] = format.block_extent();
+    let width = (width >> level).max(1).div_ceil(block_width);
+    let height = (height >> level).max(1).div_ceil(block_height);
+    (width * height) as usize * format.block_size() as usize
+}
+
+pub fn is_block_compressed(format: Format) -> bool {
+    format.block_extent() != [1, 1, 1]
+}
+
+pub fn load_ktx2(bytes: &[u8]) -> Result<CompressedImage, String> {
+    if bytes.get(..12) != Some(&KTX2_IDENTIFIER[..]) {
+        return Err("not a KTX2 file".to_string());
+    }
+    let header = |i: usize| read_u32(bytes, 12 + i * 4).ok_or("truncated KTX2 header");
+    let vk_format = header(0)?;
+    let width = header(2)?;
+    let height = header(3)?.max(1);
+    let depth = header(4)?;
+    let layer_count = header(5)?;
+    let face_count = header(6)?;
+    let level_count = header(7)?.max(1);
+    let supercompression = header(8)?;
+
+    if depth > 1 || layer_count > 1 || face_count > 1 {
+        return Err("only single-layer 2D KTX2 textures are supported".to_string());
+    }
+    if supercompression != 0 {
+        return Err(format!("unsupported KTX2 supercompression scheme {}", supercompression));
+    }
+    let format = format_from_vk(vk_format).ok_or(format!("unsupported KTX2 format {}", vk_format))?;
+
+    let levels = (0..level_count as usize)
+        .map(|level| {
+            let entry = 80 + level * 24;
+            let offset = read_u64(bytes, entry).ok_or("truncated KTX2 level index")? as usize;
+            let length = read_u64(bytes, entry + 8).ok_or("truncated KTX2 level index")? as usize;
+            bytes
+                .get(offset..offset + length)
+                .map(|x| x.to_vec())
+                .ok_or("truncated KTX2 level data".to_string())
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(CompressedImage { format, width, height, levels })
+}
+
+pub fn load_dds(bytes: &[u8]) -> Result<CompressedImage, String> {
+    if bytes.get(..4) != Some(&b"DDS "[..]) {
+        return Err("not a DDS file".to_string());
+    }
+    let height = read_u32(bytes, 12).ok_or("truncated DDS header")?;
+    let width = read_u32(bytes, 16).ok_or("truncated DDS header")?;
+    let level_count = read_u32(bytes, 28).ok_or("truncated DDS header")?.max(1);
+    let four_cc = bytes.get(84..88).ok_or("truncated DDS header")?;
+
+    let (format, mut offset) = if four_cc == b"DX10" {
+        let dxgi_format = read_u32(bytes, 128).ok_or("truncated DDS DX10 header")?;
+        let format = format_from_dxgi(dxgi_format).ok_or(format!("unsupported DXGI format {}", dxgi_format))?;
+        (format, 148)
+    } else {
+        let format = format_from_four_cc(four_cc)
+            .ok_or(format!("unsupported DDS format {}", String::from_utf8_lossy(four_cc)))?;
+        (format, 128)
+    };
+
+    let mut levels = Vec::new();
+    for level in 0..level_count {
+        let size = level_size(format, width, height, level);
+        let data = bytes.get(offset..offset + size).ok_or("truncated DDS level data")?;
+        levels.push(data.to_vec());
+        offset += size;
+    }
+
+    Ok(CompressedImage { format, width, height, levels })
+}
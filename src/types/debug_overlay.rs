@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+
+use winit::keyboard::{Key, NamedKey};
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, state::State};
+
+const GRAPH_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Clone, Debug)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+    pub toggle_key: Key,
+    /// Saves the profiler's last `dump_frames` frames to `profile_{frame}.json`.
+    pub dump_key: Key,
+    pub dump_frames: usize,
+    pub history_len: usize,
+    pub graph_len: usize,
+    pub refresh_interval: f64,
+    pub frame_times: VecDeque<f64>,
+    pub entity_count: usize,
+    title: Option<String>,
+    last_refresh: f64,
+}
+
+impl DebugOverlay {
+    pub fn new() -> DebugOverlay {
+        DebugOverlay {
+            enabled: false,
+            toggle_key: Key::Named(NamedKey::F3),
+            dump_key: Key::Named(NamedKey::F9),
+            dump_frames: 120,
+            history_len: 240,
+            graph_len: 32,
+            refresh_interval: 0.5,
+            frame_times: VecDeque::new(),
+            entity_count: 0,
+            title: None,
+            last_refresh: 0.0,
+        }
+    }
+
+    pub fn push_frame_time(&mut self, delta_time: f64) {
+        self.frame_times.push_back(delta_time);
+        while self.frame_times.len() > self.history_len {
+            self.frame_times.pop_front();
+        }
+    }
+
+    pub fn average_frame_time(&self) -> f64 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        self.frame_times.iter().sum::<f64>() / self.frame_times.len() as f64
+    }
+
+    pub fn fps(&self) -> f64 {
+        let average = self.average_frame_time();
+        if average > 0.0 { 1.0 / average } else { 0.0 }
+    }
+
+    pub fn frame_graph(&self) -> String {
+        let samples: Vec<f64> = self.frame_times.iter().rev().take(self.graph_len).rev().copied().collect();
+        let max = samples.iter().copied().fold(f64::EPSILON, f64::max);
+        samples
+            .iter()
+            .map(|x| GRAPH_LEVELS[((x / max) * (GRAPH_LEVELS.len() - 1) as f64).round() as usize])
+            .collect()
+    }
+
+    pub fn summary(&self, state: &State) -> String {
+        let max = self.frame_times.iter().copied().fold(0.0, f64::max);
+        let slowest = state
+            .profiler
+            .system_timings()
+            .max_by_key(|x| x.1)
+            .map(|(name, duration)| format!(" | {} {:.2} ms", name, duration.as_secs_f64() * 1000.0))
+            .unwrap_or_default();
+        let memory = state
+            .renderer
+            .memory
+            .last_report
+            .as_ref()
+            .map(|x| format!(" | {}", x.summary()))
+            .unwrap_or_default();
+        let skipped = match state.renderer.stats.skipped_frames {
+            0 => String::new(),
+            x => format!(" | skipped {x}"),
+        };
+        format!(
+            "FPS {:.0} | {:.2} ms (max {:.2}) {} | draws {} | tris {} | entities {}{}{}{}",
+            self.fps(),
+            self.average_frame_time() * 1000.0,
+            max * 1000.0,
+            self.frame_graph(),
+            state.renderer.stats.draw_calls,
+            state.renderer.stats.triangles,
+            self.entity_count,
+            skipped,
+            slowest,
+            memory,
+        )
+    }
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct DebugOverlayUpdater {}
+
+impl System for DebugOverlayUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let delta_time = state.delta_time;
+        let toggled = state.input.pressed.contains(&state.debug_overlay.toggle_key);
+        if state.input.pressed.contains(&state.debug_overlay.dump_key) {
+            let frame = state.profiler.history().last().map_or(0, |x| x.frame);
+            let path = format!("profile_{frame}.json");
+            match state.profiler.save_history(state.debug_overlay.dump_frames, &path) {
+                Ok(()) => log::info!("saved profile to {path}"),
+                Err(e) => log::error!("failed to save profile: {e}"),
+            }
+        }
+        let overlay = &mut state.debug_overlay;
+        overlay.push_frame_time(delta_time);
+        overlay.entity_count = world.entity_count;
+
+        if toggled {
+            overlay.enabled = !overlay.enabled;
+            overlay.last_refresh = f64::MIN;
+            if !overlay.enabled {
+                if let Some(title) = overlay.title.take() {
+                    state.window.window_handle.set_title(&title);
+                }
+                return;
+            }
+        }
+        if !overlay.enabled || state.time - overlay.last_refresh < overlay.refresh_interval {
+            return;
+        }
+
+        let title = overlay
+            .title
+            .get_or_insert_with(|| state.window.window_handle.title())
+            .clone();
+        state.debug_overlay.last_refresh = state.time;
+        let summary = state.debug_overlay.summary(state);
+        state.window.window_handle.set_title(&format!("{} | {}", title, summary));
+    }
+}
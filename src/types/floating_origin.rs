@@ -0,0 +1,59 @@
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+};
+
+use super::{camera::Camera, terrain::TerrainChunk, transform::Transform};
+
+// Shifts the whole world back to the origin once the first camera is more than `threshold` units
+// away from it, keeping f64 positions precise in very large worlds. Add it before `run` so it runs
+// before the transforms are written. Transforms, terrain chunk bounds and streamed cells follow the
+// shift, other world space data like trail history does not. See `State::origin_offset`.
+pub struct FloatingOrigin {
+    pub threshold: f64,
+}
+
+impl FloatingOrigin {
+    pub fn new(threshold: f64) -> FloatingOrigin {
+        FloatingOrigin { threshold }
+    }
+}
+
+impl System for FloatingOrigin {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let (Some(cameras), Some(mut transforms)) = (world.borrow_component_vec_mut::<Camera>(), world.borrow_component_vec_mut::<Transform>()) else {
+            return;
+        };
+        let Some(mut shift) = cameras
+            .iter()
+            .zip(transforms.iter())
+            .find_map(|(camera, transform)| camera.and(transform.as_ref()).map(|x| x.position))
+        else {
+            return;
+        };
+        if shift.length_sqr() <= self.threshold * self.threshold {
+            return;
+        }
+
+        for (entity, transform) in transforms.iter_mut().enumerate() {
+            if let Some(transform) = transform.as_mut() {
+                transform.position -= shift;
+                world.mark_changed::<Transform>(entity);
+            }
+        }
+        if let Some(mut chunks) = world.borrow_component_vec_mut::<TerrainChunk>() {
+            let offset = shift.to_vec3f();
+            for chunk in chunks.iter_mut().flatten() {
+                chunk.bounds_min -= offset;
+                chunk.bounds_max -= offset;
+            }
+        }
+
+        state.origin_offset += shift;
+        state.renderer.vp_pos -= shift;
+        state.renderer.render_origin -= shift;
+        log::debug!("floating origin moved by {:?}", shift);
+    }
+}
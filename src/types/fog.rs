@@ -0,0 +1,54 @@
+use bytemuck::{Pod, Zeroable};
+
+use super::color::Color;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FogMode {
+    None,
+    Linear { start: f32, end: f32 },
+    Exponential { density: f32 },
+    ExponentialSquared { density: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Color,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[repr(C)]
+pub struct FogData {
+    pub color: Color,
+    pub density: f32,
+    pub start: f32,
+    pub end: f32,
+    pub mode: u32,
+}
+
+impl Fog {
+    pub fn to_data(&self) -> FogData {
+        let (mode, density, start, end) = match self.mode {
+            FogMode::None => (0, 0.0, 0.0, 0.0),
+            FogMode::Linear { start, end } => (1, 0.0, start, end),
+            FogMode::Exponential { density } => (2, density, 0.0, 0.0),
+            FogMode::ExponentialSquared { density } => (3, density, 0.0, 0.0),
+        };
+        FogData {
+            color: self.color,
+            density,
+            start,
+            end,
+            mode,
+        }
+    }
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Fog {
+            mode: FogMode::None,
+            color: Color::GRAY,
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use super::{matrices::Matrix4f, vectors::Vec3f};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [[f32; 4]; 6],
+}
+
+impl Frustum {
+    pub fn from_matrix(matrix: Matrix4f) -> Frustum {
+        let m = matrix.to_array();
+        let row = |r: usize| [m[0][r], m[1][r], m[2][r], m[3][r]];
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+        let sub = |a: [f32; 4], b: [f32; 4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+
+        let mut planes = [add(r3, r0), sub(r3, r0), add(r3, r1), sub(r3, r1), add(r3, r2), sub(r3, r2)];
+        for plane in planes.iter_mut() {
+            let len = (plane[0] * plane[0] + plane[1] * plane[1] + plane[2] * plane[2]).sqrt();
+            if len > 0.0 {
+                for value in plane.iter_mut() {
+                    *value /= len;
+                }
+            }
+        }
+
+        Frustum { planes }
+    }
+
+    pub fn contains_point(&self, point: Vec3f) -> bool {
+        self.planes.iter().all(|p| p[0] * point.x + p[1] * point.y + p[2] * point.z + p[3] >= 0.0)
+    }
+
+    pub fn intersects_aabb(&self, min: Vec3f, max: Vec3f) -> bool {
+        self.planes.iter().all(|p| {
+            let x = if p[0] >= 0.0 { max.x } else { min.x };
+            let y = if p[1] >= 0.0 { max.y } else { min.y };
+            let z = if p[2] >= 0.0 { max.z } else { min.z };
+            p[0] * x + p[1] * y + p[2] * z + p[3] >= 0.0
+        })
+    }
+
+    pub fn intersects_sphere(&self, center: Vec3f, radius: f32) -> bool {
+        self.planes.iter().all(|p| p[0] * center.x + p[1] * center.y + p[2] * center.z + p[3] >= -radius)
+    }
+}
@@ -0,0 +1,272 @@
+use std::f32::consts::PI;
+
+use winit::event::MouseButton;
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::VertexData, state::State};
+
+use super::{color::Color, mesh::DynamicMesh, ray::Ray, transform::Transform, vectors::*, visibility::Visibility};
+
+const RING_SEGMENTS: usize = 48;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Clone, Debug)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub target: Option<usize>,
+    pub size: f32,
+    pub pick_radius: f32,
+    active_axis: Option<usize>,
+    drag_start: f32,
+}
+
+impl Gizmo {
+    pub fn new(mode: GizmoMode) -> Gizmo {
+        Gizmo {
+            mode,
+            target: None,
+            size: 1.0,
+            pick_radius: 0.1,
+            active_axis: None,
+            drag_start: 0.0,
+        }
+    }
+
+    pub fn active_axis(&self) -> Option<usize> {
+        self.active_axis
+    }
+
+    pub fn mesh(&self) -> (Vec<VertexData>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let thickness = self.size * 0.02;
+        for axis in 0..3 {
+            match self.mode {
+                GizmoMode::Translate => {
+                    push_box(&mut vertices, &mut indices, axis, 0.0, self.size, thickness);
+                    push_box(&mut vertices, &mut indices, axis, self.size * 0.85, self.size, thickness * 3.0);
+                }
+                GizmoMode::Scale => {
+                    push_box(&mut vertices, &mut indices, axis, 0.0, self.size, thickness);
+                    push_box(&mut vertices, &mut indices, axis, self.size * 0.9, self.size, self.size * 0.05);
+                }
+                GizmoMode::Rotate => push_ring(&mut vertices, &mut indices, axis, self.size, thickness),
+            }
+        }
+        (vertices, indices)
+    }
+
+    fn drag_value(&self, ray: &Ray, center: Vec3f, axis: usize) -> Option<f32> {
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                ray.closest_to_line(center, axis_vector(axis)).map(|(_, s)| s)
+            }
+            GizmoMode::Rotate => {
+                let t = ray.intersect_plane(center, axis_vector(axis))?;
+                let local = ray.at(t) - center;
+                let (u, v) = ring_basis(axis);
+                Some(component(local, v).atan2(component(local, u)))
+            }
+        }
+    }
+
+    fn pick(&self, ray: &Ray, center: Vec3f) -> Option<usize> {
+        let mut closest: Option<(usize, f32)> = None;
+        for axis in 0..3 {
+            let hit = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let Some((t, s)) = ray.closest_to_line(center, axis_vector(axis)) else {
+                        continue;
+                    };
+                    let mut offset = ray.at(t) - (center + axis_vector(axis) * s);
+                    (t >= 0.0 && (0.0..=self.size).contains(&s) && offset.length() <= self.pick_radius)
+                        .then_some(t)
+                }
+                GizmoMode::Rotate => ray.intersect_plane(center, axis_vector(axis)).filter(|t| {
+                    let mut offset = ray.at(*t) - center;
+                    (offset.length() - self.size).abs() <= self.pick_radius
+                }),
+            };
+            if let Some(t) = hit {
+                if closest.is_none_or(|(_, closest_t)| t < closest_t) {
+                    closest = Some((axis, t));
+                }
+            }
+        }
+        closest.map(|(axis, _)| axis)
+    }
+}
+
+fn axis_vector(axis: usize) -> Vec3f {
+    let mut val = [0.0; 3];
+    val[axis] = 1.0;
+    Vec3f::new(val)
+}
+
+fn axis_color(axis: usize) -> Color {
+    [Color::RED, Color::GREEN, Color::BLUE][axis]
+}
+
+fn component(vec: Vec3f, axis: usize) -> f32 {
+    [vec.x, vec.y, vec.z][axis]
+}
+
+fn ring_basis(axis: usize) -> (usize, usize) {
+    ((axis + 1) % 3, (axis + 2) % 3)
+}
+
+fn push_box(vertices: &mut Vec<VertexData>, indices: &mut Vec<u32>, axis: usize, from: f32, to: f32, half_width: f32) {
+    let (u, v) = ring_basis(axis);
+    let base = vertices.len() as u32;
+    for corner in 0..8 {
+        let mut position = [0.0; 3];
+        position[axis] = if corner & 1 == 0 { from } else { to };
+        position[u] = if corner & 2 == 0 { -half_width } else { half_width };
+        position[v] = if corner & 4 == 0 { -half_width } else { half_width };
+        vertices.push(VertexData {
+            position: Vec3f::new(position),
+            uv: Vec2f::new([axis as f32, 0.0]),
+            normal: axis_vector(axis),
+            color: axis_color(axis),
+            uv2: Vec2f::new([axis as f32, 0.0]),
+        });
+    }
+    let faces = [
+        [0, 2, 6, 4],
+        [1, 5, 7, 3],
+        [0, 4, 5, 1],
+        [2, 3, 7, 6],
+        [0, 1, 3, 2],
+        [4, 6, 7, 5],
+    ];
+    for [a, b, c, d] in faces {
+        indices.extend_from_slice(&[base + a, base + b, base + c, base + a, base + c, base + d]);
+    }
+}
+
+fn push_ring(vertices: &mut Vec<VertexData>, indices: &mut Vec<u32>, axis: usize, radius: f32, half_width: f32) {
+    let (u, v) = ring_basis(axis);
+    let base = vertices.len() as u32;
+    for i in 0..RING_SEGMENTS {
+        let angle = i as f32 / RING_SEGMENTS as f32 * 2.0 * PI;
+        for r in [radius - half_width, radius + half_width] {
+            let mut position = [0.0; 3];
+            position[u] = angle.cos() * r;
+            position[v] = angle.sin() * r;
+            vertices.push(VertexData {
+                position: Vec3f::new(position),
+                uv: Vec2f::new([axis as f32, 0.0]),
+                normal: axis_vector(axis),
+                color: axis_color(axis),
+                uv2: Vec2f::new([axis as f32, 0.0]),
+            });
+        }
+    }
+    for i in 0..RING_SEGMENTS as u32 {
+        let next = (i + 1) % RING_SEGMENTS as u32;
+        let (a, b) = (base + i * 2, base + i * 2 + 1);
+        let (c, d) = (base + next * 2, base + next * 2 + 1);
+        indices.extend_from_slice(&[a, b, c, c, b, d, a, c, b, c, d, b]);
+    }
+}
+
+pub fn spawn_gizmo(world: &mut World, material: &str, mode: GizmoMode) -> usize {
+    let gizmo = Gizmo::new(mode);
+    let (vertices, indices) = gizmo.mesh();
+
+    let entity = world.new_entity();
+    world.add_component(entity, Transform::new(
+        Vec3d::new([0.0, 0.0, 0.0]),
+        Vec3f::new([1.0, 1.0, 1.0]),
+        Vec3f::new([0.0, 0.0, 0.0]),
+    ));
+    world.add_component(entity, DynamicMesh {
+        vertices,
+        indices,
+        material: material.to_string(),
+        buffers: None,
+    });
+    world.add_component(entity, Visibility::new(false));
+    world.add_component(entity, gizmo);
+    entity
+}
+
+pub struct GizmoUpdater {}
+
+impl System for GizmoUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(mut gizmos) = world.borrow_component_vec_mut::<Gizmo>() else {
+            return;
+        };
+        let mut transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+        let mut visibilities = world.borrow_component_vec_mut::<Visibility>().unwrap();
+
+        let dimensions = state.window.window_handle.inner_size();
+        let ray = Ray::from_screen(
+            state.input.cursor_pos,
+            Vec2f::new([dimensions.width as f32, dimensions.height as f32]),
+            state.renderer.vp_data.view,
+            state.renderer.vp_data.projection,
+        );
+
+        for (entity, gizmo) in gizmos.iter_mut().enumerate() {
+            let Some(gizmo) = gizmo else {
+                continue;
+            };
+
+            let target = gizmo.target.filter(|x| transforms.get(*x).is_some_and(|x| x.is_some()));
+            if let Some(Some(visibility)) = visibilities.get_mut(entity) {
+                if visibility.visible != target.is_some() {
+                    visibility.visible = target.is_some();
+                    state.renderer.command_buffer_outdated = true;
+                }
+            }
+            let Some(target) = target else {
+                gizmo.active_axis = None;
+                continue;
+            };
+            // The ray is in render space, see `Renderer::render_space`.
+            let center = state.renderer.render_space(transforms[target].as_ref().unwrap().position);
+
+            if state.input.mouse_released.contains(&MouseButton::Left) {
+                gizmo.active_axis = None;
+            }
+            if let Some(ray) = ray.as_ref() {
+                if state.input.mouse_pressed.contains(&MouseButton::Left) {
+                    gizmo.active_axis = gizmo.pick(ray, center);
+                    if let Some(axis) = gizmo.active_axis {
+                        gizmo.drag_start = gizmo.drag_value(ray, center, axis).unwrap_or(0.0);
+                    }
+                } else if let Some(axis) = gizmo.active_axis {
+                    if let Some(value) = gizmo.drag_value(ray, center, axis) {
+                        let mut delta = value - gizmo.drag_start;
+                        if gizmo.mode == GizmoMode::Rotate {
+                            delta = (delta + PI).rem_euclid(2.0 * PI) - PI;
+                        }
+                        gizmo.drag_start = value;
+
+                        let transform = transforms[target].as_mut().unwrap();
+                        match gizmo.mode {
+                            GizmoMode::Translate => transform.position += (axis_vector(axis) * delta).to_vec3d(),
+                            GizmoMode::Scale => transform.scale += axis_vector(axis) * (delta / gizmo.size),
+                            GizmoMode::Rotate => transform.rotation += axis_vector(axis) * delta,
+                        }
+                        world.mark_changed::<Transform>(target);
+                    }
+                }
+            }
+
+            let position = transforms[target].as_ref().unwrap().position;
+            if let Some(Some(transform)) = transforms.get_mut(entity) {
+                transform.position = position;
+                world.mark_changed::<Transform>(entity);
+            }
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::rendering::VertexData;
+
+use super::{color::Color, mesh::Mesh, morph::MorphTarget, vectors::*};
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_JSON_CHUNK: u32 = 0x4E4F534A;
+const GLB_BIN_CHUNK: u32 = 0x004E4942;
+const MODE_TRIANGLES: u32 = 4;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    #[serde(default)]
+    buffers: Vec<BufferDef>,
+    #[serde(default)]
+    buffer_views: Vec<BufferView>,
+    #[serde(default)]
+    accessors: Vec<Accessor>,
+    #[serde(default)]
+    meshes: Vec<MeshDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferDef {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Accessor {
+    buffer_view: Option<usize>,
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+    #[serde(default)]
+    normalized: bool,
+    count: usize,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct MeshDef {
+    name: Option<String>,
+    primitives: Vec<Primitive>,
+    #[serde(default)]
+    weights: Vec<f32>,
+    extras: Option<MeshExtras>,
+}
+
+// Target names are not part of the spec, but every major exporter writes them here.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MeshExtras {
+    #[serde(default)]
+    target_names: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Primitive {
+    attributes: HashMap<String, usize>,
+    indices: Option<usize>,
+    mode: Option<u32>,
+    #[serde(default)]
+    targets: Vec<HashMap<String, usize>>,
+}
+
+fn component_size(component_type: u32) -> Result<usize, String> {
+    match component_type {
+        5120 | 5121 => Ok(1),
+        5122 | 5123 => Ok(2),
+        5125 | 5126 => Ok(4),
+        _ => Err(format!("unsupported glTF component type {}", component_type)),
+    }
+}
+
+fn component_count(kind: &str) -> Result<usize, String> {
+    match kind {
+        "SCALAR" => Ok(1),
+        "VEC2" => Ok(2),
+        "VEC3" => Ok(3),
+        "VEC4" => Ok(4),
+        _ => Err(format!("unsupported glTF accessor type {}", kind)),
+    }
+}
+
+fn read_component(bytes: &[u8], component_type: u32, normalized: bool) -> f64 {
+    let value = match component_type {
+        5120 => bytes[0] as i8 as f64,
+        5121 => bytes[0] as f64,
+        5122 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        5125 => u32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+        _ => f32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+    };
+    if !normalized {
+        return value;
+    }
+    match component_type {
+        5120 => (value / 127.0).max(-1.0),
+        5121 => value / 255.0,
+        5122 => (value / 32767.0).max(-1.0),
+        5123 => value / 65535.0,
+        _ => value,
+    }
+}
+
+fn read_accessor(document: &Document, buffers: &[Vec<u8>], index: usize) -> Result<Vec<[f64; 4]>, String> {
+    let accessor = document.accessors.get(index).ok_or("glTF accessor out of range")?;
+    let size = component_size(accessor.component_type)?;
+    let count = component_count(&accessor.kind)?;
+    let Some(view) = accessor.buffer_view else {
+        return Ok(vec![[0.0; 4]; accessor.count]);
+    };
+    let view = document.buffer_views.get(view).ok_or("glTF buffer view out of range")?;
+    let buffer = buffers.get(view.buffer).ok_or("glTF buffer out of range")?;
+    let stride = view.byte_stride.unwrap_or(size * count);
+    let start = view.byte_offset + accessor.byte_offset;
+
+    (0..accessor.count)
+        .map(|i| {
+            let mut element = [0.0; 4];
+            for (c, value) in element.iter_mut().enumerate().take(count) {
+                let offset = start + i * stride + c * size;
+                let bytes = buffer.get(offset..offset + size).ok_or("truncated glTF buffer")?;
+                *value = read_component(bytes, accessor.component_type, accessor.normalized);
+            }
+            Ok(element)
+        })
+        .collect()
+}
+
+fn parse(bytes: &[u8], directory: &Path) -> Result<(Document, Vec<Vec<u8>>), String> {
+    let read_u32 = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+            .ok_or("truncated GLB header")
+    };
+
+    let (json, mut binary) = if bytes.get(..4) == Some(&GLB_MAGIC[..]) {
+        let json_length = read_u32(12)? as usize;
+        if read_u32(16)? != GLB_JSON_CHUNK {
+            return Err("GLB is missing its JSON chunk".to_string());
+        }
+        let json = bytes.get(20..20 + json_length).ok_or("truncated GLB JSON chunk")?;
+        let bin_start = 20 + json_length;
+        let binary = match (read_u32(bin_start), read_u32(bin_start + 4)) {
+            (Ok(length), Ok(GLB_BIN_CHUNK)) => {
+                let data = bytes.get(bin_start + 8..bin_start + 8 + length as usize).ok_or("truncated GLB BIN chunk")?;
+                Some(data.to_vec())
+            }
+            _ => None,
+        };
+        (json, binary)
+    } else {
+        (bytes, None)
+    };
+
+    let document: Document = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+    let buffers = document
+        .buffers
+        .iter()
+        .map(|buffer| match &buffer.uri {
+            None => binary.take().ok_or("glTF buffer has no data".to_string()),
+            Some(uri) if uri.starts_with("data:") => Err("embedded data URIs are not supported".to_string()),
+            Some(uri) => fs::read(directory.join(uri)).map_err(|e| format!("failed to read {}: {}", uri, e)),
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok((document, buffers))
+}
+
+pub fn load_gltf(path: &str, material: &str) -> Result<Vec<Mesh>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    let (document, buffers) = parse(&bytes, directory)?;
+    let stem = Path::new(path).file_stem().map_or(String::new(), |x| x.to_string_lossy().to_string());
+
+    let mut meshes = Vec::new();
+    for (mesh_i, mesh) in document.meshes.iter().enumerate() {
+        let mesh_name = mesh.name.clone().unwrap_or(format!("{}.{}", stem, mesh_i));
+        for (primitive_i, primitive) in mesh.primitives.iter().enumerate() {
+            if primitive.mode.unwrap_or(MODE_TRIANGLES) != MODE_TRIANGLES {
+                log::warn!("skipping non-triangle primitive {} of glTF mesh {}", primitive_i, mesh_name);
+                continue;
+            }
+            let attribute = |name: &str| -> Result<Option<Vec<[f64; 4]>>, String> {
+                primitive.attributes.get(name).map(|x| read_accessor(&document, &buffers, *x)).transpose()
+            };
+
+            let positions = attribute("POSITION")?.ok_or("glTF primitive has no POSITION attribute")?;
+            let normals = attribute("NORMAL")?;
+            let uvs = attribute("TEXCOORD_0")?;
+            let lightmap_uvs = attribute("TEXCOORD_1")?;
+            let colors = attribute("COLOR_0")?;
+            let has_alpha = primitive
+                .attributes
+                .get("COLOR_0")
+                .is_some_and(|x| document.accessors[*x].kind == "VEC4");
+
+            let vertices = (0..positions.len())
+                .map(|i| {
+                    let vec2 = |x: &Option<Vec<[f64; 4]>>| x.as_ref().map_or([0.0; 2], |x| [x[i][0] as f32, x[i][1] as f32]);
+                    let vec3 = |x: &[f64; 4]| [x[0] as f32, x[1] as f32, x[2] as f32];
+                    let color = colors.as_ref().map_or(Color::WHITE, |x| {
+                        let alpha = if has_alpha { x[i][3] as f32 } else { 1.0 };
+                        Color::rgba(x[i][0] as f32, x[i][1] as f32, x[i][2] as f32, alpha)
+                    });
+                    VertexData {
+                        position: Vec3f::new(vec3(&positions[i])),
+                        uv: Vec2f::new(vec2(&uvs)),
+                        normal: Vec3f::new(normals.as_ref().map_or([0.0; 3], |x| vec3(&x[i]))),
+                        color,
+                        uv2: Vec2f::new(vec2(&lightmap_uvs)),
+                    }
+                })
+                .collect();
+
+            let indices = match primitive.indices {
+                Some(x) => read_accessor(&document, &buffers, x)?.iter().map(|x| x[0] as u32).collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            let mut morph_targets = Vec::new();
+            for (target_i, target) in primitive.targets.iter().enumerate() {
+                let deltas = |name: &str| -> Result<Vec<Vec3f>, String> {
+                    let Some(accessor) = target.get(name) else {
+                        return Ok(Vec::new());
+                    };
+                    Ok(read_accessor(&document, &buffers, *accessor)?
+                        .iter()
+                        .map(|x| Vec3f::new([x[0] as f32, x[1] as f32, x[2] as f32]))
+                        .collect())
+                };
+                let mut position_deltas = deltas("POSITION")?;
+                if position_deltas.is_empty() {
+                    position_deltas = vec![Vec3f::new([0.0; 3]); positions.len()];
+                }
+                let target_name = mesh
+                    .extras
+                    .as_ref()
+                    .and_then(|x| x.target_names.get(target_i).cloned())
+                    .unwrap_or(format!("target{}", target_i));
+                let mut morph_target = MorphTarget::new(&target_name, position_deltas, deltas("NORMAL")?);
+                morph_target.default_weight = mesh.weights.get(target_i).copied().unwrap_or(0.0);
+                morph_targets.push(morph_target);
+            }
+
+            let name = if mesh.primitives.len() == 1 {
+                mesh_name.clone()
+            } else {
+                format!("{}.{}", mesh_name, primitive_i)
+            };
+            meshes.push(Mesh {
+                name,
+                vertices,
+                indices,
+                material: material.to_string(),
+                vertex_buffer: None,
+                index_buffer: None,
+                morph_targets,
+                morph_buffer: None,
+                meshlets: Vec::new(),
+                bvh: None,
+            });
+        }
+    }
+
+    Ok(meshes)
+}
@@ -0,0 +1,158 @@
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::device::DeviceOwned;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+
+use crate::rendering::Renderer;
+
+// GLSL `buffer_reference` blocks are 16 byte aligned unless `buffer_reference_align` says otherwise.
+pub const GPU_PTR_ALIGN: u64 = 16;
+
+// Device address of a buffer of `T`, laid out as the `uint64_t`/`buffer_reference` shaders read.
+// Only built from buffers that were created with `SHADER_DEVICE_ADDRESS` on a device with the
+// buffer_device_address feature, the buffer has to outlive every use of the pointer on the GPU.
+#[repr(transparent)]
+pub struct GpuPtr<T> {
+    address: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for GpuPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for GpuPtr<T> {}
+
+impl<T> PartialEq for GpuPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.address == other.address
+    }
+}
+
+impl<T> Eq for GpuPtr<T> {}
+
+impl<T> std::fmt::Debug for GpuPtr<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GpuPtr({:#x})", self.address)
+    }
+}
+
+unsafe impl<T: 'static> Zeroable for GpuPtr<T> {}
+unsafe impl<T: 'static> Pod for GpuPtr<T> {}
+
+impl<T: BufferContents> GpuPtr<T> {
+    pub fn new(buffer: &Subbuffer<[T]>) -> Result<GpuPtr<T>, String> {
+        GpuPtr::with_align(buffer, GPU_PTR_ALIGN)
+    }
+
+    // For shaders that declare a different `buffer_reference_align`.
+    pub fn with_align(buffer: &Subbuffer<[T]>, align: u64) -> Result<GpuPtr<T>, String> {
+        if !buffer.device().enabled_features().buffer_device_address {
+            return Err("the buffer_device_address feature is not enabled".to_string());
+        }
+        if !buffer.buffer().usage().intersects(BufferUsage::SHADER_DEVICE_ADDRESS) {
+            return Err(format!("buffer usage {:?} is missing SHADER_DEVICE_ADDRESS", buffer.buffer().usage()));
+        }
+        let address = buffer.device_address().map_err(|e| e.to_string())?.get();
+        let align = align.max(align_of::<T>() as u64);
+        if address % align != 0 {
+            return Err(format!("device address {address:#x} is not {align} byte aligned"));
+        }
+        Ok(GpuPtr {
+            address,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T> GpuPtr<T> {
+    pub fn null() -> GpuPtr<T> {
+        GpuPtr {
+            address: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.address == 0
+    }
+
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    // Pointer to the element `count` places further, no bounds checks.
+    pub fn offset(self, count: u64) -> GpuPtr<T> {
+        GpuPtr {
+            address: self.address + count * size_of::<T>() as u64,
+            _marker: PhantomData,
+        }
+    }
+}
+
+// Storage buffer of pointers, so a shader can reach many buffers through one binding or push
+// constant. The table itself is addressable, see `GpuPtrTable::ptr`.
+#[derive(Clone, Debug)]
+pub struct GpuPtrTable<T> {
+    pub buffer: Subbuffer<[GpuPtr<T>]>,
+}
+
+impl<T: BufferContents> GpuPtrTable<T> {
+    pub fn new(renderer: &Renderer, pointers: &[GpuPtr<T>]) -> Result<GpuPtrTable<T>, String> {
+        if !renderer.enabled_features.buffer_device_address {
+            return Err("the buffer_device_address feature is not enabled".to_string());
+        }
+        if pointers.is_empty() {
+            return Err("pointer tables cannot be empty".to_string());
+        }
+        let buffer = Buffer::from_iter(
+            renderer.memeory_allocator.as_ref().unwrap().clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::SHADER_DEVICE_ADDRESS,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            pointers.iter().copied(),
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(GpuPtrTable { buffer })
+    }
+
+    // Validates every buffer first, nothing is created if one fails.
+    pub fn from_buffers(renderer: &Renderer, buffers: &[Subbuffer<[T]>]) -> Result<GpuPtrTable<T>, String> {
+        let pointers = buffers.iter().map(GpuPtr::new).collect::<Result<Vec<_>, _>>()?;
+        GpuPtrTable::new(renderer, &pointers)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.len() == 0
+    }
+
+    // Fails while a submitted command buffer still reads the table, try again once its fence has
+    // signaled.
+    pub fn set(&self, index: usize, pointer: GpuPtr<T>) -> Result<(), String> {
+        let mut pointers = self.buffer.write().map_err(|e| e.to_string())?;
+        let len = pointers.len();
+        let slot = pointers
+            .get_mut(index)
+            .ok_or_else(|| format!("index {index} is out of bounds for a table of {len} pointers"))?;
+        *slot = pointer;
+        Ok(())
+    }
+
+    pub fn ptr(&self) -> Result<GpuPtr<GpuPtr<T>>, String> {
+        GpuPtr::new(&self.buffer)
+    }
+}
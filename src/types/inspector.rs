@@ -0,0 +1,301 @@
+use winit::keyboard::{Key, NamedKey};
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, reflect::FieldValue, state::State};
+
+use super::{
+    color::Color,
+    transform::Transform,
+    ui::{UiNode, UiWidget},
+    ui_widgets::{UiButton, UiTextField, UiWidgetEvent},
+    vectors::{Vec2f, Vec3d, Vec3f},
+};
+
+const GLYPH_SIZE: [f32; 2] = [8.0, 16.0];
+const LISTED_ENTITIES: usize = 12;
+
+// Runtime world inspector, toggled with `toggle_key`. Lists entities, shows the fields of their
+// reflected components (see `World::register_reflect`) and edits them through the text field,
+// e.g. "Transform.position 0 1 0".
+#[derive(Clone, Debug)]
+pub struct Inspector {
+    pub toggle_key: Key,
+    pub selected: Option<usize>,
+    pub status: String,
+    nodes: InspectorNodes,
+}
+
+#[derive(Clone, Debug)]
+struct InspectorNodes {
+    panel: usize,
+    entities: usize,
+    details: usize,
+    status: usize,
+    edit: usize,
+    previous: usize,
+    next: usize,
+    spawn: usize,
+    despawn: usize,
+    // Every node of the panel, never listed as entities.
+    all: Vec<usize>,
+}
+
+fn text(font: &str, text: &str) -> UiWidget {
+    UiWidget::Text {
+        material: font.to_string(),
+        color: Color::WHITE,
+        text: text.to_string(),
+        glyph_size: Vec2f::new(GLYPH_SIZE),
+    }
+}
+
+fn spawn_node(world: &mut World, nodes: &mut Vec<usize>, node: UiNode) -> usize {
+    let entity = world.new_entity();
+    world.add_component(entity, node);
+    nodes.push(entity);
+    entity
+}
+
+fn spawn_label(world: &mut World, nodes: &mut Vec<usize>, parent: usize, position: [f32; 2], size: [f32; 2], font: &str) -> usize {
+    let node = UiNode::fixed(Vec2f::new(position), Vec2f::new(size))
+        .with_parent(parent)
+        .with_widget(text(font, ""))
+        .with_z(1002);
+    spawn_node(world, nodes, node)
+}
+
+fn spawn_button(world: &mut World, nodes: &mut Vec<usize>, parent: usize, position: [f32; 2], label: &str, material: &str, font: &str) -> usize {
+    let size = [(label.len() as f32 + 2.0) * GLYPH_SIZE[0], GLYPH_SIZE[1] + 8.0];
+    let node = UiNode::fixed(Vec2f::new(position), Vec2f::new(size))
+        .with_parent(parent)
+        .with_widget(UiWidget::Background {
+            material: material.to_string(),
+            color: Color::GRAY,
+        })
+        .with_z(1001)
+        .interactable();
+    let button = spawn_node(world, nodes, node);
+    world.add_component(button, UiButton::default());
+    let label_node = UiNode::fixed(Vec2f::new([GLYPH_SIZE[0], 4.0]), Vec2f::new([size[0], GLYPH_SIZE[1]]))
+        .with_parent(button)
+        .with_widget(text(font, label))
+        .with_z(1002);
+    spawn_node(world, nodes, label_node);
+    button
+}
+
+// `material` draws the panel and buttons, `font` the text. Both need a UI batch, see
+// `spawn_ui_batch`. Returns the entity holding the `Inspector`.
+pub fn spawn_inspector(world: &mut World, material: &str, font: &str) -> usize {
+    let mut all = Vec::new();
+    let panel_node = UiNode::fixed(Vec2f::new([10.0, 10.0]), Vec2f::new([440.0, 560.0]))
+        .with_widget(UiWidget::Background {
+            material: material.to_string(),
+            color: Color::rgba(0.05, 0.05, 0.05, 0.9),
+        })
+        .with_z(1000);
+    let panel = spawn_node(world, &mut all, panel_node);
+    world.borrow_component_vec_mut::<UiNode>().unwrap()[panel].as_mut().unwrap().visible = false;
+
+    let title = spawn_label(world, &mut all, panel, [8.0, 8.0], [424.0, 16.0], font);
+    if let UiWidget::Text { text, .. } = &mut world.borrow_component_vec_mut::<UiNode>().unwrap()[title].as_mut().unwrap().widget {
+        *text = "Inspector".to_string();
+    }
+    let previous = spawn_button(world, &mut all, panel, [8.0, 32.0], "<", material, font);
+    let next = spawn_button(world, &mut all, panel, [40.0, 32.0], ">", material, font);
+    let spawn = spawn_button(world, &mut all, panel, [72.0, 32.0], "Spawn", material, font);
+    let despawn = spawn_button(world, &mut all, panel, [136.0, 32.0], "Despawn", material, font);
+    let entities = spawn_label(world, &mut all, panel, [8.0, 64.0], [424.0, 32.0], font);
+    let details = spawn_label(world, &mut all, panel, [8.0, 104.0], [424.0, 384.0], font);
+
+    let edit_node = UiNode::fixed(Vec2f::new([8.0, 496.0]), Vec2f::new([424.0, 24.0]))
+        .with_parent(panel)
+        .with_widget(UiWidget::Background {
+            material: material.to_string(),
+            color: Color::GRAY,
+        })
+        .with_z(1001)
+        .interactable();
+    let edit = spawn_node(world, &mut all, edit_node);
+    let edit_text = spawn_label(world, &mut all, edit, [4.0, 4.0], [416.0, 16.0], font);
+    world.add_component(edit, UiTextField::new("").with_text_node(edit_text));
+    let status = spawn_label(world, &mut all, panel, [8.0, 528.0], [424.0, 16.0], font);
+
+    let entity = world.new_entity();
+    world.add_component(entity, Inspector {
+        toggle_key: Key::Named(NamedKey::F4),
+        selected: None,
+        status: String::new(),
+        nodes: InspectorNodes {
+            panel,
+            entities,
+            details,
+            status,
+            edit,
+            previous,
+            next,
+            spawn,
+            despawn,
+            all,
+        },
+    });
+    entity
+}
+
+// "Component.field value"
+fn apply_edit(world: &World, entity: usize, command: &str) -> Result<String, String> {
+    let (path, value) = command.trim().split_once(' ').ok_or("expected \"Component.field value\"")?;
+    let (component, field) = path.split_once('.').ok_or("expected \"Component.field value\"")?;
+    let components = world.reflect(entity);
+    let (_, fields) = components
+        .iter()
+        .find(|x| x.0 == component)
+        .ok_or(format!("entity {entity} has no {component}"))?;
+    let (_, current) = fields.iter().find(|x| x.0 == field).ok_or(format!("{component} has no field {field}"))?;
+    let value: FieldValue = current.parse_like(value).ok_or(format!("cannot parse \"{}\" for {field}", value.trim()))?;
+    if !world.set_reflected_field(entity, component, field, &value) {
+        return Err(format!("cannot set {component}.{field}"));
+    }
+    Ok(format!("set {component}.{field} to {value}"))
+}
+
+fn set_text(nodes: &mut [Option<UiNode>], entity: usize, new_text: String) {
+    if let Some(Some(UiNode { widget: UiWidget::Text { text, .. }, .. })) = nodes.get_mut(entity) {
+        *text = new_text;
+    }
+}
+
+pub struct InspectorUpdater {}
+
+impl System for InspectorUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(inspectors) = world.borrow_component_vec_mut::<Inspector>() else {
+            return;
+        };
+        let inspector_entities: Vec<usize> = (0..inspectors.len()).filter(|x| inspectors[*x].is_some()).collect();
+        drop(inspectors);
+
+        // Reflection borrows every reflected component, so the inspector's own components are only
+        // borrowed around it.
+        for inspector_entity in inspector_entities {
+            let Some((listed, selected, edit)) = self.handle_input(world, state, inspector_entity) else {
+                continue;
+            };
+
+            let edit_result = edit.map(|(text, selected)| match selected {
+                None => Err("no entity selected".to_string()),
+                Some(selected) => apply_edit(world, selected, &text),
+            });
+            let details = match selected {
+                None => "No entity selected".to_string(),
+                Some(selected) => {
+                    let mut details = format!("Entity {selected}\n");
+                    for (component, fields) in world.reflect(selected) {
+                        details.push_str(&format!("{component}\n"));
+                        for (field, value) in fields {
+                            details.push_str(&format!("  {field}: {value}\n"));
+                        }
+                    }
+                    details
+                }
+            };
+
+            let mut inspectors = world.borrow_component_vec_mut::<Inspector>().unwrap();
+            let inspector = inspectors[inspector_entity].as_mut().unwrap();
+            if let Some(edit_result) = edit_result {
+                inspector.status = match edit_result {
+                    Ok(status) => {
+                        let mut text_fields = world.borrow_component_vec_mut::<UiTextField>().unwrap();
+                        text_fields[inspector.nodes.edit].as_mut().unwrap().text.clear();
+                        state.renderer.command_buffer_outdated = true;
+                        status
+                    }
+                    Err(error) => error,
+                };
+            }
+
+            let position = selected.and_then(|x| listed.iter().position(|y| *y == x));
+            let window = position.unwrap_or(0).saturating_sub(LISTED_ENTITIES / 2);
+            let list = listed
+                .iter()
+                .skip(window)
+                .take(LISTED_ENTITIES)
+                .map(|x| if selected == Some(*x) { format!("[{x}]") } else { x.to_string() })
+                .collect::<Vec<String>>()
+                .join(" ");
+            let mut nodes = world.borrow_component_vec_mut::<UiNode>().unwrap();
+            set_text(&mut nodes, inspector.nodes.entities, format!("Entities ({}): {}", listed.len(), list));
+            set_text(&mut nodes, inspector.nodes.details, details);
+            set_text(&mut nodes, inspector.nodes.status, inspector.status.clone());
+        }
+    }
+}
+
+type InspectorInput = (Vec<usize>, Option<usize>, Option<(String, Option<usize>)>);
+
+impl InspectorUpdater {
+    // Toggling, selection and spawning. Returns the listed entities, the selection and a submitted
+    // edit with the entity it targets, or None while the panel is hidden.
+    fn handle_input(&self, world: &World, state: &mut State, inspector_entity: usize) -> Option<InspectorInput> {
+        let mut inspectors = world.borrow_component_vec_mut::<Inspector>().unwrap();
+        let inspector = inspectors[inspector_entity].as_mut()?;
+        let mut nodes = world.borrow_component_vec_mut::<UiNode>().unwrap();
+        let panel = nodes[inspector.nodes.panel].as_mut()?;
+        if state.input.pressed.contains(&inspector.toggle_key) {
+            panel.visible = !panel.visible;
+        }
+        if !panel.visible {
+            return None;
+        }
+
+        let listed: Vec<usize> = (0..world.entity_count)
+            .filter(|x| *x != inspector_entity && !inspector.nodes.all.contains(x) && world.has_components(*x))
+            .collect();
+        let position = inspector.selected.and_then(|x| listed.iter().position(|y| *y == x));
+        if inspector.selected.is_some_and(|x| x >= world.entity_count) {
+            inspector.selected = None;
+        }
+
+        let mut edit = None;
+        for event in state.ui.widget_events.iter() {
+            match *event {
+                UiWidgetEvent::ButtonClicked { entity } if entity == inspector.nodes.previous && !listed.is_empty() => {
+                    let i = position.map_or(listed.len() - 1, |x| (x + listed.len() - 1) % listed.len());
+                    inspector.selected = Some(listed[i]);
+                }
+                UiWidgetEvent::ButtonClicked { entity } if entity == inspector.nodes.next && !listed.is_empty() => {
+                    let i = position.map_or(0, |x| (x + 1) % listed.len());
+                    inspector.selected = Some(listed[i]);
+                }
+                UiWidgetEvent::ButtonClicked { entity } if entity == inspector.nodes.spawn => {
+                    // An entity without components is not listed, so it starts with a transform.
+                    world.defer(move |world, _, _| {
+                        let entity = world.new_entity();
+                        world.add_component(entity, Transform::new(
+                            Vec3d::new([0.0, 0.0, 0.0]),
+                            Vec3f::new([1.0, 1.0, 1.0]),
+                            Vec3f::new([0.0, 0.0, 0.0]),
+                        ));
+                        if let Some(Some(inspector)) = world.borrow_component_vec_mut::<Inspector>().unwrap().get_mut(inspector_entity) {
+                            inspector.selected = Some(entity);
+                            inspector.status = format!("spawned entity {entity}");
+                        }
+                    });
+                }
+                UiWidgetEvent::ButtonClicked { entity } if entity == inspector.nodes.despawn => {
+                    if let Some(selected) = inspector.selected.take() {
+                        world.despawn(selected);
+                        inspector.status = format!("despawned entity {selected}");
+                        state.renderer.command_buffer_outdated = true;
+                    }
+                }
+                UiWidgetEvent::TextSubmitted { entity } if entity == inspector.nodes.edit => {
+                    let text_fields = world.borrow_component_vec_mut::<UiTextField>().unwrap();
+                    edit = Some((text_fields[entity].as_ref().unwrap().text.clone(), inspector.selected));
+                }
+                _ => (),
+            }
+        }
+        Some((listed, inspector.selected, edit))
+    }
+}
@@ -0,0 +1,75 @@
+use bytemuck::{Pod, Zeroable};
+use simple_engine_derive::{Component, Reflect};
+
+use super::color::Color;
+use super::vectors::Vec3f;
+
+pub const MAX_POINT_LIGHTS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Component, Reflect)]
+#[component(reflect)]
+pub struct PointLight {
+    pub color: Color,
+    pub intensity: f32,
+    pub range: f32,
+    pub cast_shadows: bool,
+    pub shadow_resolution: u32,
+    pub shadow_bias: f32,
+}
+
+impl PointLight {
+    pub fn new(color: Color, intensity: f32, range: f32) -> PointLight {
+        PointLight {
+            color,
+            intensity,
+            range,
+            cast_shadows: false,
+            shadow_resolution: 512,
+            shadow_bias: 0.05,
+        }
+    }
+
+    pub fn with_shadows(mut self, resolution: u32) -> PointLight {
+        self.cast_shadows = true;
+        self.shadow_resolution = resolution.max(1);
+        self
+    }
+
+    pub fn to_data(&self, position: Vec3f, shadow_index: Option<usize>) -> PointLightData {
+        PointLightData {
+            position,
+            range: self.range,
+            color: self.color,
+            intensity: self.intensity,
+            shadow_index: shadow_index.map_or(-1, |x| x as i32),
+            shadow_bias: self.shadow_bias,
+            shadow_near: crate::shadows::SHADOW_NEAR,
+        }
+    }
+}
+
+impl Default for PointLight {
+    fn default() -> Self {
+        PointLight::new(Color::WHITE, 1.0, 10.0)
+    }
+}
+
+#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[repr(C)]
+pub struct PointLightData {
+    pub position: Vec3f,
+    pub range: f32,
+    pub color: Color,
+    pub intensity: f32,
+    pub shadow_index: i32,
+    pub shadow_bias: f32,
+    pub shadow_near: f32,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[repr(C)]
+pub struct LightsData {
+    pub lights: [PointLightData; MAX_POINT_LIGHTS],
+    pub count: u32,
+    pub _padding: [u32; 3],
+}
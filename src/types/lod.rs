@@ -0,0 +1,54 @@
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, state::State};
+
+use super::{static_mesh::StaticMesh, transform::Transform};
+
+#[derive(Clone, Debug)]
+pub struct LodLevel {
+    pub mesh_name: String,
+    pub max_distance: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lod {
+    pub levels: Vec<LodLevel>,
+}
+
+impl Lod {
+    pub fn new(levels: Vec<LodLevel>) -> Lod {
+        Lod { levels }
+    }
+
+    pub fn select(&self, distance: f32) -> Option<&LodLevel> {
+        self.levels
+            .iter()
+            .find(|x| distance < x.max_distance)
+            .or(self.levels.last())
+    }
+}
+
+pub struct LodUpdater {}
+
+impl System for LodUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(lods) = world.borrow_component_vec_mut::<Lod>() else {
+            return;
+        };
+        let mut static_meshes = world.borrow_component_vec_mut::<StaticMesh>().unwrap();
+        let transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+
+        let zip = lods.iter().zip(static_meshes.iter_mut()).zip(transforms.iter());
+        for ((lod, static_mesh), transform) in zip {
+            let (Some(lod), Some(static_mesh), Some(transform)) = (lod, static_mesh, transform) else {
+                continue;
+            };
+
+            let distance = (transform.position - state.renderer.vp_pos).length() as f32;
+            if let Some(level) = lod.select(distance) {
+                if level.mesh_name != static_mesh.mesh_name {
+                    static_mesh.set_mesh(state, level.mesh_name.clone());
+                }
+            }
+        }
+    }
+}
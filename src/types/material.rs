@@ -1,4 +1,14 @@
-use super::vectors::Vec3f;
+use std::hash::{Hash, Hasher};
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::pipeline::graphics::color_blend::{AttachmentBlend, BlendFactor, BlendOp};
+use vulkano::pipeline::graphics::rasterization::{CullMode, FrontFace, PolygonMode};
+
+use crate::builtin_shaders;
+
+use super::buffers::UpdatableBuffer;
+use super::color::Color;
+use super::vectors::{Vec2f, Vec3f};
 
 #[derive(Debug)]
 pub enum Attachment {
@@ -7,10 +17,300 @@ pub enum Attachment {
     Texture(String)
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Opaque,
+    #[default]
+    Alpha,
+    Additive,
+    Multiply,
+    Premultiplied,
+}
+
+impl BlendMode {
+    pub fn attachment_blend(&self) -> Option<AttachmentBlend> {
+        let blend = |src_color, dst_color, src_alpha, dst_alpha| AttachmentBlend {
+            src_color_blend_factor: src_color,
+            dst_color_blend_factor: dst_color,
+            color_blend_op: BlendOp::Add,
+            src_alpha_blend_factor: src_alpha,
+            dst_alpha_blend_factor: dst_alpha,
+            alpha_blend_op: BlendOp::Add,
+        };
+        match self {
+            BlendMode::Opaque => None,
+            BlendMode::Alpha => Some(AttachmentBlend::alpha()),
+            BlendMode::Additive => Some(blend(BlendFactor::SrcAlpha, BlendFactor::One, BlendFactor::Zero, BlendFactor::One)),
+            BlendMode::Multiply => Some(blend(BlendFactor::DstColor, BlendFactor::Zero, BlendFactor::Zero, BlendFactor::One)),
+            BlendMode::Premultiplied => Some(blend(
+                BlendFactor::One,
+                BlendFactor::OneMinusSrcAlpha,
+                BlendFactor::One,
+                BlendFactor::OneMinusSrcAlpha,
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderState {
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub polygon_mode: PolygonMode,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_bias: Option<DepthBias>,
+    pub line_width: f32,
+    pub blend_mode: BlendMode,
+}
+
+impl RenderState {
+    pub fn two_sided(mut self) -> RenderState {
+        self.cull_mode = CullMode::None;
+        self
+    }
+
+    pub fn wireframe(mut self) -> RenderState {
+        self.polygon_mode = PolygonMode::Line;
+        self
+    }
+
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> RenderState {
+        self.blend_mode = blend_mode;
+        self
+    }
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        RenderState {
+            cull_mode: CullMode::None,
+            front_face: FrontFace::CounterClockwise,
+            polygon_mode: PolygonMode::Fill,
+            depth_test: true,
+            depth_write: true,
+            depth_bias: None,
+            line_width: 1.0,
+            blend_mode: BlendMode::default(),
+        }
+    }
+}
+
+impl Eq for RenderState {}
+
+impl Hash for RenderState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cull_mode.hash(state);
+        self.front_face.hash(state);
+        self.polygon_mode.hash(state);
+        self.depth_test.hash(state);
+        self.depth_write.hash(state);
+        self.depth_bias
+            .map(|x| [x.constant_factor.to_bits(), x.clamp.to_bits(), x.slope_factor.to_bits()])
+            .hash(state);
+        self.line_width.to_bits().hash(state);
+        self.blend_mode.hash(state);
+    }
+}
+
+pub type PipelineKey = (String, String, RenderState);
+
+// Queues are drawn in declaration order. Transparent materials are drawn back to front, every
+// other queue front to back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RenderQueue {
+    Background,
+    #[default]
+    Opaque,
+    Decal,
+    Skybox,
+    Transparent,
+    Overlay,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[repr(C)]
+pub struct MaterialData {
+    pub emissive: Color,
+    pub emissive_intensity: f32,
+    pub time: f32,
+    pub uv_scroll: Vec2f,
+}
+
 #[derive(Debug)]
 pub struct Material {
     pub name: String,
     pub vertex_shader: String,
     pub fragment_shader: String,
-    pub attachments: Vec<Attachment>
+    pub attachments: Vec<Attachment>,
+    pub emissive: Color,
+    pub emissive_texture: Option<String>,
+    pub emissive_intensity: f32,
+    pub lightmap: Option<String>,
+    pub uv_scroll: Vec2f,
+    pub render_state: RenderState,
+    pub queue: RenderQueue,
+    pub priority: i32,
+    pub buffer: Option<UpdatableBuffer<MaterialData>>,
+}
+
+impl Material {
+    pub fn new(name: &str, vertex_shader: &str, fragment_shader: &str, attachments: Vec<Attachment>) -> Material {
+        Material {
+            name: name.to_string(),
+            vertex_shader: vertex_shader.to_string(),
+            fragment_shader: fragment_shader.to_string(),
+            attachments,
+            emissive: Color::BLACK,
+            emissive_texture: None,
+            emissive_intensity: 1.0,
+            lightmap: None,
+            uv_scroll: Vec2f::new([0.0, 0.0]),
+            render_state: RenderState::default(),
+            queue: RenderQueue::default(),
+            priority: 0,
+            buffer: None,
+        }
+    }
+
+    pub fn with_emissive(mut self, emissive: Color, intensity: f32) -> Material {
+        self.emissive = emissive;
+        self.emissive_intensity = intensity;
+        self
+    }
+
+    pub fn with_emissive_texture(mut self, texture: &str) -> Material {
+        self.emissive_texture = Some(texture.to_string());
+        self
+    }
+
+    pub fn with_lightmap(mut self, texture: &str) -> Material {
+        self.lightmap = Some(texture.to_string());
+        self
+    }
+
+    pub fn with_uv_scroll(mut self, uv_scroll: Vec2f) -> Material {
+        self.uv_scroll = uv_scroll;
+        self
+    }
+
+    // Uses the "water" shaders: set 2 binding 0 is the reflection texture and binding 1 the
+    // normal map, scrolled by `uv_scroll * time` from the material uniform.
+    pub fn water(name: &str, reflection_texture: &str, normal_map: &str) -> Material {
+        Material::new(
+            name,
+            "water",
+            "water",
+            vec![
+                Attachment::Texture(reflection_texture.to_string()),
+                Attachment::Texture(normal_map.to_string()),
+            ],
+        )
+        .with_uv_scroll(Vec2f::new([0.03, 0.02]))
+    }
+
+    // The constructors below use the shaders from `builtin_shaders::load`.
+    pub fn unlit_color(name: &str) -> Material {
+        Material::new(name, builtin_shaders::MESH_VS, builtin_shaders::UNLIT_COLOR_FS, vec![])
+    }
+
+    pub fn unlit_textured(name: &str, texture: &str) -> Material {
+        Material::new(
+            name,
+            builtin_shaders::MESH_VS,
+            builtin_shaders::UNLIT_TEXTURED_FS,
+            vec![Attachment::Texture(texture.to_string())],
+        )
+    }
+
+    pub fn lit(name: &str) -> Material {
+        Material::new(name, builtin_shaders::MESH_VS, builtin_shaders::LIT_FS, vec![])
+    }
+
+    // `lightmap` is a texture name from `lightmapper::bake_to_assets`.
+    pub fn lightmapped(name: &str, lightmap: &str) -> Material {
+        Material::new(name, builtin_shaders::MESH_VS, builtin_shaders::LIGHTMAPPED_FS, vec![]).with_lightmap(lightmap)
+    }
+
+    // Draw on a cube around the camera.
+    pub fn skybox(name: &str, panorama: &str) -> Material {
+        Material::new(
+            name,
+            builtin_shaders::SKYBOX_VS,
+            builtin_shaders::SKYBOX_FS,
+            vec![Attachment::Texture(panorama.to_string())],
+        )
+        .with_render_state(RenderState {
+            depth_write: false,
+            blend_mode: BlendMode::Opaque,
+            ..Default::default()
+        })
+        .with_queue(RenderQueue::Skybox)
+    }
+
+    // Vertex colored wireframe on top of everything, for gizmos and debug meshes.
+    pub fn debug_lines(name: &str) -> Material {
+        Material::new(name, builtin_shaders::MESH_VS, builtin_shaders::UNLIT_COLOR_FS, vec![])
+            .with_render_state(RenderState {
+                depth_test: false,
+                depth_write: false,
+                ..Default::default()
+            }.wireframe())
+            .with_queue(RenderQueue::Overlay)
+    }
+
+    pub fn ui(name: &str, atlas: &str) -> Material {
+        Material::new(
+            name,
+            builtin_shaders::UI_VS,
+            builtin_shaders::UI_FS,
+            vec![Attachment::Texture(atlas.to_string())],
+        )
+        .with_render_state(RenderState {
+            depth_test: false,
+            depth_write: false,
+            ..Default::default()
+        })
+        .with_queue(RenderQueue::Overlay)
+    }
+
+    pub fn with_render_state(mut self, render_state: RenderState) -> Material {
+        self.render_state = render_state;
+        self
+    }
+
+    pub fn with_queue(mut self, queue: RenderQueue) -> Material {
+        self.queue = queue;
+        self
+    }
+
+    // Lower priorities are drawn first within a queue.
+    pub fn with_priority(mut self, priority: i32) -> Material {
+        self.priority = priority;
+        self
+    }
+
+    pub fn sort_key(&self) -> (RenderQueue, i32) {
+        (self.queue, self.priority)
+    }
+
+    pub fn pipeline_key(&self) -> PipelineKey {
+        (self.vertex_shader.clone(), self.fragment_shader.clone(), self.render_state)
+    }
+
+    pub fn to_data(&self, time: f32) -> MaterialData {
+        MaterialData {
+            emissive: self.emissive,
+            emissive_intensity: self.emissive_intensity,
+            time,
+            uv_scroll: self.uv_scroll,
+        }
+    }
 }
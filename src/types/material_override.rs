@@ -0,0 +1,52 @@
+use super::color::Color;
+
+// Per-entity changes to the material of the entity's meshes without duplicating it, e.g. for damage
+// flashes or team colors. Tint and emissive go into the entity's model data, so only the entity's
+// own buffer is rewritten. Mark the component changed with `World::mark_changed` after editing it.
+#[derive(Clone, Debug, Default)]
+pub struct MaterialOverride {
+    /// Multiplies the vertex color.
+    pub tint: Option<Color>,
+    /// Replaces the material's emissive color and intensity.
+    pub emissive: Option<Color>,
+    pub emissive_intensity: f32,
+    /// Replaces the material's first texture attachment, changing it records the command buffers
+    /// again.
+    pub texture: Option<String>,
+    pub(crate) applied_texture: Option<String>,
+}
+
+impl MaterialOverride {
+    pub fn new() -> MaterialOverride {
+        MaterialOverride {
+            emissive_intensity: 1.0,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> MaterialOverride {
+        self.tint = Some(tint);
+        self
+    }
+
+    pub fn with_emissive(mut self, emissive: Color, intensity: f32) -> MaterialOverride {
+        self.emissive = Some(emissive);
+        self.emissive_intensity = intensity;
+        self
+    }
+
+    pub fn with_texture(mut self, texture: &str) -> MaterialOverride {
+        self.texture = Some(texture.to_string());
+        self
+    }
+
+    // Tint and emissive as the shaders read them, emissive.a is 1 when it replaces the material's.
+    pub(crate) fn instance_data(material_override: Option<&MaterialOverride>) -> ([f32; 4], [f32; 4]) {
+        let tint = material_override.and_then(|x| x.tint).unwrap_or(Color::WHITE).to_array();
+        let emissive = match material_override.and_then(|x| x.emissive.map(|e| (e, x.emissive_intensity))) {
+            Some((color, intensity)) => [color.r * intensity, color.g * intensity, color.b * intensity, 1.0],
+            None => [0.0; 4],
+        };
+        (tint, emissive)
+    }
+}
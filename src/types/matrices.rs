@@ -2,7 +2,9 @@ use std::ops::Mul;
 
 use bytemuck::{Pod, Zeroable};
 
-use crate::types::vectors::*;
+use crate::{rendering::VertexData, types::vectors::*};
+
+use super::{quaternion::Quaternion, simd};
 
 #[derive(Clone, Copy, Pod, Zeroable, Debug)]
 #[repr(C)]
@@ -12,16 +14,7 @@ impl Mul for Matrix4f {
     type Output = Self;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        let mut output = Matrix4f::indentity();
-        for i in (0..4).step_by(1) {
-            for j in (0..4).step_by(1) {
-                output.0[i][j] = 0.0;
-                for k in (0..4).step_by(1) {
-                    output.0[i][j] += self.0[k][j] * rhs.0[i][k];
-                }
-            }
-        }
-        output
+        Matrix4f(simd::mat4_mul(&self.0, &rhs.0))
     }
 }
 
@@ -53,6 +46,8 @@ impl Matrix4f {
         ])
     }
 
+    // Right-handed rotations: positive angles turn counter-clockwise looking down the axis towards
+    // the origin.
     pub fn rotation_x(angle: f32) -> Matrix4f {
         Matrix4f([
             [1.0, 0.0, 0.0, 0.0],
@@ -92,6 +87,81 @@ impl Matrix4f {
         Matrix4f::rotation_x(xyz.x) * Matrix4f::rotation_z(xyz.z) * Matrix4f::rotation_y(xyz.y)
     }
 
+    // Rotation by `angle` radians around `axis`, `from_axis_angle(x, a)` equals `rotation_x(a)`.
+    pub fn from_axis_angle(mut axis: Vec3f, angle: f32) -> Matrix4f {
+        let k = axis.normalize();
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+        Matrix4f([
+            [t * k.x * k.x + cos, t * k.x * k.y + sin * k.z, t * k.x * k.z - sin * k.y, 0.0],
+            [t * k.x * k.y - sin * k.z, t * k.y * k.y + cos, t * k.y * k.z + sin * k.x, 0.0],
+            [t * k.x * k.z + sin * k.y, t * k.y * k.z - sin * k.x, t * k.z * k.z + cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn from_quaternion(q: Quaternion) -> Matrix4f {
+        let Quaternion { x, y, z, w } = q.normalize();
+        Matrix4f([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + z * w), 2.0 * (x * z - y * w), 0.0],
+            [2.0 * (x * y - z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + x * w), 0.0],
+            [2.0 * (x * z + y * w), 2.0 * (y * z - x * w), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Translation * rotation_yxz * scale, the order `Transform` uses.
+    pub fn compose(translation: Vec3f, rotation: Vec3f, scale: Vec3f) -> Matrix4f {
+        Matrix4f::translation(translation) * Matrix4f::rotation_yxz(rotation) * Matrix4f::scale(scale)
+    }
+
+    // Inverse of `compose`, returns translation, `rotation_yxz` angles and scale. Shear and
+    // projection are dropped. A mirroring matrix gets a negative x scale.
+    pub fn decompose(&self) -> (Vec3f, Vec3f, Vec3f) {
+        let m = self.0;
+        let translation = Vec3f::new([m[3][0], m[3][1], m[3][2]]);
+        let mut scale = [0, 1, 2].map(|c| (m[c][0] * m[c][0] + m[c][1] * m[c][1] + m[c][2] * m[c][2]).sqrt());
+        let det = m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2]) - m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2])
+            + m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2]);
+        if det < 0.0 {
+            scale[0] = -scale[0];
+        }
+
+        // r(row, column) of the rotation part
+        let r = |row: usize, column: usize| {
+            if scale[column].abs() < f32::EPSILON {
+                0.0
+            } else {
+                m[column][row] / scale[column]
+            }
+        };
+        let x = (-r(1, 2)).clamp(-1.0, 1.0).asin();
+        let (y, z) = if r(1, 2).abs() < 0.9999 {
+            (r(0, 2).atan2(r(2, 2)), r(1, 0).atan2(r(1, 1)))
+        } else {
+            // Gimbal lock, y and z rotate around the same axis.
+            ((-r(2, 0)).atan2(r(0, 0)), 0.0)
+        };
+
+        (translation, Vec3f::new([x, y, z]), Vec3f::new(scale))
+    }
+
+    // Maps x from left to right and y from top to bottom onto -1..1, Vulkan's y points down so
+    // `top` ends up at the top of the screen. Depth maps near..far to 0..1.
+    pub fn orthographic(left: f32, right: f32, top: f32, bottom: f32, near: f32, far: f32) -> Matrix4f {
+        Matrix4f([
+            [2.0 / (right - left), 0.0, 0.0, 0.0],
+            [0.0, 2.0 / (bottom - top), 0.0, 0.0],
+            [0.0, 0.0, 1.0 / (far - near), 0.0],
+            [
+                -(right + left) / (right - left),
+                -(bottom + top) / (bottom - top),
+                -near / (far - near),
+                1.0,
+            ],
+        ])
+    }
+
     pub fn perspective(fovy: f32, aspect: f32, near: f32, far: f32) -> Matrix4f {
         let f = 1.0 / (fovy / 2.0).tan();
         let a = (far + near) / (near - far);
@@ -104,7 +174,48 @@ impl Matrix4f {
         ])
     }
 
-    pub fn look_at(mut eye: Vec3f, mut dir: Vec3f, mut up: Vec3f) -> Matrix4f {
+    // Reversed-Z for Vulkan's 0..1 depth range: the near plane maps to 1 and the far plane to 0.
+    pub fn perspective_reversed(fovy: f32, aspect: f32, near: f32, far: f32) -> Matrix4f {
+        let f = 1.0 / (fovy / 2.0).tan();
+        let a = near / (far - near);
+        let b = far * near / (far - near);
+        Matrix4f([
+            [f / aspect, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, a, -1.0],
+            [0.0, 0.0, b, 0.0],
+        ])
+    }
+
+    // `perspective_reversed` with the far plane at infinity, depth approaches 0 with distance.
+    pub fn perspective_infinite_reversed(fovy: f32, aspect: f32, near: f32) -> Matrix4f {
+        let f = 1.0 / (fovy / 2.0).tan();
+        Matrix4f([
+            [f / aspect, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, 0.0, -1.0],
+            [0.0, 0.0, near, 0.0],
+        ])
+    }
+
+    // Reversed projections have a non-negative depth scale, the standard one a negative.
+    pub fn is_reversed_z(&self) -> bool {
+        self.0[2][2] >= 0.0
+    }
+
+    // Takes a direction despite the name, see `look_to`.
+    pub fn look_at(eye: Vec3f, dir: Vec3f, up: Vec3f) -> Matrix4f {
+        Matrix4f::look_to(eye, dir, up)
+    }
+
+    // Right-handed view looking along `target - eye`.
+    pub fn look_at_rh(eye: Vec3f, target: Vec3f, up: Vec3f) -> Matrix4f {
+        Matrix4f::look_to(eye, target - eye, up)
+    }
+
+    // Right-handed view: the camera looks down -Z with +X to the right. `up` is negated so that,
+    // with `perspective`, it ends up at the top of Vulkan's y-down clip space.
+    pub fn look_to(mut eye: Vec3f, mut dir: Vec3f, mut up: Vec3f) -> Matrix4f {
         up.x *= -1.0;
         up.y *= -1.0;
         up.z *= -1.0;
@@ -121,6 +232,147 @@ impl Matrix4f {
         ])
     }
 
+    // Mirrors points about the plane `normal . x + d = 0`; `normal` must be normalized.
+    pub fn reflection(normal: Vec3f, d: f32) -> Matrix4f {
+        let n = [normal.x, normal.y, normal.z];
+        let mut output = Matrix4f::indentity();
+        for c in 0..3 {
+            for r in 0..3 {
+                output.0[c][r] -= 2.0 * n[r] * n[c];
+            }
+            output.0[3][c] = -2.0 * d * n[c];
+        }
+        output
+    }
+
+    // Transforms a plane `[a, b, c, d]` by this matrix, using the inverse transpose.
+    pub fn transform_plane(&self, plane: [f32; 4]) -> [f32; 4] {
+        let inverse = self.inverse().unwrap_or(Matrix4f::indentity());
+        [0, 1, 2, 3].map(|r| (0..4).map(|c| inverse.0[r][c] * plane[c]).sum())
+    }
+
+    // Replaces the near plane of a projection with a view space clip plane (Lengyel's oblique
+    // frustum), so geometry behind the plane is clipped without user clip distances.
+    pub fn oblique_near_plane(&self, plane: [f32; 4]) -> Matrix4f {
+        let Some(inverse) = self.inverse() else {
+            return *self;
+        };
+        let corner = [plane[0].signum(), plane[1].signum(), 1.0, 1.0];
+        let q: [f32; 4] = [0, 1, 2, 3].map(|r| (0..4).map(|c| inverse.0[c][r] * corner[c]).sum());
+        let dot: f32 = (0..4).map(|i| plane[i] * q[i]).sum();
+        if dot.abs() < f32::EPSILON {
+            return *self;
+        }
+
+        let mut output = *self;
+        for (c, column) in output.0.iter_mut().enumerate() {
+            column[2] = plane[c] / dot;
+        }
+        output
+    }
+
+    // Maps depth z to w - z, turning a reversed-Z projection into a standard 0..1 one and back.
+    fn flip_depth(&self) -> Matrix4f {
+        let mut output = *self;
+        for column in output.0.iter_mut() {
+            column[2] = column[3] - column[2];
+        }
+        output
+    }
+
+    // `oblique_near_plane` for reversed-Z projections.
+    pub fn oblique_near_plane_reversed(&self, plane: [f32; 4]) -> Matrix4f {
+        self.flip_depth().oblique_near_plane(plane).flip_depth()
+    }
+
+    pub fn to_array(&self) -> [[f32; 4]; 4] {
+        self.0
+    }
+
+    pub fn transpose(&self) -> Matrix4f {
+        let mut output = Matrix4f::indentity();
+        for i in 0..4 {
+            for j in 0..4 {
+                output.0[i][j] = self.0[j][i];
+            }
+        }
+        output
+    }
+
+    pub fn inverse(&self) -> Option<Matrix4f> {
+        let m = self.0;
+        let s0 = m[0][0] * m[1][1] - m[1][0] * m[0][1];
+        let s1 = m[0][0] * m[1][2] - m[1][0] * m[0][2];
+        let s2 = m[0][0] * m[1][3] - m[1][0] * m[0][3];
+        let s3 = m[0][1] * m[1][2] - m[1][1] * m[0][2];
+        let s4 = m[0][1] * m[1][3] - m[1][1] * m[0][3];
+        let s5 = m[0][2] * m[1][3] - m[1][2] * m[0][3];
+        let c5 = m[2][2] * m[3][3] - m[3][2] * m[2][3];
+        let c4 = m[2][1] * m[3][3] - m[3][1] * m[2][3];
+        let c3 = m[2][1] * m[3][2] - m[3][1] * m[2][2];
+        let c2 = m[2][0] * m[3][3] - m[3][0] * m[2][3];
+        let c1 = m[2][0] * m[3][2] - m[3][0] * m[2][2];
+        let c0 = m[2][0] * m[3][1] - m[3][0] * m[2][1];
+
+        let det = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+        let inv = 1.0 / det;
+
+        Some(Matrix4f([
+            [
+                (m[1][1] * c5 - m[1][2] * c4 + m[1][3] * c3) * inv,
+                (-m[0][1] * c5 + m[0][2] * c4 - m[0][3] * c3) * inv,
+                (m[3][1] * s5 - m[3][2] * s4 + m[3][3] * s3) * inv,
+                (-m[2][1] * s5 + m[2][2] * s4 - m[2][3] * s3) * inv,
+            ],
+            [
+                (-m[1][0] * c5 + m[1][2] * c2 - m[1][3] * c1) * inv,
+                (m[0][0] * c5 - m[0][2] * c2 + m[0][3] * c1) * inv,
+                (-m[3][0] * s5 + m[3][2] * s2 - m[3][3] * s1) * inv,
+                (m[2][0] * s5 - m[2][2] * s2 + m[2][3] * s1) * inv,
+            ],
+            [
+                (m[1][0] * c4 - m[1][1] * c2 + m[1][3] * c0) * inv,
+                (-m[0][0] * c4 + m[0][1] * c2 - m[0][3] * c0) * inv,
+                (m[3][0] * s4 - m[3][1] * s2 + m[3][3] * s0) * inv,
+                (-m[2][0] * s4 + m[2][1] * s2 - m[2][3] * s0) * inv,
+            ],
+            [
+                (-m[1][0] * c3 + m[1][1] * c1 - m[1][2] * c0) * inv,
+                (m[0][0] * c3 - m[0][1] * c1 + m[0][2] * c0) * inv,
+                (-m[3][0] * s3 + m[3][1] * s1 - m[3][2] * s0) * inv,
+                (m[2][0] * s3 - m[2][1] * s1 + m[2][2] * s0) * inv,
+            ],
+        ]))
+    }
+
+    pub fn transform_point(&self, point: Vec3f) -> Vec3f {
+        let [x, y, z, w] = simd::transform(&self.0, [point.x, point.y, point.z, 1.0]);
+        Vec3f::new([x / w, y / w, z / w])
+    }
+
+    // `transform_point` over a whole array, for CPU side skinning and culling.
+    pub fn transform_points(&self, points: &mut [Vec3f]) {
+        for point in points.iter_mut() {
+            *point = self.transform_point(*point);
+        }
+    }
+
+    // Positions go through the whole matrix, normals through its upper 3x3 and are renormalized,
+    // which is only exact without non-uniform scale.
+    pub fn transform_vertices(&self, vertices: &mut [VertexData]) {
+        for vertex in vertices.iter_mut() {
+            let p = vertex.position;
+            let n = vertex.normal;
+            let [x, y, z, w] = simd::transform(&self.0, [p.x, p.y, p.z, 1.0]);
+            let [nx, ny, nz, _] = simd::transform(&self.0, [n.x, n.y, n.z, 0.0]);
+            vertex.position = Vec3f::new([x / w, y / w, z / w]);
+            vertex.normal = Vec3f::new(simd::normalize3([nx, ny, nz]));
+        }
+    }
+
     pub fn vec_mul(&self, vec: Vec3f) -> Vec3f {
         Vec3f::new([
             vec.x * self.0[0][0] + vec.y * self.0[0][1] + vec.z * self.0[0][2],
@@ -129,3 +381,47 @@ impl Matrix4f {
         ])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::noise::Rng;
+
+    fn assert_close(a: &Matrix4f, b: &Matrix4f) {
+        for (a, b) in a.0.iter().flatten().zip(b.0.iter().flatten()) {
+            assert!((a - b).abs() < 1e-4, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    fn decompose_round_trips_compose() {
+        let mut rng = Rng::new(3);
+        let mut vec = |min: f32, max: f32| Vec3f::new([rng.range_f32(min, max), rng.range_f32(min, max), rng.range_f32(min, max)]);
+        for _ in 0..500 {
+            let translation = vec(-100.0, 100.0);
+            let rotation = vec(-1.5, 1.5);
+            let scale = vec(0.1, 5.0);
+            let matrix = Matrix4f::compose(translation, rotation, scale);
+            let (t, r, s) = matrix.decompose();
+            for (a, b) in [(t, translation), (r, rotation), (s, scale)] {
+                let mut offset = a - b;
+                assert!(offset.length() < 1e-3, "{:?} != {:?}", a, b);
+            }
+            assert_close(&Matrix4f::compose(t, r, s), &matrix);
+        }
+    }
+
+    #[test]
+    fn decompose_keeps_mirroring_and_gimbal_lock() {
+        let mirrored =
+            Matrix4f::compose(Vec3f::new([1.0, 2.0, 3.0]), Vec3f::new([0.3, -0.7, 1.1]), Vec3f::new([1.0, -2.0, 0.5]));
+        let (t, r, s) = mirrored.decompose();
+        assert!(s.x < 0.0);
+        assert_close(&Matrix4f::compose(t, r, s), &mirrored);
+
+        let rotation = Vec3f::new([std::f32::consts::FRAC_PI_2, 0.4, 0.9]);
+        let locked = Matrix4f::compose(Vec3f::new([0.0, 0.0, 0.0]), rotation, Vec3f::new([1.0, 1.0, 1.0]));
+        let (t, r, s) = locked.decompose();
+        assert_close(&Matrix4f::compose(t, r, s), &locked);
+    }
+}
@@ -1,6 +1,21 @@
-use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer}, command_buffer::{allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferInfo}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}, sync::{now, GpuFuture}};
+use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}};
 
-use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::{Renderer, VertexData}, state::State};
+use crate::{asset_library::AssetLibrary, debug_labels, ray_tracing, ecs::{System, World}, rendering::{Renderer, VertexData}, state::State};
+
+use super::bvh::{Aabb, Bvh};
+use super::matrices::Matrix4f;
+use super::mesh_arena::MeshAllocation;
+use super::meshlet::{build_meshlets, Meshlet};
+use super::morph::{self, MorphDelta, MorphTarget};
+use super::ray::{Hit, Ray};
+use super::skin::SkinnedMesh;
+use super::static_mesh::StaticMesh;
+use super::transform::Transform;
+use super::vectors::Vec3f;
+use super::visibility::Visibility;
+
+// Meshes with fewer triangles are raycast one triangle at a time.
+const BVH_MIN_TRIANGLES: usize = 256;
 
 #[derive(Debug)]
 pub struct Mesh {
@@ -10,15 +25,46 @@ pub struct Mesh {
     pub material: String,
     pub vertex_buffer: Option<Subbuffer<[VertexData]>>,
     pub index_buffer: Option<Subbuffer<[u32]>>,
+    pub morph_targets: Vec<MorphTarget>,
+    pub morph_buffer: Option<Subbuffer<[MorphDelta]>>,
+    /// Built when the mesh is loaded, see `build_meshlets`.
+    pub meshlets: Vec<Meshlet>,
+    /// Over the triangles, built when a mesh with many triangles is loaded. Speeds up `raycast`.
+    pub bvh: Option<Bvh>,
 }
 
 impl Mesh {
+    // In the mesh's own space, against the vertices as loaded.
+    pub fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let position = |x: u32| self.vertices[x as usize].position;
+        let Some(bvh) = self.bvh.as_ref() else {
+            return ray.intersect_triangles(position, &self.indices, 0..self.indices.len() / 3, f32::INFINITY);
+        };
+        let mut closest = None;
+        bvh.traverse_ray(ray, f32::INFINITY, |triangle, max_distance| {
+            let hit = ray.intersect_triangles(position, &self.indices, [triangle], max_distance)?;
+            closest = Some(hit);
+            Some(hit.distance)
+        });
+        closest
+    }
+
     pub fn load(&mut self, renderer: &mut Renderer) {
+        self.meshlets = build_meshlets(&self.vertices, &self.indices);
+        if self.indices.len() / 3 >= BVH_MIN_TRIANGLES && self.bvh.is_none() {
+            let bounds: Vec<Aabb> = self
+                .indices
+                .chunks_exact(3)
+                .map(|x| Aabb::from_points(x.iter().map(|i| self.vertices[*i as usize].position)))
+                .collect();
+            self.bvh = Some(Bvh::build(&bounds));
+        }
+        let ray_tracing_usage = ray_tracing::mesh_buffer_usage(renderer);
         self.vertex_buffer = Some(
             Buffer::from_iter(
                 renderer.memeory_allocator.as_ref().unwrap().clone(),
                 BufferCreateInfo {
-                    usage: BufferUsage::VERTEX_BUFFER,
+                    usage: BufferUsage::VERTEX_BUFFER | ray_tracing_usage,
                     ..Default::default()
                 },
                 AllocationCreateInfo {
@@ -34,7 +80,7 @@ impl Mesh {
             Buffer::from_iter(
                 renderer.memeory_allocator.as_ref().unwrap().clone(),
                 BufferCreateInfo {
-                    usage: BufferUsage::INDEX_BUFFER | BufferUsage::TRANSFER_DST,
+                    usage: BufferUsage::INDEX_BUFFER | BufferUsage::TRANSFER_DST | ray_tracing_usage,
                     ..Default::default()
                 },
                 AllocationCreateInfo {
@@ -46,6 +92,11 @@ impl Mesh {
             )
             .unwrap(),
         );
+        debug_labels::name_object(renderer, self.vertex_buffer.as_ref().unwrap().buffer().as_ref(), &format!("mesh {} vertices", self.name));
+        debug_labels::name_object(renderer, self.index_buffer.as_ref().unwrap().buffer().as_ref(), &format!("mesh {} indices", self.name));
+        if !self.morph_targets.is_empty() {
+            self.morph_buffer = Some(morph::create_delta_buffer(renderer, self));
+        }
     }
 }
 
@@ -58,6 +109,18 @@ impl System for MeshLoader {
         }
     }
     fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_device_restored(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        self.on_start(world, assets, state);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DynamicMeshBuffers {
+    pub vertex: Vec<Subbuffer<[VertexData]>>,
+    pub index: Vec<Subbuffer<[u32]>>,
+    pub outdated: Vec<bool>,
+    pub allocations: Vec<MeshAllocation>,
 }
 
 #[derive(Debug, Clone)]
@@ -65,143 +128,97 @@ pub struct DynamicMesh {
     pub vertices: Vec<VertexData>,
     pub indices: Vec<u32>,
     pub material: String,
-    pub vertex_buffer: Option<Subbuffer<[VertexData]>>,
-    pub index_buffer: Option<Subbuffer<[u32]>>,
+    pub buffers: Option<DynamicMeshBuffers>,
 }
 
 impl DynamicMesh {
+    // In the mesh's own space, every triangle is tested.
+    pub fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        ray.intersect_triangles(|x| self.vertices[x as usize].position, &self.indices, 0..self.indices.len() / 3, f32::INFINITY)
+    }
+
     pub fn from_mesh(mesh: String, assets: &AssetLibrary) -> DynamicMesh {
         let mesh = assets.meshes.iter().find(|x| x.name == mesh).unwrap();
         DynamicMesh {
             vertices: mesh.vertices.clone(),
             indices: mesh.indices.clone(),
             material: mesh.material.clone(),
-            vertex_buffer: None,
-            index_buffer: None
+            buffers: None,
         }
     }
 
-    pub fn load(&mut self, renderer: &mut Renderer) {
-        self.vertex_buffer = Some(
-            Buffer::from_iter(
-                renderer.memeory_allocator.as_ref().unwrap().clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::VERTEX_BUFFER,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                self.vertices.clone(),
-            )
-            .unwrap(),
-        );
-        self.index_buffer = Some(
-            Buffer::from_iter(
-                renderer.memeory_allocator.as_ref().unwrap().clone(),
-                BufferCreateInfo {
-                    usage: BufferUsage::INDEX_BUFFER | BufferUsage::TRANSFER_DST,
-                    ..Default::default()
-                },
-                AllocationCreateInfo {
-                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                    ..Default::default()
-                },
-                self.indices.clone(),
-            )
-            .unwrap(),
-        );
+    pub fn release(&mut self, renderer: &mut Renderer) {
+        if let Some(buffers) = self.buffers.take() {
+            for allocation in buffers.allocations {
+                renderer.mesh_arena.retire(allocation, renderer.frames_in_flight + 1);
+            }
+        }
     }
 
-    pub fn change_indices(&mut self, renderer: &Renderer, vec: Vec<u32>) {
-        self.indices = vec;
-        let command_buffer_allocator = StandardCommandBufferAllocator::new(
-            renderer.device.as_ref().unwrap().clone(),
-            Default::default(),
-        );
+    pub fn load(&mut self, renderer: &mut Renderer) {
+        self.release(renderer);
 
-        let mut builder = AutoCommandBufferBuilder::primary(
-            &command_buffer_allocator,
-            renderer.queue.as_ref().unwrap().queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        ).unwrap();
-
-        let temp_buffer = Buffer::from_iter(
-            renderer.memeory_allocator.as_ref().unwrap().clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_SRC,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE |
-                    MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            self.indices.to_owned(),
-        ).unwrap();
-
-        builder
-            .copy_buffer(
-                CopyBufferInfo::buffers(temp_buffer, self.index_buffer.as_ref().unwrap().to_owned())
-            )
-            .unwrap();
+        let mut buffers = DynamicMeshBuffers {
+            vertex: Vec::with_capacity(renderer.frames_in_flight),
+            index: Vec::with_capacity(renderer.frames_in_flight),
+            outdated: vec![true; renderer.frames_in_flight],
+            allocations: Vec::with_capacity(renderer.frames_in_flight),
+        };
 
-        let command_buffer = builder.build().unwrap();
+        let allocator = renderer.memeory_allocator.as_ref().unwrap().clone();
+        for _ in 0..renderer.frames_in_flight {
+            let (allocation, vertex, index) = renderer.mesh_arena.allocate(
+                &allocator,
+                self.vertices.len() as u64,
+                self.indices.len() as u64,
+            );
+            buffers.vertex.push(vertex);
+            buffers.index.push(index);
+            buffers.allocations.push(allocation);
+        }
 
-        let future = now(renderer.device.as_ref().unwrap().clone())
-            .then_execute(renderer.queue.as_ref().unwrap().clone(), command_buffer)
-            .unwrap()
-            .then_signal_fence_and_flush()
-            .unwrap();
+        self.buffers = Some(buffers);
+    }
 
-        future.wait(None).unwrap();
+    pub fn change_indices(&mut self, vec: Vec<u32>) {
+        self.indices = vec;
+        self.mark_outdated();
     }
-    
-    pub fn change_vertices(&mut self, renderer: &Renderer, vec: Vec<VertexData>) {
+
+    pub fn change_vertices(&mut self, vec: Vec<VertexData>) {
         self.vertices = vec;
-        let command_buffer_allocator = StandardCommandBufferAllocator::new(
-            renderer.device.as_ref().unwrap().clone(),
-            Default::default(),
-        );
+        self.mark_outdated();
+    }
 
-        let mut builder = AutoCommandBufferBuilder::primary(
-            &command_buffer_allocator,
-            renderer.queue.as_ref().unwrap().queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        ).unwrap();
-
-        let temp_buffer = Buffer::from_iter(
-            renderer.memeory_allocator.as_ref().unwrap().clone(),
-            BufferCreateInfo {
-                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_SRC,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE |
-                    MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-                ..Default::default()
-            },
-            self.vertices.to_owned(),
-        ).unwrap();
-
-        builder
-            .copy_buffer(
-                CopyBufferInfo::buffers(temp_buffer, self.vertex_buffer.as_ref().unwrap().to_owned())
-            )
-            .unwrap();
+    fn mark_outdated(&mut self) {
+        if let Some(buffers) = self.buffers.as_mut() {
+            buffers.outdated.iter_mut().for_each(|x| *x = true);
+        }
+    }
 
-        let command_buffer = builder.build().unwrap();
+    pub fn fits_buffers(&self) -> bool {
+        match self.buffers.as_ref() {
+            Some(buffers) => {
+                buffers.vertex[0].len() as usize >= self.vertices.len()
+                    && buffers.index[0].len() as usize >= self.indices.len()
+            }
+            None => true,
+        }
+    }
 
-        let future = now(renderer.device.as_ref().unwrap().clone())
-            .then_execute(renderer.queue.as_ref().unwrap().clone(), command_buffer)
-            .unwrap()
-            .then_signal_fence_and_flush()
-            .unwrap();
+    // Returns the number of bytes written, 0 if the frame's buffers were up to date.
+    pub fn upload(&mut self, frame: usize) -> usize {
+        let Some(buffers) = self.buffers.as_mut() else {
+            return 0;
+        };
+        if !buffers.outdated[frame] {
+            return 0;
+        }
 
-        // future.wait(None).unwrap();
+        buffers.vertex[frame].write().unwrap()[..self.vertices.len()].copy_from_slice(&self.vertices);
+        buffers.index[frame].write().unwrap()[..self.indices.len()].copy_from_slice(&self.indices);
+        buffers.outdated[frame] = false;
+        std::mem::size_of_val(self.vertices.as_slice()) + std::mem::size_of_val(self.indices.as_slice())
     }
 }
 
@@ -214,4 +231,117 @@ impl System for DynamicMeshLoader {
         }
     }
     fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_despawn(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State, entity_id: usize) {
+        let Some(mut meshes) = world.borrow_component_vec_mut::<DynamicMesh>() else {
+            return;
+        };
+        if let Some(mesh) = meshes[entity_id].as_mut() {
+            mesh.release(&mut state.renderer);
+        }
+    }
+
+    fn on_device_restored(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        // The old allocations belong to the previous device's arena.
+        for mesh in world.borrow_component_vec_mut::<DynamicMesh>().unwrap().iter_mut().flatten() {
+            mesh.buffers = None;
+        }
+        self.on_start(world, assets, state);
+    }
+}
+
+// Skins the mesh's positions on the CPU with the current joint matrices.
+fn skinned_positions(skinned_mesh: &SkinnedMesh, mesh: &Mesh) -> Vec<Vec3f> {
+    mesh.vertices
+        .iter()
+        .zip(skinned_mesh.weights.iter())
+        .map(|(vertex, weights)| {
+            let mut position = Vec3f::new([0.0; 3]);
+            for (joint, weight) in weights.joints.iter().zip(weights.weights) {
+                if let Some(matrix) = skinned_mesh.joint_matrices.get(*joint as usize).filter(|_| weight > 0.0) {
+                    position += matrix.transform_point(vertex.position) * weight;
+                }
+            }
+            position
+        })
+        .collect()
+}
+
+// Moves a hit from the entity's space into the ray's.
+fn to_world(hit: Hit, ray: &Ray, matrix: &Matrix4f, triangle: [Vec3f; 3]) -> Hit {
+    let [a, b, c] = triangle.map(|x| matrix.transform_point(x));
+    let mut edge = b - a;
+    let mut normal = edge.cross(c - a).normalize();
+    if normal.dot(ray.direction) > 0.0 {
+        normal *= -1.0;
+    }
+    Hit {
+        point: ray.at(hit.distance),
+        normal,
+        ..hit
+    }
+}
+
+// The closest visible static, dynamic or skinned mesh under the ray, against its triangles.
+// Independent of any physics, e.g. for picking and hitscan weapons. The ray is in world space, rays
+// from the camera's matrices are in render space, see `Renderer::render_space`.
+pub fn raycast(world: &World, assets: &AssetLibrary, ray: &Ray) -> Option<(usize, Hit)> {
+    let transforms = world.borrow_component_vec_mut::<Transform>()?;
+    let static_meshes = world.borrow_component_vec_mut::<StaticMesh>();
+    let dynamic_meshes = world.borrow_component_vec_mut::<DynamicMesh>();
+    let skinned_meshes = world.borrow_component_vec_mut::<SkinnedMesh>();
+    let visibilities = world.borrow_component_vec_mut::<Visibility>();
+
+    let mut closest: Option<(usize, Hit)> = None;
+    for (entity, transform) in transforms.iter().enumerate() {
+        let Some(transform) = transform.as_ref() else {
+            continue;
+        };
+        if visibilities.as_ref().and_then(|x| x.get(entity).copied().flatten()).is_some_and(|x| !x.visible) {
+            continue;
+        }
+        let matrix = transform.matrix();
+        let Some(inverse) = matrix.inverse() else {
+            continue;
+        };
+        // Not normalized, so distances along it stay in world units.
+        let origin = inverse.transform_point(ray.origin);
+        let local = Ray {
+            origin,
+            direction: inverse.transform_point(ray.origin + ray.direction) - origin,
+        };
+
+        let mut hits: Vec<(Hit, [Vec3f; 3])> = Vec::new();
+        let triangle = |indices: &[u32], hit: &Hit, position: &dyn Fn(u32) -> Vec3f| {
+            [0, 1, 2].map(|x| position(indices[hit.triangle * 3 + x]))
+        };
+        if let Some(static_mesh) = static_meshes.as_ref().and_then(|x| x.get(entity)?.as_ref()) {
+            if let Some(mesh) = assets.meshes.iter().find(|x| x.name == static_mesh.mesh_name) {
+                if let Some(hit) = mesh.raycast(&local) {
+                    hits.push((hit, triangle(&mesh.indices, &hit, &|x| mesh.vertices[x as usize].position)));
+                }
+            }
+        }
+        if let Some(dynamic_mesh) = dynamic_meshes.as_ref().and_then(|x| x.get(entity)?.as_ref()) {
+            if let Some(hit) = dynamic_mesh.raycast(&local) {
+                hits.push((hit, triangle(&dynamic_mesh.indices, &hit, &|x| dynamic_mesh.vertices[x as usize].position)));
+            }
+        }
+        if let Some(skinned_mesh) = skinned_meshes.as_ref().and_then(|x| x.get(entity)?.as_ref()) {
+            if let Some(mesh) = assets.meshes.iter().find(|x| x.name == skinned_mesh.mesh_name) {
+                let positions = skinned_positions(skinned_mesh, mesh);
+                let position = |x: u32| positions[x as usize];
+                if let Some(hit) = local.intersect_triangles(position, &mesh.indices, 0..mesh.indices.len() / 3, f32::INFINITY) {
+                    hits.push((hit, triangle(&mesh.indices, &hit, &position)));
+                }
+            }
+        }
+
+        for (hit, vertices) in hits {
+            if closest.is_none_or(|x| hit.distance < x.1.distance) {
+                closest = Some((entity, to_world(hit, ray, &matrix, vertices)));
+            }
+        }
+    }
+    closest
 }
@@ -0,0 +1,186 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+
+use crate::rendering::VertexData;
+
+pub const VERTEX_PAGE_LEN: u64 = 65536;
+pub const INDEX_PAGE_LEN: u64 = 262144;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArenaAllocation {
+    pub page: usize,
+    pub offset: u64,
+    pub len: u64,
+}
+
+#[derive(Clone, Debug)]
+struct ArenaPage<T> {
+    buffer: Subbuffer<[T]>,
+    free: Vec<Range<u64>>,
+    dedicated: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct SubbufferArena<T> {
+    usage: BufferUsage,
+    page_len: u64,
+    pages: Vec<Option<ArenaPage<T>>>,
+}
+
+impl<T> SubbufferArena<T>
+where
+    T: BufferContents,
+{
+    pub fn new(usage: BufferUsage, page_len: u64) -> SubbufferArena<T> {
+        SubbufferArena {
+            usage,
+            page_len,
+            pages: Vec::new(),
+        }
+    }
+
+    fn create_page(&mut self, allocator: &Arc<StandardMemoryAllocator>, len: u64, dedicated: bool) -> usize {
+        let page = ArenaPage {
+            buffer: Buffer::new_slice::<T>(
+                allocator.clone(),
+                BufferCreateInfo {
+                    usage: self.usage,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                len,
+            )
+            .unwrap(),
+            free: std::iter::once(0..len).collect(),
+            dedicated,
+        };
+        match self.pages.iter().position(|x| x.is_none()) {
+            Some(i) => {
+                self.pages[i] = Some(page);
+                i
+            }
+            None => {
+                self.pages.push(Some(page));
+                self.pages.len() - 1
+            }
+        }
+    }
+
+    pub fn allocate(&mut self, allocator: &Arc<StandardMemoryAllocator>, len: u64) -> (ArenaAllocation, Subbuffer<[T]>) {
+        let len = len.max(1);
+        let found = self.pages.iter().enumerate().find_map(|(page_i, page)| {
+            let page = page.as_ref().filter(|x| !x.dedicated)?;
+            let range_i = page.free.iter().position(|x| x.end - x.start >= len)?;
+            Some((page_i, range_i))
+        });
+
+        let (page_i, range_i) = match found {
+            Some(x) => x,
+            None if len > self.page_len / 4 => (self.create_page(allocator, len, true), 0),
+            None => (self.create_page(allocator, self.page_len, false), 0),
+        };
+
+        let page = self.pages[page_i].as_mut().unwrap();
+        let offset = page.free[range_i].start;
+        page.free[range_i].start += len;
+        if page.free[range_i].is_empty() {
+            page.free.remove(range_i);
+        }
+
+        let allocation = ArenaAllocation { page: page_i, offset, len };
+        (allocation, page.buffer.clone().slice(offset..offset + len))
+    }
+
+    pub fn free(&mut self, allocation: ArenaAllocation) {
+        let Some(page) = self.pages[allocation.page].as_mut() else {
+            return;
+        };
+        let range = allocation.offset..allocation.offset + allocation.len;
+        let i = page.free.partition_point(|x| x.start < range.start);
+        page.free.insert(i, range);
+        if i + 1 < page.free.len() && page.free[i].end == page.free[i + 1].start {
+            page.free[i].end = page.free.remove(i + 1).end;
+        }
+        if i > 0 && page.free[i - 1].end == page.free[i].start {
+            page.free[i - 1].end = page.free.remove(i).end;
+        }
+
+        let empty = page.free.len() == 1 && page.free[0] == (0..page.buffer.len());
+        if page.dedicated && empty {
+            self.pages[allocation.page] = None;
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.iter().flatten().count()
+    }
+
+    pub fn free_len(&self) -> u64 {
+        self.pages.iter().flatten().flat_map(|x| x.free.iter()).map(|x| x.end - x.start).sum()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MeshAllocation {
+    pub vertex: ArenaAllocation,
+    pub index: ArenaAllocation,
+}
+
+#[derive(Clone, Debug)]
+pub struct MeshArena {
+    pub vertex: SubbufferArena<VertexData>,
+    pub index: SubbufferArena<u32>,
+    frame: u64,
+    retired: Vec<(u64, MeshAllocation)>,
+}
+
+impl MeshArena {
+    pub fn new() -> MeshArena {
+        MeshArena {
+            vertex: SubbufferArena::new(BufferUsage::VERTEX_BUFFER, VERTEX_PAGE_LEN),
+            index: SubbufferArena::new(BufferUsage::INDEX_BUFFER, INDEX_PAGE_LEN),
+            frame: 0,
+            retired: Vec::new(),
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        allocator: &Arc<StandardMemoryAllocator>,
+        vertices: u64,
+        indices: u64,
+    ) -> (MeshAllocation, Subbuffer<[VertexData]>, Subbuffer<[u32]>) {
+        let (vertex, vertex_buffer) = self.vertex.allocate(allocator, vertices);
+        let (index, index_buffer) = self.index.allocate(allocator, indices);
+        (MeshAllocation { vertex, index }, vertex_buffer, index_buffer)
+    }
+
+    // Ranges can still be read by frames in flight, so they are only reused after `delay` frames.
+    pub fn retire(&mut self, allocation: MeshAllocation, delay: usize) {
+        self.retired.push((self.frame + delay as u64, allocation));
+    }
+
+    pub fn advance_frame(&mut self) {
+        self.frame += 1;
+        let frame = self.frame;
+        let (ready, pending): (Vec<_>, Vec<_>) = self.retired.drain(..).partition(|x| x.0 <= frame);
+        self.retired = pending;
+        for (_, allocation) in ready {
+            self.vertex.free(allocation.vertex);
+            self.index.free(allocation.index);
+        }
+    }
+}
+
+impl Default for MeshArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,221 @@
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::DrawIndexedIndirectCommand;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::graphics::rasterization::CullMode;
+
+use crate::asset_library::AssetLibrary;
+use crate::ecs::World;
+use crate::rendering::{Renderer, VertexData};
+use crate::state::State;
+
+use super::frustum::Frustum;
+use super::matrices::Matrix4f;
+use super::static_mesh::StaticMesh;
+use super::transform::Transform;
+use super::vectors::Vec3f;
+
+pub const MESHLET_MAX_VERTICES: usize = 64;
+pub const MESHLET_MAX_TRIANGLES: usize = 124;
+
+// A run of triangles in the mesh's index buffer, small enough to be culled on its own.
+#[derive(Clone, Copy, Debug)]
+pub struct Meshlet {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub center: Vec3f,
+    pub radius: f32,
+    /// Average triangle normal, every normal is within the cone around it.
+    pub cone_axis: Vec3f,
+    /// Sine of the cone's half angle, 1 when the triangles face too many ways to be back face
+    /// culled together.
+    pub cone_cutoff: f32,
+}
+
+// Splits the index buffer into consecutive meshlets, without reordering, so each one is a plain
+// index range. Relies on the usual locality of exported index buffers.
+pub fn build_meshlets(vertices: &[VertexData], indices: &[u32]) -> Vec<Meshlet> {
+    let mut meshlets = Vec::new();
+    let mut first_triangle = 0;
+    let mut unique: Vec<u32> = Vec::with_capacity(MESHLET_MAX_VERTICES);
+    let triangles = indices.len() / 3;
+    for triangle in 0..triangles {
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        let new_vertices = corners.iter().filter(|x| !unique.contains(x)).count();
+        if triangle - first_triangle == MESHLET_MAX_TRIANGLES || unique.len() + new_vertices > MESHLET_MAX_VERTICES {
+            meshlets.push(meshlet_bounds(vertices, indices, first_triangle, triangle));
+            first_triangle = triangle;
+            unique.clear();
+        }
+        for corner in corners {
+            if !unique.contains(corner) {
+                unique.push(*corner);
+            }
+        }
+    }
+    if first_triangle < triangles {
+        meshlets.push(meshlet_bounds(vertices, indices, first_triangle, triangles));
+    }
+    meshlets
+}
+
+fn meshlet_bounds(vertices: &[VertexData], indices: &[u32], first_triangle: usize, end_triangle: usize) -> Meshlet {
+    let range = &indices[first_triangle * 3..end_triangle * 3];
+    let position = |i: &u32| vertices[*i as usize].position;
+
+    let mut min = position(&range[0]);
+    let mut max = min;
+    for p in range.iter().map(position) {
+        min = Vec3f::new([min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)]);
+        max = Vec3f::new([max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)]);
+    }
+    let center = (min + max) * 0.5;
+    let radius = range.iter().map(|x| (position(x) - center).length()).fold(0.0, f32::max);
+
+    // Vertex normals rather than the winding, which flips with the front face setting. A triangle
+    // without a usable normal disables the cone.
+    let normals: Option<Vec<Vec3f>> = range
+        .chunks_exact(3)
+        .map(|x| {
+            let mut normal = x.iter().fold(Vec3f::new([0.0, 0.0, 0.0]), |a, i| a + vertices[*i as usize].normal);
+            (normal.length_sqr() > 0.0).then(|| normal.normalize())
+        })
+        .collect();
+    let normals = normals.unwrap_or_default();
+    let mut sum = normals.iter().fold(Vec3f::new([0.0, 0.0, 0.0]), |a, b| a + *b);
+    let (cone_axis, cone_cutoff) = if !normals.is_empty() && sum.length_sqr() > 0.0 {
+        let mut axis = sum.normalize();
+        let min_dot = normals.iter().map(|x| axis.dot(*x)).fold(1.0, f32::min);
+        let cutoff = if min_dot <= 0.0 { 1.0 } else { (1.0 - min_dot * min_dot).sqrt() };
+        (axis, cutoff)
+    } else {
+        (Vec3f::new([0.0, 0.0, 1.0]), 1.0)
+    };
+
+    Meshlet {
+        first_index: first_triangle as u32 * 3,
+        index_count: range.len() as u32,
+        center,
+        radius,
+        cone_axis,
+        cone_cutoff,
+    }
+}
+
+impl Meshlet {
+    // `model` is the render space model matrix with uniform `scale`, `eye` the camera in render
+    // space. The cone test only holds for back face culled materials.
+    pub fn is_visible(&self, model: &Matrix4f, scale: f32, frustum: &Frustum, eye: Vec3f, cone: bool) -> bool {
+        let center = model.transform_point(self.center);
+        let radius = self.radius * scale;
+        if !frustum.intersects_sphere(center, radius) {
+            return false;
+        }
+        if !cone || self.cone_cutoff >= 1.0 {
+            return true;
+        }
+        let mut axis = model.transform_point(self.center + self.cone_axis) - center;
+        let mut axis = axis.normalize();
+        let mut to_center = center - eye;
+        axis.dot(to_center) < self.cone_cutoff * to_center.length() + radius
+    }
+}
+
+pub(crate) fn indirect_buffer(renderer: &Renderer, count: usize) -> Subbuffer<[DrawIndexedIndirectCommand]> {
+    Buffer::new_slice(
+        renderer.memeory_allocator.as_ref().unwrap().clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::INDIRECT_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        count as u64,
+    )
+    .unwrap()
+}
+
+// Gives every static mesh entity with more than one meshlet an indirect buffer per frame in
+// flight, drawn instead of the whole index range. Runs before the command buffers are recorded.
+pub(crate) fn prepare_cluster_draws(world: &World, assets: &AssetLibrary, state: &mut State) {
+    let enabled = state.renderer.settings.cluster_culling && state.renderer.enabled_features.multi_draw_indirect;
+    let Some(static_meshes) = world.borrow_component_vec_mut::<StaticMesh>().filter(|_| enabled) else {
+        state.renderer.cluster_draws.clear();
+        return;
+    };
+
+    let mut draws = std::mem::take(&mut state.renderer.cluster_draws);
+    draws.retain(|entity, _| static_meshes.get(*entity).is_some_and(|x| x.is_some()));
+    for (entity, static_mesh) in static_meshes.iter().enumerate() {
+        let Some(static_mesh) = static_mesh else {
+            continue;
+        };
+        let Some(mesh) = assets.meshes.iter().find(|x| x.name == static_mesh.mesh_name) else {
+            continue;
+        };
+        if mesh.meshlets.len() < 2 {
+            draws.remove(&entity);
+            continue;
+        }
+        if draws.get(&entity).is_some_and(|x| x[0].len() == mesh.meshlets.len() as u64) {
+            continue;
+        }
+        let buffers = (0..state.renderer.frames_in_flight)
+            .map(|_| indirect_buffer(&state.renderer, mesh.meshlets.len()))
+            .collect();
+        draws.insert(entity, buffers);
+    }
+    state.renderer.cluster_draws = draws;
+}
+
+// Writes this frame's indirect commands, culled meshlets get zero indices and occluded entities
+// zero instances.
+pub(crate) fn cull_clusters(world: &World, assets: &AssetLibrary, state: &mut State) {
+    if state.renderer.cluster_draws.is_empty() {
+        return;
+    }
+    let (Some(static_meshes), Some(transforms)) = (
+        world.borrow_component_vec_mut::<StaticMesh>(),
+        world.borrow_component_vec_mut::<Transform>(),
+    ) else {
+        return;
+    };
+
+    let frame_i = state.renderer.current_frame;
+    let frustum = Frustum::from_matrix(state.renderer.vp_data.projection * state.renderer.vp_data.view);
+    let eye = state.renderer.render_space(state.renderer.vp_pos);
+    let mut culled = 0;
+    for (entity, buffers) in state.renderer.cluster_draws.iter() {
+        let (Some(Some(static_mesh)), Some(Some(transform))) = (static_meshes.get(*entity), transforms.get(*entity)) else {
+            continue;
+        };
+        let Some(mesh) = assets.meshes.iter().find(|x| x.name == static_mesh.mesh_name) else {
+            continue;
+        };
+        let back_face_culled = assets
+            .materials
+            .iter()
+            .find(|x| x.name == mesh.material)
+            .is_some_and(|x| x.render_state.cull_mode == CullMode::Back);
+        let model = Matrix4f::compose(state.renderer.render_space(transform.position), transform.rotation, transform.scale);
+        let scale = transform.scale.x.abs().max(transform.scale.y.abs()).max(transform.scale.z.abs());
+        // Non-uniform scale bends the normals, so the cones no longer bound them.
+        let uniform = transform.scale.x == transform.scale.y && transform.scale.y == transform.scale.z;
+        let occluded = state.renderer.occlusion_query_pools.is_some() && state.renderer.occluded.get(*entity).copied().unwrap_or(false);
+
+        let mut commands = buffers[frame_i].write().unwrap();
+        for (command, meshlet) in commands.iter_mut().zip(mesh.meshlets.iter()) {
+            let visible = meshlet.is_visible(&model, scale, &frustum, eye, back_face_culled && uniform);
+            culled += !visible as usize;
+            *command = DrawIndexedIndirectCommand {
+                index_count: if visible { meshlet.index_count } else { 0 },
+                instance_count: !occluded as u32,
+                first_index: meshlet.first_index,
+                vertex_offset: 0,
+                first_instance: 0,
+            };
+        }
+    }
+    state.renderer.stats.culled_meshlets = culled;
+}
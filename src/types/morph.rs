@@ -0,0 +1,186 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+
+use crate::asset_library::AssetLibrary;
+use crate::debug_labels;
+use crate::ecs::World;
+use crate::rendering::Renderer;
+use crate::state::State;
+
+use super::mesh::Mesh;
+use super::vectors::Vec3f;
+
+#[derive(Clone, Debug)]
+pub struct MorphTarget {
+    pub name: String,
+    pub position_deltas: Vec<Vec3f>,
+    /// Empty if the target only moves positions.
+    pub normal_deltas: Vec<Vec3f>,
+    pub default_weight: f32,
+}
+
+impl MorphTarget {
+    pub fn new(name: &str, position_deltas: Vec<Vec3f>, normal_deltas: Vec<Vec3f>) -> MorphTarget {
+        MorphTarget {
+            name: name.to_string(),
+            position_deltas,
+            normal_deltas,
+            default_weight: 0.0,
+        }
+    }
+}
+
+// vec4s so the std430 layout matches without padding tricks in the shader.
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Default)]
+#[repr(C)]
+pub struct MorphDelta {
+    pub position: [f32; 4],
+    pub normal: [f32; 4],
+}
+
+// Deltas are stored target-major: delta of `vertex` in `target` is at
+// `target * vertex_count + vertex`.
+pub(crate) fn create_delta_buffer(renderer: &Renderer, mesh: &Mesh) -> Subbuffer<[MorphDelta]> {
+    let vertex_count = mesh.vertices.len();
+    let deltas = mesh.morph_targets.iter().flat_map(|target| {
+        assert_eq!(target.position_deltas.len(), vertex_count, "morph target {} of mesh {} needs one delta per vertex", target.name, mesh.name);
+        (0..vertex_count).map(move |i| {
+            let position = target.position_deltas[i];
+            let normal = target.normal_deltas.get(i).copied().unwrap_or(Vec3f::new([0.0; 3]));
+            MorphDelta {
+                position: [position.x, position.y, position.z, 0.0],
+                normal: [normal.x, normal.y, normal.z, 0.0],
+            }
+        })
+    });
+
+    let buffer = Buffer::from_iter(
+        renderer.memeory_allocator.as_ref().unwrap().clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        deltas.collect::<Vec<_>>(),
+    )
+    .unwrap();
+    debug_labels::name_object(renderer, buffer.buffer().as_ref(), &format!("mesh {} morph deltas", mesh.name));
+    buffer
+}
+
+#[derive(Clone, Debug)]
+pub struct MorphBuffers {
+    pub deltas: Subbuffer<[MorphDelta]>,
+    pub weights: Vec<Subbuffer<[f32]>>,
+}
+
+// Blend shape weights for `mesh_name`, which must be the mesh drawn by the same entity. Weights
+// start at the mesh's defaults and can be driven by an `Animator` through morph channels.
+#[derive(Clone, Debug)]
+pub struct MorphWeights {
+    pub mesh_name: String,
+    pub weights: Vec<f32>,
+    pub buffers: Option<MorphBuffers>,
+}
+
+impl MorphWeights {
+    pub fn new(mesh_name: &str) -> MorphWeights {
+        MorphWeights {
+            mesh_name: mesh_name.to_string(),
+            weights: Vec::new(),
+            buffers: None,
+        }
+    }
+
+    pub fn with_weights(mut self, weights: Vec<f32>) -> MorphWeights {
+        self.weights = weights;
+        self
+    }
+
+    pub fn set(&mut self, target: usize, weight: f32) {
+        if let Some(x) = self.weights.get_mut(target) {
+            *x = weight;
+        }
+    }
+
+    pub fn set_named(&mut self, assets: &AssetLibrary, target: &str, weight: f32) {
+        let mesh = assets.meshes.iter().find(|x| x.name == self.mesh_name).unwrap();
+        match mesh.morph_targets.iter().position(|x| x.name == target) {
+            Some(i) => self.set(i, weight),
+            None => log::warn!("mesh {} has no morph target {}", self.mesh_name, target),
+        }
+    }
+
+    pub fn load(&mut self, renderer: &Renderer, mesh: &Mesh) {
+        let deltas = mesh
+            .morph_buffer
+            .as_ref()
+            .unwrap_or_else(|| panic!("mesh {} has no morph targets", mesh.name))
+            .clone();
+        let defaults = mesh.morph_targets.iter().map(|x| x.default_weight);
+        self.weights = self.weights.iter().copied().chain(defaults.skip(self.weights.len())).take(mesh.morph_targets.len()).collect();
+
+        let weights = (0..renderer.frames_in_flight.max(1))
+            .map(|_| {
+                Buffer::from_iter(
+                    renderer.memeory_allocator.as_ref().unwrap().clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                        ..Default::default()
+                    },
+                    self.weights.clone(),
+                )
+                .unwrap()
+            })
+            .collect();
+        self.buffers = Some(MorphBuffers { deltas, weights });
+    }
+
+    pub fn write_weights(&self, frame: usize) {
+        let Some(buffers) = self.buffers.as_ref() else {
+            return;
+        };
+        let mut weights = buffers.weights[frame].write().unwrap();
+        let len = weights.len().min(self.weights.len());
+        weights[..len].copy_from_slice(&self.weights[..len]);
+    }
+}
+
+pub fn prepare_morph_weights(world: &World, assets: &AssetLibrary, state: &mut State) {
+    let Some(mut morph_weights) = world.borrow_component_vec_mut::<MorphWeights>() else {
+        return;
+    };
+
+    for morph_weights in morph_weights.iter_mut().flatten() {
+        let mesh = assets.meshes.iter().find(|x| x.name == morph_weights.mesh_name).unwrap();
+        // Also catches the mesh being reloaded with new deltas.
+        let outdated = match (morph_weights.buffers.as_ref(), mesh.morph_buffer.as_ref()) {
+            (Some(buffers), Some(deltas)) => !Arc::ptr_eq(buffers.deltas.buffer(), deltas.buffer()),
+            _ => true,
+        };
+        if outdated {
+            morph_weights.load(&state.renderer, mesh);
+            state.renderer.command_buffer_outdated = true;
+        }
+        morph_weights.write_weights(state.renderer.current_frame);
+    }
+}
+
+// Buffers were created on the previous device.
+pub fn release_morph_weights(world: &World) {
+    if let Some(mut morph_weights) = world.borrow_component_vec_mut::<MorphWeights>() {
+        for morph_weights in morph_weights.iter_mut().flatten() {
+            morph_weights.buffers = None;
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use std::f32::consts::TAU;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ecs::PersistentResource, state::State};
+
+use super::vectors::{Vec2f, Vec3f};
+
+// SplitMix64, fast and fine for gameplay and generation, not for anything secret. The same seed
+// always gives the same sequence on every platform.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { seed, state: seed }
+    }
+
+    // Seeded from the clock, read the seed back with `seed` to reproduce a run.
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.as_nanos() as u64);
+        Rng::new(nanos)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        *self = Rng::new(seed);
+    }
+
+    // A generator with its own sequence, so one system drawing more numbers does not change what
+    // another gets.
+    pub fn fork(&mut self) -> Rng {
+        Rng::new(self.next_u64())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    // In [0, 1).
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // In [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // In [min, max).
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    // In [min, max), `min` when the range is empty.
+    pub fn range_i32(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+        min + self.index((max as i64 - min as i64) as usize) as i32
+    }
+
+    // In [0, len), 0 when `len` is 0.
+    pub fn index(&mut self, len: usize) -> usize {
+        if len == 0 {
+            return 0;
+        }
+        // Multiply and shift rather than modulo, which favours low values.
+        ((self.next_u32() as u64 * len as u64) >> 32) as usize
+    }
+
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        items.get(self.index(items.len()))
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.index(i + 1));
+        }
+    }
+
+    pub fn unit_vec2(&mut self) -> Vec2f {
+        let angle = self.next_f32() * TAU;
+        Vec2f::new([angle.cos(), angle.sin()])
+    }
+
+    pub fn unit_vec3(&mut self) -> Vec3f {
+        let z = self.range_f32(-1.0, 1.0);
+        let angle = self.next_f32() * TAU;
+        let r = (1.0 - z * z).sqrt();
+        Vec3f::new([r * angle.cos(), r * angle.sin(), z])
+    }
+}
+
+impl PersistentResource for Rng {
+    fn get(state: &State) -> &Rng {
+        &state.rng
+    }
+
+    fn get_mut(state: &mut State) -> &mut Rng {
+        &mut state.rng
+    }
+}
+
+// Edge midpoints of a cube, gradients for simplex noise and for 3D Perlin noise.
+const GRADIENTS_3: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+const GRADIENTS_2: [[f32; 2]; 8] =
+    [[1.0, 1.0], [-1.0, 1.0], [1.0, -1.0], [-1.0, -1.0], [1.0, 0.0], [-1.0, 0.0], [0.0, 1.0], [0.0, -1.0]];
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Lattice cell and the position inside it.
+fn cell(x: f32) -> (usize, f32) {
+    let floor = x.floor();
+    ((floor as i32 & 255) as usize, x - floor)
+}
+
+// Gradient noise, every function returns roughly -1 to 1 and 0 at integer coordinates for Perlin.
+// The seed only shuffles the lattice, so the same seed gives the same noise everywhere.
+#[derive(Clone, Debug)]
+pub struct Noise {
+    /// A shuffle of 0 to 255, twice, so lookups can add offsets without wrapping.
+    permutation: Vec<u8>,
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Noise {
+        let mut permutation: Vec<u8> = (0..=255).collect();
+        Rng::new(seed).shuffle(&mut permutation);
+        permutation.extend_from_within(..);
+        Noise { permutation }
+    }
+
+    fn hash(&self, i: usize) -> usize {
+        self.permutation[i] as usize
+    }
+
+    pub fn perlin2(&self, x: f32, y: f32) -> f32 {
+        let ((xi, x), (yi, y)) = (cell(x), cell(y));
+        let gradient = |hash: usize, x: f32, y: f32| {
+            let [gx, gy] = GRADIENTS_2[hash & 7];
+            gx * x + gy * y
+        };
+        let (a, b) = (self.hash(xi) + yi, self.hash(xi + 1) + yi);
+        let (u, v) = (fade(x), fade(y));
+        lerp(
+            lerp(gradient(self.hash(a), x, y), gradient(self.hash(b), x - 1.0, y), u),
+            lerp(gradient(self.hash(a + 1), x, y - 1.0), gradient(self.hash(b + 1), x - 1.0, y - 1.0), u),
+            v,
+        )
+    }
+
+    pub fn perlin3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let ((xi, x), (yi, y), (zi, z)) = (cell(x), cell(y), cell(z));
+        let gradient = |hash: usize, x: f32, y: f32, z: f32| {
+            let [gx, gy, gz] = GRADIENTS_3[hash % 12];
+            gx * x + gy * y + gz * z
+        };
+        let (a, b) = (self.hash(xi) + yi, self.hash(xi + 1) + yi);
+        let (aa, ab, ba, bb) = (self.hash(a) + zi, self.hash(a + 1) + zi, self.hash(b) + zi, self.hash(b + 1) + zi);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let near = lerp(
+            lerp(gradient(self.hash(aa), x, y, z), gradient(self.hash(ba), x - 1.0, y, z), u),
+            lerp(gradient(self.hash(ab), x, y - 1.0, z), gradient(self.hash(bb), x - 1.0, y - 1.0, z), u),
+            v,
+        );
+        let far = lerp(
+            lerp(gradient(self.hash(aa + 1), x, y, z - 1.0), gradient(self.hash(ba + 1), x - 1.0, y, z - 1.0), u),
+            lerp(
+                gradient(self.hash(ab + 1), x, y - 1.0, z - 1.0),
+                gradient(self.hash(bb + 1), x - 1.0, y - 1.0, z - 1.0),
+                u,
+            ),
+            v,
+        );
+        lerp(near, far, w)
+    }
+
+    // Fewer directional artifacts than Perlin noise and cheaper in 3D.
+    pub fn simplex2(&self, x: f32, y: f32) -> f32 {
+        const F2: f32 = 0.366_025_42; // (sqrt(3) - 1) / 2
+        const G2: f32 = 0.211_324_87; // (3 - sqrt(3)) / 6
+        let s = (x + y) * F2;
+        let (i, j) = ((x + s).floor(), (y + s).floor());
+        let t = (i + j) * G2;
+        let (x0, y0) = (x - (i - t), y - (j - t));
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let corners = [
+            (0, 0, x0, y0),
+            (i1, j1, x0 - i1 as f32 + G2, y0 - j1 as f32 + G2),
+            (1, 1, x0 - 1.0 + 2.0 * G2, y0 - 1.0 + 2.0 * G2),
+        ];
+        let (ii, jj) = ((i as i32 & 255) as usize, (j as i32 & 255) as usize);
+        let sum: f32 = corners
+            .into_iter()
+            .map(|(di, dj, x, y)| {
+                let t = 0.5 - x * x - y * y;
+                if t < 0.0 {
+                    return 0.0;
+                }
+                let [gx, gy, _] = GRADIENTS_3[self.hash(ii + di + self.hash(jj + dj)) % 12];
+                t * t * t * t * (gx * x + gy * y)
+            })
+            .sum();
+        70.0 * sum
+    }
+
+    pub fn simplex3(&self, x: f32, y: f32, z: f32) -> f32 {
+        const F3: f32 = 1.0 / 3.0;
+        const G3: f32 = 1.0 / 6.0;
+        let s = (x + y + z) * F3;
+        let (i, j, k) = ((x + s).floor(), (y + s).floor(), (z + s).floor());
+        let t = (i + j + k) * G3;
+        let (x0, y0, z0) = (x - (i - t), y - (j - t), z - (k - t));
+        // Which of the six tetrahedra of the skewed cube the point is in.
+        let (first, second) = if x0 >= y0 {
+            if y0 >= z0 {
+                ([1, 0, 0], [1, 1, 0])
+            } else if x0 >= z0 {
+                ([1, 0, 0], [1, 0, 1])
+            } else {
+                ([0, 0, 1], [1, 0, 1])
+            }
+        } else if y0 < z0 {
+            ([0, 0, 1], [0, 1, 1])
+        } else if x0 < z0 {
+            ([0, 1, 0], [0, 1, 1])
+        } else {
+            ([0, 1, 0], [1, 1, 0])
+        };
+        let (ii, jj, kk) = ((i as i32 & 255) as usize, (j as i32 & 255) as usize, (k as i32 & 255) as usize);
+        let sum: f32 = [([0, 0, 0], 0.0), (first, G3), (second, 2.0 * G3), ([1, 1, 1], 3.0 * G3)]
+            .into_iter()
+            .map(|([di, dj, dk], offset)| {
+                let (x, y, z) = (x0 - di as f32 + offset, y0 - dj as f32 + offset, z0 - dk as f32 + offset);
+                let t = 0.6 - x * x - y * y - z * z;
+                if t < 0.0 {
+                    return 0.0;
+                }
+                let [gx, gy, gz] = GRADIENTS_3[self.hash(ii + di + self.hash(jj + dj + self.hash(kk + dk))) % 12];
+                t * t * t * t * (gx * x + gy * y + gz * z)
+            })
+            .sum();
+        32.0 * sum
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum NoiseKind {
+    #[default]
+    Perlin,
+    Simplex,
+}
+
+// Fractal Brownian motion, octaves of noise at rising frequency and falling amplitude. Samples are
+// divided by the total amplitude so they stay roughly within -1 to 1.
+#[derive(Clone, Debug)]
+pub struct Fbm {
+    pub noise: Noise,
+    pub kind: NoiseKind,
+    pub octaves: u32,
+    /// Of the first octave, in cycles per unit.
+    pub frequency: f32,
+    /// Frequency multiplier between octaves.
+    pub lacunarity: f32,
+    /// Amplitude multiplier between octaves.
+    pub gain: f32,
+}
+
+impl Fbm {
+    pub fn new(noise: Noise) -> Fbm {
+        Fbm {
+            noise,
+            kind: NoiseKind::Perlin,
+            octaves: 5,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    pub fn with_kind(mut self, kind: NoiseKind) -> Fbm {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_octaves(mut self, octaves: u32) -> Fbm {
+        self.octaves = octaves.max(1);
+        self
+    }
+
+    pub fn with_frequency(mut self, frequency: f32) -> Fbm {
+        self.frequency = frequency;
+        self
+    }
+
+    pub fn with_lacunarity(mut self, lacunarity: f32) -> Fbm {
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    pub fn with_gain(mut self, gain: f32) -> Fbm {
+        self.gain = gain;
+        self
+    }
+
+    fn octaves(&self, sample: impl Fn(f32) -> f32) -> f32 {
+        let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, self.frequency);
+        for _ in 0..self.octaves {
+            sum += sample(frequency) * amplitude;
+            total += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+
+    pub fn sample2(&self, x: f32, y: f32) -> f32 {
+        self.octaves(|f| match self.kind {
+            NoiseKind::Perlin => self.noise.perlin2(x * f, y * f),
+            NoiseKind::Simplex => self.noise.simplex2(x * f, y * f),
+        })
+    }
+
+    pub fn sample3(&self, x: f32, y: f32, z: f32) -> f32 {
+        self.octaves(|f| match self.kind {
+            NoiseKind::Perlin => self.noise.perlin3(x * f, y * f, z * f),
+            NoiseKind::Simplex => self.noise.simplex3(x * f, y * f, z * f),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(noise: &Noise) -> Vec<f32> {
+        let mut samples = Vec::new();
+        for i in 0..4000 {
+            let (x, y, z) = (i as f32 * 0.173 - 300.0, i as f32 * 0.311 % 97.0, i as f32 * 0.057);
+            samples.extend([noise.perlin2(x, y), noise.perlin3(x, y, z), noise.simplex2(x, y), noise.simplex3(x, y, z)]);
+        }
+        samples
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        for seed in 0..4 {
+            let noise = Noise::new(seed);
+            let max = samples(&noise).into_iter().fold(0.0, |a: f32, b| a.max(b.abs()));
+            assert!(max <= 1.05, "seed {seed} reached {max}");
+            assert!(max > 0.3, "seed {seed} only reached {max}");
+            let fbm = Fbm::new(noise).with_kind(NoiseKind::Simplex);
+            assert!((0..1000).all(|i| fbm.sample2(i as f32 * 0.37, i as f32 * 0.11).abs() <= 1.05));
+        }
+    }
+
+    #[test]
+    fn noise_is_deterministic_per_seed() {
+        assert_eq!(samples(&Noise::new(42)), samples(&Noise::new(42)));
+        assert_ne!(samples(&Noise::new(42)), samples(&Noise::new(43)));
+
+        let (mut a, mut b) = (Rng::new(7), Rng::new(7));
+        assert!((0..100).all(|_| a.next_u64() == b.next_u64()));
+    }
+}
@@ -0,0 +1,25 @@
+use super::vectors::Vec3f;
+
+// The mirror plane passes through the entity's position. `texture` names the generated texture
+// the reflection is rendered into, which materials can use like any other texture.
+#[derive(Clone, Debug)]
+pub struct PlanarReflection {
+    pub texture: String,
+    pub normal: Vec3f,
+    pub clip_offset: f32,
+}
+
+impl PlanarReflection {
+    pub fn new(texture: &str) -> PlanarReflection {
+        PlanarReflection {
+            texture: texture.to_string(),
+            normal: Vec3f::new([0.0, 1.0, 0.0]),
+            clip_offset: 0.05,
+        }
+    }
+
+    pub fn with_normal(mut self, normal: Vec3f) -> PlanarReflection {
+        self.normal = normal;
+        self
+    }
+}
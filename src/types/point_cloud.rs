@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+
+use crate::asset_library::AssetLibrary;
+use crate::ecs::World;
+use crate::rendering::{frame_descriptor_writes, pipeline_for_key, PipelineVariant};
+use crate::state::State;
+
+use super::buffers::UpdatableBuffer;
+use super::color::Color;
+use super::transform::Transform;
+use super::vectors::Vec3f;
+
+#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[repr(C)]
+pub struct PointData {
+    pub position: Vec3f,
+    pub size: f32,
+    pub color: Color,
+}
+
+impl PointData {
+    pub fn new(position: Vec3f, color: Color) -> PointData {
+        PointData { position, size: 1.0, color }
+    }
+}
+
+#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[repr(C)]
+pub struct PointCloudData {
+    pub point_size: f32,
+    pub attenuation_distance: f32,
+    pub min_size: f32,
+    pub max_size: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct PointCloud {
+    pub material: String,
+    pub point_size: f32,
+    pub attenuation_distance: f32,
+    pub min_size: f32,
+    pub max_size: f32,
+    points: Vec<PointData>,
+    buffer: Option<Subbuffer<[PointData]>>,
+    params: Option<UpdatableBuffer<PointCloudData>>,
+}
+
+impl PointCloud {
+    pub fn new(points: Vec<PointData>, material: &str) -> PointCloud {
+        PointCloud {
+            material: material.to_string(),
+            point_size: 4.0,
+            attenuation_distance: 10.0,
+            min_size: 1.0,
+            max_size: 64.0,
+            points,
+            buffer: None,
+            params: None,
+        }
+    }
+
+    pub fn points(&self) -> &[PointData] {
+        &self.points
+    }
+
+    pub fn set_points(&mut self, points: Vec<PointData>) {
+        self.points = points;
+        self.buffer = None;
+    }
+
+    pub(crate) fn buffers(&self) -> Vec<Arc<Buffer>> {
+        self.buffer
+            .iter()
+            .map(|x| x.buffer().clone())
+            .chain(self.params.iter().flat_map(|x| x.buffers.iter().map(|x| x.buffer().clone())))
+            .collect()
+    }
+
+    pub fn to_data(&self) -> PointCloudData {
+        PointCloudData {
+            point_size: self.point_size,
+            attenuation_distance: self.attenuation_distance,
+            min_size: self.min_size,
+            max_size: self.max_size,
+        }
+    }
+}
+
+pub fn prepare_point_clouds(world: &World, state: &mut State) {
+    let Some(mut point_clouds) = world.borrow_component_vec_mut::<PointCloud>() else {
+        return;
+    };
+
+    for point_cloud in point_clouds.iter_mut().flatten() {
+        if point_cloud.params.is_none() {
+            point_cloud.params = Some(UpdatableBuffer::new(&state.renderer, BufferUsage::UNIFORM_BUFFER));
+        }
+        point_cloud.params.as_ref().unwrap().write(state, point_cloud.to_data());
+
+        if point_cloud.buffer.is_none() && !point_cloud.points.is_empty() {
+            point_cloud.buffer = Some(
+                Buffer::from_iter(
+                    state.renderer.memeory_allocator.as_ref().unwrap().clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                            | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                        ..Default::default()
+                    },
+                    point_cloud.points.iter().copied(),
+                )
+                .unwrap(),
+            );
+            state.renderer.command_buffer_outdated = true;
+        }
+    }
+}
+
+pub fn prepare_pipelines(world: &World, assets: &AssetLibrary, state: &mut State) {
+    let Some(point_clouds) = world.borrow_component_vec_mut::<PointCloud>() else {
+        return;
+    };
+
+    for point_cloud in point_clouds.iter().flatten() {
+        let material = assets.materials.iter().find(|x| x.name == point_cloud.material).unwrap();
+        let key = material.pipeline_key();
+        if !state.renderer.point_pipelines.contains_key(&key) {
+            let pipeline = pipeline_for_key(state, assets, &key, PipelineVariant::Points);
+            state.renderer.point_pipelines.insert(key, pipeline);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn record(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    world: &World,
+    assets: &AssetLibrary,
+    state: &State,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    frame_i: usize,
+    transforms: &[Option<Transform>],
+    is_visible: &dyn Fn(usize) -> bool,
+) -> usize {
+    let Some(point_clouds) = world.borrow_component_vec_mut::<PointCloud>() else {
+        return 0;
+    };
+
+    let mut draw_calls = 0;
+    for (entity, point_cloud) in point_clouds.iter().enumerate() {
+        let Some(point_cloud) = point_cloud.as_ref() else {
+            continue;
+        };
+        let (Some(transform), Some(buffer)) = (transforms.get(entity).and_then(|x| x.as_ref()), point_cloud.buffer.as_ref()) else {
+            continue;
+        };
+        if !is_visible(entity) {
+            continue;
+        }
+
+        let material = assets.materials.iter().find(|x| x.name == point_cloud.material).unwrap();
+        let pipeline: Arc<GraphicsPipeline> = state.renderer.point_pipelines.get(&material.pipeline_key()).unwrap().clone();
+        let layouts = pipeline.layout().set_layouts();
+
+        let vp_set = PersistentDescriptorSet::new(
+            descriptor_set_allocator,
+            layouts[0].clone(),
+            frame_descriptor_writes(state, &layouts[0], frame_i),
+            [],
+        )
+        .unwrap();
+
+        let mut writes = vec![
+            WriteDescriptorSet::buffer(0, transform.buffer.as_ref().unwrap().buffer(frame_i)),
+            WriteDescriptorSet::buffer(1, buffer.clone()),
+        ];
+        if layouts[1].bindings().contains_key(&2) {
+            writes.push(WriteDescriptorSet::buffer(2, point_cloud.params.as_ref().unwrap().buffer(frame_i)));
+        }
+        let m_set = PersistentDescriptorSet::new(descriptor_set_allocator, layouts[1].clone(), writes, []).unwrap();
+
+        builder
+            .bind_pipeline_graphics(pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, (vp_set, m_set))
+            .unwrap()
+            .draw(buffer.len() as u32, 1, 0, 0)
+            .unwrap();
+        draw_calls += 1;
+    }
+    draw_calls
+}
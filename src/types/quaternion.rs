@@ -0,0 +1,65 @@
+use std::ops::Mul;
+
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+use super::vectors::Vec3f;
+
+// Rotation as a unit quaternion, `w` is the scalar part. Same handedness as the `Matrix4f`
+// rotations: positive angles turn counter-clockwise looking down the axis towards the origin.
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize)]
+#[repr(C)]
+pub struct Quaternion {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Mul for Quaternion {
+    type Output = Self;
+
+    // Applies `rhs` first, like matrix multiplication.
+    fn mul(self, rhs: Self) -> Self::Output {
+        Quaternion {
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        }
+    }
+}
+
+impl Quaternion {
+    pub fn new(val: [f32; 4]) -> Quaternion {
+        Quaternion {
+            x: val[0],
+            y: val[1],
+            z: val[2],
+            w: val[3],
+        }
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion::new([0.0, 0.0, 0.0, 1.0])
+    }
+
+    pub fn from_axis_angle(mut axis: Vec3f, angle: f32) -> Quaternion {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        Quaternion::new([axis.x * sin, axis.y * sin, axis.z * sin, cos])
+    }
+
+    pub fn length(&self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt()
+    }
+
+    pub fn normalize(&self) -> Quaternion {
+        let len = self.length();
+        Quaternion::new([self.x / len, self.y / len, self.z / len, self.w / len])
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion::new([-self.x, -self.y, -self.z, self.w])
+    }
+}
@@ -0,0 +1,146 @@
+use super::{matrices::Matrix4f, vectors::*};
+
+// Where a ray hit a mesh, in the space the ray was given in.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub distance: f32,
+    pub point: Vec3f,
+    /// Of the triangle, facing the ray.
+    pub normal: Vec3f,
+    pub triangle: usize,
+    /// Weights of the triangle's second and third vertex at the hit point.
+    pub barycentric: Vec2f,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Vec3f,
+    pub direction: Vec3f,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3f, mut direction: Vec3f) -> Ray {
+        Ray {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn from_screen(cursor: Vec2f, screen_size: Vec2f, view: Matrix4f, projection: Matrix4f) -> Option<Ray> {
+        let inverse = (projection * view).inverse()?;
+        let x = 2.0 * cursor.x / screen_size.x - 1.0;
+        let y = 2.0 * cursor.y / screen_size.y - 1.0;
+        // Reversed-Z has the near plane at 1, 0.5 keeps the second point finite for an infinite far plane.
+        let (near_z, far_z) = if projection.is_reversed_z() { (1.0, 0.5) } else { (-1.0, 1.0) };
+        let near = inverse.transform_point(Vec3f::new([x, y, near_z]));
+        let far = inverse.transform_point(Vec3f::new([x, y, far_z]));
+        Some(Ray::new(near, far - near))
+    }
+
+    pub fn at(&self, t: f32) -> Vec3f {
+        self.origin + self.direction * t
+    }
+
+    pub fn intersect_plane(&self, point: Vec3f, mut normal: Vec3f) -> Option<f32> {
+        let denominator = normal.dot(self.direction);
+        if denominator.abs() < f32::EPSILON {
+            return None;
+        }
+        let t = normal.dot(point - self.origin) / denominator;
+        (t >= 0.0).then_some(t)
+    }
+
+    pub fn closest_to_line(&self, point: Vec3f, line_direction: Vec3f) -> Option<(f32, f32)> {
+        let mut d1 = self.direction;
+        let mut d2 = line_direction;
+        let r = self.origin - point;
+        let b = d1.dot(d2);
+        let c = d1.dot(r);
+        let e = d2.dot(d2);
+        let f = d2.dot(r);
+        let denominator = e - b * b;
+        if denominator.abs() < f32::EPSILON {
+            return None;
+        }
+        let t = (b * f - c * e) / denominator;
+        let s = (f - b * c) / denominator;
+        Some((t, s))
+    }
+
+    // Both sides count, returns the distance and the weights of `b` and `c`.
+    pub fn intersect_triangle(&self, a: Vec3f, b: Vec3f, c: Vec3f) -> Option<(f32, Vec2f)> {
+        let mut edge1 = b - a;
+        let edge2 = c - a;
+        let mut direction = self.direction;
+        let p = direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+        let mut s = self.origin - a;
+        let u = s.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let mut q = s.cross(edge1);
+        let v = q.dot(self.direction) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = q.dot(edge2) * inverse;
+        (t >= 0.0).then_some((t, Vec2f::new([u, v])))
+    }
+
+    // Distance to where the ray enters the box, 0 if it starts inside.
+    pub fn intersect_aabb(&self, min: Vec3f, max: Vec3f) -> Option<f32> {
+        let origin = [self.origin.x, self.origin.y, self.origin.z];
+        let direction = [self.direction.x, self.direction.y, self.direction.z];
+        let (min, max) = ([min.x, min.y, min.z], [max.x, max.y, max.z]);
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        for axis in 0..3 {
+            let inverse = 1.0 / direction[axis];
+            let t0 = (min[axis] - origin[axis]) * inverse;
+            let t1 = (max[axis] - origin[axis]) * inverse;
+            // NaN from a zero direction inside the slab leaves the bounds unchanged.
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        (near <= far).then_some(near)
+    }
+
+    // The closest hit over indexed triangles, `triangles` picks which ones are tested and
+    // `position` looks up a vertex.
+    pub fn intersect_triangles(
+        &self,
+        position: impl Fn(u32) -> Vec3f,
+        indices: &[u32],
+        triangles: impl IntoIterator<Item = usize>,
+        max_distance: f32,
+    ) -> Option<Hit> {
+        let mut closest: Option<Hit> = None;
+        for triangle in triangles {
+            let [a, b, c] = [0, 1, 2].map(|x| position(indices[triangle * 3 + x]));
+            let Some((distance, barycentric)) = self.intersect_triangle(a, b, c) else {
+                continue;
+            };
+            if distance > closest.map_or(max_distance, |x| x.distance) {
+                continue;
+            }
+            let mut edge = b - a;
+            let mut normal = edge.cross(c - a).normalize();
+            if normal.dot(self.direction) > 0.0 {
+                normal *= -1.0;
+            }
+            closest = Some(Hit {
+                distance,
+                point: self.at(distance),
+                normal,
+                triangle,
+                barycentric,
+            });
+        }
+        closest
+    }
+}
@@ -1,12 +1,13 @@
 use std::sync::Arc;
 
 use vulkano::shader::{ShaderModule, ShaderModuleCreateInfo};
-use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::{get_pipeline, Renderer}, state::State, utility::read_file_to_words};
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::{prepare_pipelines, Renderer}, state::State, utility::read_file_to_words};
 
 #[derive(Debug)]
 pub enum ShaderType {
     Fragment,
     Vertex,
+    Compute,
 }
 
 #[derive(Debug)]
@@ -35,6 +36,15 @@ impl Shader {
             module: None
         }
     }
+
+    pub fn from_words(name: &str, shader_type: ShaderType, source: Vec<u32>) -> Shader {
+        Shader {
+            name: name.to_string(),
+            shader_type,
+            source,
+            module: None
+        }
+    }
 }
 
 pub struct ShaderLoader {}
@@ -45,19 +55,11 @@ impl System for ShaderLoader {
             shader.load(&mut state.renderer);
         }
 
-        let fragment_shaders = assets.shaders.iter()
-            .filter(|x| matches!(x.shader_type, ShaderType::Fragment));
-        let vertex_shaders = assets.shaders.iter()
-            .filter(|x| matches!(x.shader_type, ShaderType::Vertex));
-        
-        for frag in fragment_shaders {
-            for vert in vertex_shaders.clone() {
-                state.renderer.pipelines.insert(
-                    (vert.name.clone(), frag.name.clone()),
-                    get_pipeline(state, vert, frag)
-                );
-            }
-        }
+        prepare_pipelines(assets, state);
     }
     fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_device_restored(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        self.on_start(world, assets, state);
+    }
 }
@@ -0,0 +1,219 @@
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use vulkano::shader::spirv::bytes_to_words;
+
+use crate::asset_library::AssetLibrary;
+use crate::builtin_shaders;
+
+use super::material::{Attachment, Material};
+use super::shader::{Shader, ShaderType};
+
+// Every node is a vec4, inputs are indices of earlier nodes so graphs can not form cycles.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Node {
+    Constant { value: [f32; 4] },
+    VertexColor,
+    /// xy is the first UV set.
+    Uv,
+    Uv2,
+    /// World space normal in xyz.
+    Normal,
+    /// `MaterialData::time` in every component.
+    Time,
+    /// xy of `uv`, offset by `speed` per second.
+    Scroll { uv: usize, speed: [f32; 2] },
+    /// Samples a texture at xy of `uv`, textures are bound to set 2 in order of appearance.
+    Texture { texture: String, uv: usize },
+    Add { a: usize, b: usize },
+    Subtract { a: usize, b: usize },
+    Multiply { a: usize, b: usize },
+    Mix { a: usize, b: usize, t: usize },
+    Sin { a: usize },
+    /// Rim term, 1 where the surface is seen edge on.
+    Fresnel { power: f32 },
+}
+
+// A material authored as data, translated into a fragment shader for `builtin_shaders::MESH_VS`.
+// Stored as RON in `assets/materials/{name}.ron`:
+// (nodes: [VertexColor, Fresnel(power: 3.0)], color: 0, emissive: Some(1))
+// The shader is compiled ahead of time by `bake`, loading the graph at runtime needs no compiler.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShaderGraph {
+    #[serde(default)]
+    pub name: String,
+    pub nodes: Vec<Node>,
+    /// rgb is the surface color and a its alpha.
+    pub color: usize,
+    /// rgb is added to the color.
+    #[serde(default)]
+    pub emissive: Option<usize>,
+}
+
+impl ShaderGraph {
+    pub fn from_ron(name: &str, text: &str) -> Result<ShaderGraph, String> {
+        let mut graph: ShaderGraph = ron::from_str(text).map_err(|e| format!("material {name}: {e}"))?;
+        graph.name = name.to_string();
+        Ok(graph)
+    }
+
+    pub fn load(name: &str) -> Result<ShaderGraph, String> {
+        let path = format!("assets/materials/{name}.ron");
+        let text = fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+        ShaderGraph::from_ron(name, &text)
+    }
+
+    pub fn shader_name(&self) -> String {
+        format!("graph_{}_fs", self.name)
+    }
+
+    // Texture names in binding order.
+    pub fn textures(&self) -> Vec<String> {
+        let mut textures: Vec<String> = Vec::new();
+        for node in self.nodes.iter() {
+            if let Node::Texture { texture, .. } = node {
+                if !textures.contains(texture) {
+                    textures.push(texture.clone());
+                }
+            }
+        }
+        textures
+    }
+
+    pub fn to_glsl(&self) -> Result<String, String> {
+        let input = |node: usize, index: usize| {
+            if index < node {
+                Ok(format!("n{index}"))
+            } else {
+                Err(format!("material {}: node {node} reads node {index}, which does not come before it", self.name))
+            }
+        };
+        let textures = self.textures();
+
+        let mut body = String::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let expression = match node {
+                Node::Constant { value: [x, y, z, w] } => format!("vec4({x:?}, {y:?}, {z:?}, {w:?})"),
+                Node::VertexColor => "color".to_string(),
+                Node::Uv => "vec4(uv, 0.0, 0.0)".to_string(),
+                Node::Uv2 => "vec4(uv2, 0.0, 0.0)".to_string(),
+                Node::Normal => "vec4(normalize(normal), 0.0)".to_string(),
+                Node::Time => "vec4(material.time)".to_string(),
+                Node::Scroll { uv, speed: [x, y] } => {
+                    format!("vec4({}.xy + vec2({x:?}, {y:?}) * material.time, 0.0, 0.0)", input(i, *uv)?)
+                }
+                Node::Texture { texture, uv } => {
+                    let binding = textures.iter().position(|x| x == texture).unwrap();
+                    format!("texture(texture_{binding}, {}.xy)", input(i, *uv)?)
+                }
+                Node::Add { a, b } => format!("{} + {}", input(i, *a)?, input(i, *b)?),
+                Node::Subtract { a, b } => format!("{} - {}", input(i, *a)?, input(i, *b)?),
+                Node::Multiply { a, b } => format!("{} * {}", input(i, *a)?, input(i, *b)?),
+                Node::Mix { a, b, t } => format!("mix({}, {}, {})", input(i, *a)?, input(i, *b)?, input(i, *t)?),
+                Node::Sin { a } => format!("sin({})", input(i, *a)?),
+                Node::Fresnel { power } => format!(
+                    "vec4(pow(1.0 - max(dot(normalize(normal), normalize(inverse(vp.view)[3].xyz - position)), 0.0), {power:?}))"
+                ),
+            };
+            writeln!(body, "    vec4 n{i} = {expression};").unwrap();
+        }
+        let output = self.nodes.len();
+        let color = input(output, self.color)?;
+        // A `MaterialOverride` emissive replaces the graph's.
+        let emissive = match self.emissive {
+            Some(x) => format!("mix({}.rgb, instance_emissive.rgb, instance_emissive.a)", input(output, x)?),
+            None => "instance_emissive.rgb * instance_emissive.a".to_string(),
+        };
+
+        let mut glsl = String::from(GRAPH_HEADER);
+        for binding in 0..textures.len() {
+            writeln!(glsl, "layout(set = 2, binding = {binding}) uniform sampler2D texture_{binding};").unwrap();
+        }
+        write!(glsl, "\nvoid main() {{\n{body}    out_color = vec4({color}.rgb + {emissive}, {color}.a);\n}}\n").unwrap();
+        Ok(glsl)
+    }
+
+    // Where `bake` puts the compiled shader, next to the other shaders loaded by `Shader::new`.
+    pub fn shader_path(&self) -> String {
+        format!("shaders/bin/{}.spv", self.shader_name())
+    }
+
+    // Compiles the generated shader to `shader_path` with glslc, found like the build script does
+    // through GLSLC. An authoring step, run it again after editing the graph.
+    pub fn bake(&self) -> Result<(), String> {
+        let glsl = self.to_glsl()?;
+        let glslc = env::var("GLSLC").unwrap_or_else(|_| "glslc".to_string());
+        let source = env::temp_dir().join(format!("simple_engine_{}.frag", self.shader_name()));
+        let output = self.shader_path();
+        if let Some(parent) = Path::new(&output).parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+        }
+        fs::write(&source, glsl).map_err(|e| format!("{}: {e}", source.display()))?;
+        let result = Command::new(&glslc)
+            .arg("--target-env=vulkan1.0")
+            .arg(&source)
+            .arg("-o")
+            .arg(&output)
+            .output()
+            .map_err(|e| format!("material {}: failed to run {glslc}: {e}", self.name))?;
+        if !result.status.success() {
+            return Err(format!("material {}: {}", self.name, String::from_utf8_lossy(&result.stderr)));
+        }
+        Ok(())
+    }
+
+    // The shader written by `bake`.
+    pub fn baked_shader(&self) -> Result<Shader, String> {
+        let path = self.shader_path();
+        let bytes = fs::read(&path).map_err(|e| format!("material {}: {path}: {e}, bake the graph first", self.name))?;
+        let words = bytes_to_words(&bytes).map_err(|e| format!("material {}: {e}", self.name))?;
+        Ok(Shader::from_words(&self.shader_name(), ShaderType::Fragment, words.into_owned()))
+    }
+
+    pub fn material(&self) -> Material {
+        let attachments = self.textures().into_iter().map(Attachment::Texture).collect();
+        Material::new(&self.name, builtin_shaders::MESH_VS, &self.shader_name(), attachments)
+    }
+
+    // Adds the baked shader and a material named after the graph, before the engine starts.
+    pub fn add_to(&self, assets: &mut AssetLibrary) -> Result<(), String> {
+        let shader = self.baked_shader()?;
+        assets.shaders.retain(|x| x.name != shader.name);
+        assets.shaders.push(shader);
+        assets.materials.retain(|x| x.name != self.name);
+        assets.materials.push(self.material());
+        Ok(())
+    }
+}
+
+// Inputs from `builtin_shaders::MESH_VS`.
+const GRAPH_HEADER: &str = "#version 450
+
+layout(set = 0, binding = 0) uniform VPData {
+    mat4 view;
+    mat4 projection;
+    vec4 clip_planes[4];
+    mat4 ui_projection;
+} vp;
+
+layout(set = 1, binding = 1) uniform MaterialData {
+    vec4 emissive;
+    float emissive_intensity;
+    float time;
+    vec2 uv_scroll;
+} material;
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec3 normal;
+layout(location = 3) in vec4 color;
+layout(location = 5) in vec2 uv2;
+layout(location = 6) flat in vec4 instance_emissive;
+
+layout(location = 0) out vec4 out_color;
+
+";
@@ -0,0 +1,170 @@
+// Math kernels behind the `simd` feature. Every x86_64 cpu has SSE2 and every aarch64 cpu has
+// NEON, so there is no runtime detection. Other targets and builds without the feature use the
+// scalar versions. Matrices are column major, like `Matrix4f`.
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod imp {
+    use std::arch::x86_64::*;
+
+    #[inline]
+    fn load(x: &[f32; 4]) -> __m128 {
+        unsafe { _mm_loadu_ps(x.as_ptr()) }
+    }
+
+    #[inline]
+    fn store(x: __m128) -> [f32; 4] {
+        let mut output = [0.0; 4];
+        unsafe { _mm_storeu_ps(output.as_mut_ptr(), x) };
+        output
+    }
+
+    #[inline]
+    fn combine(columns: &[__m128; 4], x: [f32; 4]) -> __m128 {
+        unsafe {
+            let xy = _mm_add_ps(_mm_mul_ps(columns[0], _mm_set1_ps(x[0])), _mm_mul_ps(columns[1], _mm_set1_ps(x[1])));
+            let zw = _mm_add_ps(_mm_mul_ps(columns[2], _mm_set1_ps(x[2])), _mm_mul_ps(columns[3], _mm_set1_ps(x[3])));
+            _mm_add_ps(xy, zw)
+        }
+    }
+
+    pub fn mat4_mul(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+        let columns = a.each_ref().map(load);
+        b.map(|x| store(combine(&columns, x)))
+    }
+
+    #[inline]
+    pub fn transform(m: &[[f32; 4]; 4], x: [f32; 4]) -> [f32; 4] {
+        store(combine(&m.each_ref().map(load), x))
+    }
+
+    #[inline]
+    fn dot(a: __m128, b: __m128) -> f32 {
+        unsafe {
+            let product = _mm_mul_ps(a, b);
+            let high = _mm_movehl_ps(product, product);
+            let sum = _mm_add_ps(product, high);
+            _mm_cvtss_f32(_mm_add_ss(sum, _mm_shuffle_ps::<0b01>(sum, sum)))
+        }
+    }
+
+    pub fn dot3(a: [f32; 3], b: [f32; 3]) -> f32 {
+        dot(load(&[a[0], a[1], a[2], 0.0]), load(&[b[0], b[1], b[2], 0.0]))
+    }
+
+    pub fn cross3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        let a = load(&[a[0], a[1], a[2], 0.0]);
+        let b = load(&[b[0], b[1], b[2], 0.0]);
+        let output = unsafe {
+            // yzx
+            const SHUFFLE: i32 = 0b11_00_10_01;
+            let a_yzx = _mm_shuffle_ps::<SHUFFLE>(a, a);
+            let b_yzx = _mm_shuffle_ps::<SHUFFLE>(b, b);
+            let c = _mm_sub_ps(_mm_mul_ps(a, b_yzx), _mm_mul_ps(a_yzx, b));
+            store(_mm_shuffle_ps::<SHUFFLE>(c, c))
+        };
+        [output[0], output[1], output[2]]
+    }
+
+    pub fn normalize3(a: [f32; 3]) -> [f32; 3] {
+        let v = load(&[a[0], a[1], a[2], 0.0]);
+        let length = dot(v, v).sqrt();
+        let output = store(unsafe { _mm_div_ps(v, _mm_set1_ps(length)) });
+        [output[0], output[1], output[2]]
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+mod imp {
+    use std::arch::aarch64::*;
+
+    #[inline]
+    fn load(x: &[f32; 4]) -> float32x4_t {
+        unsafe { vld1q_f32(x.as_ptr()) }
+    }
+
+    #[inline]
+    fn store(x: float32x4_t) -> [f32; 4] {
+        let mut output = [0.0; 4];
+        unsafe { vst1q_f32(output.as_mut_ptr(), x) };
+        output
+    }
+
+    #[inline]
+    fn combine(columns: &[float32x4_t; 4], x: [f32; 4]) -> float32x4_t {
+        unsafe {
+            let output = vmulq_n_f32(columns[0], x[0]);
+            let output = vfmaq_n_f32(output, columns[1], x[1]);
+            let output = vfmaq_n_f32(output, columns[2], x[2]);
+            vfmaq_n_f32(output, columns[3], x[3])
+        }
+    }
+
+    pub fn mat4_mul(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+        let columns = a.each_ref().map(load);
+        b.map(|x| store(combine(&columns, x)))
+    }
+
+    #[inline]
+    pub fn transform(m: &[[f32; 4]; 4], x: [f32; 4]) -> [f32; 4] {
+        store(combine(&m.each_ref().map(load), x))
+    }
+
+    pub fn dot3(a: [f32; 3], b: [f32; 3]) -> f32 {
+        unsafe { vaddvq_f32(vmulq_f32(load(&[a[0], a[1], a[2], 0.0]), load(&[b[0], b[1], b[2], 0.0]))) }
+    }
+
+    pub fn cross3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        let a_yzx = load(&[a[1], a[2], a[0], 0.0]);
+        let b_yzx = load(&[b[1], b[2], b[0], 0.0]);
+        let a = load(&[a[0], a[1], a[2], 0.0]);
+        let b = load(&[b[0], b[1], b[2], 0.0]);
+        let c = store(unsafe { vsubq_f32(vmulq_f32(a, b_yzx), vmulq_f32(a_yzx, b)) });
+        [c[1], c[2], c[0]]
+    }
+
+    pub fn normalize3(a: [f32; 3]) -> [f32; 3] {
+        let v = load(&[a[0], a[1], a[2], 0.0]);
+        let length = unsafe { vaddvq_f32(vmulq_f32(v, v)) }.sqrt();
+        let output = store(unsafe { vdivq_f32(v, vdupq_n_f32(length)) });
+        [output[0], output[1], output[2]]
+    }
+}
+
+#[cfg(not(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+mod imp {
+    pub fn mat4_mul(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+        let mut output = [[0.0; 4]; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..4 {
+                    output[i][j] += a[k][j] * b[i][k];
+                }
+            }
+        }
+        output
+    }
+
+    #[inline]
+    pub fn transform(m: &[[f32; 4]; 4], x: [f32; 4]) -> [f32; 4] {
+        [0, 1, 2, 3].map(|r| m[0][r] * x[0] + m[1][r] * x[1] + m[2][r] * x[2] + m[3][r] * x[3])
+    }
+
+    pub fn dot3(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    pub fn cross3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [
+            (a[1] * b[2]) - (a[2] * b[1]),
+            (a[2] * b[0]) - (a[0] * b[2]),
+            (a[0] * b[1]) - (a[1] * b[0]),
+        ]
+    }
+
+    pub fn normalize3(a: [f32; 3]) -> [f32; 3] {
+        let len = dot3(a, a).sqrt();
+        [a[0] / len, a[1] / len, a[2] / len]
+    }
+}
+
+pub(crate) use imp::*;
@@ -0,0 +1,119 @@
+use bytemuck::{Pod, Zeroable};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::sync::Sharing;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+
+use crate::rendering::{Renderer, VertexData};
+
+use super::matrices::Matrix4f;
+use super::mesh::Mesh;
+
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Default)]
+#[repr(C)]
+pub struct SkinWeights {
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+}
+
+impl SkinWeights {
+    pub fn new(joints: [u32; 4], weights: [f32; 4]) -> SkinWeights {
+        SkinWeights { joints, weights }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SkinBuffers {
+    pub source: Subbuffer<[VertexData]>,
+    pub weights: Subbuffer<[SkinWeights]>,
+    pub joints: Vec<Subbuffer<[Matrix4f]>>,
+    pub output: Vec<Subbuffer<[VertexData]>>,
+}
+
+// Vertices of `mesh_name` are skinned on the GPU into a per-frame buffer which the shadow and main
+// passes both draw from. `joint_matrices` hold each joint's world transform times its inverse bind
+// matrix and are uploaded every frame.
+#[derive(Clone, Debug)]
+pub struct SkinnedMesh {
+    pub mesh_name: String,
+    pub weights: Vec<SkinWeights>,
+    pub joint_matrices: Vec<Matrix4f>,
+    pub buffers: Option<SkinBuffers>,
+}
+
+fn create_slice<T: BufferContents>(renderer: &Renderer, data: impl ExactSizeIterator<Item = T>) -> Subbuffer<[T]> {
+    Buffer::from_iter(
+        renderer.memeory_allocator.as_ref().unwrap().clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data,
+    )
+    .unwrap()
+}
+
+impl SkinnedMesh {
+    pub fn new(mesh_name: &str, weights: Vec<SkinWeights>, joint_count: usize) -> SkinnedMesh {
+        SkinnedMesh {
+            mesh_name: mesh_name.to_string(),
+            weights,
+            joint_matrices: vec![Matrix4f::indentity(); joint_count.max(1)],
+            buffers: None,
+        }
+    }
+
+    pub fn load(&mut self, renderer: &Renderer, mesh: &Mesh) {
+        assert_eq!(mesh.vertices.len(), self.weights.len(), "skinned mesh {} needs one weight per vertex", self.mesh_name);
+
+        let frames = renderer.frames_in_flight.max(1);
+        // Written on the compute queue with async compute, drawn on the graphics queue.
+        let queue_families = renderer.compute_queue_families();
+        let sharing = if queue_families.len() > 1 {
+            Sharing::Concurrent(queue_families.into_iter().collect())
+        } else {
+            Sharing::Exclusive
+        };
+        self.buffers = Some(SkinBuffers {
+            source: create_slice(renderer, mesh.vertices.iter().copied()),
+            weights: create_slice(renderer, self.weights.iter().copied()),
+            joints: (0..frames)
+                .map(|_| create_slice(renderer, self.joint_matrices.iter().copied()))
+                .collect(),
+            output: (0..frames)
+                .map(|_| {
+                    Buffer::new_slice(
+                        renderer.memeory_allocator.as_ref().unwrap().clone(),
+                        BufferCreateInfo {
+                            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::VERTEX_BUFFER,
+                            sharing: sharing.clone(),
+                            ..Default::default()
+                        },
+                        AllocationCreateInfo {
+                            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                            ..Default::default()
+                        },
+                        mesh.vertices.len() as u64,
+                    )
+                    .unwrap()
+                })
+                .collect(),
+        });
+    }
+
+    pub fn write_joints(&self, frame: usize) {
+        let Some(buffers) = self.buffers.as_ref() else {
+            return;
+        };
+        let mut joints = buffers.joints[frame].write().unwrap();
+        let len = joints.len().min(self.joint_matrices.len());
+        joints[..len].copy_from_slice(&self.joint_matrices[..len]);
+    }
+
+    pub fn joint_count(&self) -> usize {
+        self.buffers.as_ref().map_or(self.joint_matrices.len(), |x| x.joints[0].len() as usize)
+    }
+}
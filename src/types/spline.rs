@@ -0,0 +1,368 @@
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+};
+
+use super::{easing::Easing, transform::Transform, tween::TweenRepeat, vectors::*};
+
+// Samples per segment for the arc length table, enough for segments that do not bend sharply.
+const LENGTH_SAMPLES: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplineKind {
+    /// Cubic segments sharing their end points: point, control, control, point, control, ...
+    Bezier,
+    /// Passes through every point, tangents come from the neighbouring points.
+    CatmullRom,
+    /// Point and tangent pairs: point, tangent, point, tangent, ...
+    Hermite,
+}
+
+// A path of cubic segments. Every kind is stored as Bezier segments, `t` runs from 0 to 1 over the
+// whole spline with every segment taking the same share. Use the `*_at_distance` functions for a
+// constant speed.
+#[derive(Clone, Debug)]
+pub struct Spline {
+    kind: SplineKind,
+    points: Vec<Vec3f>,
+    closed: bool,
+    segments: Vec<[Vec3f; 4]>,
+    /// Arc length at every sample, `LENGTH_SAMPLES` per segment and the end.
+    lengths: Vec<f32>,
+}
+
+impl Spline {
+    pub fn new(kind: SplineKind, points: Vec<Vec3f>) -> Spline {
+        let mut spline = Spline {
+            kind,
+            points,
+            closed: false,
+            segments: Vec::new(),
+            lengths: Vec::new(),
+        };
+        spline.update();
+        spline
+    }
+
+    pub fn bezier(points: Vec<Vec3f>) -> Spline {
+        Spline::new(SplineKind::Bezier, points)
+    }
+
+    pub fn catmull_rom(points: Vec<Vec3f>) -> Spline {
+        Spline::new(SplineKind::CatmullRom, points)
+    }
+
+    pub fn hermite(points: Vec<(Vec3f, Vec3f)>) -> Spline {
+        Spline::new(SplineKind::Hermite, points.into_iter().flat_map(|(point, tangent)| [point, tangent]).collect())
+    }
+
+    // Adds a segment from the last point back to the first. Closed Bezier splines take the
+    // controls of that segment from the end of `points`.
+    pub fn with_closed(mut self, closed: bool) -> Spline {
+        self.closed = closed;
+        self.update();
+        self
+    }
+
+    pub fn kind(&self) -> SplineKind {
+        self.kind
+    }
+
+    pub fn points(&self) -> &[Vec3f] {
+        &self.points
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn set_points(&mut self, points: Vec<Vec3f>) {
+        self.points = points;
+        self.update();
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    fn update(&mut self) {
+        let points = &self.points;
+        let wrap = |i: usize| points[i % points.len()];
+        self.segments = match self.kind {
+            SplineKind::Bezier => {
+                let count = if self.closed { points.len() / 3 } else { points.len().saturating_sub(1) / 3 };
+                (0..count).map(|i| [wrap(i * 3), wrap(i * 3 + 1), wrap(i * 3 + 2), wrap(i * 3 + 3)]).collect()
+            }
+            SplineKind::CatmullRom => {
+                let count = if self.closed || points.is_empty() { points.len() } else { points.len() - 1 };
+                // Open splines repeat their end points, so the ends get a one sided tangent.
+                let point = |i: isize| match self.closed {
+                    true => points[i.rem_euclid(points.len() as isize) as usize],
+                    false => points[i.clamp(0, points.len() as isize - 1) as usize],
+                };
+                (0..count as isize)
+                    .map(|i| {
+                        let (p0, p1, p2, p3) = (point(i - 1), point(i), point(i + 1), point(i + 2));
+                        [p1, p1 + (p2 - p0) * (1.0 / 6.0), p2 - (p3 - p1) * (1.0 / 6.0), p2]
+                    })
+                    .collect()
+            }
+            SplineKind::Hermite => {
+                let pairs = points.len() / 2;
+                let count = if self.closed { pairs } else { pairs.saturating_sub(1) };
+                let pair = |i: usize| (points[i % pairs * 2], points[i % pairs * 2 + 1]);
+                (0..count)
+                    .map(|i| {
+                        let ((p0, t0), (p1, t1)) = (pair(i), pair(i + 1));
+                        [p0, p0 + t0 * (1.0 / 3.0), p1 - t1 * (1.0 / 3.0), p1]
+                    })
+                    .collect()
+            }
+        };
+
+        self.lengths = vec![0.0];
+        let mut previous = self.evaluate(0.0);
+        let samples = self.segments.len() * LENGTH_SAMPLES;
+        for i in 1..=samples {
+            let point = self.evaluate(i as f32 / samples as f32);
+            let mut step = point - previous;
+            self.lengths.push(self.lengths.last().unwrap() + step.length());
+            previous = point;
+        }
+    }
+
+    // The segment `t` falls in and the position inside it.
+    fn segment(&self, t: f32) -> Option<(&[Vec3f; 4], f32)> {
+        let count = self.segments.len();
+        if count == 0 {
+            return None;
+        }
+        let scaled = t.clamp(0.0, 1.0) * count as f32;
+        let index = (scaled as usize).min(count - 1);
+        Some((&self.segments[index], scaled - index as f32))
+    }
+
+    pub fn evaluate(&self, t: f32) -> Vec3f {
+        let Some(([p0, p1, p2, p3], t)) = self.segment(t) else {
+            return self.points.first().copied().unwrap_or(Vec3f::new([0.0, 0.0, 0.0]));
+        };
+        let u = 1.0 - t;
+        *p0 * (u * u * u) + *p1 * (3.0 * u * u * t) + *p2 * (3.0 * u * t * t) + *p3 * (t * t * t)
+    }
+
+    // Derivative along the segment, not normalized.
+    pub fn tangent(&self, t: f32) -> Vec3f {
+        let Some(([p0, p1, p2, p3], t)) = self.segment(t) else {
+            return Vec3f::new([0.0, 0.0, 0.0]);
+        };
+        let u = 1.0 - t;
+        (*p1 - *p0) * (3.0 * u * u) + (*p2 - *p1) * (6.0 * u * t) + (*p3 - *p2) * (3.0 * t * t)
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.lengths.last().unwrap()
+    }
+
+    // The `t` that is `distance` along the spline, clamped to its ends.
+    pub fn t_at_distance(&self, distance: f32) -> f32 {
+        let samples = self.lengths.len() - 1;
+        if samples == 0 || distance <= 0.0 {
+            return 0.0;
+        }
+        if distance >= self.length() {
+            return 1.0;
+        }
+        let i = self.lengths.partition_point(|x| *x <= distance).clamp(1, samples);
+        let (start, end) = (self.lengths[i - 1], self.lengths[i]);
+        let fraction = if end > start { (distance - start) / (end - start) } else { 0.0 };
+        (i as f32 - 1.0 + fraction) / samples as f32
+    }
+
+    pub fn point_at_distance(&self, distance: f32) -> Vec3f {
+        self.evaluate(self.t_at_distance(distance))
+    }
+
+    pub fn tangent_at_distance(&self, distance: f32) -> Vec3f {
+        self.tangent(self.t_at_distance(distance))
+    }
+}
+
+// Moves the entity's `Transform` along `spline` at `speed` units per second. `easing` shapes every
+// pass, with `Easing::Linear` the speed is constant.
+#[derive(Clone, Debug)]
+pub struct FollowSpline {
+    pub spline: Spline,
+    pub speed: f32,
+    pub easing: Easing,
+    pub repeat: TweenRepeat,
+    /// Turns the entity to face along the path, forward being +X as for cameras.
+    pub orient: bool,
+    pub playing: bool,
+    /// Added to every point, so a spline can be placed in a large world.
+    pub offset: Vec3d,
+    /// Distance covered, counting every pass.
+    pub travelled: f32,
+}
+
+impl FollowSpline {
+    pub fn new(spline: Spline, speed: f32) -> FollowSpline {
+        FollowSpline {
+            spline,
+            speed,
+            easing: Easing::Linear,
+            repeat: TweenRepeat::Once,
+            orient: false,
+            playing: true,
+            offset: Vec3d::new([0.0, 0.0, 0.0]),
+            travelled: 0.0,
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> FollowSpline {
+        self.easing = easing;
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat: TweenRepeat) -> FollowSpline {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn with_orient(mut self, orient: bool) -> FollowSpline {
+        self.orient = orient;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Vec3d) -> FollowSpline {
+        self.offset = offset;
+        self
+    }
+
+    // Progress of the current pass from 0 to 1, before easing.
+    pub fn progress(&self) -> f32 {
+        let length = self.spline.length();
+        if length <= 0.0 {
+            return 1.0;
+        }
+        let passes = self.travelled / length;
+        match self.repeat {
+            TweenRepeat::Once => passes.min(1.0),
+            TweenRepeat::Loop => passes.fract(),
+            TweenRepeat::PingPong => {
+                let phase = passes % 2.0;
+                if phase > 1.0 { 2.0 - phase } else { phase }
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.repeat == TweenRepeat::Once && self.progress() >= 1.0
+    }
+
+    // Position and direction of travel.
+    pub fn sample(&self) -> (Vec3d, Vec3f) {
+        let distance = self.easing.apply(self.progress()) * self.spline.length();
+        let mut direction = self.spline.tangent_at_distance(distance);
+        let phase = self.travelled / self.spline.length().max(f32::EPSILON) % 2.0;
+        if self.repeat == TweenRepeat::PingPong && phase > 1.0 {
+            direction *= -1.0;
+        }
+        (self.offset + self.spline.point_at_distance(distance).to_vec3d(), direction)
+    }
+}
+
+// `Transform::rotation` that turns +X, the camera forward, towards `direction`.
+fn facing(mut direction: Vec3f) -> Option<Vec3f> {
+    if direction.length() <= f32::EPSILON {
+        return None;
+    }
+    let direction = direction.normalize();
+    Some(Vec3f::new([0.0, direction.z.atan2(direction.x), -direction.y.clamp(-1.0, 1.0).asin()]))
+}
+
+pub struct FollowSplineUpdater {}
+
+impl System for FollowSplineUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(mut followers) = world.borrow_component_vec_mut::<FollowSpline>() else {
+            return;
+        };
+        let Some(mut transforms) = world.borrow_component_vec_mut::<Transform>() else {
+            return;
+        };
+
+        for (entity, (follower, transform)) in followers.iter_mut().zip(transforms.iter_mut()).enumerate() {
+            let (Some(follower), Some(transform)) = (follower.as_mut(), transform.as_mut()) else {
+                continue;
+            };
+            if !follower.playing || follower.is_finished() {
+                continue;
+            }
+
+            follower.travelled += follower.speed * state.delta_time as f32;
+            let (position, direction) = follower.sample();
+            transform.position = position;
+            if let Some(rotation) = facing(direction).filter(|_| follower.orient) {
+                transform.rotation = rotation;
+            }
+            world.mark_changed::<Transform>(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn splines() -> Vec<Spline> {
+        let v = |x: f32, y: f32, z: f32| Vec3f::new([x, y, z]);
+        vec![
+            Spline::bezier(vec![
+                v(0.0, 0.0, 0.0),
+                v(1.0, 2.0, 0.0),
+                v(3.0, 2.0, 1.0),
+                v(4.0, 0.0, 0.0),
+                v(5.0, -1.0, 0.0),
+                v(6.0, 1.0, 2.0),
+                v(7.0, 0.0, 0.0),
+            ]),
+            Spline::catmull_rom(vec![v(0.0, 0.0, 0.0), v(1.0, 3.0, 0.0), v(2.0, 0.0, 1.0), v(5.0, 1.0, 0.0)]),
+            Spline::hermite(vec![
+                (v(0.0, 0.0, 0.0), v(4.0, 0.0, 0.0)),
+                (v(2.0, 2.0, 0.0), v(0.0, 4.0, 0.0)),
+                (v(0.0, 4.0, 1.0), v(-4.0, 0.0, 0.0)),
+            ]),
+        ]
+    }
+
+    fn distance(a: Vec3f, b: Vec3f) -> f32 {
+        let mut offset = a - b;
+        offset.length()
+    }
+
+    #[test]
+    fn spline_passes_through_end_points() {
+        for spline in splines() {
+            let (first, last) = match spline.kind() {
+                SplineKind::Hermite => (spline.points()[0], spline.points()[spline.points().len() - 2]),
+                _ => (spline.points()[0], *spline.points().last().unwrap()),
+            };
+            assert!(distance(spline.evaluate(0.0), first) < 1e-5, "{:?} starts off its first point", spline.kind());
+            assert!(distance(spline.evaluate(1.0), last) < 1e-5, "{:?} ends off its last point", spline.kind());
+        }
+    }
+
+    #[test]
+    fn t_at_distance_is_monotonic() {
+        for spline in splines() {
+            let length = spline.length();
+            assert!(length > 0.0);
+            let ts: Vec<f32> = (0..=500).map(|i| spline.t_at_distance(length * i as f32 / 500.0)).collect();
+            assert_eq!(ts[0], 0.0);
+            assert_eq!(ts[500], 1.0);
+            assert!(ts.windows(2).all(|x| x[0] <= x[1]), "{:?} goes backwards", spline.kind());
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+};
+
+use super::atlas::TextureAtlas;
+#[cfg(feature = "ui")]
+use super::ui::{UiNode, UiWidget};
+
+// Steps through atlas regions at a fixed rate. Sets the region of a `UiWidget::Sprite` on the same
+// entity, other users read `region`.
+#[derive(Clone, Debug)]
+pub struct SpriteAnimation {
+    pub atlas: String,
+    /// Region names, in order.
+    pub frames: Vec<String>,
+    pub fps: f32,
+    pub looping: bool,
+    pub playing: bool,
+    /// Fired whenever the frame at the index is reached, see `drain_events`.
+    pub frame_events: Vec<(usize, String)>,
+    frame: usize,
+    elapsed: f32,
+    events: Vec<String>,
+}
+
+impl SpriteAnimation {
+    pub fn new(atlas: &str, frames: Vec<String>, fps: f32) -> SpriteAnimation {
+        SpriteAnimation {
+            atlas: atlas.to_string(),
+            frames,
+            fps,
+            looping: true,
+            playing: true,
+            frame_events: Vec::new(),
+            frame: 0,
+            elapsed: 0.0,
+            events: Vec::new(),
+        }
+    }
+
+    // Every region named `{prefix}{number}`, ordered by the number, e.g. "walk_0" to "walk_11".
+    pub fn from_prefix(atlas: &TextureAtlas, prefix: &str, fps: f32) -> SpriteAnimation {
+        let mut frames: Vec<(u32, &String)> = atlas
+            .regions
+            .keys()
+            .filter_map(|x| Some((x.strip_prefix(prefix)?.parse().ok()?, x)))
+            .collect();
+        frames.sort();
+        SpriteAnimation::new(&atlas.name, frames.into_iter().map(|(_, x)| x.clone()).collect(), fps)
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> SpriteAnimation {
+        self.looping = looping;
+        self
+    }
+
+    pub fn with_event(mut self, frame: usize, event: &str) -> SpriteAnimation {
+        self.frame_events.push((frame, event.to_string()));
+        self
+    }
+
+    // Restarts a finished animation that does not loop.
+    pub fn play(&mut self) {
+        if self.is_finished() {
+            self.set_frame(0);
+        }
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.set_frame(0);
+    }
+
+    pub fn set_frame(&mut self, frame: usize) {
+        self.frame = frame.min(self.frames.len().saturating_sub(1));
+        self.elapsed = 0.0;
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn region(&self) -> Option<&str> {
+        self.frames.get(self.frame).map(String::as_str)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.frame + 1 >= self.frames.len() && !self.playing
+    }
+
+    pub fn drain_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn advance(&mut self, delta_time: f32) {
+        if !self.playing || self.frames.is_empty() || self.fps <= 0.0 {
+            return;
+        }
+        let frame_time = 1.0 / self.fps;
+        self.elapsed += delta_time;
+        while self.elapsed >= frame_time {
+            self.elapsed -= frame_time;
+            if self.frame + 1 < self.frames.len() {
+                self.frame += 1;
+            } else if self.looping {
+                self.frame = 0;
+            } else {
+                self.playing = false;
+                self.elapsed = 0.0;
+                return;
+            }
+            let frame = self.frame;
+            self.events
+                .extend(self.frame_events.iter().filter(|(x, _)| *x == frame).map(|(_, x)| x.clone()));
+        }
+    }
+}
+
+pub struct SpriteAnimationUpdater {}
+
+impl System for SpriteAnimationUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(mut animations) = world.borrow_component_vec_mut::<SpriteAnimation>() else {
+            return;
+        };
+        #[cfg(feature = "ui")]
+        let mut nodes = world.borrow_component_vec_mut::<UiNode>();
+
+        #[cfg_attr(not(feature = "ui"), allow(unused_variables))]
+        for (entity, animation) in animations.iter_mut().enumerate() {
+            let Some(animation) = animation.as_mut() else {
+                continue;
+            };
+            animation.advance(state.delta_time as f32);
+
+            #[cfg(feature = "ui")]
+            if let Some(UiWidget::Sprite { atlas, region, .. }) =
+                nodes.as_mut().and_then(|x| x.get_mut(entity)?.as_mut()).map(|x| &mut x.widget)
+            {
+                if let Some(current) = animation.region().filter(|x| *x != region) {
+                    *atlas = animation.atlas.clone();
+                    *region = current.to_string();
+                }
+            }
+        }
+    }
+}
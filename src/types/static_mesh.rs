@@ -1,6 +1,10 @@
+use serde::{Deserialize, Serialize};
+use simple_engine_derive::{Component, Reflect};
+
 use crate::state::State;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize, Component, Reflect)]
+#[component(persistent, reflect)]
 pub struct StaticMesh {
     pub mesh_name: String
 }
@@ -0,0 +1,391 @@
+use std::{cell::RefCell, fs::File, io::{Cursor, Read}};
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::VertexData, state::State};
+
+use super::{bvh::{Aabb, Bvh}, color::Color, frustum::Frustum, noise::Fbm, mesh::DynamicMesh, transform::Transform, vectors::*, visibility::Visibility};
+
+#[derive(Clone, Debug)]
+pub struct Heightmap {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn load(name: &str) -> Heightmap {
+        let mut file = File::open(format!("assets/heightmaps/{}.png", name)).unwrap();
+        let mut png_bytes: Vec<u8> = Vec::new();
+        file.read_to_end(&mut png_bytes).unwrap();
+
+        let cursor = Cursor::new(png_bytes);
+        let decoder = png::Decoder::new(cursor);
+        let mut reader = decoder.read_info().unwrap();
+        let mut image_data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut image_data).unwrap();
+
+        let channels = info.color_type.samples();
+        let (bytes_per_sample, max_value) = match info.bit_depth {
+            png::BitDepth::Sixteen => (2, u16::MAX as f32),
+            _ => (1, u8::MAX as f32),
+        };
+        let stride = channels * bytes_per_sample;
+
+        let data = (0..(info.width * info.height) as usize)
+            .map(|i| {
+                let offset = i * stride;
+                let value = if bytes_per_sample == 2 {
+                    u16::from_be_bytes([image_data[offset], image_data[offset + 1]]) as f32
+                } else {
+                    image_data[offset] as f32
+                };
+                value / max_value
+            })
+            .collect();
+
+        Heightmap {
+            width: info.width,
+            height: info.height,
+            data,
+        }
+    }
+
+    // Heights from `fbm` sampled at pixel centers across a unit square, so its frequency counts
+    // cycles over the whole map, mapped from -1..1 to 0..1.
+    pub fn from_fbm(width: u32, height: u32, fbm: &Fbm) -> Heightmap {
+        let data = (0..height)
+            .flat_map(|z| (0..width).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                let u = (x as f32 + 0.5) / width as f32;
+                let v = (z as f32 + 0.5) / height as f32;
+                (fbm.sample2(u, v) * 0.5 + 0.5).clamp(0.0, 1.0)
+            })
+            .collect();
+        Heightmap { width, height, data }
+    }
+
+    pub fn sample(&self, x: u32, z: u32) -> f32 {
+        let x = x.min(self.width - 1);
+        let z = z.min(self.height - 1);
+        self.data[(z * self.width + x) as usize]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TerrainSettings {
+    pub material: String,
+    pub chunk_size: u32,
+    pub cell_size: f32,
+    pub height_scale: f32,
+    pub lod_distances: Vec<f32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TerrainChunk {
+    pub bounds_min: Vec3f,
+    pub bounds_max: Vec3f,
+    pub lods: Vec<Vec<u32>>,
+    pub lod_distances: Vec<f32>,
+    pub current_lod: usize,
+}
+
+impl TerrainChunk {
+    fn select_lod(&self, distance: f32) -> usize {
+        self.lod_distances
+            .iter()
+            .take_while(|x| distance >= **x)
+            .count()
+            .min(self.lods.len() - 1)
+    }
+}
+
+fn lod_indices(size_x: u32, size_z: u32, step: u32) -> Vec<u32> {
+    let row = size_x + 1;
+    let mut indices = Vec::new();
+    for z in (0..size_z).step_by(step as usize) {
+        for x in (0..size_x).step_by(step as usize) {
+            let a = z * row + x;
+            let b = z * row + x + step;
+            let c = (z + step) * row + x;
+            let d = (z + step) * row + x + step;
+            indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+    indices
+}
+
+// The border of a chunk walked counterclockwise from above, as grid coordinates. Skirts hang off
+// it facing outwards.
+fn border(size_x: u32, size_z: u32) -> Vec<Vec<(u32, u32)>> {
+    vec![
+        (0..=size_x).map(|x| (x, 0)).collect(),
+        (0..=size_z).map(|z| (size_x, z)).collect(),
+        (0..=size_x).rev().map(|x| (x, size_z)).collect(),
+        (0..=size_z).rev().map(|z| (0, z)).collect(),
+    ]
+}
+
+// Walls below the chunk's border, down past its lowest point, so the gaps between neighbours at
+// different LODs show skirt instead of sky. `first_skirt` is the index of the first lowered
+// vertex, laid out in `border` order.
+fn skirt_indices(size_x: u32, size_z: u32, step: u32, first_skirt: u32) -> Vec<u32> {
+    let row = size_x + 1;
+    let mut indices = Vec::new();
+    let mut bottom = first_skirt;
+    for edge in border(size_x, size_z) {
+        for i in (0..edge.len() - 1).step_by(step as usize) {
+            let (x0, z0) = edge[i];
+            let (x1, z1) = edge[i + step as usize];
+            let (t0, t1) = (z0 * row + x0, z1 * row + x1);
+            let (b0, b1) = (bottom + i as u32, bottom + i as u32 + step);
+            indices.extend_from_slice(&[t0, t1, b0, t1, b1, b0]);
+        }
+        bottom += edge.len() as u32;
+    }
+    indices
+}
+
+// Returns the chunk entities, or an error for settings that can not produce a mesh.
+pub fn spawn_terrain(world: &mut World, heightmap: &Heightmap, settings: &TerrainSettings) -> Result<Vec<usize>, String> {
+    if settings.chunk_size == 0 {
+        return Err("terrain chunk_size must be at least 1".to_string());
+    }
+    if heightmap.width < 2 || heightmap.height < 2 {
+        return Err(format!("heightmap is {}x{}, terrain needs at least 2x2", heightmap.width, heightmap.height));
+    }
+    if heightmap.data.len() != (heightmap.width * heightmap.height) as usize {
+        return Err(format!(
+            "heightmap has {} samples, {}x{} needs {}",
+            heightmap.data.len(),
+            heightmap.width,
+            heightmap.height,
+            heightmap.width * heightmap.height
+        ));
+    }
+
+    let height_at = |x: u32, z: u32| heightmap.sample(x, z) * settings.height_scale;
+    let chunks_x = (heightmap.width - 1).div_ceil(settings.chunk_size);
+    let chunks_z = (heightmap.height - 1).div_ceil(settings.chunk_size);
+
+    let mut entities = Vec::new();
+    for cz in 0..chunks_z {
+        for cx in 0..chunks_x {
+            let origin_x = cx * settings.chunk_size;
+            let origin_z = cz * settings.chunk_size;
+            // The last row and column of chunks end at the heightmap's edge.
+            let size_x = settings.chunk_size.min(heightmap.width - 1 - origin_x);
+            let size_z = settings.chunk_size.min(heightmap.height - 1 - origin_z);
+            let mut min_height = f32::MAX;
+            let mut max_height = f32::MIN;
+
+            let mut vertices = Vec::new();
+            for z in 0..=size_z {
+                for x in 0..=size_x {
+                    let hx = origin_x + x;
+                    let hz = origin_z + z;
+                    let height = height_at(hx, hz);
+                    min_height = min_height.min(height);
+                    max_height = max_height.max(height);
+
+                    let dx = height_at(hx + 1, hz) - height_at(hx.saturating_sub(1), hz);
+                    let dz = height_at(hx, hz + 1) - height_at(hx, hz.saturating_sub(1));
+                    let normal = Vec3f::new([-dx, 2.0 * settings.cell_size, -dz]).normalize();
+
+                    let uv = Vec2f::new([
+                        hx as f32 / (heightmap.width - 1) as f32,
+                        hz as f32 / (heightmap.height - 1) as f32,
+                    ]);
+
+                    vertices.push(VertexData {
+                        position: Vec3f::new([
+                            x as f32 * settings.cell_size,
+                            height,
+                            z as f32 * settings.cell_size,
+                        ]),
+                        uv,
+                        normal,
+                        color: Color::WHITE,
+                        uv2: uv,
+                    });
+                }
+            }
+
+            let skirt_height = min_height - settings.cell_size;
+            let first_skirt = vertices.len() as u32;
+            for (x, z) in border(size_x, size_z).into_iter().flatten() {
+                let mut vertex = vertices[(z * (size_x + 1) + x) as usize];
+                vertex.position.y = skirt_height;
+                vertices.push(vertex);
+            }
+
+            let mut lods = Vec::new();
+            let mut step = 1;
+            // A step that does not divide the chunk would index past its last row of vertices.
+            while step <= size_x.max(size_z)
+                && size_x.is_multiple_of(step)
+                && size_z.is_multiple_of(step)
+                && lods.len() <= settings.lod_distances.len()
+            {
+                let mut indices = lod_indices(size_x, size_z, step);
+                indices.extend(skirt_indices(size_x, size_z, step, first_skirt));
+                lods.push(indices);
+                step *= 2;
+            }
+
+            let position = Vec3f::new([
+                origin_x as f32 * settings.cell_size,
+                0.0,
+                origin_z as f32 * settings.cell_size,
+            ]);
+
+            let entity = world.new_entity();
+            world.add_component(entity, Transform::new(
+                position.to_vec3d(),
+                Vec3f::new([1.0, 1.0, 1.0]),
+                Vec3f::new([0.0, 0.0, 0.0]),
+            ));
+            world.add_component(entity, DynamicMesh {
+                vertices,
+                indices: lods[0].clone(),
+                material: settings.material.clone(),
+                buffers: None,
+            });
+            world.add_component(entity, Visibility::default());
+            world.add_component(entity, TerrainChunk {
+                bounds_min: Vec3f::new([position.x, skirt_height, position.z]),
+                bounds_max: Vec3f::new([
+                    position.x + size_x as f32 * settings.cell_size,
+                    max_height,
+                    position.z + size_z as f32 * settings.cell_size,
+                ]),
+                lods,
+                lod_distances: settings.lod_distances.clone(),
+                current_lod: 0,
+            });
+            entities.push(entity);
+        }
+    }
+    Ok(entities)
+}
+
+// Chunks are culled through a `Bvh` over their render space bounds, rebuilt when chunks are added
+// or removed and when the render origin moves.
+pub struct TerrainUpdater {
+    culling: RefCell<Option<(Bvh, Vec<usize>, Vec3d)>>,
+}
+
+impl TerrainUpdater {
+    pub fn new() -> TerrainUpdater {
+        TerrainUpdater {
+            culling: RefCell::new(None),
+        }
+    }
+}
+
+impl Default for TerrainUpdater {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl System for TerrainUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(mut chunks) = world.borrow_component_vec_mut::<TerrainChunk>() else {
+            return;
+        };
+        let mut meshes = world.borrow_component_vec_mut::<DynamicMesh>().unwrap();
+        let mut visibilities = world.borrow_component_vec_mut::<Visibility>().unwrap();
+
+        let entities: Vec<usize> = (0..chunks.len()).filter(|x| chunks[*x].is_some()).collect();
+        let origin = state.renderer.render_origin;
+        let mut culling = self.culling.borrow_mut();
+        let outdated = culling.as_ref().is_none_or(|(_, cached, cached_origin)| {
+            *cached != entities || cached_origin.x != origin.x || cached_origin.y != origin.y || cached_origin.z != origin.z
+        });
+        if outdated {
+            let bounds: Vec<Aabb> = entities
+                .iter()
+                .map(|x| {
+                    let chunk = chunks[*x].as_ref().unwrap();
+                    Aabb::new(
+                        state.renderer.render_space(chunk.bounds_min.to_vec3d()),
+                        state.renderer.render_space(chunk.bounds_max.to_vec3d()),
+                    )
+                })
+                .collect();
+            *culling = Some((Bvh::build(&bounds), entities, origin));
+        }
+        let (bvh, entities, _) = culling.as_ref().unwrap();
+
+        let frustum = Frustum::from_matrix(state.renderer.vp_data.projection * state.renderer.vp_data.view);
+        let camera_pos = state.renderer.vp_pos.to_vec3f();
+        let mut in_frustum = vec![false; chunks.len()];
+        bvh.traverse_frustum(&frustum, |x| in_frustum[entities[x]] = true);
+
+        let zip = chunks.iter_mut().zip(meshes.iter_mut()).zip(visibilities.iter_mut()).zip(in_frustum);
+        for (((chunk, mesh), visibility), visible) in zip {
+            let (Some(chunk), Some(mesh), Some(visibility)) = (chunk, mesh, visibility) else {
+                continue;
+            };
+
+            if visible != visibility.visible {
+                visibility.visible = visible;
+                state.renderer.command_buffer_outdated = true;
+            }
+            if !visible {
+                continue;
+            }
+
+            let mut offset = (chunk.bounds_min + chunk.bounds_max) * 0.5 - camera_pos;
+            let lod = chunk.select_lod(offset.length());
+            if lod != chunk.current_lod {
+                chunk.current_lod = lod;
+                mesh.change_indices(chunk.lods[lod].clone());
+                state.renderer.command_buffer_outdated = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::noise::Noise;
+
+    #[test]
+    fn fbm_heightmap_is_not_flat() {
+        let heightmap = Heightmap::from_fbm(32, 32, &Fbm::new(Noise::new(7)).with_frequency(4.0));
+        let min = heightmap.data.iter().copied().fold(f32::MAX, f32::min);
+        let max = heightmap.data.iter().copied().fold(f32::MIN, f32::max);
+        assert!(max - min > 0.1, "heights span {min}..{max}");
+        assert!(heightmap.data.iter().all(|x| (0.0..=1.0).contains(x)));
+    }
+
+    #[test]
+    fn uneven_chunks_stay_inside_the_heightmap() {
+        let heightmap = Heightmap { width: 7, height: 6, data: vec![0.5; 42] };
+        let settings = TerrainSettings {
+            material: String::new(),
+            chunk_size: 4,
+            cell_size: 1.0,
+            height_scale: 1.0,
+            lod_distances: vec![10.0, 20.0],
+        };
+        let mut world = World::new();
+        let entities = spawn_terrain(&mut world, &heightmap, &settings).unwrap();
+        assert_eq!(entities.len(), 4);
+
+        let chunks = world.borrow_component_vec_mut::<TerrainChunk>().unwrap();
+        let meshes = world.borrow_component_vec_mut::<DynamicMesh>().unwrap();
+        let mut max = Vec3f::new([0.0, 0.0, 0.0]);
+        for entity in entities {
+            let chunk = chunks[entity].as_ref().unwrap();
+            let mesh = meshes[entity].as_ref().unwrap();
+            max = Vec3f::new([max.x.max(chunk.bounds_max.x), 0.0, max.z.max(chunk.bounds_max.z)]);
+            for lod in chunk.lods.iter() {
+                assert!(lod.iter().all(|x| (*x as usize) < mesh.vertices.len()));
+            }
+        }
+        assert_eq!((max.x, max.z), (6.0, 5.0));
+    }
+}
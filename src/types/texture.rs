@@ -1,15 +1,19 @@
-use std::{fs::File, sync::Arc, io::{Cursor, Read}};
+use std::{fs::{self, File}, sync::Arc, io::{Cursor, Read}};
 
-use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage}, command_buffer::{allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferToImageInfo}, format::Format, image::{sampler::{Sampler, SamplerCreateInfo}, view::{ImageView, ImageViewCreateInfo}, Image, ImageCreateInfo, ImageType, ImageUsage}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}, sync::{now, GpuFuture}};
+use vulkano::{buffer::{Buffer, BufferCreateInfo, BufferUsage}, command_buffer::{allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BufferImageCopy, CommandBufferUsage, CopyBufferToImageInfo}, format::Format, image::{sampler::{Sampler, SamplerCreateInfo, SamplerMipmapMode}, view::{ImageView, ImageViewCreateInfo}, Image, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}, sync::{now, GpuFuture, Sharing}};
 
-use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::Renderer, state::State};
+use crate::{asset_library::AssetLibrary, debug_labels, ecs::{System, World}, rendering::Renderer, state::State};
+
+use super::compressed_texture;
 
 #[derive(Debug)]
 pub struct Texture {
     pub name: String,
     pub image: Option<Arc<Image>>,
     pub image_view: Option<Arc<ImageView>>,
-    pub sampler: Option<Arc<Sampler>>
+    pub sampler: Option<Arc<Sampler>>,
+    pub srgb: bool,
+    pub generated: bool,
 }
 
 impl Texture {
@@ -18,41 +22,80 @@ impl Texture {
             name, 
             image: None,
             image_view: None, 
-            sampler: None
+            sampler: None,
+            srgb: true,
+            generated: false,
+        }
+    }
+
+    // Wraps an image rendered by the engine, so it can be bound like any other texture.
+    // Generated textures are owned by whatever renders them and are never loaded from disk.
+    pub fn from_view(name: &str, image_view: Arc<ImageView>, sampler: Arc<Sampler>) -> Texture {
+        Texture {
+            image: Some(image_view.image().clone()),
+            image_view: Some(image_view),
+            sampler: Some(sampler),
+            generated: true,
+            ..Texture::new(name.to_string())
+        }
+    }
+
+    // For data such as normal or roughness maps, which must not be gamma decoded when sampled.
+    pub fn linear(name: String) -> Texture {
+        Texture {
+            srgb: false,
+            ..Texture::new(name)
         }
     }
 
     fn load(&mut self, renderer: &mut Renderer) {
-        let (image_data, image_dimensions) = {
-            let mut file = File::open(format!("assets/textures/{}.png", self.name))
-                .unwrap();
-            let mut png_bytes: Vec<u8> = Vec::new();
-            file.read_to_end(&mut png_bytes).unwrap();
-            
-            let cursor = Cursor::new(png_bytes);
-            let decoder = png::Decoder::new(cursor);
-            let mut reader = decoder.read_info().unwrap();
-            let info = reader.info().clone();
-            let mut image_data = Vec::new();
-            let depth: u32 = match info.bit_depth {
-                png::BitDepth::One => 1,
-                png::BitDepth::Two => 2,
-                png::BitDepth::Four => 4,
-                png::BitDepth::Eight => 8,
-                png::BitDepth::Sixteen => 16,
-            };
-            image_data.resize((info.width * info.height * depth) as usize, 0);
-            reader.next_frame(&mut image_data).unwrap();
-            (image_data, [info.width, info.height, 1])
-        };
+        let image = decode_texture(&self.name, self.srgb, |x| renderer.supports_texture_format(x)).unwrap();
+        self.upload(renderer, image);
+    }
+    fn upload(&mut self, renderer: &mut Renderer, image: DecodedImage) {
+        let command_buffer_allocator = StandardCommandBufferAllocator::new(
+            renderer.device.as_ref().unwrap().clone(),
+            Default::default(),
+        );
 
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &command_buffer_allocator,
+            renderer.queue.as_ref().unwrap().queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        ).unwrap();
+        let mip_levels = image.levels.len();
+        self.record_upload(&mut builder, renderer, image, &[]);
+        let command_buffer = builder.build().unwrap();
+
+        let future = now(renderer.device.as_ref().unwrap().clone())
+            .then_execute(renderer.queue.as_ref().unwrap().clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap();
+
+        future.wait(None).unwrap();
+        self.finish_upload(renderer, mip_levels);
+    }
+
+    // Creates the image and records the copy from a staging buffer. `finish_upload` must only be
+    // called once the recorded commands have completed. The image is shared between
+    // `queue_families` when recording for a queue other than the graphics one.
+    pub(crate) fn record_upload<L>(&mut self, builder: &mut AutoCommandBufferBuilder<L>, renderer: &Renderer, image: DecodedImage, queue_families: &[u32]) {
+        let DecodedImage { format, extent: image_dimensions, levels } = image;
+        let sharing = if queue_families.len() > 1 {
+            Sharing::Concurrent(queue_families.iter().copied().collect())
+        } else {
+            Sharing::Exclusive
+        };
         self.image = Some(Image::new(
             renderer.memeory_allocator.as_ref().unwrap().clone(),
             ImageCreateInfo {
                 image_type: ImageType::Dim2d,
-                format: Format::R8G8B8A8_UNORM,
+                format,
                 extent: image_dimensions,
+                mip_levels: levels.len() as u32,
                 usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+                sharing,
                 ..Default::default()
             },
             AllocationCreateInfo {
@@ -60,17 +103,27 @@ impl Texture {
                 ..Default::default()
             },
         ).unwrap());
-        
-        let command_buffer_allocator = StandardCommandBufferAllocator::new(
-            renderer.device.as_ref().unwrap().clone(),
-            Default::default(),
-        );
+        debug_labels::name_object(renderer, self.image.as_ref().unwrap().as_ref(), &format!("texture {}", self.name));
 
-        let mut builder = AutoCommandBufferBuilder::primary(
-            &command_buffer_allocator,
-            renderer.queue.as_ref().unwrap().queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        ).unwrap();
+        let mut regions = Vec::with_capacity(levels.len());
+        let mut buffer_offset = 0;
+        for (level, data) in levels.iter().enumerate() {
+            regions.push(BufferImageCopy {
+                buffer_offset,
+                image_subresource: ImageSubresourceLayers {
+                    mip_level: level as u32,
+                    ..self.image.as_ref().unwrap().subresource_layers()
+                },
+                image_extent: [
+                    (image_dimensions[0] >> level).max(1),
+                    (image_dimensions[1] >> level).max(1),
+                    1,
+                ],
+                ..Default::default()
+            });
+            buffer_offset += data.len() as u64;
+        }
+        let image_data: Vec<u8> = levels.concat();
 
         let temp_buffer = Buffer::from_iter(
             renderer.memeory_allocator.as_ref().unwrap().clone(),
@@ -86,22 +139,17 @@ impl Texture {
             image_data,
         ).unwrap();
 
+        debug_labels::begin(builder, renderer, &format!("upload texture {}", self.name), debug_labels::UPLOAD_COLOR);
         builder
-            .copy_buffer_to_image(
-                CopyBufferToImageInfo::buffer_image(temp_buffer, self.image.as_ref().unwrap().to_owned())
-            )
-            .unwrap();
-
-        let command_buffer = builder.build().unwrap();
-
-        let future = now(renderer.device.as_ref().unwrap().clone())
-            .then_execute(renderer.queue.as_ref().unwrap().clone(), command_buffer)
-            .unwrap()
-            .then_signal_fence_and_flush()
+            .copy_buffer_to_image(CopyBufferToImageInfo {
+                regions: regions.into(),
+                ..CopyBufferToImageInfo::buffer_image(temp_buffer, self.image.as_ref().unwrap().to_owned())
+            })
             .unwrap();
+        debug_labels::end(builder, renderer);
+    }
 
-        future.wait(None).unwrap();
-
+    pub(crate) fn finish_upload(&mut self, renderer: &Renderer, mip_levels: usize) {
         self.image_view = Some(
             ImageView::new(
                 self.image.as_ref().unwrap().clone(),
@@ -112,19 +160,100 @@ impl Texture {
         self.sampler = Some(
             Sampler::new(
                 renderer.device.as_ref().unwrap().clone(), 
-                SamplerCreateInfo::default()
+                SamplerCreateInfo {
+                    mipmap_mode: SamplerMipmapMode::Linear,
+                    lod: 0.0..=(mip_levels - 1) as f32,
+                    ..Default::default()
+                }
             ).unwrap()
         );
     }
 }
 
+#[derive(Debug)]
+pub struct DecodedImage {
+    pub format: Format,
+    pub extent: [u32; 3],
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl DecodedImage {
+    pub fn size(&self) -> u64 {
+        self.levels.iter().map(|x| x.len() as u64).sum()
+    }
+}
+
+// Prefers a compressed KTX2 or DDS file the device can sample, falling back to PNG. Only touches
+// the filesystem, so it can run on a worker thread.
+pub fn decode_texture(name: &str, srgb: bool, supports_format: impl Fn(Format) -> bool) -> Result<DecodedImage, String> {
+    let compressed = ["ktx2", "dds"].iter().find_map(|extension| {
+        let path = format!("assets/textures/{}.{}", name, extension);
+        let bytes = fs::read(&path).ok()?;
+        let image = match *extension {
+            "ktx2" => compressed_texture::load_ktx2(&bytes),
+            _ => compressed_texture::load_dds(&bytes),
+        };
+        match image {
+            Ok(image) if supports_format(image.format) => Some(image),
+            Ok(image) => {
+                log::warn!("{} uses unsupported format {:?}, falling back to PNG", path, image.format);
+                None
+            }
+            Err(e) => {
+                log::warn!("failed to load {}: {}", path, e);
+                None
+            }
+        }
+    });
+
+    match compressed {
+        Some(image) => Ok(DecodedImage {
+            format: image.format,
+            extent: [image.width, image.height, 1],
+            levels: image.levels,
+        }),
+        None => {
+            let (image_data, extent) = decode_png(name)?;
+            let format = if srgb { Format::R8G8B8A8_SRGB } else { Format::R8G8B8A8_UNORM };
+            Ok(DecodedImage { format, extent, levels: vec![image_data] })
+        }
+    }
+}
+
+fn decode_png(name: &str) -> Result<(Vec<u8>, [u32; 3]), String> {
+    let path = format!("assets/textures/{}.png", name);
+    let mut file = File::open(&path).map_err(|e| format!("failed to open {}: {}", path, e))?;
+    let mut png_bytes: Vec<u8> = Vec::new();
+    file.read_to_end(&mut png_bytes).map_err(|e| format!("failed to read {}: {}", path, e))?;
+
+    let cursor = Cursor::new(png_bytes);
+    let decoder = png::Decoder::new(cursor);
+    let mut reader = decoder.read_info().map_err(|e| format!("failed to decode {}: {}", path, e))?;
+    let info = reader.info().clone();
+    let mut image_data = Vec::new();
+    let depth: u32 = match info.bit_depth {
+        png::BitDepth::One => 1,
+        png::BitDepth::Two => 2,
+        png::BitDepth::Four => 4,
+        png::BitDepth::Eight => 8,
+        png::BitDepth::Sixteen => 16,
+    };
+    image_data.resize((info.width * info.height * depth) as usize, 0);
+    reader.next_frame(&mut image_data).map_err(|e| format!("failed to decode {}: {}", path, e))?;
+    Ok((image_data, [info.width, info.height, 1]))
+}
+
 pub struct TextureLoader {}
 
 impl System for TextureLoader {
     fn on_start(&self, _world: &World, assets: &mut AssetLibrary, state: &mut State) {
-        for mesh in assets.textures.iter_mut() {
+        for mesh in assets.textures.iter_mut().filter(|x| !x.generated) {
             mesh.load(&mut state.renderer);
         }
     }
     fn on_update(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+
+    fn on_device_restored(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        self.on_start(world, assets, state);
+    }
 }
@@ -0,0 +1,439 @@
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::VertexData, state::State};
+
+use super::{color::Color, mesh::DynamicMesh, transform::Transform, vectors::*, visibility::Visibility};
+
+// Tile 0 is empty, like in Tiled.
+pub const EMPTY_TILE: u32 = 0;
+
+// Tiled stores flips in the top bits of every tile id.
+const TILED_FLIP_FLAGS: u32 = 0xf000_0000;
+
+#[derive(Clone, Debug)]
+pub enum TileSet {
+    /// Tile n is region `regions[n - 1]` of the atlas.
+    Atlas { atlas: String, regions: Vec<String> },
+    /// Equally sized tiles left to right, top to bottom, as Tiled lays them out. Sizes in pixels.
+    Grid {
+        texture_size: [u32; 2],
+        tile_size: [u32; 2],
+        columns: u32,
+        margin: u32,
+        spacing: u32,
+    },
+}
+
+impl TileSet {
+    fn uv(&self, assets: &AssetLibrary, tile: u32) -> Option<(Vec2f, Vec2f)> {
+        let index = tile.checked_sub(1)?;
+        match self {
+            TileSet::Atlas { atlas, regions } => {
+                let atlas = assets.atlases.iter().find(|x| x.name == *atlas)?;
+                atlas.region_uv(regions.get(index as usize)?)
+            }
+            TileSet::Grid { texture_size, tile_size, columns, margin, spacing } => {
+                let column = index % columns.max(&1);
+                let row = index / columns.max(&1);
+                let x = margin + column * (tile_size[0] + spacing);
+                let y = margin + row * (tile_size[1] + spacing);
+                let uv = |x: u32, y: u32| Vec2f::new([x as f32 / texture_size[0] as f32, y as f32 / texture_size[1] as f32]);
+                Some((uv(x, y), uv(x + tile_size[0], y + tile_size[1])))
+            }
+        }
+    }
+}
+
+// Axis aligned rectangle in the tilemap's local XY plane.
+#[derive(Clone, Copy, Debug)]
+pub struct TileRect {
+    pub min: Vec2f,
+    pub max: Vec2f,
+}
+
+// A grid of tiles on the XY plane, row 0 at the top. Drawn by `DynamicMesh` chunks spawned with
+// `spawn_tilemap`, only chunks with changed tiles are rebuilt.
+#[derive(Clone, Debug)]
+pub struct Tilemap {
+    pub name: String,
+    pub tileset: TileSet,
+    pub material: String,
+    pub width: u32,
+    pub height: u32,
+    /// World units per tile.
+    pub tile_size: Vec2f,
+    pub chunk_size: u32,
+    /// Tiles that `collision_rects` treats as solid.
+    pub solid_tiles: Vec<u32>,
+    tiles: Vec<u32>,
+    dirty: Vec<bool>,
+    chunks: Vec<usize>,
+}
+
+impl Tilemap {
+    pub fn new(name: &str, tileset: TileSet, material: &str, width: u32, height: u32, tile_size: Vec2f) -> Tilemap {
+        let mut tilemap = Tilemap {
+            name: name.to_string(),
+            tileset,
+            material: material.to_string(),
+            width,
+            height,
+            tile_size,
+            chunk_size: 16,
+            solid_tiles: Vec::new(),
+            tiles: vec![EMPTY_TILE; (width * height) as usize],
+            dirty: Vec::new(),
+            chunks: Vec::new(),
+        };
+        tilemap.dirty = vec![true; tilemap.chunk_count() as usize];
+        tilemap
+    }
+
+    // Only before `spawn_tilemap`.
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Tilemap {
+        self.chunk_size = chunk_size.max(1);
+        self.dirty = vec![true; self.chunk_count() as usize];
+        self
+    }
+
+    pub fn with_solid_tiles(mut self, solid_tiles: Vec<u32>) -> Tilemap {
+        self.solid_tiles = solid_tiles;
+        self
+    }
+
+    fn chunks_x(&self) -> u32 {
+        self.width.div_ceil(self.chunk_size)
+    }
+
+    fn chunk_count(&self) -> u32 {
+        self.chunks_x() * self.height.div_ceil(self.chunk_size)
+    }
+
+    pub fn tile(&self, x: u32, y: u32) -> u32 {
+        if x >= self.width || y >= self.height {
+            return EMPTY_TILE;
+        }
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    pub fn set_tile(&mut self, x: u32, y: u32, tile: u32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let slot = &mut self.tiles[(y * self.width + x) as usize];
+        if *slot != tile {
+            *slot = tile;
+            let chunk = (y / self.chunk_size) * self.chunks_x() + x / self.chunk_size;
+            self.dirty[chunk as usize] = true;
+        }
+    }
+
+    pub fn is_solid(&self, x: u32, y: u32) -> bool {
+        let tile = self.tile(x, y);
+        tile != EMPTY_TILE && self.solid_tiles.contains(&tile)
+    }
+
+    // Local position of the tile's bottom-left corner.
+    pub fn tile_position(&self, x: u32, y: u32) -> Vec2f {
+        Vec2f::new([x as f32 * self.tile_size.x, (self.height as f32 - 1.0 - y as f32) * self.tile_size.y])
+    }
+
+    // Solid tiles merged into as few rectangles as rows allow, for physics colliders.
+    pub fn collision_rects(&self) -> Vec<TileRect> {
+        // Runs of solid tiles per row as (start, end, first row, last row), extended downwards
+        // while the next row has the same run.
+        let mut open: Vec<(u32, u32, u32, u32)> = Vec::new();
+        let mut rects = Vec::new();
+        for y in 0..=self.height {
+            let mut runs = Vec::new();
+            let mut x = 0;
+            while y < self.height && x < self.width {
+                if !self.is_solid(x, y) {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < self.width && self.is_solid(x, y) {
+                    x += 1;
+                }
+                runs.push((start, x));
+            }
+            let mut next = Vec::new();
+            for run in open.drain(..) {
+                if let Some(i) = runs.iter().position(|x| *x == (run.0, run.1)) {
+                    runs.remove(i);
+                    next.push((run.0, run.1, run.2, y));
+                } else {
+                    let min = self.tile_position(run.0, run.3);
+                    let top = self.tile_position(run.0, run.2);
+                    rects.push(TileRect {
+                        min,
+                        max: Vec2f::new([run.1 as f32 * self.tile_size.x, top.y + self.tile_size.y]),
+                    });
+                }
+            }
+            next.extend(runs.into_iter().map(|(start, end)| (start, end, y, y)));
+            open = next;
+        }
+        rects
+    }
+
+    fn chunk_vertices(&self, assets: &AssetLibrary, chunk: u32) -> Vec<VertexData> {
+        let origin_x = (chunk % self.chunks_x()) * self.chunk_size;
+        let origin_y = (chunk / self.chunks_x()) * self.chunk_size;
+        let origin = self.tile_position(origin_x, origin_y);
+        let mut vertices = Vec::with_capacity((self.chunk_size * self.chunk_size * 4) as usize);
+        for y in origin_y..origin_y + self.chunk_size {
+            for x in origin_x..origin_x + self.chunk_size {
+                let position = self.tile_position(x, y) - origin;
+                let vertex = |dx: f32, dy: f32, u: f32, v: f32| VertexData {
+                    position: Vec3f::new([position.x + dx * self.tile_size.x, position.y + dy * self.tile_size.y, 0.0]),
+                    uv: Vec2f::new([u, v]),
+                    normal: Vec3f::new([0.0, 0.0, 1.0]),
+                    color: Color::WHITE,
+                    uv2: Vec2f::new([u, v]),
+                };
+                // Empty tiles and those without a region collapse into a point.
+                match self.tileset.uv(assets, self.tile(x, y)) {
+                    Some((min, max)) => vertices.extend([
+                        vertex(0.0, 0.0, min.x, max.y),
+                        vertex(1.0, 0.0, max.x, max.y),
+                        vertex(1.0, 1.0, max.x, min.y),
+                        vertex(0.0, 1.0, min.x, min.y),
+                    ]),
+                    None => vertices.extend([vertex(0.0, 0.0, 0.0, 0.0); 4]),
+                }
+            }
+        }
+        vertices
+    }
+
+    // Every tile layer of a Tiled map saved as JSON (.tmj) or XML (.tmx) with CSV data and an
+    // embedded tileset. Tiles with a `solid` bool property go into `solid_tiles`.
+    pub fn load_tiled(path: &str, material: &str) -> Result<Vec<Tilemap>, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        let map = if path.ends_with(".tmx") { parse_tmx(&text) } else { parse_tmj(&text) };
+        let map = map.map_err(|e| format!("{path}: {e}"))?;
+        let name = Path::new(path).file_stem().unwrap_or_default().to_string_lossy();
+        Ok(map
+            .layers
+            .into_iter()
+            .map(|(layer, data)| {
+                let mut tilemap = Tilemap::new(
+                    &format!("{name}_{layer}"),
+                    map.tileset.clone(),
+                    material,
+                    map.size[0],
+                    map.size[1],
+                    Vec2f::new([1.0, map.tile_size[1] as f32 / map.tile_size[0] as f32]),
+                )
+                .with_solid_tiles(map.solid_tiles.clone());
+                for (i, gid) in data.iter().enumerate().take(tilemap.tiles.len()) {
+                    let gid = gid & !TILED_FLIP_FLAGS;
+                    tilemap.tiles[i] = if gid < map.first_gid { EMPTY_TILE } else { gid - map.first_gid + 1 };
+                }
+                tilemap
+            })
+            .collect())
+    }
+}
+
+struct TiledMap {
+    size: [u32; 2],
+    tile_size: [u32; 2],
+    tileset: TileSet,
+    first_gid: u32,
+    solid_tiles: Vec<u32>,
+    layers: Vec<(String, Vec<u32>)>,
+}
+
+fn parse_tmj(text: &str) -> Result<TiledMap, String> {
+    let map: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let number = |value: &Value, key: &str| value[key].as_u64().map(|x| x as u32).ok_or(format!("missing {key}"));
+    let tileset = map["tilesets"].get(0).ok_or("no tileset")?;
+    if tileset.get("source").is_some() {
+        return Err("external tilesets are not supported, embed the tileset".to_string());
+    }
+    let solid_tiles = tileset["tiles"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|tile| {
+            tile["properties"].as_array().into_iter().flatten().any(|x| x["name"] == "solid" && x["value"] == true)
+        })
+        .filter_map(|tile| Some(tile["id"].as_u64()? as u32 + 1))
+        .collect();
+    let layers = map["layers"]
+        .as_array()
+        .ok_or("missing layers")?
+        .iter()
+        .filter(|x| x["type"] == "tilelayer")
+        .map(|layer| {
+            let data = layer["data"].as_array().ok_or("only uncompressed layer data is supported")?;
+            let name = layer["name"].as_str().unwrap_or_default().to_string();
+            Ok((name, data.iter().map(|x| x.as_u64().unwrap_or(0) as u32).collect()))
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(TiledMap {
+        size: [number(&map, "width")?, number(&map, "height")?],
+        tile_size: [number(&map, "tilewidth")?, number(&map, "tileheight")?],
+        tileset: TileSet::Grid {
+            texture_size: [number(tileset, "imagewidth")?, number(tileset, "imageheight")?],
+            tile_size: [number(tileset, "tilewidth")?, number(tileset, "tileheight")?],
+            columns: number(tileset, "columns")?,
+            margin: number(tileset, "margin").unwrap_or(0),
+            spacing: number(tileset, "spacing").unwrap_or(0),
+        },
+        first_gid: number(tileset, "firstgid")?,
+        solid_tiles,
+        layers,
+    })
+}
+
+// Start tags named `name`, including their attributes, and the text up to the matching end tag.
+fn xml_elements<'a>(text: &'a str, name: &str) -> Vec<(&'a str, &'a str)> {
+    let open = format!("<{name} ");
+    let close = format!("</{name}>");
+    let mut elements = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start..];
+        let tag_end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..tag_end];
+        let content = if tag.ends_with('/') {
+            ""
+        } else {
+            let end = rest.find(&close).unwrap_or(rest.len());
+            &rest[(tag_end + 1).min(end)..end]
+        };
+        elements.push((tag, content));
+        rest = &rest[tag_end.min(rest.len())..];
+    }
+    elements
+}
+
+fn xml_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!(" {name}=\"");
+    let start = tag.find(&key)? + key.len();
+    let length = tag[start..].find('"')?;
+    Some(&tag[start..start + length])
+}
+
+fn parse_tmx(text: &str) -> Result<TiledMap, String> {
+    let number = |tag: &str, name: &str| {
+        xml_attribute(tag, name).and_then(|x| x.parse::<u32>().ok()).ok_or(format!("missing {name}"))
+    };
+    let (map, _) = *xml_elements(text, "map").first().ok_or("no map")?;
+    let (tileset, tileset_content) = *xml_elements(text, "tileset").first().ok_or("no tileset")?;
+    if xml_attribute(tileset, "source").is_some() {
+        return Err("external tilesets are not supported, embed the tileset".to_string());
+    }
+    let (image, _) = *xml_elements(tileset_content, "image").first().ok_or("tileset without an image")?;
+    let solid_tiles = xml_elements(tileset_content, "tile")
+        .into_iter()
+        .filter(|(_, content)| {
+            xml_elements(content, "property")
+                .iter()
+                .any(|(x, _)| xml_attribute(x, "name") == Some("solid") && xml_attribute(x, "value") == Some("true"))
+        })
+        .filter_map(|(tag, _)| Some(xml_attribute(tag, "id")?.parse::<u32>().ok()? + 1))
+        .collect();
+    let layers = xml_elements(text, "layer")
+        .into_iter()
+        .map(|(tag, content)| {
+            let (data, values) = *xml_elements(content, "data").first().ok_or("layer without data")?;
+            if xml_attribute(data, "encoding") != Some("csv") {
+                return Err("only CSV layer data is supported".to_string());
+            }
+            let tiles = values
+                .split(',')
+                .map(|x| x.trim().parse::<u32>().map_err(|e| format!("bad tile {x:?}: {e}")))
+                .collect::<Result<_, _>>()?;
+            Ok((xml_attribute(tag, "name").unwrap_or_default().to_string(), tiles))
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(TiledMap {
+        size: [number(map, "width")?, number(map, "height")?],
+        tile_size: [number(map, "tilewidth")?, number(map, "tileheight")?],
+        tileset: TileSet::Grid {
+            texture_size: [number(image, "width")?, number(image, "height")?],
+            tile_size: [number(tileset, "tilewidth")?, number(tileset, "tileheight")?],
+            columns: number(tileset, "columns")?,
+            margin: number(tileset, "margin").unwrap_or(0),
+            spacing: number(tileset, "spacing").unwrap_or(0),
+        },
+        first_gid: number(tileset, "firstgid")?,
+        solid_tiles,
+        layers,
+    })
+}
+
+// Marks a chunk entity, `tilemap` is the entity with the `Tilemap`.
+#[derive(Clone, Debug)]
+pub struct TilemapChunk {
+    pub tilemap: usize,
+    pub index: u32,
+}
+
+// Spawns the tilemap with its bottom-left corner at `position` and a `DynamicMesh` per chunk.
+// Chunks are placed once, moving the tilemap's transform does not move them.
+pub fn spawn_tilemap(world: &mut World, mut tilemap: Tilemap, position: Vec3d) -> usize {
+    let entity = world.new_entity();
+    let quads = tilemap.chunk_size * tilemap.chunk_size;
+    let indices: Vec<u32> = (0..quads).flat_map(|i| [0, 1, 2, 2, 3, 0].map(|x| i * 4 + x)).collect();
+    for index in 0..tilemap.chunk_count() {
+        let origin = tilemap.tile_position(
+            (index % tilemap.chunks_x()) * tilemap.chunk_size,
+            (index / tilemap.chunks_x()) * tilemap.chunk_size,
+        );
+        let chunk = world.new_entity();
+        world.add_component(chunk, Transform::new(
+            position + Vec3d::new([origin.x as f64, origin.y as f64, 0.0]),
+            Vec3f::new([1.0, 1.0, 1.0]),
+            Vec3f::new([0.0, 0.0, 0.0]),
+        ));
+        // Sized for a full chunk, so edits never grow the buffers or change the draw.
+        world.add_component(chunk, DynamicMesh {
+            vertices: vec![VertexData::new(Vec3f::new([0.0, 0.0, 0.0]), Vec2f::new([0.0, 0.0]), Vec3f::new([0.0, 0.0, 1.0])); quads as usize * 4],
+            indices: indices.clone(),
+            material: tilemap.material.clone(),
+            buffers: None,
+        });
+        world.add_component(chunk, Visibility::default());
+        world.add_component(chunk, TilemapChunk { tilemap: entity, index });
+        tilemap.chunks.push(chunk);
+    }
+    world.add_component(entity, Transform::new(position, Vec3f::new([1.0, 1.0, 1.0]), Vec3f::new([0.0, 0.0, 0.0])));
+    world.add_component(entity, tilemap);
+    entity
+}
+
+pub struct TilemapUpdater {}
+
+impl System for TilemapUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, assets: &mut AssetLibrary, _state: &mut State) {
+        let Some(mut tilemaps) = world.borrow_component_vec_mut::<Tilemap>() else {
+            return;
+        };
+        let Some(mut meshes) = world.borrow_component_vec_mut::<DynamicMesh>() else {
+            return;
+        };
+
+        for tilemap in tilemaps.iter_mut().flatten() {
+            for (index, chunk) in tilemap.chunks.clone().into_iter().enumerate() {
+                if !tilemap.dirty[index] {
+                    continue;
+                }
+                let Some(mesh) = meshes.get_mut(chunk).and_then(|x| x.as_mut()) else {
+                    continue;
+                };
+                mesh.change_vertices(tilemap.chunk_vertices(assets, index as u32));
+                tilemap.dirty[index] = false;
+            }
+        }
+    }
+}
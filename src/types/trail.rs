@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::VertexData, state::State};
+
+use super::{color::Color, mesh::DynamicMesh, transform::Transform, vectors::*};
+
+#[derive(Clone, Debug)]
+pub struct Trail {
+    pub target: usize,
+    pub width: f32,
+    pub color: Color,
+    pub lifetime: f64,
+    pub min_distance: f32,
+    max_points: usize,
+    points: VecDeque<(Vec3f, f64)>,
+}
+
+impl Trail {
+    pub fn new(target: usize, max_points: usize) -> Trail {
+        Trail {
+            target,
+            width: 0.2,
+            color: Color::WHITE,
+            lifetime: 0.5,
+            min_distance: 0.05,
+            max_points: max_points.max(2),
+            points: VecDeque::new(),
+        }
+    }
+
+    pub fn max_points(&self) -> usize {
+        self.max_points
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    fn record(&mut self, position: Vec3f, time: f64) {
+        while self.points.back().is_some_and(|x| time - x.1 > self.lifetime) {
+            self.points.pop_back();
+        }
+        let moved = self.points.front().is_none_or(|x| (position - x.0).length_sqr() >= self.min_distance * self.min_distance);
+        if moved {
+            self.points.push_front((position, time));
+            self.points.truncate(self.max_points);
+        } else {
+            self.points[0].0 = position;
+        }
+    }
+
+    // The vertex and index counts never change, so the recorded draw stays valid and only the
+    // buffer contents are re-uploaded. Unused segments collapse into degenerate triangles.
+    pub fn mesh(&self, camera_position: Vec3f, time: f64) -> (Vec<VertexData>, Vec<u32>) {
+        let hidden = VertexData::new(Vec3f::new([0.0; 3]), Vec2f::new([0.0; 2]), Vec3f::new([0.0; 3]))
+            .with_color(Color::TRANSPARENT);
+        let mut vertices = vec![hidden; self.max_points * 2];
+        let mut indices = vec![0; (self.max_points - 1) * 6];
+
+        let count = self.points.len();
+        if count < 2 {
+            return (vertices, indices);
+        }
+        let mut side = Vec3f::new([0.0, 1.0, 0.0]);
+        for (i, (position, spawn_time)) in self.points.iter().enumerate() {
+            let previous = self.points[i.saturating_sub(1)].0;
+            let next = self.points[(i + 1).min(count - 1)].0;
+            let mut tangent = previous - next;
+            let mut to_camera = camera_position - *position;
+            let mut cross = tangent.cross(to_camera);
+            if cross.length_sqr() > f32::EPSILON {
+                side = cross.normalize();
+            }
+
+            let fade = (1.0 - (time - spawn_time) / self.lifetime).clamp(0.0, 1.0) as f32;
+            let half_width = self.width * 0.5 * fade;
+            let color = self.color.with_alpha(self.color.a * fade);
+            let u = i as f32 / (count - 1) as f32;
+            let normal = to_camera.normalize();
+            vertices[i * 2] = VertexData::new(*position - side * half_width, Vec2f::new([u, 0.0]), normal).with_color(color);
+            vertices[i * 2 + 1] = VertexData::new(*position + side * half_width, Vec2f::new([u, 1.0]), normal).with_color(color);
+        }
+        for i in 0..count - 1 {
+            let (a, b, c, d) = (i * 2, i * 2 + 1, i * 2 + 2, i * 2 + 3);
+            indices[i * 6..i * 6 + 6].copy_from_slice(&[a, b, c, c, b, d].map(|x| x as u32));
+        }
+        (vertices, indices)
+    }
+}
+
+pub fn spawn_trail(world: &mut World, target: usize, material: &str, max_points: usize) -> usize {
+    let trail = Trail::new(target, max_points);
+    let (vertices, indices) = trail.mesh(Vec3f::new([0.0; 3]), 0.0);
+
+    let entity = world.new_entity();
+    world.add_component(entity, Transform::new(
+        Vec3d::new([0.0, 0.0, 0.0]),
+        Vec3f::new([1.0, 1.0, 1.0]),
+        Vec3f::new([0.0, 0.0, 0.0]),
+    ));
+    world.add_component(entity, DynamicMesh {
+        vertices,
+        indices,
+        material: material.to_string(),
+        buffers: None,
+    });
+    world.add_component(entity, trail);
+    entity
+}
+
+pub struct TrailUpdater {}
+
+impl System for TrailUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(mut trails) = world.borrow_component_vec_mut::<Trail>() else {
+            return;
+        };
+        let transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+        let mut meshes = world.borrow_component_vec_mut::<DynamicMesh>().unwrap();
+        let camera_position = state.renderer.vp_pos.to_vec3f();
+
+        for (entity, trail) in trails.iter_mut().enumerate() {
+            let (Some(trail), Some(Some(mesh))) = (trail.as_mut(), meshes.get_mut(entity)) else {
+                continue;
+            };
+            if let Some(Some(target)) = transforms.get(trail.target) {
+                trail.record(target.position.to_vec3f(), state.time);
+            }
+            let (vertices, indices) = trail.mesh(camera_position, state.time);
+            mesh.change_vertices(vertices);
+            mesh.change_indices(indices);
+        }
+    }
+}
@@ -1,22 +1,36 @@
+use std::cell::RefCell;
+
 use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+use simple_engine_derive::{Component, Reflect};
 use vulkano::buffer::BufferUsage;
 
 use crate::{
     asset_library::AssetLibrary,
-    ecs::{System, World},
+    ecs::{Changed, System, World},
     state::State,
     types::vectors::*,
 };
 
-use super::{buffers::UpdatableBuffer, matrices::Matrix4f};
+use super::{buffers::UpdatableBuffer, camera::update_render_origin, material_override::MaterialOverride, matrices::Matrix4f};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize, Component, Reflect)]
+#[component(persistent, reflect)]
 pub struct Transform {
     pub position: Vec3d,
     pub scale: Vec3f,
     pub rotation: Vec3f,
+    #[serde(skip)]
+    #[reflect(skip)]
     pub buffer: Option<UpdatableBuffer<ModelData>>,
-    pub changed: bool
+    /// No longer read, mark the transform with `World::mark_changed` after mutating it.
+    #[deprecated(note = "use World::mark_changed::<Transform>")]
+    #[serde(default)]
+    #[reflect(skip)]
+    pub changed: bool,
+    #[serde(skip)]
+    #[reflect(skip)]
+    pending_writes: usize,
 }
 
 #[repr(C)]
@@ -24,58 +38,144 @@ pub struct Transform {
 pub struct ModelData {
     model: Matrix4f,
     rotation: Matrix4f,
+    tint: [f32; 4],
+    emissive: [f32; 4],
 }
 
 impl Transform {
+    #[allow(deprecated)]
     pub fn new(pos: Vec3d, scl: Vec3f, rot: Vec3f) -> Transform {
         Transform {
             position: pos,
             scale: scl,
             rotation: rot,
             buffer: None,
-            changed: false
+            changed: false,
+            pending_writes: 0,
         }
     }
 
-    pub fn load(&mut self, state: &State) {
+    // Drops shear, see `Matrix4f::decompose`.
+    pub fn from_matrix(matrix: Matrix4f) -> Transform {
+        let (translation, rotation, scale) = matrix.decompose();
+        Transform::new(translation.to_vec3d(), scale, rotation)
+    }
+
+    pub fn matrix(&self) -> Matrix4f {
+        Matrix4f::compose(self.position.to_vec3f(), self.rotation, self.scale)
+    }
+
+    pub fn load(&mut self, state: &State, material_override: Option<&MaterialOverride>) {
         self.buffer = Some(UpdatableBuffer::new(&state.renderer, BufferUsage::UNIFORM_BUFFER));
-        self.update_buffer(state);
+        self.buffer.as_ref().unwrap().write_all(state, self.model_data(state, material_override));
+    }
+
+    pub fn update_buffer(&mut self, state: &State, material_override: Option<&MaterialOverride>) {
+        self.buffer.as_ref().unwrap().write(state, self.model_data(state, material_override));
     }
 
-    pub fn update_buffer(&mut self, state: &State) {
-        self.buffer.as_mut().unwrap().write_all(state, ModelData {
-            model: Matrix4f::translation(self.position.to_vec3f())
-                * Matrix4f::rotation_yxz(self.rotation)
-                * Matrix4f::scale(self.scale),
+    fn model_data(&self, state: &State, material_override: Option<&MaterialOverride>) -> ModelData {
+        let (tint, emissive) = MaterialOverride::instance_data(material_override);
+        ModelData {
+            model: Matrix4f::compose(state.renderer.render_space(self.position), self.rotation, self.scale),
             rotation: Matrix4f::rotation_yxz(self.rotation),
-        });
+            tint,
+            emissive,
+        }
+    }
+}
+
+// Only transforms marked changed are written, see `World::mark_changed`. Each change is written to
+// the buffer of every frame in flight over the next frames.
+pub struct TransformUpdater {
+    pending: RefCell<Vec<usize>>,
+}
+
+impl TransformUpdater {
+    pub fn new() -> TransformUpdater {
+        TransformUpdater {
+            pending: RefCell::new(Vec::new()),
+        }
     }
 }
 
-pub struct TransformUpdater {}
+impl Default for TransformUpdater {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl System for TransformUpdater {
     fn on_start(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
-        for transform in world
-            .borrow_component_vec_mut::<Transform>()
-            .unwrap()
-            .iter_mut()
-            .filter(|x| x.is_some())
-        {
-            transform.as_mut().unwrap().load(state);
+        let overrides = world.borrow_component_vec_mut::<MaterialOverride>();
+        for (entity, transform) in world.borrow_component_vec_mut::<Transform>().unwrap().iter_mut().enumerate() {
+            if let Some(transform) = transform.as_mut() {
+                transform.load(state, overrides.as_ref().and_then(|x| x.get(entity)?.as_ref()));
+            }
         }
     }
 
     fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
-        for transform in world
-            .borrow_component_vec_mut::<Transform>()
-            .unwrap()
-            .iter_mut()
-            .filter(|x| x.is_some())
-        {
-            if transform.as_mut().unwrap().changed {
-                transform.as_mut().unwrap().update_buffer(state);
+        let origin_moved = update_render_origin(world, state);
+        let mut transforms = world.borrow_component_vec_mut::<Transform>().unwrap();
+        let mut overrides = world.borrow_component_vec_mut::<MaterialOverride>();
+        let mut pending = self.pending.borrow_mut();
+        let mut changed = if origin_moved {
+            (0..transforms.len()).filter(|x| transforms[*x].is_some()).collect()
+        } else {
+            world.query::<Changed<Transform>>()
+        };
+        if let Some(overrides) = overrides.as_mut() {
+            for entity in world.query::<Changed<MaterialOverride>>() {
+                let material_override = overrides[entity].as_mut().unwrap();
+                // Textures are bound when the command buffers are recorded.
+                if material_override.texture != material_override.applied_texture {
+                    material_override.applied_texture = material_override.texture.clone();
+                    state.renderer.command_buffer_outdated = true;
+                }
+                if transforms.get(entity).is_some_and(|x| x.is_some()) {
+                    changed.push(entity);
+                }
+            }
+            // Both queries list entities in order, the stable sort merges the two runs in linear time.
+            changed.sort();
+            changed.dedup();
+        }
+        let material_override = |entity: usize| overrides.as_ref().and_then(|x| x.get(entity)?.as_ref());
+        for entity in changed {
+            let transform = transforms[entity].as_mut().unwrap();
+            // Loading writes every frame's buffer already.
+            if transform.buffer.is_none() {
+                transform.load(state, material_override(entity));
+                continue;
+            }
+            if transform.pending_writes == 0 {
+                pending.push(entity);
+            }
+            transform.pending_writes = state.renderer.frames_in_flight;
+        }
+
+        // Despawned entities drop out here.
+        pending.retain(|entity| {
+            let Some(transform) = transforms[*entity].as_mut() else {
+                return false;
+            };
+            // Replaced through `add_component` while pending and loaded since, see above.
+            if transform.pending_writes == 0 {
+                return false;
+            }
+            transform.pending_writes -= 1;
+            transform.update_buffer(state, material_override(*entity));
+            transform.pending_writes > 0
+        });
+    }
+
+    fn on_device_restored(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        for entity in self.pending.borrow_mut().drain(..) {
+            if let Some(transform) = world.borrow_component_vec_mut::<Transform>().unwrap()[entity].as_mut() {
+                transform.pending_writes = 0;
             }
         }
+        self.on_start(world, assets, state);
     }
 }
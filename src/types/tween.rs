@@ -0,0 +1,170 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    asset_library::AssetLibrary,
+    ecs::{System, World},
+    state::State,
+};
+
+use super::{easing::Easing, transform::Transform, vectors::*};
+
+static NEXT_TWEEN_HANDLE: AtomicU64 = AtomicU64::new(0);
+
+pub type Lens<T> = Arc<dyn Fn(&mut T, f32)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TweenHandle(u64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TweenRepeat {
+    #[default]
+    Once,
+    Loop,
+    PingPong,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TweenCompleted {
+    pub handle: TweenHandle,
+    pub entity: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Tweens {
+    cancelled: Vec<TweenHandle>,
+    completed: Vec<TweenCompleted>,
+}
+
+impl Tweens {
+    pub fn new() -> Tweens {
+        Tweens::default()
+    }
+
+    pub fn cancel(&mut self, handle: TweenHandle) {
+        self.cancelled.push(handle);
+    }
+
+    pub fn drain_completed(&mut self) -> Vec<TweenCompleted> {
+        std::mem::take(&mut self.completed)
+    }
+}
+
+#[derive(Clone)]
+pub struct Tween<T> {
+    pub handle: TweenHandle,
+    pub duration: f64,
+    pub elapsed: f64,
+    pub easing: Easing,
+    pub repeat: TweenRepeat,
+    pub lens: Lens<T>,
+}
+
+impl<T> Tween<T> {
+    pub fn new(duration: f64, easing: Easing, lens: impl Fn(&mut T, f32) + 'static) -> Tween<T> {
+        Tween {
+            handle: TweenHandle(NEXT_TWEEN_HANDLE.fetch_add(1, Ordering::Relaxed)),
+            duration,
+            elapsed: 0.0,
+            easing,
+            repeat: TweenRepeat::Once,
+            lens: Arc::new(lens),
+        }
+    }
+
+    pub fn with_repeat(mut self, repeat: TweenRepeat) -> Tween<T> {
+        self.repeat = repeat;
+        self
+    }
+
+    fn progress(&self) -> (f32, bool) {
+        if self.duration <= 0.0 {
+            return (1.0, self.repeat == TweenRepeat::Once);
+        }
+        let cycles = self.elapsed / self.duration;
+        match self.repeat {
+            TweenRepeat::Once => (cycles.min(1.0) as f32, cycles >= 1.0),
+            TweenRepeat::Loop => (cycles.fract() as f32, false),
+            TweenRepeat::PingPong => {
+                let phase = cycles % 2.0;
+                ((if phase > 1.0 { 2.0 - phase } else { phase }) as f32, false)
+            }
+        }
+    }
+}
+
+impl Tween<Transform> {
+    pub fn position(start: Vec3d, end: Vec3d, duration: f64, easing: Easing) -> Tween<Transform> {
+        Tween::new(duration, easing, move |transform: &mut Transform, t| {
+            transform.position = start + (end - start) * t as f64;
+        })
+    }
+
+    pub fn scale(start: Vec3f, end: Vec3f, duration: f64, easing: Easing) -> Tween<Transform> {
+        Tween::new(duration, easing, move |transform: &mut Transform, t| {
+            transform.scale = start + (end - start) * t;
+        })
+    }
+
+    pub fn rotation(start: Vec3f, end: Vec3f, duration: f64, easing: Easing) -> Tween<Transform> {
+        Tween::new(duration, easing, move |transform: &mut Transform, t| {
+            transform.rotation = start + (end - start) * t;
+        })
+    }
+}
+
+pub struct TweenUpdater<T> {
+    marker: PhantomData<T>,
+}
+
+impl<T> TweenUpdater<T> {
+    pub fn new() -> TweenUpdater<T> {
+        TweenUpdater { marker: PhantomData }
+    }
+}
+
+impl<T> Default for TweenUpdater<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: 'static + Clone> System for TweenUpdater<T> {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(mut tweens) = world.borrow_component_vec_mut::<Tween<T>>() else {
+            return;
+        };
+        let Some(mut targets) = world.borrow_component_vec_mut::<T>() else {
+            return;
+        };
+
+        for (entity, (tween_slot, target)) in tweens.iter_mut().zip(targets.iter_mut()).enumerate() {
+            let (Some(tween), Some(target)) = (tween_slot.as_mut(), target.as_mut()) else {
+                continue;
+            };
+            if let Some(i) = state.tweens.cancelled.iter().position(|x| *x == tween.handle) {
+                state.tweens.cancelled.swap_remove(i);
+                *tween_slot = None;
+                continue;
+            }
+
+            tween.elapsed += state.delta_time;
+            let (progress, finished) = tween.progress();
+            (tween.lens)(target, tween.easing.apply(progress));
+            world.mark_changed::<T>(entity);
+            if finished {
+                state.tweens.completed.push(TweenCompleted {
+                    handle: tween.handle,
+                    entity,
+                });
+                *tween_slot = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,509 @@
+use winit::event::MouseButton;
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, rendering::VertexData, state::State};
+
+use super::{atlas::{AtlasRegion, TextureAtlas}, color::Color, mesh::DynamicMesh, transform::Transform, ui_widgets::UiWidgetEvent, vectors::*};
+
+#[derive(Clone, Copy, Debug)]
+pub struct UiRect {
+    pub min: Vec2f,
+    pub max: Vec2f,
+}
+
+impl Default for UiRect {
+    fn default() -> Self {
+        UiRect {
+            min: Vec2f::new([0.0, 0.0]),
+            max: Vec2f::new([0.0, 0.0]),
+        }
+    }
+}
+
+impl UiRect {
+    pub fn size(&self) -> Vec2f {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: Vec2f) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x && point.y >= self.min.y && point.y <= self.max.y
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum UiWidget {
+    #[default]
+    None,
+    Background {
+        material: String,
+        color: Color,
+    },
+    Image {
+        material: String,
+        color: Color,
+        uv_min: Vec2f,
+        uv_max: Vec2f,
+    },
+    Text {
+        material: String,
+        color: Color,
+        text: String,
+        glyph_size: Vec2f,
+    },
+    Sprite {
+        material: String,
+        color: Color,
+        atlas: String,
+        region: String,
+    },
+    NineSlice {
+        material: String,
+        color: Color,
+        atlas: String,
+        region: String,
+        border: [f32; 4],
+        scale: f32,
+    },
+}
+
+impl UiWidget {
+    fn material(&self) -> Option<&str> {
+        match self {
+            UiWidget::None => None,
+            UiWidget::Background { material, .. }
+            | UiWidget::Image { material, .. }
+            | UiWidget::Text { material, .. }
+            | UiWidget::Sprite { material, .. }
+            | UiWidget::NineSlice { material, .. } => Some(material),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UiNode {
+    pub parent: Option<usize>,
+    pub anchor_min: Vec2f,
+    pub anchor_max: Vec2f,
+    pub offset_min: Vec2f,
+    pub offset_max: Vec2f,
+    pub z: i32,
+    pub visible: bool,
+    pub interactable: bool,
+    pub widget: UiWidget,
+    pub rect: UiRect,
+    pub hovered: bool,
+    pub pressed: bool,
+}
+
+impl UiNode {
+    pub fn new(anchor_min: Vec2f, anchor_max: Vec2f, offset_min: Vec2f, offset_max: Vec2f) -> UiNode {
+        UiNode {
+            parent: None,
+            anchor_min,
+            anchor_max,
+            offset_min,
+            offset_max,
+            z: 0,
+            visible: true,
+            interactable: false,
+            widget: UiWidget::None,
+            rect: UiRect::default(),
+            hovered: false,
+            pressed: false,
+        }
+    }
+
+    pub fn fixed(position: Vec2f, size: Vec2f) -> UiNode {
+        UiNode::new(Vec2f::new([0.0, 0.0]), Vec2f::new([0.0, 0.0]), position, position + size)
+    }
+
+    pub fn stretch(margin: f32) -> UiNode {
+        UiNode::new(
+            Vec2f::new([0.0, 0.0]),
+            Vec2f::new([1.0, 1.0]),
+            Vec2f::new([margin, margin]),
+            Vec2f::new([-margin, -margin]),
+        )
+    }
+
+    pub fn with_parent(mut self, parent: usize) -> UiNode {
+        self.parent = Some(parent);
+        self
+    }
+
+    pub fn with_widget(mut self, widget: UiWidget) -> UiNode {
+        self.widget = widget;
+        self
+    }
+
+    pub fn with_z(mut self, z: i32) -> UiNode {
+        self.z = z;
+        self
+    }
+
+    pub fn interactable(mut self) -> UiNode {
+        self.interactable = true;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UiEventKind {
+    Enter,
+    Leave,
+    Press,
+    Release,
+    Click,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UiEvent {
+    pub entity: usize,
+    pub kind: UiEventKind,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UiState {
+    pub events: Vec<UiEvent>,
+    pub widget_events: Vec<UiWidgetEvent>,
+    pub hovered: Option<usize>,
+    pub pressed: Option<usize>,
+    /// Text field receiving keyboard and IME input.
+    pub focused: Option<usize>,
+}
+
+impl UiState {
+    pub fn new() -> UiState {
+        UiState::default()
+    }
+
+    pub fn drain_events(&mut self) -> Vec<UiEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn drain_widget_events(&mut self) -> Vec<UiWidgetEvent> {
+        std::mem::take(&mut self.widget_events)
+    }
+
+    pub fn clicked(&self, entity: usize) -> bool {
+        self.events.contains(&UiEvent {
+            entity,
+            kind: UiEventKind::Click,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UiBatch {
+    pub material: String,
+}
+
+pub fn spawn_ui_batch(world: &mut World, material: &str) -> usize {
+    let (vertices, indices) = empty_batch();
+    let entity = world.new_entity();
+    world.add_component(entity, Transform::new(
+        Vec3d::new([0.0, 0.0, 0.0]),
+        Vec3f::new([1.0, 1.0, 1.0]),
+        Vec3f::new([0.0, 0.0, 0.0]),
+    ));
+    world.add_component(entity, DynamicMesh {
+        vertices,
+        indices,
+        material: material.to_string(),
+        buffers: None,
+    });
+    world.add_component(entity, UiBatch {
+        material: material.to_string(),
+    });
+    entity
+}
+
+fn empty_batch() -> (Vec<VertexData>, Vec<u32>) {
+    let vertex = VertexData {
+        position: Vec3f::new([0.0, 0.0, 0.0]),
+        uv: Vec2f::new([0.0, 0.0]),
+        normal: Vec3f::new([0.0, 0.0, 0.0]),
+        color: Color::TRANSPARENT,
+        uv2: Vec2f::new([0.0, 0.0]),
+    };
+    (vec![vertex; 3], vec![0, 1, 2])
+}
+
+// Offsets are in logical pixels and `scale` converts them to the physical pixels rects are in.
+fn resolve_rect(nodes: &mut [Option<UiNode>], resolved: &mut [bool], entity: usize, screen: UiRect, scale: f32) -> UiRect {
+    if resolved[entity] {
+        return nodes[entity].as_ref().unwrap().rect;
+    }
+    resolved[entity] = true;
+
+    let parent = nodes[entity].as_ref().unwrap().parent;
+    let parent_rect = match parent {
+        Some(parent) if nodes.get(parent).is_some_and(|x| x.is_some()) => resolve_rect(nodes, resolved, parent, screen, scale),
+        _ => screen,
+    };
+
+    let node = nodes[entity].as_mut().unwrap();
+    let parent_size = parent_rect.size();
+    node.rect = UiRect {
+        min: parent_rect.min + parent_size * node.anchor_min + node.offset_min * scale,
+        max: parent_rect.min + parent_size * node.anchor_max + node.offset_max * scale,
+    };
+    node.rect
+}
+
+fn layout(nodes: &mut [Option<UiNode>], state: &State) {
+    let dimensions = state.window.window_handle.inner_size();
+    let screen = UiRect {
+        min: Vec2f::new([0.0, 0.0]),
+        max: Vec2f::new([dimensions.width as f32, dimensions.height as f32]),
+    };
+    let scale = state.window.scale_factor() as f32;
+    let mut resolved = vec![false; nodes.len()];
+    for entity in 0..nodes.len() {
+        if nodes[entity].is_some() {
+            resolve_rect(nodes, &mut resolved, entity, screen, scale);
+        }
+    }
+}
+
+fn is_visible(nodes: &[Option<UiNode>], entity: usize) -> bool {
+    let mut current = Some(entity);
+    while let Some(entity) = current {
+        let Some(Some(node)) = nodes.get(entity) else {
+            return true;
+        };
+        if !node.visible {
+            return false;
+        }
+        current = node.parent.filter(|x| *x != entity);
+    }
+    true
+}
+
+struct QuadBuilder {
+    vertices: Vec<VertexData>,
+    indices: Vec<u32>,
+    // Rects are in physical pixels, vertices in the logical pixels of the UI projection.
+    scale: f32,
+}
+
+impl QuadBuilder {
+    fn push(&mut self, rect: UiRect, uv_min: Vec2f, uv_max: Vec2f, color: Color) {
+        let base = self.vertices.len() as u32;
+        let to_ui = |x: f32, y: f32| Vec3f::new([x / self.scale, y / self.scale, 0.0]);
+        let corners = [
+            (rect.min.x, rect.min.y, uv_min.x, uv_min.y),
+            (rect.max.x, rect.min.y, uv_max.x, uv_min.y),
+            (rect.max.x, rect.max.y, uv_max.x, uv_max.y),
+            (rect.min.x, rect.max.y, uv_min.x, uv_max.y),
+        ];
+        for (x, y, u, v) in corners {
+            self.vertices.push(VertexData {
+                position: to_ui(x, y),
+                uv: Vec2f::new([u, v]),
+                normal: Vec3f::new([color.r, color.g, color.b]),
+                color,
+                uv2: Vec2f::new([u, v]),
+            });
+        }
+        self.indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    fn push_nine_slice(&mut self, rect: UiRect, atlas: &TextureAtlas, region: AtlasRegion, border: [f32; 4], scale: f32, color: Color) {
+        let size = rect.size();
+        let [left, top, right, bottom] = border;
+        let fit = |a: f32, b: f32, available: f32| {
+            let total = (a + b) * scale;
+            if total > available && total > 0.0 { available / total * scale } else { scale }
+        };
+        let scale_x = fit(left, right, size.x);
+        let scale_y = fit(top, bottom, size.y);
+
+        let xs = [rect.min.x, rect.min.x + left * scale_x, rect.max.x - right * scale_x, rect.max.x];
+        let ys = [rect.min.y, rect.min.y + top * scale_y, rect.max.y - bottom * scale_y, rect.max.y];
+        let (rx, ry, rw, rh) = (region.x as f32, region.y as f32, region.w as f32, region.h as f32);
+        let us = [rx, rx + left, rx + rw - right, rx + rw];
+        let vs = [ry, ry + top, ry + rh - bottom, ry + rh];
+
+        for row in 0..3 {
+            for column in 0..3 {
+                let cell = UiRect {
+                    min: Vec2f::new([xs[column], ys[row]]),
+                    max: Vec2f::new([xs[column + 1], ys[row + 1]]),
+                };
+                if cell.size().x <= 0.0 || cell.size().y <= 0.0 {
+                    continue;
+                }
+                self.push(cell, atlas.uv(us[column], vs[row]), atlas.uv(us[column + 1], vs[row + 1]), color);
+            }
+        }
+    }
+
+    fn push_text(&mut self, rect: UiRect, text: &str, glyph_size: Vec2f, color: Color) {
+        let cell = 1.0 / 16.0;
+        let mut cursor = rect.min;
+        for character in text.chars() {
+            if character == '\n' {
+                cursor = Vec2f::new([rect.min.x, cursor.y + glyph_size.y]);
+                continue;
+            }
+            if cursor.x + glyph_size.x > rect.max.x {
+                cursor = Vec2f::new([rect.min.x, cursor.y + glyph_size.y]);
+            }
+            if cursor.y + glyph_size.y > rect.max.y {
+                break;
+            }
+            let code = if character.is_ascii() { character as u32 } else { '?' as u32 };
+            let uv_min = Vec2f::new([(code % 16) as f32 * cell, (code / 16) as f32 * cell]);
+            self.push(
+                UiRect { min: cursor, max: cursor + glyph_size },
+                uv_min,
+                uv_min + Vec2f::new([cell, cell]),
+                color,
+            );
+            cursor.x += glyph_size.x;
+        }
+    }
+}
+
+fn same_geometry(mesh: &DynamicMesh, vertices: &[VertexData], indices: &[u32]) -> bool {
+    let same_vertex = |a: &VertexData, b: &VertexData| {
+        [a.position.x, a.position.y, a.position.z, a.uv.x, a.uv.y, a.normal.x, a.normal.y, a.normal.z]
+            == [b.position.x, b.position.y, b.position.z, b.uv.x, b.uv.y, b.normal.x, b.normal.y, b.normal.z]
+    };
+    mesh.indices == indices
+        && mesh.vertices.len() == vertices.len()
+        && mesh.vertices.iter().zip(vertices.iter()).all(|(a, b)| same_vertex(a, b))
+}
+
+pub struct UiUpdater {}
+
+impl System for UiUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(mut nodes) = world.borrow_component_vec_mut::<UiNode>() else {
+            return;
+        };
+
+        layout(&mut nodes, state);
+
+        let mut order: Vec<usize> = (0..nodes.len())
+            .filter(|x| nodes[*x].is_some() && is_visible(&nodes, *x))
+            .collect();
+        order.sort_by_key(|x| nodes[*x].as_ref().unwrap().z);
+
+        let cursor = state.input.cursor_pos;
+        let hovered = order
+            .iter()
+            .rev()
+            .copied()
+            .find(|x| nodes[*x].as_ref().is_some_and(|node| node.interactable && node.rect.contains(cursor)));
+
+        let ui = &mut state.ui;
+        ui.events.clear();
+        if hovered != ui.hovered {
+            if let Some(entity) = ui.hovered {
+                ui.events.push(UiEvent { entity, kind: UiEventKind::Leave });
+            }
+            if let Some(entity) = hovered {
+                ui.events.push(UiEvent { entity, kind: UiEventKind::Enter });
+            }
+            ui.hovered = hovered;
+        }
+        if state.input.mouse_pressed.contains(&MouseButton::Left) {
+            ui.pressed = hovered;
+            if let Some(entity) = hovered {
+                ui.events.push(UiEvent { entity, kind: UiEventKind::Press });
+            }
+        }
+        if state.input.mouse_released.contains(&MouseButton::Left) {
+            if let Some(entity) = ui.pressed.take() {
+                ui.events.push(UiEvent { entity, kind: UiEventKind::Release });
+                if hovered == Some(entity) {
+                    ui.events.push(UiEvent { entity, kind: UiEventKind::Click });
+                }
+            }
+        }
+        for (entity, node) in nodes.iter_mut().enumerate() {
+            if let Some(node) = node {
+                node.hovered = ui.hovered == Some(entity);
+                node.pressed = ui.pressed == Some(entity);
+            }
+        }
+    }
+}
+
+pub struct UiBatcher {}
+
+impl System for UiBatcher {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, assets: &mut AssetLibrary, state: &mut State) {
+        let (Some(mut nodes), Some(batches)) = (
+            world.borrow_component_vec_mut::<UiNode>(),
+            world.borrow_component_vec_mut::<UiBatch>(),
+        ) else {
+            return;
+        };
+
+        layout(&mut nodes, state);
+
+        let scale = state.window.scale_factor() as f32;
+        let mut order: Vec<usize> = (0..nodes.len())
+            .filter(|x| nodes[*x].is_some() && is_visible(&nodes, *x))
+            .collect();
+        order.sort_by_key(|x| nodes[*x].as_ref().unwrap().z);
+
+        let mut meshes = world.borrow_component_vec_mut::<DynamicMesh>().unwrap();
+        for (batch, mesh) in batches.iter().zip(meshes.iter_mut()) {
+            let (Some(batch), Some(mesh)) = (batch, mesh) else {
+                continue;
+            };
+
+            let mut builder = QuadBuilder {
+                vertices: Vec::new(),
+                indices: Vec::new(),
+                scale,
+            };
+            for node in order.iter().filter_map(|x| nodes[*x].as_ref()) {
+                if node.widget.material() != Some(batch.material.as_str()) {
+                    continue;
+                }
+                match &node.widget {
+                    UiWidget::None => (),
+                    UiWidget::Background { color, .. } => {
+                        builder.push(node.rect, Vec2f::new([0.0, 0.0]), Vec2f::new([1.0, 1.0]), *color)
+                    }
+                    UiWidget::Image { color, uv_min, uv_max, .. } => builder.push(node.rect, *uv_min, *uv_max, *color),
+                    UiWidget::Text { color, text, glyph_size, .. } => {
+                        builder.push_text(node.rect, text, *glyph_size * scale, *color)
+                    }
+                    UiWidget::Sprite { color, atlas, region, .. } => {
+                        let uv = assets.atlases.iter().find(|x| x.name == *atlas).and_then(|x| x.region_uv(region));
+                        if let Some((uv_min, uv_max)) = uv {
+                            builder.push(node.rect, uv_min, uv_max, *color);
+                        }
+                    }
+                    UiWidget::NineSlice { color, atlas, region, border, scale: border_scale, .. } => {
+                        let atlas = assets.atlases.iter().find(|x| x.name == *atlas);
+                        if let Some((atlas, region)) = atlas.and_then(|x| Some((x, x.region(region)?))) {
+                            builder.push_nine_slice(node.rect, atlas, region, *border, *border_scale * scale, *color);
+                        }
+                    }
+                }
+            }
+
+            let (vertices, indices) = if builder.indices.is_empty() {
+                empty_batch()
+            } else {
+                (builder.vertices, builder.indices)
+            };
+            if same_geometry(mesh, &vertices, &indices) {
+                continue;
+            }
+            if mesh.indices.len() != indices.len() {
+                state.renderer.command_buffer_outdated = true;
+            }
+            mesh.change_vertices(vertices);
+            mesh.change_indices(indices);
+        }
+    }
+}
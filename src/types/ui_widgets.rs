@@ -0,0 +1,289 @@
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::MouseButton,
+    keyboard::{Key, NamedKey},
+};
+
+use crate::{asset_library::AssetLibrary, ecs::{System, World}, state::State};
+
+use super::{color::Color, ui::{UiEventKind, UiNode, UiWidget}};
+
+#[derive(Clone, Copy, Debug)]
+pub struct UiStyle {
+    pub normal: Color,
+    pub hovered: Color,
+    pub pressed: Color,
+}
+
+impl UiStyle {
+    fn color(&self, node: &UiNode) -> Color {
+        if node.pressed {
+            self.pressed
+        } else if node.hovered {
+            self.hovered
+        } else {
+            self.normal
+        }
+    }
+}
+
+impl Default for UiStyle {
+    fn default() -> Self {
+        UiStyle {
+            normal: Color::rgb(0.3, 0.3, 0.3),
+            hovered: Color::rgb(0.4, 0.4, 0.4),
+            pressed: Color::rgb(0.2, 0.2, 0.2),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UiButton {
+    pub style: UiStyle,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct UiCheckbox {
+    pub checked: bool,
+    pub style: UiStyle,
+    pub checked_color: Color,
+}
+
+impl UiCheckbox {
+    pub fn new(checked: bool) -> UiCheckbox {
+        UiCheckbox {
+            checked,
+            style: UiStyle::default(),
+            checked_color: Color::rgb(0.2, 0.6, 0.2),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct UiSlider {
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub handle: Option<usize>,
+}
+
+impl UiSlider {
+    pub fn new(value: f32, min: f32, max: f32) -> UiSlider {
+        UiSlider {
+            value,
+            min,
+            max,
+            handle: None,
+        }
+    }
+
+    pub fn with_handle(mut self, handle: usize) -> UiSlider {
+        self.handle = Some(handle);
+        self
+    }
+
+    fn fraction(&self) -> f32 {
+        if self.max > self.min {
+            ((self.value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+// Focused by clicking it. `text_node` is the node whose `UiWidget::Text` shows the contents and
+// the IME composition preview.
+#[derive(Clone, Debug)]
+pub struct UiTextField {
+    pub text: String,
+    pub max_length: Option<usize>,
+    pub style: UiStyle,
+    pub focused_color: Color,
+    pub text_node: Option<usize>,
+}
+
+impl UiTextField {
+    pub fn new(text: &str) -> UiTextField {
+        UiTextField {
+            text: text.to_string(),
+            max_length: None,
+            style: UiStyle::default(),
+            focused_color: Color::rgb(0.15, 0.15, 0.15),
+            text_node: None,
+        }
+    }
+
+    pub fn with_text_node(mut self, text_node: usize) -> UiTextField {
+        self.text_node = Some(text_node);
+        self
+    }
+
+    pub fn with_max_length(mut self, max_length: usize) -> UiTextField {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    fn insert(&mut self, text: &str) -> bool {
+        let room = self.max_length.map_or(usize::MAX, |x| x.saturating_sub(self.text.chars().count()));
+        let before = self.text.len();
+        self.text.extend(text.chars().take(room));
+        self.text.len() != before
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UiWidgetEvent {
+    ButtonClicked { entity: usize },
+    CheckboxToggled { entity: usize, checked: bool },
+    SliderChanged { entity: usize, value: f32 },
+    TextChanged { entity: usize },
+    TextSubmitted { entity: usize },
+}
+
+fn set_color(node: &mut UiNode, new_color: Color) {
+    match &mut node.widget {
+        UiWidget::Background { color, .. }
+        | UiWidget::Image { color, .. }
+        | UiWidget::Text { color, .. }
+        | UiWidget::Sprite { color, .. }
+        | UiWidget::NineSlice { color, .. } => *color = new_color,
+        UiWidget::None => (),
+    }
+}
+
+pub struct UiWidgetUpdater {}
+
+impl System for UiWidgetUpdater {
+    fn on_start(&self, _world: &World, _assets: &mut AssetLibrary, _state: &mut State) {}
+    fn on_update(&self, world: &World, _assets: &mut AssetLibrary, state: &mut State) {
+        let Some(mut nodes) = world.borrow_component_vec_mut::<UiNode>() else {
+            return;
+        };
+        let ui = &mut state.ui;
+        ui.widget_events.clear();
+        let clicked = |entity: usize| {
+            ui.events.iter().any(|x| x.entity == entity && x.kind == UiEventKind::Click)
+        };
+        let mut widget_events = Vec::new();
+
+        if let Some(buttons) = world.borrow_component_vec_mut::<UiButton>() {
+            for (entity, button) in buttons.iter().enumerate() {
+                let (Some(button), Some(Some(node))) = (button, nodes.get_mut(entity)) else {
+                    continue;
+                };
+                set_color(node, button.style.color(node));
+                if clicked(entity) {
+                    widget_events.push(UiWidgetEvent::ButtonClicked { entity });
+                }
+            }
+        }
+
+        if let Some(mut checkboxes) = world.borrow_component_vec_mut::<UiCheckbox>() {
+            for (entity, checkbox) in checkboxes.iter_mut().enumerate() {
+                let (Some(checkbox), Some(Some(node))) = (checkbox, nodes.get_mut(entity)) else {
+                    continue;
+                };
+                if clicked(entity) {
+                    checkbox.checked = !checkbox.checked;
+                    widget_events.push(UiWidgetEvent::CheckboxToggled {
+                        entity,
+                        checked: checkbox.checked,
+                    });
+                }
+                let color = if checkbox.checked && !node.hovered {
+                    checkbox.checked_color
+                } else {
+                    checkbox.style.color(node)
+                };
+                set_color(node, color);
+            }
+        }
+
+        if let Some(mut sliders) = world.borrow_component_vec_mut::<UiSlider>() {
+            for (entity, slider) in sliders.iter_mut().enumerate() {
+                let Some(slider) = slider else {
+                    continue;
+                };
+                let Some(Some(node)) = nodes.get(entity) else {
+                    continue;
+                };
+                if node.pressed {
+                    let width = node.rect.size().x;
+                    let fraction = if width > 0.0 {
+                        ((state.input.cursor_pos.x - node.rect.min.x) / width).clamp(0.0, 1.0)
+                    } else {
+                        0.0
+                    };
+                    let value = slider.min + fraction * (slider.max - slider.min);
+                    if value != slider.value {
+                        slider.value = value;
+                        widget_events.push(UiWidgetEvent::SliderChanged { entity, value });
+                    }
+                }
+
+                let fraction = slider.fraction();
+                if let Some(Some(handle)) = slider.handle.and_then(|x| nodes.get_mut(x)) {
+                    handle.anchor_min.x = fraction;
+                    handle.anchor_max.x = fraction;
+                }
+            }
+        }
+
+        if let Some(mut text_fields) = world.borrow_component_vec_mut::<UiTextField>() {
+            let is_text_field = |entity: &usize| text_fields.get(*entity).is_some_and(|x| x.is_some());
+            let mut focused = ui.focused.filter(is_text_field);
+            if state.input.mouse_pressed.contains(&MouseButton::Left) {
+                focused = ui.pressed.filter(is_text_field);
+            }
+            if state.input.pressed.contains(&Key::Named(NamedKey::Escape)) {
+                focused = None;
+            }
+            if focused != ui.focused {
+                let window = &state.window.window_handle;
+                window.set_ime_allowed(focused.is_some());
+                if let Some(Some(node)) = focused.and_then(|x| nodes.get(x)) {
+                    let size = node.rect.size();
+                    window.set_ime_cursor_area(
+                        PhysicalPosition::new(node.rect.min.x, node.rect.min.y),
+                        PhysicalSize::new(size.x, size.y),
+                    );
+                }
+                ui.focused = focused;
+            }
+
+            for (entity, text_field) in text_fields.iter_mut().enumerate() {
+                let Some(text_field) = text_field else {
+                    continue;
+                };
+                let is_focused = focused == Some(entity);
+                if is_focused {
+                    let mut changed = text_field.insert(&state.input.text_input);
+                    if state.input.pressed.contains(&Key::Named(NamedKey::Backspace)) && state.input.ime_preedit.is_empty() {
+                        changed |= text_field.text.pop().is_some();
+                    }
+                    if changed {
+                        widget_events.push(UiWidgetEvent::TextChanged { entity });
+                    }
+                    if state.input.pressed.contains(&Key::Named(NamedKey::Enter)) && state.input.ime_preedit.is_empty() {
+                        widget_events.push(UiWidgetEvent::TextSubmitted { entity });
+                    }
+                }
+
+                if let Some(Some(node)) = nodes.get_mut(entity) {
+                    let color = if is_focused { text_field.focused_color } else { text_field.style.color(node) };
+                    set_color(node, color);
+                }
+                if let Some(Some(text_node)) = text_field.text_node.and_then(|x| nodes.get_mut(x)) {
+                    if let UiWidget::Text { text, .. } = &mut text_node.widget {
+                        text.clone_from(&text_field.text);
+                        if is_focused {
+                            text.push_str(&state.input.ime_preedit);
+                        }
+                    }
+                }
+            }
+        }
+
+        ui.widget_events = widget_events;
+    }
+}
@@ -1,14 +1,17 @@
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
 
 use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+use super::simd;
+
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Vec2f {
     pub x: f32,
     pub y: f32,
 }
-#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Vec3f {
     pub x: f32,
@@ -16,13 +19,13 @@ pub struct Vec3f {
     pub z: f32,
 }
 
-#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Vec2d {
     pub x: f64,
     pub y: f64,
 }
-#[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Vec3d {
     pub x: f64,
@@ -204,6 +207,13 @@ impl DivAssign<f64> for Vec3d {
     }
 }
 
+impl Mul for Vec2f {
+    type Output = Vec2f;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Vec2f::new([self.x * rhs.x, self.y * rhs.y])
+    }
+}
+
 impl Mul<f32> for Vec2f {
     type Output = Vec2f;
     fn mul(self, rhs: f32) -> Self::Output {
@@ -427,15 +437,11 @@ impl Vec3f {
     }
 
     pub fn dot(&mut self, vec: Vec3f) -> f32 {
-        self.x * vec.x + self.y * vec.y + self.z * vec.z
+        simd::dot3([self.x, self.y, self.z], [vec.x, vec.y, vec.z])
     }
 
     pub fn cross(&mut self, vec: Vec3f) -> Vec3f {
-        Vec3f {
-            x: (self.y * vec.z) - (self.z * vec.y),
-            y: (self.z * vec.x) - (self.x * vec.z),
-            z: (self.x * vec.y) - (self.y * vec.x),
-        }
+        Vec3f::new(simd::cross3([self.x, self.y, self.z], [vec.x, vec.y, vec.z]))
     }
 
     pub fn length_sqr(&mut self) -> f32 {
@@ -447,12 +453,7 @@ impl Vec3f {
     }
 
     pub fn normalize(&mut self) -> Vec3f {
-        let len = self.length();
-        Vec3f {
-            x: self.x / len,
-            y: self.y / len,
-            z: self.z / len,
-        }
+        Vec3f::new(simd::normalize3([self.x, self.y, self.z]))
     }
 }
 
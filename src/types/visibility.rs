@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use simple_engine_derive::{Component, Reflect};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Component, Reflect)]
+#[component(persistent, reflect)]
+pub struct Visibility {
+    pub visible: bool,
+}
+
+impl Visibility {
+    pub fn new(visible: bool) -> Visibility {
+        Visibility { visible }
+    }
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use crate::state::State;
+
+// Kept when nobody drains them, older ones are dropped.
+const MAX_EVENTS: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct SlowFrame {
+    pub time: f64,
+    pub gpu_time: Option<Duration>,
+    pub fence_wait: Duration,
+    /// Slowest of `rendering::GPU_PASSES`, if the queue supports timestamps.
+    pub pass: Option<(&'static str, Duration)>,
+    /// Material with the most triangles drawn.
+    pub material: Option<String>,
+}
+
+// Reports frames over `RendererSettings::frame_watchdog`, so games can react, e.g. by lowering the
+// render scale. Only the first frame of a run of slow frames is logged.
+#[derive(Clone, Debug, Default)]
+pub struct FrameWatchdog {
+    /// Slow frames in a row.
+    pub streak: usize,
+    events: Vec<SlowFrame>,
+}
+
+impl FrameWatchdog {
+    pub fn drain_events(&mut self) -> Vec<SlowFrame> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+pub fn check_frame(state: &mut State) {
+    let Some(threshold) = state.renderer.settings.frame_watchdog else {
+        return;
+    };
+    let stats = &state.renderer.stats;
+    let watchdog = &mut state.renderer.watchdog;
+    if stats.gpu_time.is_none_or(|x| x <= threshold) && stats.fence_wait <= threshold {
+        watchdog.streak = 0;
+        return;
+    }
+
+    let pass = stats.gpu_passes.iter().copied().max_by_key(|x| x.1);
+    let material = stats.materials.iter().max_by_key(|x| x.1.triangles).map(|x| x.0.clone());
+    if watchdog.streak == 0 {
+        log::warn!(
+            "slow frame: gpu {:.2} ms, fence wait {:.2} ms, slowest pass {}, heaviest material {}",
+            stats.gpu_time.unwrap_or_default().as_secs_f64() * 1000.0,
+            stats.fence_wait.as_secs_f64() * 1000.0,
+            pass.map(|(name, x)| format!("{name} ({:.2} ms)", x.as_secs_f64() * 1000.0)).unwrap_or("unknown".to_string()),
+            material.as_deref().unwrap_or("none"),
+        );
+    }
+    watchdog.streak += 1;
+    if watchdog.events.len() >= MAX_EVENTS {
+        watchdog.events.remove(0);
+    }
+    watchdog.events.push(SlowFrame {
+        time: state.time,
+        gpu_time: stats.gpu_time,
+        fence_wait: stats.fence_wait,
+        pass,
+        material,
+    });
+}